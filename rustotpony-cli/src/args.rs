@@ -0,0 +1,838 @@
+use crate::messages::tr;
+use crate::{Cli, DEFAULT_LIST_COLUMNS, DUPLICATE_RESOLUTIONS, IMPORT_FORMATS, LIST_COLUMNS};
+use clap::{App, Arg, ArgGroup, SubCommand};
+
+impl Cli {
+    pub(crate) fn get_cli_api_matches() -> clap::ArgMatches<'static> {
+        App::new("🐴  RusTOTPony")
+            .version(env!("CARGO_PKG_VERSION"))
+            .author("German Lashevich <german.lashevich@gmail.com>")
+            .about(tr("CLI manager of one-time password generators aka Google Authenticator"))
+            .arg(
+                Arg::with_name("database")
+                    .short("d")
+                    .long("database")
+                    .takes_value(true)
+                    .global(true)
+                    .help(tr("Database location: a file path, s3://bucket/key or sftp://host/path")),
+            )
+            .arg(
+                Arg::with_name("read-only")
+                    .long("read-only")
+                    .global(true)
+                    .help(tr("Never modify the database")),
+            )
+            .arg(
+                Arg::with_name("follow-symlinks")
+                    .long("follow-symlinks")
+                    .global(true)
+                    .help(tr("Write to the file a symlinked database points to instead of refusing")),
+            )
+            .arg(
+                Arg::with_name("exact-names")
+                    .long("exact-names")
+                    .global(true)
+                    .help(tr("Match generator names exactly instead of ignoring case, allows names differing only in case")),
+            )
+            .arg(
+                Arg::with_name("no-color")
+                    .long("no-color")
+                    .global(true)
+                    .help(tr("Don't color the output, the same as setting NO_COLOR")),
+            )
+            .arg(
+                Arg::with_name("allow-core-dumps")
+                    .long("allow-core-dumps")
+                    .global(true)
+                    .help(tr("Keep core dumps enabled, for debugging")),
+            )
+            .arg(
+                Arg::with_name("dry-run")
+                    .long("dry-run")
+                    .global(true)
+                    .help(tr("Show what would be changed without saving anything")),
+            )
+            .arg(
+                Arg::with_name("group")
+                    .long("group")
+                    .takes_value(true)
+                    .global(true)
+                    .help(tr("Split shown codes into groups of this size, 0 disables grouping")),
+            )
+            .arg(
+                Arg::with_name("cache-key")
+                    .long("cache-key")
+                    .takes_value(true)
+                    .value_name("MINUTES")
+                    .global(true)
+                    .help(tr("Keep the database key in memory for this long instead of asking for the password again")),
+            )
+            .arg(
+                Arg::with_name("unlock-time")
+                    .long("unlock-time")
+                    .takes_value(true)
+                    .value_name("MILLISECONDS")
+                    .global(true)
+                    .help(tr("How long unlocking a new database should take on this machine, 250 by default")),
+            )
+            .arg(
+                Arg::with_name("journal")
+                    .long("journal")
+                    .global(true)
+                    .help(tr("Append changes of a local database to a journal next to it instead of rewriting the file")),
+            )
+            .subcommand(
+                SubCommand::with_name("dash").about(tr("Show realtime dashboard with all generators")),
+            )
+            .subcommand(
+                SubCommand::with_name("list")
+                    .about(tr("List all generators"))
+                    .arg(
+                        Arg::with_name("page")
+                            .long("page")
+                            .takes_value(true)
+                            .help(tr("Number of the page to show, starting from 1")),
+                    )
+                    .arg(
+                        Arg::with_name("per-page")
+                            .long("per-page")
+                            .takes_value(true)
+                            .help(tr("Number of generators on a page, all of them by default")),
+                    )
+                    .arg(
+                        Arg::with_name("archived")
+                            .long("archived")
+                            .conflicts_with_all(&["page", "per-page"])
+                            .help(tr("List archived generators instead")),
+                    )
+                    .arg(Self::columns_arg()),
+            )
+            .subcommand(
+                SubCommand::with_name("search")
+                    .about(tr("List generators whose name, username or issuer contains the text"))
+                    .arg(Arg::with_name("QUERY").required(true))
+                    .arg(Self::columns_arg()),
+            )
+            // .subcommand(
+            //     SubCommand::with_name("show-all")
+            //         .about(tr("Shows all generators with their's current values")),
+            // )
+            .subcommand(
+                SubCommand::with_name("show")
+                    .about(tr("Show generator with its current value"))
+                    .arg(
+                        Arg::with_name("APPNAME")
+                            .help(tr("Name of the generator, it's picked interactively when not given")),
+                    )
+                    .arg(
+                        Arg::with_name("reveal")
+                            .long("reveal")
+                            .help(tr("Confirm showing a hidden generator")),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("login")
+                    .about(tr("Print username of generator and then its current code, one per line"))
+                    .arg(
+                        Arg::with_name("APPNAME")
+                            .help(tr("Name of the generator, it's picked interactively when not given")),
+                    )
+                    .arg(
+                        Arg::with_name("wait")
+                            .long("wait")
+                            .help(tr("Wait for Enter before printing the code, so it's fresh when the form asks for it")),
+                    )
+                    .arg(
+                        Arg::with_name("reveal")
+                            .long("reveal")
+                            .help(tr("Confirm using a hidden generator")),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("add")
+                    .about(tr("Add a new generator"))
+                    .arg(Arg::with_name("APPNAME").required(true))
+                    .arg(Arg::with_name("USERNAME"))
+                    .arg(
+                        Arg::with_name("type")
+                            .long("type")
+                            .takes_value(true)
+                            .possible_values(&["totp", "motp", "ocra"])
+                            .help(tr("Generator algorithm, motp asks for a PIN and uses the secret as is")),
+                    )
+                    .arg(
+                        Arg::with_name("suite")
+                            .long("suite")
+                            .takes_value(true)
+                            .help(tr("OCRA suite, OCRA-1:HOTP-SHA1-6:QN08 by default")),
+                    )
+                    .arg(
+                        Arg::with_name("epoch")
+                            .long("epoch")
+                            .takes_value(true)
+                            .help(tr("Unix time from which TOTP time steps are counted, 0 by default")),
+                    )
+                    .arg(
+                        Arg::with_name("encoding")
+                            .long("encoding")
+                            .takes_value(true)
+                            .possible_values(&["base32", "hex", "raw"])
+                            .help(tr("Encoding of the secret, base32 by default")),
+                    )
+                    .arg(
+                        Arg::with_name("hidden")
+                            .long("hidden")
+                            .help(tr("Leave the generator out of listings and the dashboard")),
+                    )
+                    .arg(
+                        Arg::with_name("protect")
+                            .long("protect")
+                            .help(tr("Encrypt the secret with an additional passphrase")),
+                    )
+                    .arg(
+                        Arg::with_name("on-duplicate")
+                            .long("on-duplicate")
+                            .takes_value(true)
+                            .possible_values(DUPLICATE_RESOLUTIONS)
+                            .help(tr("What to do if another generator already has the secret, asked by default")),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("provision")
+                    .about(tr("Add a generator with a new random secret and print its QR code"))
+                    .arg(Arg::with_name("APPNAME").required(true))
+                    .arg(Arg::with_name("USERNAME")),
+            )
+            .subcommand(
+                SubCommand::with_name("reenroll")
+                    .about(
+                        tr("Print QR code and otpauth URI for setting generator up on another device"),
+                    )
+                    .arg(Arg::with_name("APPNAME").required(true))
+                    .arg(
+                        Arg::with_name("yes")
+                            .long("yes")
+                            .help(tr("Don't ask for confirmation")),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("share")
+                    .about(tr("Encrypt one generator with a new passphrase for handing it to someone"))
+                    .arg(Arg::with_name("APPNAME").required(true))
+                    .arg(
+                        Arg::with_name("to-passphrase")
+                            .long("to-passphrase")
+                            .help(tr("Encrypt with a passphrase agreed with the recipient (the only way for now)")),
+                    )
+                    .arg(
+                        Arg::with_name("yes")
+                            .long("yes")
+                            .help(tr("Don't ask for confirmation")),
+                    )
+                    .after_help(tr("The recipient adds the generator with `totp receive`")),
+            )
+            .subcommand(
+                SubCommand::with_name("handoff")
+                    .about(tr("Show the current code with its expiry for someone who has to log in now"))
+                    .arg(
+                        Arg::with_name("APPNAME")
+                            .help(tr("Name of the generator, it's picked interactively when not given")),
+                    )
+                    .arg(
+                        Arg::with_name("json")
+                            .long("json")
+                            .help(tr("Print the code as JSON instead of a QR code")),
+                    )
+                    .arg(
+                        Arg::with_name("reveal")
+                            .long("reveal")
+                            .help(tr("Confirm handing off a code of a hidden generator")),
+                    )
+                    .after_help(tr("Only the code leaves the database, never the secret. Use `totp share` to hand over the generator itself")),
+            )
+            .subcommand(
+                SubCommand::with_name("receive")
+                    .about(tr("Add a generator shared with `totp share`"))
+                    .arg(
+                        Arg::with_name("SHARE")
+                            .help(tr("Text of the shared generator, read from the input if it's missing")),
+                    )
+                    .arg(
+                        Arg::with_name("as")
+                            .long("as")
+                            .takes_value(true)
+                            .help(tr("Name for the generator instead of the shared one")),
+                    )
+                    .arg(
+                        Arg::with_name("on-duplicate")
+                            .long("on-duplicate")
+                            .takes_value(true)
+                            .possible_values(DUPLICATE_RESOLUTIONS)
+                            .help(tr("What to do if another generator already has the secret, asked by default")),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("respond")
+                    .about(tr("Compute response of a challenge-response (OCRA) generator"))
+                    .arg(Arg::with_name("APPNAME").required(true))
+                    .arg(Arg::with_name("CHALLENGE").required(true)),
+            )
+            .subcommand(
+                SubCommand::with_name("reveal-secret")
+                    .about(tr("Print generator secret, the disclosure is recorded in the history"))
+                    .arg(Arg::with_name("APPNAME").required(true))
+                    .arg(
+                        Arg::with_name("yes")
+                            .long("yes")
+                            .help(tr("Don't ask for confirmation")),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("protect")
+                    .about(tr("Encrypt generator secret with an additional passphrase"))
+                    .arg(Arg::with_name("APPNAME").required(true)),
+            )
+            .subcommand(
+                SubCommand::with_name("unprotect")
+                    .about(tr("Remove additional passphrase from generator"))
+                    .arg(Arg::with_name("APPNAME").required(true)),
+            )
+            .subcommand(
+                SubCommand::with_name("format")
+                    .about(tr("Change how codes of generator are shown"))
+                    .arg(Arg::with_name("APPNAME").required(true))
+                    .arg(
+                        Arg::with_name("length")
+                            .long("length")
+                            .takes_value(true)
+                            .help(tr("Number of characters in a code, 6 by default")),
+                    )
+                    .arg(
+                        Arg::with_name("alphabet")
+                            .long("alphabet")
+                            .takes_value(true)
+                            .help(tr("Characters used instead of digits, or 'steam' for Steam Guard codes")),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("epoch")
+                    .about(tr("Change unix time from which time steps of generator are counted"))
+                    .arg(Arg::with_name("APPNAME").required(true))
+                    .arg(Arg::with_name("SECONDS").required(true)),
+            )
+            .subcommand(
+                SubCommand::with_name("hide")
+                    .about(tr("Leave generator out of listings and the dashboard"))
+                    .arg(Arg::with_name("APPNAME").required(true)),
+            )
+            .subcommand(
+                SubCommand::with_name("unhide")
+                    .about(tr("Show hidden generator in listings again"))
+                    .arg(Arg::with_name("APPNAME").required(true)),
+            )
+            .subcommand(
+                SubCommand::with_name("archive")
+                    .about(tr("Move generator out of listings and the dashboard, keeping it in the database"))
+                    .arg(Arg::with_name("APPNAME").required(true)),
+            )
+            .subcommand(
+                SubCommand::with_name("unarchive")
+                    .about(tr("Bring archived generator back"))
+                    .arg(Arg::with_name("APPNAME").required(true)),
+            )
+            .subcommand(
+                SubCommand::with_name("icon")
+                    .about(tr("Set icon of generator, kept for graphical authenticator apps"))
+                    .arg(Arg::with_name("APPNAME").required(true))
+                    .arg(
+                        Arg::with_name("name")
+                            .long("name")
+                            .takes_value(true)
+                            .help(tr("Name of an icon in an icon pack, e.g. github")),
+                    )
+                    .arg(
+                        Arg::with_name("url")
+                            .long("url")
+                            .takes_value(true)
+                            .help(tr("Address of an image")),
+                    )
+                    .arg(
+                        Arg::with_name("file")
+                            .long("file")
+                            .takes_value(true)
+                            .help(tr("PNG, JPEG, SVG or WebP image to store in the database")),
+                    )
+                    .arg(
+                        Arg::with_name("clear")
+                            .long("clear")
+                            .help(tr("Remove the icon")),
+                    )
+                    .group(
+                        ArgGroup::with_name("icon")
+                            .args(&["name", "url", "file", "clear"])
+                            .required(true),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("tag")
+                    .about(tr("Add tags to generator"))
+                    .arg(Arg::with_name("APPNAME").required(true))
+                    .arg(Arg::with_name("TAG").required(true).multiple(true)),
+            )
+            .subcommand(
+                SubCommand::with_name("untag")
+                    .about(tr("Remove tags from generator"))
+                    .arg(Arg::with_name("APPNAME").required(true))
+                    .arg(Arg::with_name("TAG").required(true).multiple(true)),
+            )
+            .subcommand(
+                SubCommand::with_name("delete")
+                    .about(tr("Delete generator"))
+                    .arg(Arg::with_name("APPNAME").required(true)),
+            )
+            .subcommand(
+                SubCommand::with_name("rename")
+                    .about(tr("Rename generator"))
+                    .arg(Arg::with_name("APPNAME").required(true))
+                    .arg(Arg::with_name("NEWNAME").required(true)),
+            )
+            .subcommand(
+                SubCommand::with_name("rename-all")
+                    .about(tr("Rename many generators at once, use --dry-run to preview new names"))
+                    .arg(
+                        Arg::with_name("prefix")
+                            .long("prefix")
+                            .takes_value(true)
+                            .help(tr("Put this text before names, e.g. work/")),
+                    )
+                    .arg(
+                        Arg::with_name("suffix")
+                            .long("suffix")
+                            .takes_value(true)
+                            .help(tr("Put this text after names")),
+                    )
+                    .arg(
+                        Arg::with_name("regex")
+                            .long("regex")
+                            .takes_value(true)
+                            .requires("replace")
+                            .help(tr("Replace matches of this regular expression in names")),
+                    )
+                    .arg(
+                        Arg::with_name("replace")
+                            .long("replace")
+                            .takes_value(true)
+                            .requires("regex")
+                            .help(tr("Replacement for --regex, $1 refers to the first group")),
+                    )
+                    .group(
+                        ArgGroup::with_name("rule")
+                            .args(&["prefix", "suffix", "regex"])
+                            .required(true),
+                    )
+                    .arg(
+                        Arg::with_name("name")
+                            .long("name")
+                            .takes_value(true)
+                            .multiple(true)
+                            .number_of_values(1)
+                            .help(tr("Rename only generators with names matching this pattern, * and ? are wildcards")),
+                    )
+                    .arg(
+                        Arg::with_name("tag")
+                            .long("tag")
+                            .takes_value(true)
+                            .multiple(true)
+                            .number_of_values(1)
+                            .help(tr("Rename only generators with this tag")),
+                    ),
+            )
+            .subcommand(SubCommand::with_name("eradicate").about(tr("Delete all generators")))
+            .subcommand(SubCommand::with_name("history").about(tr("Show log of database changes")))
+            .subcommand(
+                SubCommand::with_name("init")
+                    .about(tr("Create a new database, instead of the first save creating it with the defaults"))
+                    .arg(
+                        Arg::with_name("LOCATION")
+                            .help(tr("Where to create the database, the one of --database by default")),
+                    )
+                    .arg(
+                        Arg::with_name("cipher")
+                            .long("cipher")
+                            .takes_value(true)
+                            .possible_values(&["xchacha20-poly1305", "aes-256-gcm"])
+                            .help(tr("Cipher of the database, xchacha20-poly1305 by default")),
+                    )
+                    .arg(
+                        Arg::with_name("scrypt-cost")
+                            .long("scrypt-cost")
+                            .takes_value(true)
+                            .value_name("LOG_N")
+                            .help(tr("scrypt cost as log2 of N instead of the one measured for --unlock-time")),
+                    )
+                    .arg(
+                        Arg::with_name("indexed")
+                            .long("indexed")
+                            .help(tr("Encrypt every generator on its own, behind an encrypted index")),
+                    )
+                    .arg(
+                        Arg::with_name("import")
+                            .long("import")
+                            .takes_value(true)
+                            .value_name("FILE")
+                            .help(tr("Import generators from the file into the new database")),
+                    )
+                    .arg(
+                        Arg::with_name("from")
+                            .long("from")
+                            .takes_value(true)
+                            .requires("import")
+                            .possible_values(IMPORT_FORMATS)
+                            .help(tr("App the imported file comes from, otpauth URIs by default")),
+                    )
+                    .arg(
+                        Arg::with_name("on-duplicate")
+                            .long("on-duplicate")
+                            .takes_value(true)
+                            .requires("import")
+                            .possible_values(DUPLICATE_RESOLUTIONS)
+                            .help(tr("What to do with imported entries with the same secret, asked for each of them by default")),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("import")
+                    .about(tr("Import generators from a file of otpauth:// URIs, one per line, or from other apps"))
+                    .arg(
+                        Arg::with_name("FILE")
+                            .required(true)
+                            .help(tr("File to import, - reads the standard input, or a folder with --from folder")),
+                    )
+                    .arg(
+                        Arg::with_name("from")
+                            .long("from")
+                            .takes_value(true)
+                            .possible_values(IMPORT_FORMATS)
+                            .help(tr("App the file comes from, otpauth URIs by default")),
+                    )
+                    .arg(
+                        Arg::with_name("json")
+                            .long("json")
+                            .help(tr("Print the report as JSON")),
+                    )
+                    .arg(
+                        Arg::with_name("on-duplicate")
+                            .long("on-duplicate")
+                            .takes_value(true)
+                            .possible_values(DUPLICATE_RESOLUTIONS)
+                            .help(tr("What to do with entries whose secret another generator already has, asked for each of them by default")),
+                    )
+                    .after_help(tr("Use --dry-run to see what would be imported without saving anything")),
+            )
+            .subcommand(
+                SubCommand::with_name("export")
+                    .about(tr("Export generators as otpauth:// URIs, one per line"))
+                    .arg(
+                        Arg::with_name("FILE")
+                            .required(true)
+                            .help(tr("File to write, - prints the URIs")),
+                    )
+                    .arg(
+                        Arg::with_name("name")
+                            .long("name")
+                            .takes_value(true)
+                            .multiple(true)
+                            .number_of_values(1)
+                            .help(tr("Export only generators with names matching this pattern, * and ? are wildcards")),
+                    )
+                    .arg(
+                        Arg::with_name("tag")
+                            .long("tag")
+                            .takes_value(true)
+                            .multiple(true)
+                            .number_of_values(1)
+                            .help(tr("Export only generators with this tag")),
+                    )
+                    .arg(
+                        Arg::with_name("format")
+                            .long("format")
+                            .takes_value(true)
+                            .possible_values(&["uris", "html", "aegis", "andotp"])
+                            .help(tr("otpauth URIs (the default), a printable HTML page or an encrypted backup of Aegis or andOTP")),
+                    )
+                    .arg(
+                        Arg::with_name("qr")
+                            .long("qr")
+                            .help(tr("Add QR codes to the HTML page")),
+                    )
+                    .arg(
+                        Arg::with_name("mask-secrets")
+                            .long("mask-secrets")
+                            .help(tr("Leave secrets out of the text of the HTML page, only QR codes carry them")),
+                    )
+                    .arg(
+                        Arg::with_name("recipient")
+                            .long("recipient")
+                            .short("r")
+                            .takes_value(true)
+                            .multiple(true)
+                            .number_of_values(1)
+                            .value_name("PUBLIC KEY")
+                            .help(tr("Encrypt the export to this age public key (age1…), any of the recipients can decrypt it")),
+                    )
+                    .after_help(
+                        tr("Exported secrets aren't encrypted unless the format is an encrypted backup or \
+                         recipients are given, every export is recorded in the history"),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("paper-backup")
+                    .about(tr("Write the encrypted database as a printable page of QR codes"))
+                    .arg(
+                        Arg::with_name("OUTPUT")
+                            .required(true)
+                            .help(tr("Path of a new HTML file")),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("paper-restore")
+                    .about(tr("Restore a database from the scanned QR codes of a paper backup"))
+                    .after_help(
+                        tr("Texts of the QR codes are read from the input one per line, in any order, \
+                         until an empty line. The database must not exist yet."),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("salvage")
+                    .about(tr("Recover readable generators from a damaged database"))
+                    .arg(
+                        Arg::with_name("OUTPUT")
+                            .required(true)
+                            .help(tr("Path of a new database for recovered generators")),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("change-password")
+                    .about(tr("Encrypt database with a new password")),
+            )
+            .subcommand(
+                SubCommand::with_name("require-two-persons")
+                    .about(tr("Encrypt database with passphrases of two persons, both are needed to open it"))
+                    .after_help(
+                        tr("Each person enters their own passphrase. A decoy database is removed. \
+                         `totp change-password` goes back to a single password."),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("split-key")
+                    .about(tr("Split the database key into shares for trusted people"))
+                    .arg(
+                        Arg::with_name("shares")
+                            .long("shares")
+                            .short("n")
+                            .takes_value(true)
+                            .required(true)
+                            .help(tr("Number of shares")),
+                    )
+                    .arg(
+                        Arg::with_name("threshold")
+                            .long("threshold")
+                            .short("k")
+                            .takes_value(true)
+                            .required(true)
+                            .help(tr("Number of shares required to recover the key")),
+                    )
+                    .after_help(
+                        tr("Fewer shares than the threshold tell nothing about the key. Shares stop \
+                         working once the password is changed."),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("recover-key")
+                    .about(tr("Set a new password with the shares of the database key"))
+                    .after_help(tr("Shares are read from the input one per line, until an empty line.")),
+            )
+            .subcommand(
+                SubCommand::with_name("decoy")
+                    .about(tr("Set up a duress password which opens a separate decoy database")),
+            )
+            .subcommand(
+                SubCommand::with_name("doctor")
+                    .about(tr("Check database for problems"))
+                    .arg(
+                        Arg::with_name("json")
+                            .long("json")
+                            .help(tr("Print findings as JSON")),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("stats")
+                    .about(tr("Summarize generators, e.g. before a cleanup"))
+                    .arg(
+                        Arg::with_name("oldest")
+                            .long("oldest")
+                            .takes_value(true)
+                            .help(tr("Number of generators with the oldest secrets to show, 5 by default")),
+                    )
+                    .arg(
+                        Arg::with_name("json")
+                            .long("json")
+                            .help(tr("Print statistics as JSON")),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("fix-permissions")
+                    .about(tr("Make database file accessible only by you")),
+            )
+            .subcommand(
+                SubCommand::with_name("sync")
+                    .about(tr("Synchronize database with a WebDAV server"))
+                    .subcommand(
+                        Self::sync_subcommand("pull").about(tr("Download database from the server")),
+                    )
+                    .subcommand(
+                        Self::sync_subcommand("push").about(tr("Upload database to the server")),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("git")
+                    .about(tr("Keep history of database changes in a git repository"))
+                    .subcommand(
+                        SubCommand::with_name("init")
+                            .about(tr("Start committing every database change"))
+                            .arg(Arg::with_name("REMOTE").help(tr("URL of a remote repository"))),
+                    )
+                    .subcommand(SubCommand::with_name("log").about(tr("Show database history")))
+                    .subcommand(
+                        SubCommand::with_name("push")
+                            .about(tr("Push history to the remote repository")),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("recovery")
+                    .about(tr("Manage recovery codes of generators"))
+                    .subcommand(
+                        SubCommand::with_name("add")
+                            .about(
+                                tr("Store recovery codes, they are read from the input one per line"),
+                            )
+                            .arg(Arg::with_name("APPNAME").required(true)),
+                    )
+                    .subcommand(
+                        SubCommand::with_name("list")
+                            .about(tr("Print stored recovery codes"))
+                            .arg(Arg::with_name("APPNAME").required(true)),
+                    )
+                    .subcommand(
+                        SubCommand::with_name("use")
+                            .about(tr("Print a recovery code and remove it"))
+                            .arg(Arg::with_name("APPNAME").required(true))
+                            .arg(
+                                Arg::with_name("CODE")
+                                    .help(tr("Code to remove, the oldest by default")),
+                            ),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("alias")
+                    .about(tr("Manage short names of generators, accepted wherever a name is"))
+                    .subcommand(
+                        SubCommand::with_name("add")
+                            .about(tr("Add an alias to a generator"))
+                            .arg(Arg::with_name("APPNAME").required(true))
+                            .arg(Arg::with_name("ALIAS").required(true)),
+                    )
+                    .subcommand(
+                        SubCommand::with_name("remove")
+                            .about(tr("Remove an alias"))
+                            .arg(Arg::with_name("ALIAS").required(true)),
+                    )
+                    .subcommand(SubCommand::with_name("list").about(tr("List aliases"))),
+            )
+            .subcommand(
+                SubCommand::with_name("tags")
+                    .about(tr("Reorganize tags of all generators"))
+                    .subcommand(
+                        SubCommand::with_name("rename")
+                            .about(tr("Rename a tag on all generators"))
+                            .arg(Arg::with_name("TAG").required(true))
+                            .arg(Arg::with_name("NEWTAG").required(true)),
+                    )
+                    .subcommand(
+                        SubCommand::with_name("merge")
+                            .about(tr("Replace tags with one tag on all generators"))
+                            .arg(Arg::with_name("TAG").required(true).multiple(true))
+                            .arg(
+                                Arg::with_name("into")
+                                    .long("into")
+                                    .takes_value(true)
+                                    .required(true)
+                                    .help(tr("Tag to keep")),
+                            ),
+                    )
+                    .subcommand(
+                        SubCommand::with_name("list").about(tr("List tags with numbers of generators")),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("snapshot")
+                    .about(tr("Manage labeled copies of the database"))
+                    .subcommand(
+                        SubCommand::with_name("create")
+                            .about(tr("Save current state of the database"))
+                            .arg(Arg::with_name("LABEL").required(true)),
+                    )
+                    .subcommand(SubCommand::with_name("list").about(tr("List snapshots")))
+                    .subcommand(
+                        SubCommand::with_name("restore")
+                            .about(tr("Replace the database with a snapshot"))
+                            .arg(Arg::with_name("LABEL").required(true)),
+                    ),
+            )
+            .after_help(tr("Try `totp help [SUBCOMMAND]` to see help for the given subcommand"))
+            .get_matches()
+    }
+
+    fn columns_arg() -> Arg<'static, 'static> {
+        Arg::with_name("columns")
+            .long("columns")
+            .takes_value(true)
+            .use_delimiter(true)
+            .possible_values(LIST_COLUMNS)
+            .help(tr(
+                "Comma-separated columns of the table, name,key,username by default",
+            ))
+    }
+
+    pub(crate) fn list_columns<'a>(matches: &'a clap::ArgMatches) -> Vec<&'a str> {
+        match matches.values_of("columns") {
+            Some(columns) => columns.collect(),
+            None => DEFAULT_LIST_COLUMNS.to_vec(),
+        }
+    }
+
+    fn sync_subcommand(name: &str) -> App<'static, 'static> {
+        SubCommand::with_name(name)
+            .arg(
+                Arg::with_name("URL")
+                    .required(true)
+                    .help(tr("URL of the database file on the WebDAV server")),
+            )
+            .arg(
+                Arg::with_name("username")
+                    .short("u")
+                    .long("username")
+                    .takes_value(true)
+                    .help(tr("WebDAV username, password is read from RUSTOTPONY_WEBDAV_PASSWORD or prompted")),
+            )
+            .arg(
+                Arg::with_name("force")
+                    .long("force")
+                    .help(tr("Overwrite the other side even if it was changed since the last sync")),
+            )
+            .arg(
+                Arg::with_name("allow-insecure-http")
+                    .long("allow-insecure-http")
+                    .help(tr("Send the password over plain http:// too, anyone on the way can read it")),
+            )
+    }
+}
@@ -0,0 +1,237 @@
+use crate::messages::tr;
+use crate::theme::Role;
+use crate::{fail, fail_with, Cli, SECRET_MASK};
+use rustotpony_core::*;
+use std::io::{self, IsTerminal, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+impl Cli {
+    /// Generates the current code, grouped for display. The passphrase of a
+    /// protected application is asked.
+    fn current_code(&self, app: &GenApp) -> Result<String, Error> {
+        let code = if app.is_protected() {
+            app.unlock(&Self::get_passphrase(app.get_name()))?
+                .get_code()
+        } else {
+            app.get_code()
+        };
+        Ok(app.get_format().group(&code, self.group))
+    }
+
+    pub(crate) fn show_application(&self, name: Option<&str>, reveal: bool) {
+        let rustotpony = self.app();
+        let app = match self.choose_application(&rustotpony, name, reveal) {
+            Some(app) => app,
+            None => return,
+        };
+        let name = app.get_name();
+        let code = if app.get_ocra().is_some() {
+            format!(
+                "requires a challenge, use `totp respond {} CHALLENGE`",
+                name
+            )
+        } else {
+            match self.current_code(app) {
+                Ok(code) => code,
+                Err(err) => {
+                    fail(&err);
+                    return;
+                }
+            }
+        };
+        let yes_no = |value: bool| if value { "yes" } else { "no" };
+        say!("name:      {}", app.get_name());
+        say!("username:  {}", app.get_username());
+        if !app.get_aliases().is_empty() {
+            say!("aliases:   {}", app.get_aliases().join(", "));
+        }
+        match app.get_icon() {
+            Some(Icon::Name { name }) => say!("icon:      {}", name),
+            Some(Icon::Url { url }) => say!("icon:      {}", url),
+            Some(Icon::Image { mime, .. }) => say!("icon:      {} image", mime),
+            None => {}
+        }
+        say!(
+            "secret:    {} (use `totp reveal-secret {}` to print it)",
+            SECRET_MASK,
+            name
+        );
+        say!("hidden:    {}", yes_no(app.is_hidden()));
+        if app.is_archived() {
+            say!("archived:  yes");
+        }
+        say!("protected: {}", yes_no(app.is_protected()));
+        if app.get_epoch() != 0 {
+            say!("epoch:     {}", format_timestamp(app.get_epoch()));
+        }
+        say!("code:      {}", self.theme.paint(Role::Code, &code));
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        if let Some(left) = app.seconds_left(now) {
+            self.print_countdown(left, app.get_time_step().unwrap_or(left));
+        }
+    }
+
+    /// Tells on stderr how long the printed code stays valid, so stdout
+    /// stays clean for scripts.
+    fn print_countdown(&self, left: u64, step: u64) {
+        if !io::stderr().is_terminal() {
+            return;
+        }
+        let width = 10;
+        let filled = (left * width / step.max(1)) as usize;
+        let bar = format!("{:-<width$}", "=".repeat(filled), width = width as usize);
+        let role = if left <= 5 {
+            Role::Expiring
+        } else {
+            Role::Countdown
+        };
+        esay!(
+            "           {} valid for {} more seconds",
+            self.theme.paint(role, &format!("[{}]", bar)),
+            left
+        );
+    }
+
+    pub(crate) fn login(&self, name: Option<&str>, reveal: bool, wait: bool) {
+        let rustotpony = self.app();
+        let app = match self.choose_application(&rustotpony, name, reveal) {
+            Some(app) if app.get_ocra().is_some() => {
+                say!(
+                    "Application '{}' requires a challenge, use `totp respond {} CHALLENGE`",
+                    app.get_name(),
+                    app.get_name()
+                );
+                return;
+            }
+            Some(app) => app,
+            None => return,
+        };
+        // Scripts read the username from the first line, it's there even if
+        // empty
+        if app.get_username().is_empty() {
+            esay!("Application '{}' has no username", app.get_name());
+        }
+        println!("{}", app.get_username());
+        if wait {
+            eprint!("{}", tr("Press Enter for the code…"));
+            io::stderr().flush().ok();
+            let mut line = String::new();
+            if io::stdin().read_line(&mut line).is_err() {
+                return;
+            }
+        }
+        match self.current_code(app) {
+            Ok(code) => println!("{}", code),
+            Err(err) => fail(&err),
+        }
+    }
+
+    pub(crate) fn reveal_secret(&self, name: &str, confirmed: bool) {
+        let question = format!(
+            "Secret of '{}' will be printed and the disclosure recorded in the history. Continue?",
+            name
+        );
+        if let Some(secret) = self.disclose(name, confirmed, &question, |app, passphrase| {
+            app.reveal_secret(name, passphrase)
+        }) {
+            println!("{}", secret);
+        }
+    }
+
+    pub(crate) fn respond_to_challenge(&self, name: &str, challenge: &str) {
+        let mut app = self.app();
+        let (protected, ocra) = match app.get_application(name) {
+            Ok(application) => (application.is_protected(), application.get_ocra()),
+            Err(err) => {
+                fail(&err);
+                return;
+            }
+        };
+        let uses_pin = match ocra {
+            Some(Ok(ref ocra)) => ocra.uses_pin(),
+            Some(Err(err)) => {
+                fail(&err);
+                return;
+            }
+            None => {
+                say!(
+                    "Application '{}' isn't a challenge-response generator",
+                    name
+                );
+                return;
+            }
+        };
+        let passphrase = if protected {
+            Some(Self::get_passphrase(name))
+        } else {
+            None
+        };
+        let pin = if uses_pin {
+            Some(rpassword::prompt_password_stdout(tr("Enter your PIN: ")).unwrap())
+        } else {
+            None
+        };
+        let response = match app.respond_to_challenge(
+            name,
+            challenge,
+            pin.as_deref(),
+            passphrase.as_deref(),
+        ) {
+            Ok(response) => response,
+            Err(err) => {
+                fail(&err);
+                return;
+            }
+        };
+        // The counter must be saved before the response is used
+        if app.get_changes().is_empty() {
+            println!("{}", response);
+            return;
+        }
+        match app.flush() {
+            Ok(_) => {
+                if app.is_dry_run() {
+                    self.report_saved(&app, "");
+                }
+                println!("{}", response);
+            }
+            Err(err) => fail_with(
+                &format!("Couldn't save the counter: {} Aborting…", err),
+                &err,
+            ),
+        }
+    }
+
+    pub(crate) fn hand_off_code(&self, name: Option<&str>, json: bool, reveal: bool) {
+        let rustotpony = self.app();
+        let app = match self.choose_application(&rustotpony, name, reveal) {
+            Some(app) => app,
+            None => return,
+        };
+        let unlocked = if app.is_protected() {
+            app.unlock(&Self::get_passphrase(app.get_name()))
+        } else {
+            Ok(app.clone())
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let handoff = match unlocked.and_then(|app| app.hand_off(now)) {
+            Ok(handoff) => handoff,
+            Err(err) => {
+                fail(&err);
+                return;
+            }
+        };
+        if json {
+            println!("{}", handoff.to_json());
+            return;
+        }
+        Self::print_qr_code(&handoff.to_text());
+        println!("{}", handoff.to_text());
+    }
+}
@@ -0,0 +1,122 @@
+use crate::theme::Role;
+use crate::{fail, Cli};
+use rustotpony_core::*;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+impl Cli {
+    pub(crate) fn show_dashboard(&self) {
+        match self.app().get_applications() {
+            Ok(apps) => {
+                let mut is_first_iteration = true;
+                // Prepare sorted keys for displaying apps in order
+                let mut keys: Vec<String> = apps
+                    .iter()
+                    .filter(|(_, app)| !app.is_hidden() && !app.is_archived())
+                    .map(|(key, _)| key.clone())
+                    .collect();
+                keys.sort();
+                let lines_count = keys.len() + 1;
+                let group = self.group;
+                let code_width = keys
+                    .iter()
+                    .map(|key| {
+                        let format = apps[key].get_format();
+                        format
+                            .group(&"-".repeat(format.get_length()), group)
+                            .chars()
+                            .count()
+                    })
+                    .max()
+                    .unwrap_or(0);
+                let entries: Vec<&GenApp> = keys.iter().map(|key| &apps[key]).collect();
+                // Codes change only at second boundaries, lines are rendered
+                // once a second and reused between refreshes
+                let mut lines: Vec<String> = Vec::new();
+                let mut rendered_at = None;
+                say!("Welcome to RusTOTPony realtime dashboard! Press ^C to quit.");
+                ctrlc::set_handler(move || {
+                    print!("\x1B[{}A\x1B[0G\x1B[0J", lines_count + 1);
+                    say!("I won't tell anyone about this 🤫");
+                    std::process::exit(0);
+                })
+                .expect("Error setting Ctrl-C handler");
+                loop {
+                    if is_first_iteration {
+                        is_first_iteration = false;
+                    } else {
+                        print!("\x1B[{}A", lines_count);
+                    }
+                    self.print_progress_bar();
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap()
+                        .as_secs();
+                    if rendered_at != Some(now) {
+                        lines = self.render_dashboard_lines(&entries, now, group, code_width);
+                        rendered_at = Some(now);
+                    }
+                    for line in lines.iter() {
+                        println!("{}", line);
+                    }
+                    thread::sleep(Duration::from_millis(100));
+                }
+            }
+            Err(err) => fail(&err),
+        }
+    }
+
+    /// Renders a dashboard line for every application. Codes are computed
+    /// on all available cores, so databases with hundreds of applications
+    /// are refreshed in time too.
+    fn render_dashboard_lines(
+        &self,
+        apps: &[&GenApp],
+        timestamp: u64,
+        group: Option<usize>,
+        width: usize,
+    ) -> Vec<String> {
+        let render = |app: &GenApp| {
+            let code = if app.is_protected() || app.get_ocra().is_some() {
+                "-".repeat(app.get_format().get_length())
+            } else {
+                app.generate(timestamp)
+            };
+            let code = app.get_format().group(&code, group);
+            let code = format!("{:width$}", code, width = width);
+            format!("{} {}", self.theme.paint(Role::Code, &code), app.get_name())
+        };
+        let threads = thread::available_parallelism().map_or(1, |count| count.get());
+        let chunk_size = apps.len().div_ceil(threads).max(1);
+        thread::scope(|scope| {
+            let handles: Vec<_> = apps
+                .chunks(chunk_size)
+                .map(|chunk| scope.spawn(move || chunk.iter().map(|app| render(app)).collect()))
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| -> Vec<String> {
+                    handle.join().expect("Couldn't generate codes")
+                })
+                .collect()
+        })
+    }
+
+    fn print_progress_bar(&self) {
+        let width = 60;
+        let now = SystemTime::now();
+        let since_the_epoch = now.duration_since(UNIX_EPOCH).unwrap();
+        let in_ms =
+            since_the_epoch.as_secs() * 1000 + since_the_epoch.subsec_nanos() as u64 / 1_000_000;
+        let step = in_ms % 30_000;
+        let idx = step * width / 30_000;
+        // Codes are about to change during the last few seconds
+        let role = if step >= 25_000 {
+            Role::Expiring
+        } else {
+            Role::Countdown
+        };
+        let bar = format!("{:60}", "=".repeat(idx as usize));
+        say!("[{}]", self.theme.paint(role, &bar));
+    }
+}
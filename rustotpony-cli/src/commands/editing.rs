@@ -0,0 +1,398 @@
+use crate::messages::tr;
+use crate::{fail_with, Cli, NewGenerator, DEFAULT_OCRA_SUITE};
+use rustotpony_core::*;
+use std::path::Path;
+
+impl Cli {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn create_application(
+        &self,
+        name: &str,
+        username: &str,
+        kind: &str,
+        encoding: &str,
+        suite: Option<&str>,
+        epoch: Option<&str>,
+        hidden: bool,
+        protect: bool,
+        on_duplicate: Option<DuplicateResolution>,
+    ) {
+        let epoch = match epoch.map(str::parse::<u64>) {
+            Some(Ok(epoch)) => epoch,
+            Some(Err(_)) => {
+                say!("Epoch must be a number of seconds");
+                return;
+            }
+            None => 0,
+        };
+        let secret = rpassword::prompt_password_stdout(tr("Enter your secret code: ")).unwrap();
+        let generator = match (kind, encoding) {
+            ("motp", _) => {
+                let pin = rpassword::prompt_password_stdout(tr("Enter your PIN: ")).unwrap();
+                MOTP::new(&secret, &pin).map(NewGenerator::Motp)
+            }
+            ("ocra", _) => {
+                let totp = match encoding {
+                    "hex" => TOTP::new_hex(&secret),
+                    "raw" => TOTP::new_raw(&secret),
+                    _ => TOTP::new(&secret),
+                };
+                totp.and_then(|totp| {
+                    OCRA::new(
+                        totp.get_secret_bytes().to_vec(),
+                        suite.unwrap_or(DEFAULT_OCRA_SUITE),
+                    )
+                })
+                .map(NewGenerator::Ocra)
+            }
+            (_, "hex") => TOTP::new_hex(&secret).map(NewGenerator::Totp),
+            (_, "raw") => TOTP::new_raw(&secret).map(NewGenerator::Totp),
+            _ => TOTP::new(&secret).map(NewGenerator::Totp),
+        };
+        let generator = match generator {
+            Ok(generator) => generator,
+            Err(err) => {
+                fail_with(&format!("{} Aborting…", err), &err);
+                return;
+            }
+        };
+        let passphrase = if protect {
+            match Self::get_new_passphrase(name) {
+                Some(passphrase) => Some(passphrase),
+                None => return,
+            }
+        } else {
+            None
+        };
+        let mut app = self.app();
+        // mOTP secrets are arbitrary strings, there is nothing to check
+        let warnings = match (&generator, encoding) {
+            (NewGenerator::Motp(_), _) => Ok(Vec::new()),
+            (NewGenerator::Ocra(ocra), _) => {
+                app.check_totp(&TOTP::from_bytes(ocra.get_secret_bytes().to_vec()))
+            }
+            (NewGenerator::Totp(totp), "hex") | (NewGenerator::Totp(totp), "raw") => {
+                app.check_totp(totp)
+            }
+            _ => app.check_secret(&secret),
+        };
+        match warnings {
+            Ok(warnings) => {
+                for warning in warnings {
+                    println!("{}", self.theme.warning(&warning));
+                }
+            }
+            Err(err) => {
+                fail_with(&format!("{} Aborting…", err), &err);
+                return;
+            }
+        }
+        let duplicate = match generator {
+            NewGenerator::Totp(ref totp) => app.find_same_secret(totp.get_secret_bytes()),
+            NewGenerator::Ocra(ref ocra) => app.find_same_secret(ocra.get_secret_bytes()),
+            NewGenerator::Motp(_) => Ok(None),
+        };
+        let duplicate = match duplicate {
+            Ok(duplicate) => duplicate,
+            Err(err) => {
+                fail_with(&format!("{} Aborting…", err), &err);
+                return;
+            }
+        };
+        if let Some(existing) = duplicate {
+            let resolution =
+                on_duplicate.unwrap_or_else(|| Self::ask_duplicate_resolution(name, &existing));
+            match app.resolve_duplicate(&existing, name, resolution) {
+                Ok(true) => {}
+                Ok(false) if resolution == DuplicateResolution::Skip => {
+                    say!("Nothing was added.");
+                    return;
+                }
+                Ok(false) => {
+                    match app.flush() {
+                        Ok(_) => self.report_saved(
+                            &app,
+                            &format!("Application '{}' renamed to '{}'", existing, name),
+                        ),
+                        Err(err) => fail_with(&format!("{} Aborting…", err), &err),
+                    }
+                    return;
+                }
+                Err(err) => {
+                    fail_with(&format!("{} Aborting…", err), &err);
+                    return;
+                }
+            }
+        }
+        let created = match generator {
+            NewGenerator::Totp(ref totp) => app.create_totp_application(name, username, totp),
+            NewGenerator::Motp(ref motp) => app.create_motp_application(name, username, motp),
+            NewGenerator::Ocra(ref ocra) => app.create_ocra_application(name, username, ocra),
+        };
+        match created
+            .and_then(|_| match epoch {
+                0 => Ok(()),
+                epoch => app.set_epoch(name, epoch),
+            })
+            .and_then(|_| app.set_hidden(name, hidden))
+            .and_then(|_| match passphrase {
+                Some(ref passphrase) => app.protect_application(name, passphrase),
+                None => Ok(()),
+            })
+            .and_then(|_| app.flush())
+        {
+            Ok(_) => self.report_saved(&app, &format!("New application created: {}", name)),
+            Err(err) => fail_with(&format!("{} Aborting…", err), &err),
+        }
+    }
+
+    pub(crate) fn delete_application(&self, name: &str) {
+        let mut app = self.app();
+        // A prefix is reported by the full name of what was deleted
+        let name = &match app.resolve_name(name) {
+            Ok(name) => name,
+            Err(err) => {
+                fail_with(
+                    &format!("Couldn't delete application '{}': {}", name, err),
+                    &err,
+                );
+                return;
+            }
+        };
+        match app.delete_application(name).and_then(|_| app.flush()) {
+            Ok(_) => self.report_saved(
+                &app,
+                &format!("Application '{}' successfully deleted", name),
+            ),
+            Err(err) => fail_with(
+                &format!("Couldn't delete application '{}': {}", name, err),
+                &err,
+            ),
+        };
+    }
+
+    pub(crate) fn rename_applications(&self, filter: &ExportFilter, rule: &RenameRule) {
+        let mut app = self.app();
+        match app
+            .rename_applications(filter, rule)
+            .and_then(|renames| app.flush().map(|_| renames))
+        {
+            Ok(renames) if renames.is_empty() => say!("No names were changed"),
+            Ok(renames) => {
+                self.report_saved(&app, &format!("{} applications renamed:", renames.len()));
+                if !app.is_dry_run() {
+                    for (name, newname) in renames {
+                        say!("  '{}' → '{}'", name, newname);
+                    }
+                }
+            }
+            Err(err) => fail_with(&format!("Couldn't rename applications: {}", err), &err),
+        }
+    }
+
+    pub(crate) fn rename_application(&self, name: &str, newname: &str) {
+        let mut app = self.app();
+        let name = &match app.resolve_name(name) {
+            Ok(name) => name,
+            Err(err) => {
+                fail_with(
+                    &format!("Couldn't rename application '{}': {}", name, err),
+                    &err,
+                );
+                return;
+            }
+        };
+        match app
+            .rename_application(name, newname)
+            .and_then(|_| app.flush())
+        {
+            Ok(_) => self.report_saved(
+                &app,
+                &format!(
+                    "Application '{}' successfully renamed to '{}'",
+                    name, newname
+                ),
+            ),
+            Err(err) => fail_with(
+                &format!("Couldn't rename application '{}': {}", name, err),
+                &err,
+            ),
+        };
+    }
+
+    pub(crate) fn set_code_format(&self, name: &str, alphabet: Option<&str>, length: Option<&str>) {
+        let length = match length.map(str::parse::<usize>) {
+            Some(Ok(length)) => Some(length),
+            Some(Err(_)) => {
+                say!("Code length must be a number");
+                return;
+            }
+            None => None,
+        };
+        let format = match alphabet {
+            Some("steam") => CodeFormat::with_alphabet(STEAM_ALPHABET, length.unwrap_or(5)),
+            Some(alphabet) => CodeFormat::with_alphabet(alphabet, length.unwrap_or(6)),
+            None => CodeFormat::decimal(length.unwrap_or(6)),
+        };
+        let mut app = self.app();
+        match format
+            .and_then(|format| app.set_code_format(name, format))
+            .and_then(|_| app.flush())
+        {
+            Ok(_) => self.report_saved(&app, &format!("Code format of '{}' changed", name)),
+            Err(err) => fail_with(
+                &format!("Couldn't change application '{}': {}", name, err),
+                &err,
+            ),
+        }
+    }
+
+    pub(crate) fn set_epoch(&self, name: &str, epoch: &str) {
+        let epoch = match epoch.parse::<u64>() {
+            Ok(epoch) => epoch,
+            Err(_) => {
+                say!("Epoch must be a number of seconds");
+                return;
+            }
+        };
+        let mut app = self.app();
+        match app.set_epoch(name, epoch).and_then(|_| app.flush()) {
+            Ok(_) => self.report_saved(&app, &format!("Epoch of '{}' changed", name)),
+            Err(err) => fail_with(
+                &format!("Couldn't change application '{}': {}", name, err),
+                &err,
+            ),
+        }
+    }
+
+    pub(crate) fn hide_application(&self, name: &str, hidden: bool) {
+        let mut app = self.app();
+        let message = if hidden {
+            format!("Application '{}' is hidden now", name)
+        } else {
+            format!("Application '{}' is visible now", name)
+        };
+        match app.set_hidden(name, hidden).and_then(|_| app.flush()) {
+            Ok(_) => self.report_saved(&app, &message),
+            Err(err) => fail_with(
+                &format!("Couldn't change application '{}': {}", name, err),
+                &err,
+            ),
+        }
+    }
+
+    pub(crate) fn archive_application(&self, name: &str, archived: bool) {
+        let mut app = self.app();
+        let message = if archived {
+            format!("Application '{}' is archived now", name)
+        } else {
+            format!("Application '{}' is active again", name)
+        };
+        match app.set_archived(name, archived).and_then(|_| app.flush()) {
+            Ok(_) => self.report_saved(&app, &message),
+            Err(err) => fail_with(
+                &format!("Couldn't change application '{}': {}", name, err),
+                &err,
+            ),
+        }
+    }
+
+    pub(crate) fn set_icon(&self, name: &str, args: &clap::ArgMatches) {
+        let icon = if let Some(icon) = args.value_of("name") {
+            Icon::named(icon).map(Some)
+        } else if let Some(url) = args.value_of("url") {
+            Icon::from_url(url).map(Some)
+        } else if let Some(path) = args.value_of("file") {
+            Self::read_icon(Path::new(path)).map(Some)
+        } else {
+            Ok(None)
+        };
+        let mut app = self.app();
+        match icon.and_then(|icon| {
+            let cleared = icon.is_none();
+            app.set_icon(name, icon)
+                .and_then(|_| app.flush())
+                .map(|_| cleared)
+        }) {
+            Ok(true) => self.report_saved(&app, &format!("Icon of '{}' removed", name)),
+            Ok(false) => self.report_saved(&app, &format!("Icon of '{}' set", name)),
+            Err(err) => fail_with(&format!("Couldn't set icon of '{}': {}", name, err), &err),
+        }
+    }
+
+    fn read_icon(path: &Path) -> Result<Icon, Error> {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+        let mime = match extension.as_deref() {
+            Some("png") => "image/png",
+            Some("jpg") | Some("jpeg") => "image/jpeg",
+            Some("svg") => "image/svg+xml",
+            Some("webp") => "image/webp",
+            _ => {
+                return Err(Error::new(
+                    ErrorCode::Unsupported,
+                    "Icon file has to be a .png, .jpg, .svg or .webp image",
+                ))
+            }
+        };
+        let data = std::fs::read(path).map_err(|err| {
+            Error::new(
+                ErrorCode::Io,
+                format!("Couldn't read {}: {}", path.display(), err),
+            )
+        })?;
+        Icon::image(mime, &data)
+    }
+
+    pub(crate) fn tag_application(&self, name: &str, tags: &[String], add: bool) {
+        let mut app = self.app();
+        let result = if add {
+            app.tag_application(name, tags)
+        } else {
+            app.untag_application(name, tags)
+        };
+        match result.and_then(|changed| app.flush().map(|_| changed)) {
+            Ok(_) => {
+                let tags = app
+                    .get_application(name)
+                    .map(|application| application.get_tags().join(", "))
+                    .unwrap_or_default();
+                let message = if tags.is_empty() {
+                    format!("Application '{}' has no tags", name)
+                } else {
+                    format!("Tags of application '{}': {}", name, tags)
+                };
+                self.report_saved(&app, &message)
+            }
+            Err(err) => fail_with(
+                &format!("Couldn't change application '{}': {}", name, err),
+                &err,
+            ),
+        }
+    }
+
+    pub(crate) fn protect_application(&self, name: &str, protect: bool) {
+        let mut app = self.app();
+        let result = if protect {
+            match Self::get_new_passphrase(name) {
+                Some(passphrase) => app.protect_application(name, &passphrase),
+                None => return,
+            }
+        } else {
+            app.unprotect_application(name, &Self::get_passphrase(name))
+        };
+        let message = if protect {
+            format!("Application '{}' is protected now", name)
+        } else {
+            format!("Application '{}' isn't protected anymore", name)
+        };
+        match result.and_then(|_| app.flush()) {
+            Ok(_) => self.report_saved(&app, &message),
+            Err(err) => fail_with(
+                &format!("Couldn't change application '{}': {}", name, err),
+                &err,
+            ),
+        }
+    }
+}
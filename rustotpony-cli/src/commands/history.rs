@@ -0,0 +1,167 @@
+use crate::{fail, Cli};
+use rustotpony_core::*;
+use std::path::{Path, PathBuf};
+
+impl Cli {
+    pub(crate) fn show_history(&self) {
+        let app = self.app();
+        let history = match app.get_history() {
+            Ok(history) => history,
+            Err(err) => return fail(&err),
+        };
+        if history.is_empty() {
+            say!("History is empty");
+            return;
+        }
+        for record in history {
+            let action = match (record.get_operation(), record.get_details()) {
+                (Operation::Create, _) => format!("created '{}'", record.get_name()),
+                (Operation::Rename, Some(newname)) => {
+                    format!("renamed '{}' to '{}'", record.get_name(), newname)
+                }
+                (Operation::Rename, None) => format!("renamed '{}'", record.get_name()),
+                (Operation::Delete, _) => format!("deleted '{}'", record.get_name()),
+                (Operation::DeleteAll, _) => String::from("deleted all applications"),
+                (Operation::Hide, _) => format!("hid '{}'", record.get_name()),
+                (Operation::Unhide, _) => format!("unhid '{}'", record.get_name()),
+                (Operation::Archive, _) => format!("archived '{}'", record.get_name()),
+                (Operation::Unarchive, _) => format!("unarchived '{}'", record.get_name()),
+                (Operation::Update, Some(setting)) => {
+                    format!("changed {} of '{}'", setting, record.get_name())
+                }
+                (Operation::Update, None) => format!("changed '{}'", record.get_name()),
+                (Operation::Protect, _) => format!("protected '{}'", record.get_name()),
+                (Operation::Unprotect, _) => format!("unprotected '{}'", record.get_name()),
+                (Operation::AddRecoveryCodes, Some(count)) => {
+                    format!("added {} recovery codes to '{}'", count, record.get_name())
+                }
+                (Operation::AddRecoveryCodes, None) => {
+                    format!("added recovery codes to '{}'", record.get_name())
+                }
+                (Operation::UseRecoveryCode, _) => {
+                    format!("used recovery code of '{}'", record.get_name())
+                }
+                (Operation::RevealSecret, Some("reenroll")) => {
+                    format!("reenrolled '{}'", record.get_name())
+                }
+                (Operation::RevealSecret, Some("export")) => {
+                    format!("exported '{}'", record.get_name())
+                }
+                (Operation::RevealSecret, Some("share")) => {
+                    format!("shared '{}'", record.get_name())
+                }
+                (Operation::RevealSecret, _) => {
+                    format!("revealed secret of '{}'", record.get_name())
+                }
+            };
+            say!("{} {}", format_timestamp(record.get_timestamp()), action);
+        }
+        if let Err(idx) = verify_history(history) {
+            say!(
+                "WARNING: history was tampered with, records starting from #{} can't be trusted",
+                idx + 1
+            );
+        }
+    }
+
+    pub(crate) fn manage_git_history(&self, args: &clap::ArgMatches) {
+        if self.database.contains("://") {
+            say!("History can be kept only for local databases");
+            return;
+        }
+        let history = GitHistory::for_database(Path::new(&self.database));
+        if matches!(args.subcommand_name(), Some("init" | "push")) {
+            if let Err(err) = self.compact_journal() {
+                return fail(&err);
+            }
+        }
+        let result = match args.subcommand() {
+            ("init", Some(sub_git)) => history
+                .enable(sub_git.value_of("REMOTE"))
+                .map(|_| say!("Database changes will be committed to git.")),
+            ("log", Some(_)) => history
+                .log()
+                .map(|lines| lines.iter().for_each(|line| println!("{}", line))),
+            ("push", Some(_)) => history.push().map(|_| say!("History pushed.")),
+            _ => {
+                println!("{}", args.usage());
+                Ok(())
+            }
+        };
+        if let Err(err) = result {
+            fail(&err);
+        }
+    }
+
+    pub(crate) fn manage_snapshots(&self, args: &clap::ArgMatches) {
+        let storage = match storage_for(&self.database) {
+            Ok(storage) => storage,
+            Err(err) => {
+                fail(&err);
+                return;
+            }
+        };
+        let store = self.snapshot_store();
+        let writes_snapshot = match args.subcommand_name() {
+            Some("create") => true,
+            Some("restore") => !self.read_only,
+            _ => false,
+        };
+        if writes_snapshot {
+            if let Err(err) = self.compact_journal() {
+                return fail(&err);
+            }
+        }
+        let result = match args.subcommand() {
+            ("create", Some(sub_snapshot)) => {
+                let label = sub_snapshot
+                    .value_of("LABEL")
+                    .expect("Couldn't read LABEL for 'snapshot create' command");
+                store
+                    .create(label, &*storage)
+                    .map(|_| say!("Snapshot '{}' created", label))
+            }
+            ("list", Some(_)) => store.list().map(|snapshots| {
+                if snapshots.is_empty() {
+                    say!("There are no snapshots");
+                }
+                for snapshot in snapshots {
+                    say!(
+                        "{} {}",
+                        format_timestamp(snapshot.created_at),
+                        snapshot.label
+                    );
+                }
+            }),
+            ("restore", Some(_)) if self.read_only => Err(Error::new(
+                ErrorCode::ReadOnly,
+                "Database is opened in read-only mode",
+            )),
+            ("restore", Some(sub_snapshot)) => {
+                let label = sub_snapshot
+                    .value_of("LABEL")
+                    .expect("Couldn't read LABEL for 'snapshot restore' command");
+                store
+                    .restore(label, &*storage)
+                    .map(|_| say!("Database restored from snapshot '{}'", label))
+            }
+            _ => {
+                println!("{}", args.usage());
+                Ok(())
+            }
+        };
+        if let Err(err) = result {
+            fail(&err);
+        }
+    }
+
+    fn snapshot_store(&self) -> SnapshotStore {
+        let db_path = if self.database.contains("://") {
+            Self::get_database_path()
+        } else {
+            PathBuf::from(&self.database)
+        };
+        let dir = db_path.parent().unwrap_or_else(|| Path::new("."));
+        SnapshotStore::new(dir.join("snapshots"))
+    }
+}
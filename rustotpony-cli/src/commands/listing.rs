@@ -0,0 +1,103 @@
+use crate::messages::{self, tr};
+use crate::table::Table;
+use crate::{fail, Cli, SECRET_MASK};
+use rustotpony_core::*;
+
+impl Cli {
+    pub(crate) fn show_applications_list(
+        &self,
+        page: Option<&str>,
+        per_page: Option<&str>,
+        columns: &[&str],
+    ) {
+        let parse = |value: Option<&str>, default: usize| match value.map(str::parse::<usize>) {
+            Some(Ok(number)) => Some(number),
+            Some(Err(_)) => None,
+            None => Some(default),
+        };
+        let (page, per_page) = match (parse(page, 1), parse(per_page, 0)) {
+            (Some(page), Some(per_page)) => (page, per_page),
+            _ => {
+                say!("Page and page size must be numbers");
+                return;
+            }
+        };
+        let listing = match self.app().list_applications(page, per_page) {
+            Ok(listing) => listing,
+            Err(err) => return fail(&err),
+        };
+        if listing.items.is_empty() {
+            say!("There are no applications");
+            return;
+        }
+        Self::print_applications_table(&listing.items, columns);
+        if listing.pages > 1 {
+            say!(
+                "Page {} of {}, {} generators in total",
+                listing.page,
+                listing.pages,
+                listing.total
+            );
+        }
+    }
+
+    pub(crate) fn show_archived_applications(&self, columns: &[&str]) {
+        let archived = match self.app().list_archived_applications() {
+            Ok(archived) => archived,
+            Err(err) => return fail(&err),
+        };
+        if archived.is_empty() {
+            say!("There are no archived applications");
+            return;
+        }
+        Self::print_applications_table(&archived, columns);
+    }
+
+    pub(crate) fn search_applications(&self, query: &str, columns: &[&str]) {
+        let found = match self.app().search_applications(query) {
+            Ok(found) => found,
+            Err(err) => return fail(&err),
+        };
+        if found.is_empty() {
+            say!("Nothing matches '{}'", query);
+            return;
+        }
+        Self::print_applications_table(&found, columns);
+    }
+
+    fn print_applications_table(applications: &[ApplicationSummary], columns: &[&str]) {
+        let headers: Vec<&str> = columns
+            .iter()
+            .map(|column| match *column {
+                "last-used" => tr("last used"),
+                column => messages::catalog().lookup(column),
+            })
+            .collect();
+        let mut table = Table::new(&headers);
+        for application in applications {
+            table.push_row(
+                columns
+                    .iter()
+                    .map(|column| Self::column_value(application, column))
+                    .collect(),
+            );
+        }
+        println!("{}", table.render());
+    }
+
+    fn column_value(application: &ApplicationSummary, column: &str) -> String {
+        match column {
+            "name" => String::from(application.get_name()),
+            "key" if application.is_protected() => String::from("(protected)"),
+            "key" => String::from(SECRET_MASK),
+            "issuer" => String::from(application.get_issuer()),
+            "username" => String::from(application.get_username()),
+            "digits" => application.get_digits().to_string(),
+            "algorithm" => application.get_algorithm(),
+            "last-used" => application
+                .get_last_used()
+                .map_or_else(|| String::from("-"), format_timestamp),
+            _ => unreachable!("Unknown column '{}'", column),
+        }
+    }
+}
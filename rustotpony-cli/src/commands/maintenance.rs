@@ -0,0 +1,353 @@
+use crate::{exit_code, fail, fail_with, Cli};
+use rustotpony_core::*;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+impl Cli {
+    pub(crate) fn check_database(&self, as_json: bool) {
+        let storage = storage_for(&self.database).unwrap_or_else(|err| {
+            fail(&err);
+            std::process::exit(exit_code());
+        });
+        let findings = Self::build(self.open_database(storage)).diagnose();
+        if as_json {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&findings).expect("Couldn't serialize findings")
+            );
+        } else if findings.is_empty() {
+            say!("No problems found.");
+        } else {
+            for finding in findings.iter() {
+                let severity = match finding.severity {
+                    Severity::Warning => "warning",
+                    Severity::Error => "error",
+                };
+                say!("{}[{}]: {}", severity, finding.check, finding.message);
+            }
+        }
+        if findings
+            .iter()
+            .any(|finding| finding.severity == Severity::Error)
+        {
+            std::process::exit(1);
+        }
+    }
+
+    pub(crate) fn show_stats(&self, oldest: usize, as_json: bool) {
+        let stats = match self.app().get_stats(oldest) {
+            Ok(stats) => stats,
+            Err(err) => return fail(&err),
+        };
+        if as_json {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&stats).expect("Couldn't serialize statistics")
+            );
+            return;
+        }
+        let counts = |counts: Vec<String>| {
+            if counts.is_empty() {
+                String::from("-")
+            } else {
+                counts.join(", ")
+            }
+        };
+        say!(
+            "generators: {} ({} hidden, {} archived, {} protected)",
+            stats.applications,
+            stats.hidden,
+            stats.archived,
+            stats.protected
+        );
+        say!(
+            "algorithms: {}",
+            counts(
+                stats
+                    .algorithms
+                    .iter()
+                    .map(|(algorithm, count)| format!("{}: {}", algorithm, count))
+                    .collect()
+            )
+        );
+        say!(
+            "digits:     {}",
+            counts(
+                stats
+                    .digits
+                    .iter()
+                    .map(|(digits, count)| format!("{} digits: {}", digits, count))
+                    .collect()
+            )
+        );
+        let mut label = "oldest:";
+        for (name, created) in &stats.oldest {
+            println!("{:<12}{} {}", label, format_timestamp(*created), name);
+            label = "";
+        }
+        say!(
+            "untagged:   {}",
+            match stats.untagged.len() {
+                0 => String::from("none"),
+                count => format!("{} ({})", count, stats.untagged.join(", ")),
+            }
+        );
+        if let Some(size) = stats.size {
+            say!("file size:  {} bytes", size);
+        }
+    }
+
+    pub(crate) fn fix_permissions(&self) {
+        let storage = storage_for(&self.database).unwrap_or_else(|err| {
+            fail(&err);
+            std::process::exit(exit_code());
+        });
+        let path = match storage.local_path() {
+            Some(path) if path.exists() => path,
+            Some(_) => {
+                say!("There is no database yet");
+                return;
+            }
+            None => {
+                say!("Database isn't stored in a local file, there are no permissions to fix");
+                return;
+            }
+        };
+        if let Err(err) = check_write_target(path) {
+            fail(&err);
+            return;
+        }
+        if self.dry_run {
+            say!("Dry run, permissions of {} weren't changed", path.display());
+            return;
+        }
+        match restrict_permissions(path) {
+            Ok(_) => say!("Only you can access {} now.", path.display()),
+            Err(err) => fail(&err),
+        }
+    }
+
+    pub(crate) fn change_password(&self) {
+        if self.dry_run {
+            say!("Dry run, the password wasn't changed");
+            return;
+        }
+        let storage = storage_for(&self.database).unwrap_or_else(|err| {
+            fail(&err);
+            std::process::exit(exit_code());
+        });
+        self.warn_about_permissions(storage.as_ref());
+        // The current password is checked before the new one is asked, the
+        // key is kept, so it isn't asked again when the database is saved
+        let db = Self::build(
+            self.open_database(storage)
+                .read_only(self.read_only)
+                .key_cache(Duration::from_secs(600)),
+        );
+        if let Err(err) = db.load_content() {
+            fail(&err);
+            return;
+        }
+        let kdf = match db.password_kdf() {
+            Ok(kdf) => kdf,
+            Err(err) => return fail(&err),
+        };
+        let password = match Self::prompt_new_password("new database password", Some(&kdf)) {
+            Ok(password) => password,
+            Err(err) => {
+                fail_with(&format!("{}. Aborting…", err), &err);
+                return;
+            }
+        };
+        self.cancel_on_interrupt();
+        match db.change_password(&password) {
+            Ok(_) => say!(
+                "Password changed. Snapshots and git history are still encrypted with the old one."
+            ),
+            Err(err) => fail_with(&format!("Couldn't change password: {}", err), &err),
+        }
+    }
+
+    pub(crate) fn require_two_persons(&self) {
+        if self.dry_run {
+            say!("Dry run, the password wasn't changed");
+            return;
+        }
+        let storage = storage_for(&self.database).unwrap_or_else(|err| {
+            fail(&err);
+            std::process::exit(exit_code());
+        });
+        self.warn_about_permissions(storage.as_ref());
+        let db = Self::build(
+            self.open_database(storage)
+                .read_only(self.read_only)
+                .key_cache(Duration::from_secs(600)),
+        );
+        if let Err(err) = db.load_content() {
+            fail(&err);
+            return;
+        }
+        let kdf = db.two_persons_kdf();
+        let passphrases = Self::prompt_new_password("passphrase of the first person", Some(&kdf))
+            .and_then(|first| {
+                Self::prompt_new_password("passphrase of the second person", Some(&kdf))
+                    .map(|second| (first, second))
+            });
+        let (first, second) = match passphrases {
+            Ok(passphrases) => passphrases,
+            Err(err) => {
+                fail_with(&format!("{}. Aborting…", err), &err);
+                return;
+            }
+        };
+        match db.require_two_persons(&first, &second) {
+            Ok(_) => say!(
+                "Database now requires both passphrases. Snapshots and git history are still encrypted with the old password."
+            ),
+            Err(err) => fail_with(&format!("Couldn't change password: {}", err), &err),
+        }
+    }
+
+    pub(crate) fn split_key(&self, shares: u8, threshold: u8) {
+        let storage = storage_for(&self.database).unwrap_or_else(|err| {
+            fail(&err);
+            std::process::exit(exit_code());
+        });
+        match Self::build(self.open_database(storage)).split_key(shares, threshold) {
+            Ok(shares) => {
+                say!(
+                    "Give each share to a different person, any {} of them recover the key:",
+                    threshold
+                );
+                for share in shares {
+                    println!("{}", share);
+                }
+            }
+            Err(err) => fail_with(&format!("Couldn't split the key: {}", err), &err),
+        }
+    }
+
+    pub(crate) fn recover_key(&self) {
+        if self.dry_run {
+            say!("Dry run, the password wasn't changed");
+            return;
+        }
+        let storage = storage_for(&self.database).unwrap_or_else(|err| {
+            fail(&err);
+            std::process::exit(exit_code());
+        });
+        self.warn_about_permissions(storage.as_ref());
+        say!("Enter the shares of the key, one per line, and finish with an empty line:");
+        let shares = Self::read_lines();
+        let db = Self::build(self.open_database(storage).read_only(self.read_only));
+        let kdf = match db.recovery_kdf(&shares) {
+            Ok(kdf) => kdf,
+            Err(err) => return fail_with(&format!("Couldn't recover the key: {}", err), &err),
+        };
+        let password = match Self::prompt_new_password("new database password", Some(&kdf)) {
+            Ok(password) => password,
+            Err(err) => {
+                fail_with(&format!("{}. Aborting…", err), &err);
+                return;
+            }
+        };
+        match db.recover_key(&shares, &password) {
+            Ok(_) => say!("Key recovered, the database is encrypted with the new password."),
+            Err(err) => fail_with(&format!("Couldn't recover the key: {}", err), &err),
+        }
+    }
+
+    pub(crate) fn create_decoy(&self) {
+        if self.dry_run {
+            say!("Dry run, the decoy database wasn't created");
+            return;
+        }
+        let storage = storage_for(&self.database).unwrap_or_else(|err| {
+            fail(&err);
+            std::process::exit(exit_code());
+        });
+        self.warn_about_permissions(storage.as_ref());
+        let db =
+            Self::build(self.with_key_cache(self.open_database(storage).read_only(self.read_only)));
+        if let Ok(true) = db.requires_two_persons() {
+            say!("Database requires two persons, a decoy would be opened by one");
+            return;
+        }
+        let password = match Self::prompt_new_password("decoy password", None) {
+            Ok(password) => password,
+            Err(err) => {
+                fail_with(&format!("{}. Aborting…", err), &err);
+                return;
+            }
+        };
+        match db.create_decoy(&password) {
+            Ok(_) => say!(
+                "Decoy database created. Entering the decoy password opens it instead of your applications."
+            ),
+            Err(err) => fail_with(&format!("Couldn't create decoy database: {}", err), &err),
+        }
+    }
+
+    pub(crate) fn salvage_database(&self, output: &str) {
+        if Path::new(output).exists() {
+            say!("{} already exists. Aborting…", output);
+            return;
+        }
+        let storage = storage_for(&self.database).unwrap_or_else(|err| {
+            fail(&err);
+            std::process::exit(exit_code());
+        });
+        let report = match Self::build(self.open_database(storage)).salvage() {
+            Ok(report) => report,
+            Err(err) => {
+                fail_with(&format!("Couldn't salvage database: {}", err), &err);
+                return;
+            }
+        };
+        let unlock_time = self.unlock_time.unwrap_or(DEFAULT_UNLOCK_TIME);
+        let mut recovered = RusTOTPony::new(Self::build(
+            self.open_database(Box::new(FileStorage::new(PathBuf::from(output))))
+                .new_secret(move || Self::get_new_secret(unlock_time)),
+        ));
+        let mut lost = report.damaged;
+        let mut recovered_count = 0;
+        for application in report.applications.iter() {
+            match recovered.insert_application(application.clone()) {
+                Ok(_) => recovered_count += 1,
+                Err(err) => lost.push(format!("'{}': {}", application.get_name(), err)),
+            }
+        }
+        say!("Enter a password for the recovered database.");
+        if let Err(err) = recovered.flush() {
+            fail_with(&format!("Couldn't save recovered database: {}", err), &err);
+            return;
+        }
+        say!(
+            "{} applications were recovered to {}",
+            recovered_count,
+            output
+        );
+        if !lost.is_empty() {
+            say!("The following entries were lost:");
+            for description in lost {
+                say!("  {}", description);
+            }
+        }
+    }
+
+    /// Writes changes kept in the journal into the database file before the
+    /// file is copied elsewhere. The password is asked only if there is a
+    /// journal.
+    pub(crate) fn compact_journal(&self) -> Result<(), Error> {
+        let storage = storage_for(&self.database)?;
+        self.open_database(storage).build()?.compact_journal()
+    }
+
+    pub(crate) fn eradicate_database(&self) {
+        let mut app = self.app();
+        match app.delete_all_applications().and_then(|_| app.flush()) {
+            Ok(_) => self.report_saved(&app, "Done."),
+            Err(err) => fail_with(&format!("Couldn't delete applications: {}", err), &err),
+        }
+    }
+}
@@ -0,0 +1,10 @@
+mod codes;
+mod dashboard;
+mod editing;
+mod history;
+mod listing;
+mod maintenance;
+mod organizing;
+mod sharing;
+mod sync;
+mod transfer;
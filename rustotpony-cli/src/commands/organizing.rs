@@ -0,0 +1,169 @@
+use crate::table;
+use crate::{fail, fail_with, Cli};
+use unicode_width::UnicodeWidthStr;
+
+impl Cli {
+    pub(crate) fn manage_recovery_codes(&self, args: &clap::ArgMatches) {
+        let (command, sub_recovery) = match args.subcommand() {
+            (command, Some(sub_recovery)) => (command, sub_recovery),
+            _ => {
+                println!("{}", args.usage());
+                return;
+            }
+        };
+        let name = sub_recovery
+            .value_of("APPNAME")
+            .expect("Couldn't read APPNAME for 'recovery' command");
+        let mut app = self.app();
+        match command {
+            "add" => {
+                say!("Enter recovery codes one per line, finish with an empty line:");
+                let codes = Self::read_lines();
+                match app
+                    .add_recovery_codes(name, &codes)
+                    .and_then(|added| app.flush().map(|_| added))
+                {
+                    Ok(added) => self.report_saved(
+                        &app,
+                        &format!("{} recovery codes added to '{}'", added, name),
+                    ),
+                    Err(err) => fail_with(&format!("Couldn't add recovery codes: {}", err), &err),
+                }
+            }
+            "list" => match app.get_application(name) {
+                Ok(application) if application.get_recovery_codes().is_empty() => {
+                    say!("Application '{}' has no recovery codes", name)
+                }
+                Ok(application) => {
+                    for code in application.get_recovery_codes() {
+                        println!("{}", code);
+                    }
+                }
+                Err(err) => fail(&err),
+            },
+            _ => {
+                let code = sub_recovery.value_of("CODE");
+                match app
+                    .use_recovery_code(name, code)
+                    .and_then(|code| app.flush().map(|_| code))
+                {
+                    Ok(_) if app.is_dry_run() => self.report_saved(&app, ""),
+                    Ok(code) => {
+                        let left = app
+                            .get_application(name)
+                            .map(|application| application.get_recovery_codes().len())
+                            .unwrap_or(0);
+                        println!("{}", code);
+                        say!("{} recovery codes left", left);
+                    }
+                    Err(err) => fail_with(&format!("Couldn't use recovery code: {}", err), &err),
+                }
+            }
+        }
+    }
+
+    pub(crate) fn manage_tags(&self, args: &clap::ArgMatches) {
+        let mut app = self.app();
+        let result = match args.subcommand() {
+            ("rename", Some(sub_tags)) => {
+                let tag = sub_tags.value_of("TAG").unwrap();
+                let newtag = sub_tags.value_of("NEWTAG").unwrap();
+                app.rename_tag(tag, newtag).map(|count| {
+                    format!(
+                        "Tag '{}' renamed to '{}' on {} applications",
+                        tag, newtag, count
+                    )
+                })
+            }
+            ("merge", Some(sub_tags)) => {
+                let tags: Vec<String> = sub_tags
+                    .values_of("TAG")
+                    .map(|tags| tags.map(String::from).collect())
+                    .unwrap_or_default();
+                let into = sub_tags.value_of("into").unwrap();
+                app.merge_tags(&tags, into)
+                    .map(|count| format!("Tags merged into '{}' on {} applications", into, count))
+            }
+            ("list", Some(_)) => {
+                let tags = match app.get_tag_counts() {
+                    Ok(tags) => tags,
+                    Err(err) => return fail(&err),
+                };
+                if tags.is_empty() {
+                    say!("There are no tags");
+                }
+                let width = tags.iter().map(|(tag, _)| tag.width()).max().unwrap_or(0);
+                for (tag, count) in tags {
+                    say!("{}  {}", table::pad(&tag, width), count);
+                }
+                return;
+            }
+            _ => {
+                println!("{}", args.usage());
+                return;
+            }
+        };
+        match result.and_then(|message| app.flush().map(|_| message)) {
+            Ok(message) => self.report_saved(&app, &message),
+            Err(err) => fail_with(&format!("Couldn't change tags: {}", err), &err),
+        }
+    }
+
+    pub(crate) fn manage_aliases(&self, args: &clap::ArgMatches) {
+        let (command, sub_alias) = match args.subcommand() {
+            (command, Some(sub_alias)) => (command, sub_alias),
+            _ => {
+                println!("{}", args.usage());
+                return;
+            }
+        };
+        let mut app = self.app();
+        let alias = sub_alias.value_of("ALIAS").unwrap_or_default();
+        match command {
+            "add" => {
+                let name = sub_alias
+                    .value_of("APPNAME")
+                    .expect("Couldn't read APPNAME for 'alias add' command");
+                match app.resolve_name(name).and_then(|name| {
+                    app.add_alias(&name, alias)
+                        .and_then(|_| app.flush())
+                        .map(|_| name)
+                }) {
+                    Ok(name) => self.report_saved(
+                        &app,
+                        &format!("'{}' is now an alias of '{}'", alias.trim(), name),
+                    ),
+                    Err(err) => {
+                        fail_with(&format!("Couldn't add alias '{}': {}", alias, err), &err)
+                    }
+                }
+            }
+            "remove" => match app
+                .remove_alias(alias)
+                .and_then(|name| app.flush().map(|_| name))
+            {
+                Ok(name) => {
+                    self.report_saved(&app, &format!("Alias '{}' of '{}' removed", alias, name))
+                }
+                Err(err) => fail_with(&format!("Couldn't remove alias '{}': {}", alias, err), &err),
+            },
+            _ => {
+                let aliases = match app.get_aliases() {
+                    Ok(aliases) => aliases,
+                    Err(err) => return fail(&err),
+                };
+                if aliases.is_empty() {
+                    say!("There are no aliases");
+                }
+                let width = aliases
+                    .iter()
+                    .map(|(alias, _)| alias.width())
+                    .max()
+                    .unwrap_or(0);
+                for (alias, name) in aliases {
+                    say!("{} → {}", table::pad(&alias, width), name);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use crate::messages::tr;
+use crate::{fail, fail_with, Cli};
+#[cfg(feature = "qr")]
+use qrcode::render::{svg, unicode};
+#[cfg(feature = "qr")]
+use qrcode::QrCode;
+use rustotpony_core::*;
+
+impl Cli {
+    pub(crate) fn reenroll_application(&self, name: &str, confirmed: bool) {
+        let question = format!(
+            "QR code with the secret of '{}' will be printed and the disclosure recorded in the history. Continue?",
+            name
+        );
+        let uri = match self.disclose(name, confirmed, &question, |app, passphrase| {
+            app.reenroll(name, passphrase)
+        }) {
+            Some(uri) => uri,
+            None => return,
+        };
+        Self::print_otpauth_uri(&uri);
+    }
+
+    pub(crate) fn share_application(&self, name: &str, confirmed: bool) {
+        let question = format!(
+            "Secret of '{}' will be printed encrypted with a new passphrase and the disclosure recorded in the history. Continue?",
+            name
+        );
+        let share_passphrase = match Self::prompt_new_password(
+            "passphrase of the share",
+            Kdf::scrypt(PASSPHRASE_LOG_N).ok().as_ref(),
+        ) {
+            Ok(passphrase) => passphrase,
+            Err(err) => {
+                fail_with(&format!("{}. Aborting…", err), &err);
+                return;
+            }
+        };
+        let share = match self.disclose(name, confirmed, &question, |app, passphrase| {
+            app.share_application(name, passphrase, &share_passphrase)
+        }) {
+            Some(share) => share,
+            None => return,
+        };
+        Self::print_qr_code(&share);
+        println!("{}", share);
+        say!("Tell the recipient the passphrase another way, e.g. in person");
+    }
+
+    pub(crate) fn receive_application(
+        &self,
+        share: Option<&str>,
+        rename: Option<&str>,
+        on_duplicate: Option<DuplicateResolution>,
+    ) {
+        let share = match share {
+            Some(share) => String::from(share),
+            None => {
+                say!("Paste the shared generator and finish with an empty line:");
+                Self::read_lines().concat()
+            }
+        };
+        let passphrase =
+            rpassword::prompt_password_stdout(tr("Enter passphrase of the share: ")).unwrap();
+        let mut received = match GenApp::from_share(&share, &passphrase) {
+            Ok(received) => received,
+            Err(err) => {
+                fail_with(&format!("{} Aborting…", err), &err);
+                return;
+            }
+        };
+        if let Some(name) = rename {
+            received = received.with_name(name);
+        }
+        let name = String::from(received.get_name());
+        let mut app = self.app();
+        let report = match app.import_applications(vec![Ok(received)], |_, existing| {
+            on_duplicate.unwrap_or_else(|| Self::ask_duplicate_resolution(&name, existing))
+        }) {
+            Ok(report) => report,
+            Err(err) => {
+                fail_with(&format!("Couldn't add '{}': {}", name, err), &err);
+                return;
+            }
+        };
+        let message = match report.items.first().map(|item| &item.action) {
+            Some(ImportAction::Add) => format!("New application created: {}", name),
+            Some(ImportAction::Renamed { existing }) => {
+                format!("Application '{}' renamed to '{}'", existing, name)
+            }
+            Some(ImportAction::Replaced { existing }) => {
+                format!("Application '{}' replaced with '{}'", existing, name)
+            }
+            Some(ImportAction::Skip) => {
+                say!("'{}' is already there, nothing was added.", name);
+                return;
+            }
+            Some(ImportAction::NameConflict) => {
+                say!(
+                    "Another application is named '{}', use --as to choose another name. Aborting…",
+                    name
+                );
+                return;
+            }
+            _ => {
+                say!("Nothing was added.");
+                return;
+            }
+        };
+        match app.flush() {
+            Ok(_) => self.report_saved(&app, &message),
+            Err(err) => fail_with(&format!("{} Aborting…", err), &err),
+        }
+    }
+
+    pub(crate) fn provision_application(&self, name: &str, username: &str) {
+        let mut app = self.app();
+        let uri = match app.provision(name, username) {
+            Ok(uri) => uri,
+            Err(err) => {
+                fail_with(
+                    &format!("Couldn't provision application '{}': {}", name, err),
+                    &err,
+                );
+                return;
+            }
+        };
+        if app.is_dry_run() {
+            self.report_saved(&app, "");
+            return;
+        }
+        match app.flush() {
+            Ok(_) => {
+                say!("New application created: {}", name);
+                Self::print_otpauth_uri(&uri);
+            }
+            Err(err) => fail_with(&format!("{} Aborting…", err), &err),
+        }
+    }
+
+    fn print_otpauth_uri(uri: &str) {
+        Self::print_qr_code(uri);
+        println!("{}", uri);
+    }
+
+    #[cfg(feature = "qr")]
+    pub(crate) fn print_qr_code(uri: &str) {
+        match QrCode::new(uri.as_bytes()) {
+            Ok(code) => println!(
+                "{}",
+                code.render::<unicode::Dense1x2>()
+                    .dark_color(unicode::Dense1x2::Light)
+                    .light_color(unicode::Dense1x2::Dark)
+                    .build()
+            ),
+            Err(err) => fail(&Error::new(
+                ErrorCode::Other,
+                format!("Couldn't render QR code: {}", err),
+            )),
+        }
+    }
+
+    #[cfg(not(feature = "qr"))]
+    pub(crate) fn print_qr_code(_uri: &str) {}
+
+    #[cfg(feature = "qr")]
+    pub(crate) fn qr_code_svg(uri: &str) -> Option<String> {
+        let code = QrCode::new(uri.as_bytes()).ok()?;
+        let image = code.render::<svg::Color>().min_dimensions(200, 200).build();
+        // The XML declaration isn't needed inside an HTML page
+        match image.split_once("?>") {
+            Some((_, svg)) => Some(String::from(svg)),
+            None => Some(image),
+        }
+    }
+
+    #[cfg(not(feature = "qr"))]
+    pub(crate) fn qr_code_svg(_uri: &str) -> Option<String> {
+        None
+    }
+
+    /// Runs an operation which discloses the secret of the application. The
+    /// result is returned only after the disclosure is recorded in the
+    /// history, so dry runs and read-only databases never reveal anything.
+    pub(crate) fn disclose<F>(
+        &self,
+        name: &str,
+        confirmed: bool,
+        question: &str,
+        operation: F,
+    ) -> Option<String>
+    where
+        F: FnOnce(&mut RusTOTPony<JsonDatabase>, Option<&str>) -> Result<String, Error>,
+    {
+        let mut app = self.app();
+        let passphrase = match app.get_application(name) {
+            Ok(application) if application.is_protected() => Some(Self::get_passphrase(name)),
+            Ok(_) => None,
+            Err(err) => {
+                fail(&err);
+                return None;
+            }
+        };
+        if !confirmed && !Self::confirm(question) {
+            say!("Aborting…");
+            return None;
+        }
+        let result = match operation(&mut app, passphrase.as_deref()) {
+            Ok(result) => result,
+            Err(err) => {
+                fail_with(
+                    &format!("Couldn't disclose secret of '{}': {}", name, err),
+                    &err,
+                );
+                return None;
+            }
+        };
+        if app.is_dry_run() {
+            self.report_saved(&app, "");
+            return None;
+        }
+        match app.flush() {
+            Ok(_) => Some(result),
+            Err(err) => {
+                fail_with(
+                    &format!(
+                        "Couldn't record the disclosure, secret wasn't revealed: {}",
+                        err
+                    ),
+                    &err,
+                );
+                None
+            }
+        }
+    }
+}
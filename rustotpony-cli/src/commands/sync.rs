@@ -0,0 +1,67 @@
+#[cfg(feature = "webdav")]
+use crate::fail_with;
+#[cfg(feature = "webdav")]
+use crate::messages::tr;
+#[cfg(feature = "webdav")]
+use crate::progress::ProgressBar;
+use crate::Cli;
+#[cfg(feature = "webdav")]
+use rustotpony_core::*;
+#[cfg(feature = "webdav")]
+use std::path::PathBuf;
+
+impl Cli {
+    #[cfg(not(feature = "webdav"))]
+    pub(crate) fn sync_database(&self, _args: &clap::ArgMatches, _is_push: bool) {
+        say!("This build of totp doesn't include the 'webdav' feature");
+    }
+
+    #[cfg(feature = "webdav")]
+    pub(crate) fn sync_database(&self, args: &clap::ArgMatches, is_push: bool) {
+        let url = args
+            .value_of("URL")
+            .expect("Couldn't read URL for 'sync' command");
+        if self.database.contains("://") {
+            say!("Only local databases can be synchronized");
+            return;
+        }
+        if self.read_only && !is_push {
+            say!("Database is opened in read-only mode");
+            return;
+        }
+        let bar = ProgressBar::new();
+        let mut remote = WebDavRemote::new(url)
+            .allow_insecure_http(args.is_present("allow-insecure-http"))
+            .on_progress(move |progress| bar.show(progress));
+        if let Some(username) = args.value_of("username") {
+            let password = std::env::var("RUSTOTPONY_WEBDAV_PASSWORD").unwrap_or_else(|_| {
+                rpassword::prompt_password_stdout(tr("Enter your WebDAV password: ")).unwrap()
+            });
+            remote = remote.with_credentials(username, &password);
+        }
+        let db_path = PathBuf::from(&self.database);
+        let force = args.is_present("force");
+        if is_push {
+            if let Err(err) = self.compact_journal() {
+                return fail_with(&format!("Couldn't sync database: {}", err), &err);
+            }
+        }
+        remote = remote.cancellation(self.cancellation.clone());
+        self.cancel_on_interrupt();
+        let result = if is_push {
+            remote.push(&db_path, force)
+        } else {
+            remote.pull(&db_path, force)
+        };
+        match result {
+            Ok(SyncOutcome::UpToDate) => say!("Already up to date."),
+            Ok(SyncOutcome::Pulled) => say!("Database downloaded."),
+            Ok(SyncOutcome::Pushed) => say!("Database uploaded."),
+            Ok(SyncOutcome::Conflict(path)) => say!(
+                "Both local and remote databases were changed. Remote copy was saved to {}",
+                path.display()
+            ),
+            Err(err) => fail_with(&format!("Couldn't sync database: {}", err), &err),
+        }
+    }
+}
@@ -0,0 +1,356 @@
+use crate::messages::tr;
+use crate::progress::ProgressBar;
+use crate::{exit_code, fail, fail_with, record_failure, Cli};
+use rustotpony_core::*;
+use std::io::{self, IsTerminal, Read};
+
+impl Cli {
+    pub(crate) fn import_applications(
+        &self,
+        file: &str,
+        from: &str,
+        as_json: bool,
+        on_duplicate: Option<DuplicateResolution>,
+    ) {
+        let entries = match Self::read_import_entries(file, from) {
+            Ok(entries) => entries,
+            Err(err) => {
+                fail(&err);
+                return;
+            }
+        };
+        let mut app = self.app();
+        // Questions would get mixed into the JSON report, and the answers
+        // can't be read when entries come from the standard input
+        let interactive = !as_json && file != "-" && io::stdin().is_terminal();
+        self.cancel_on_interrupt();
+        // Questions are asked in the middle of the import, the bar would
+        // be drawn over them
+        if !interactive {
+            let bar = ProgressBar::new();
+            app.on_change(move |event| {
+                if let Event::Progress(progress) = event {
+                    bar.show(progress);
+                }
+            });
+        }
+        let report = match app.import_applications(entries, |entry, existing| match on_duplicate {
+            Some(resolution) => resolution,
+            None if interactive => Self::ask_duplicate_resolution(entry.get_name(), existing),
+            None => DuplicateResolution::Skip,
+        }) {
+            Ok(report) => report,
+            Err(err) => {
+                fail_with(&format!("Couldn't import applications: {}", err), &err);
+                return;
+            }
+        };
+        if as_json {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&report).expect("Couldn't serialize report")
+            );
+        } else {
+            for item in report.items.iter() {
+                match &item.action {
+                    ImportAction::Add => say!("  add '{}'", item.name),
+                    ImportAction::Skip => say!("  skip '{}', it's already there", item.name),
+                    ImportAction::NameConflict => say!(
+                        "  conflict '{}', another application has this name",
+                        item.name
+                    ),
+                    ImportAction::SecretConflict { existing } => say!(
+                        "  conflict '{}', '{}' has the same secret",
+                        item.name,
+                        existing
+                    ),
+                    ImportAction::Duplicate { existing } => {
+                        say!("  skip '{}', '{}' has the same secret", item.name, existing)
+                    }
+                    ImportAction::Renamed { existing } => {
+                        say!("  rename '{}' to '{}'", existing, item.name)
+                    }
+                    ImportAction::Replaced { existing } => {
+                        say!("  replace '{}' with '{}'", existing, item.name)
+                    }
+                    ImportAction::Invalid { error } => {
+                        say!("  invalid {}: {}", item.name, error)
+                    }
+                }
+            }
+            let left_out = [
+                (report.skipped(), "skipped"),
+                (report.conflicts(), "conflicting"),
+                (report.invalid(), "invalid"),
+            ];
+            let total: usize = left_out.iter().map(|(count, _)| count).sum();
+            if total > 0 {
+                let counts: Vec<String> = left_out
+                    .iter()
+                    .filter(|(count, _)| *count > 0)
+                    .map(|(count, what)| format!("{} {}", count, what))
+                    .collect();
+                say!(
+                    "{} of {} entries left out: {}.",
+                    total,
+                    report.items.len(),
+                    counts.join(", ")
+                );
+            }
+        }
+        if report.imported() == 0 {
+            if !as_json {
+                say!("Nothing to import.");
+            }
+        } else if app.is_dry_run() {
+            if !as_json {
+                say!(
+                    "Dry run, {} applications would be imported.",
+                    report.imported()
+                );
+            }
+        } else {
+            match app.flush() {
+                Ok(_) if !as_json => say!("{} applications imported.", report.imported()),
+                Ok(_) => {}
+                Err(err) => fail_with(
+                    &format!("Couldn't save imported applications: {}", err),
+                    &err,
+                ),
+            }
+        }
+        if report.has_problems() {
+            std::process::exit(1);
+        }
+    }
+
+    /// Reads the file to import, or the folder with `--from folder`, and
+    /// parses it in the format of the app it comes from.
+    fn read_import_entries(file: &str, from: &str) -> Result<Vec<Result<GenApp, Error>>, Error> {
+        if from == "folder" {
+            return parse_uri_folder(&expand_home(file));
+        }
+        let data = if file == "-" && from == "otpauth" {
+            Ok(Self::read_lines().join("\n").into_bytes())
+        } else if file == "-" {
+            let mut data = Vec::new();
+            io::stdin().read_to_end(&mut data).map(|_| data)
+        } else {
+            std::fs::read(file)
+        };
+        let data = data
+            .map_err(|err| Error::new(ErrorCode::Io, format!("Couldn't read {}: {}", file, err)))?;
+        match from {
+            "1password" => parse_1password(&data),
+            "lastpass" => parse_lastpass(&data),
+            _ => Ok(String::from_utf8_lossy(&data)
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(GenApp::from_otpauth_uri)
+                .collect()),
+        }
+    }
+
+    pub(crate) fn export_applications(
+        &self,
+        file: &str,
+        filter: &ExportFilter,
+        format: &str,
+        qr: bool,
+        mask_secrets: bool,
+        recipients: &[String],
+    ) {
+        let to_stdout = file == "-";
+        let backup = format == "aegis" || format == "andotp";
+        if backup && !recipients.is_empty() {
+            say!(
+                "Backups of {} are encrypted with a password, not to recipients",
+                format
+            );
+            return;
+        }
+        if to_stdout && backup {
+            say!("Encrypted backups are written to files only, password prompts use the terminal");
+            return;
+        }
+        if !to_stdout && expand_home(file).exists() {
+            say!("{} already exists. Aborting…", file);
+            return;
+        }
+        let mut app = self.app();
+        // Standard output carries the URIs, prompts go to stderr then
+        let report = app.export_applications(filter, |name| {
+            let prompt = text!("Enter passphrase for '{}': ", name);
+            let passphrase = if to_stdout {
+                rpassword::prompt_password_stderr(&prompt)
+            } else {
+                rpassword::prompt_password_stdout(&prompt)
+            };
+            passphrase.ok()
+        });
+        let note = |message: String| {
+            if to_stdout {
+                eprintln!("{}", message);
+            } else {
+                println!("{}", message);
+            }
+        };
+        let report = match report {
+            Ok(report) => report,
+            Err(err) => {
+                note(text!("Nothing was exported: {}", err));
+                record_failure(&err);
+                return;
+            }
+        };
+        for (name, reason) in report.skipped.iter() {
+            note(
+                self.theme
+                    .warning(&text!("'{}' wasn't exported: {}", name, reason)),
+            );
+        }
+        if report.applications.is_empty() {
+            note(String::from(tr("Nothing to export.")));
+            return;
+        }
+        if app.is_dry_run() {
+            note(text!(
+                "Dry run, {} applications would be exported.",
+                report.applications.len()
+            ));
+            return;
+        }
+        let data = match format {
+            "html" => {
+                if qr && !cfg!(feature = "qr") {
+                    note(
+                        self.theme
+                            .warning("totp was built without QR code support, the page has none"),
+                    );
+                }
+                let qr_code: &QrCodeRenderer = &Self::qr_code_svg;
+                HtmlBackup {
+                    mask_secrets,
+                    qr_code: if qr { Some(qr_code) } else { None },
+                }
+                .render(&report.applications)
+                .into_bytes()
+            }
+            "aegis" | "andotp" => {
+                // Key derivations of other apps aren't known here, the
+                // estimate is the worst case of a fast hash
+                let exported =
+                    Self::prompt_new_password("password of the backup", Some(&Kdf::Sha256))
+                        .and_then(|password| match format {
+                            "aegis" => report.to_aegis(&password).map(String::into_bytes),
+                            _ => report.to_andotp(&password),
+                        });
+                match exported {
+                    Ok(backup) => backup,
+                    Err(err) => {
+                        note(text!("Nothing was exported: {}", err));
+                        record_failure(&err);
+                        return;
+                    }
+                }
+            }
+            _ => (report.uris().join("\n") + "\n").into_bytes(),
+        };
+        let data = if recipients.is_empty() {
+            data
+        } else {
+            match encrypt_to_recipients(&data, recipients) {
+                Ok(encrypted) => encrypted,
+                Err(err) => {
+                    note(text!("Nothing was exported: {}", err));
+                    record_failure(&err);
+                    return;
+                }
+            }
+        };
+        // The disclosure is recorded before the secrets leave the database
+        if let Err(err) = app.flush() {
+            note(text!(
+                "Couldn't record the export, nothing was exported: {}",
+                err
+            ));
+            record_failure(&err);
+            return;
+        }
+        if to_stdout {
+            print!("{}", String::from_utf8_lossy(&data));
+            return;
+        }
+        match FileStorage::new(expand_home(file)).write(&data) {
+            Ok(_) => say!(
+                "{} applications exported to {}",
+                report.applications.len(),
+                file
+            ),
+            Err(err) => fail_with(&format!("Couldn't write {}: {}", file, err), &err),
+        }
+    }
+
+    pub(crate) fn create_paper_backup(&self, output: &str) {
+        if expand_home(output).exists() {
+            say!("{} already exists. Aborting…", output);
+            return;
+        }
+        let storage = storage_for(&self.database).unwrap_or_else(|err| {
+            fail(&err);
+            std::process::exit(exit_code());
+        });
+        let parts = match Self::build(self.open_database(storage)).paper_backup(PAPER_PART_SIZE) {
+            Ok(parts) => parts,
+            Err(err) => {
+                fail_with(&format!("Couldn't make paper backup: {}", err), &err);
+                return;
+            }
+        };
+        if !cfg!(feature = "qr") {
+            println!(
+                "{}",
+                self.theme.warning(
+                    "totp was built without QR code support, parts will be printed as text only"
+                )
+            );
+        }
+        let qr_code: &QrCodeRenderer = &Self::qr_code_svg;
+        let page = HtmlBackup {
+            mask_secrets: false,
+            qr_code: Some(qr_code),
+        }
+        .render_paper(&parts);
+        match FileStorage::new(expand_home(output)).write(page.as_bytes()) {
+            Ok(_) => say!(
+                "Paper backup in {} parts written to {}, print it and keep it with the database password in mind",
+                parts.len(),
+                output
+            ),
+            Err(err) => fail_with(&format!("Couldn't write {}: {}", output, err), &err),
+        }
+    }
+
+    pub(crate) fn restore_paper_backup(&self) {
+        let storage = storage_for(&self.database).unwrap_or_else(|err| {
+            fail(&err);
+            std::process::exit(exit_code());
+        });
+        say!("Scan the QR codes of the backup, one per line, and finish with an empty line:");
+        let parts = Self::read_lines();
+        if self.dry_run {
+            say!("Dry run, nothing was restored.");
+            return;
+        }
+        let db = Self::build(self.open_database(storage).read_only(self.read_only));
+        match db.restore_paper_backup(&parts) {
+            Ok(count) => say!(
+                "Database with {} applications was restored to {}",
+                count,
+                self.database
+            ),
+            Err(err) => fail_with(&format!("Couldn't restore paper backup: {}", err), &err),
+        }
+    }
+}
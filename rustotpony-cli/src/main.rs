@@ -0,0 +1,1104 @@
+#[macro_use]
+mod messages;
+mod args;
+mod commands;
+#[cfg(feature = "picker")]
+mod picker;
+mod progress;
+mod table;
+mod theme;
+
+use messages::tr;
+use progress::ProgressBar;
+use rustotpony_core::*;
+use std::io::{self, IsTerminal, Write};
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Once;
+use std::time::Duration;
+use theme::Theme;
+use tracing_subscriber::EnvFilter;
+use zxcvbn::time_estimates::CrackTimeSeconds;
+use zxcvbn::Score;
+
+const CONFIG_PATH: &str = ".rustotpony/db.json";
+// Catalogs of translated messages, e.g. de.json
+const LOCALES_PATH: &str = ".rustotpony/locales";
+// Shown instead of secrets, doesn't depend on the secret length
+const SECRET_MASK: &str = "********";
+const LOG_ENV: &str = "RUSTOTPONY_LOG";
+const DEFAULT_OCRA_SUITE: &str = "OCRA-1:HOTP-SHA1-6:QN08";
+const LIST_COLUMNS: &[&str] = &[
+    "name",
+    "key",
+    "issuer",
+    "username",
+    "digits",
+    "algorithm",
+    "last-used",
+];
+const DEFAULT_LIST_COLUMNS: &[&str] = &["name", "key", "username"];
+const DUPLICATE_RESOLUTIONS: &[&str] = &["skip", "rename", "replace", "keep"];
+const IMPORT_FORMATS: &[&str] = &["otpauth", "folder", "1password", "lastpass"];
+
+// Code of the last error, the process exits with it, see `ErrorCode`
+static EXIT_CODE: AtomicI32 = AtomicI32::new(0);
+static INTERRUPT_HANDLER: Once = Once::new();
+// Commands which don't need a database or create it on their own, the
+// first-run setup isn't offered for them. `totp` alone shows the
+// dashboard, so it's offered then.
+const WITHOUT_SETUP: &[&str] = &[
+    "doctor",
+    "eradicate",
+    "fix-permissions",
+    "git",
+    "paper-restore",
+    "recover-key",
+    "salvage",
+    "snapshot",
+    "sync",
+];
+// Choices of how long unlocking a new database takes, in milliseconds
+const UNLOCK_TIMES: &[(&str, u64)] = &[("normal", 250), ("strong", 1000), ("paranoid", 3000)];
+
+fn main() {
+    // Diagnostics are written to stderr, e.g. RUSTOTPONY_LOG=debug
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_env(LOG_ENV).unwrap_or_else(|_| EnvFilter::new("warn")),
+        )
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .init();
+    let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+    if let Some(problem) = messages::init(&home.join(LOCALES_PATH)) {
+        eprintln!("{}", problem);
+    }
+    // Bugs keep the usual report and exit with their own code
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        std::process::exit(ErrorCode::Internal.code());
+    }));
+    Cli::run();
+    std::process::exit(exit_code());
+}
+
+fn record_failure(err: &Error) {
+    EXIT_CODE.store(err.code().code(), Ordering::Relaxed);
+}
+
+fn exit_code() -> i32 {
+    EXIT_CODE.load(Ordering::Relaxed)
+}
+
+/// Prints the error like other messages and makes the process exit with
+/// the code of its kind.
+fn fail(err: &Error) {
+    println!("{}", messages::catalog().translate(err.message()));
+    record_failure(err);
+}
+
+fn fail_with(message: &str, err: &Error) {
+    println!("{}", messages::catalog().translate(message));
+    record_failure(err);
+}
+
+enum NewGenerator {
+    Totp(TOTP),
+    Motp(MOTP),
+    Ocra(OCRA),
+}
+
+struct Cli {
+    database: String,
+    read_only: bool,
+    dry_run: bool,
+    // Size of groups codes are split into for display
+    group: Option<usize>,
+    // How long the derived database key is kept in memory
+    key_ttl: Option<Duration>,
+    // How long deriving the key of a new database should take
+    unlock_time: Option<Duration>,
+    // Whether changes of a local database are saved to its journal
+    journal: bool,
+    exact_names: bool,
+    theme: Theme,
+    // Password set by the first-run setup, so it isn't asked again at once
+    setup_secret: Option<String>,
+    // Cancelled by Ctrl-C during imports, password changes and syncs
+    cancellation: CancellationToken,
+}
+
+impl Cli {
+    fn app(&self) -> RusTOTPony<JsonDatabase> {
+        let storage = storage_for(&self.database).unwrap_or_else(|err| {
+            fail(&err);
+            std::process::exit(exit_code());
+        });
+        self.warn_about_permissions(storage.as_ref());
+        let unlock_time = self.unlock_time.unwrap_or(DEFAULT_UNLOCK_TIME);
+        let db = Self::build(
+            self.with_key_cache(
+                self.open_database(storage)
+                    .new_secret(move || Self::get_new_secret(unlock_time))
+                    .read_only(self.read_only),
+            ),
+        );
+        let mut app = RusTOTPony::new(db);
+        app.set_dry_run(self.dry_run);
+        app.set_cancellation(self.cancellation.clone());
+        if self.exact_names {
+            app.set_name_matching(NameMatching::Exact);
+        }
+        app
+    }
+
+    fn warn_about_permissions(&self, storage: &dyn Storage) {
+        if let Some(problem) = storage.local_path().and_then(permissions_problem) {
+            println!(
+                "{}",
+                self.theme.warning(&text!(
+                    "{}, run `totp fix-permissions` to restrict access to it",
+                    problem
+                ))
+            );
+        }
+    }
+
+    fn with_key_cache(&self, db: JsonDatabaseBuilder) -> JsonDatabaseBuilder {
+        match self.key_ttl {
+            Some(ttl) => db.key_cache(ttl),
+            None => db,
+        }
+    }
+
+    /// Database asking for the password, and for the passphrase of the
+    /// second person if it requires two.
+    fn open_database(&self, storage: Box<dyn Storage>) -> JsonDatabaseBuilder {
+        let is_local = storage.local_path().is_some();
+        let db = JsonDatabase::builder().storage(storage);
+        let mut db = match &self.setup_secret {
+            Some(secret) => {
+                let secret = secret.clone();
+                db.secret(move || secret.clone())
+            }
+            None => db.secret(Self::get_secret),
+        }
+        .second_secret(Self::get_second_secret);
+        let bar = ProgressBar::new();
+        db = db
+            .on_progress(move |progress| bar.show(progress))
+            .cancellation(self.cancellation.clone());
+        if self.journal && is_local {
+            db = db.journal(DEFAULT_COMPACT_AFTER);
+        }
+        match self.unlock_time {
+            Some(unlock_time) => db.unlock_time(unlock_time),
+            None => db,
+        }
+    }
+
+    /// Makes the first Ctrl-C cancel the running operation, which leaves
+    /// the database as it was, and the second one quit at once.
+    fn cancel_on_interrupt(&self) {
+        let token = self.cancellation.clone();
+        // An import of the first-run setup may have set it already
+        INTERRUPT_HANDLER.call_once(|| {
+            ctrlc::set_handler(move || {
+                if token.is_cancelled() {
+                    std::process::exit(130);
+                }
+                esay!("\nCancelling, press Ctrl-C again to quit right away…");
+                token.cancel();
+            })
+            .expect("Error setting Ctrl-C handler");
+        });
+    }
+
+    /// Guides through creating the database when there is none yet: where
+    /// it's kept, its password, how long unlocking takes and an import from
+    /// another app. It's offered only in a terminal, scripts create the
+    /// database with `init` or get an empty one on the first save.
+    fn set_up_first_run(&mut self, command: &str) {
+        if WITHOUT_SETUP.contains(&command)
+            || self.read_only
+            || self.dry_run
+            || self.database.contains("://")
+            || Path::new(&self.database).exists()
+            || !io::stdin().is_terminal()
+            || !io::stdout().is_terminal()
+        {
+            return;
+        }
+        say!("Welcome to RusTOTPony! There is no database yet, let's set it up.");
+        say!();
+        let default_location = self.database.clone();
+        loop {
+            let location = Self::ask(&text!(
+                "Where should the database be kept? [{}] ",
+                default_location
+            ));
+            if location.is_empty() {
+                break;
+            }
+            if location.contains("://") {
+                say!("Remote databases are chosen with --database, enter a local path");
+                continue;
+            }
+            self.database = Self::resolve_database_location(&location);
+            break;
+        }
+        if Path::new(&self.database).exists() {
+            say!(
+                "There is a database at {} already, it's used.",
+                self.database
+            );
+            self.print_location_hint(&default_location);
+            return;
+        }
+
+        if self.unlock_time.is_none() {
+            say!("How long should unlocking the database take? The longer it takes, the slower guessing the password is.");
+            for (idx, (name, millis)) in UNLOCK_TIMES.iter().enumerate() {
+                say!("  {}) {}, {} ms", idx + 1, tr(name), millis);
+            }
+            let millis = loop {
+                let answer = Self::ask(tr("Choose [1]: "));
+                match answer.parse::<usize>() {
+                    _ if answer.is_empty() => break UNLOCK_TIMES[0].1,
+                    Ok(choice) if (1..=UNLOCK_TIMES.len()).contains(&choice) => {
+                        break UNLOCK_TIMES[choice - 1].1
+                    }
+                    _ => say!("Choose a number from 1 to {}", UNLOCK_TIMES.len()),
+                }
+            };
+            self.unlock_time = Some(Duration::from_millis(millis));
+            say!();
+        }
+
+        let kdf = Kdf::benchmark(self.unlock_time.unwrap_or(DEFAULT_UNLOCK_TIME));
+        let password = Self::prompt_new_password("new database password", Some(&kdf))
+            .unwrap_or_else(|err| {
+                fail_with(&format!("{}. Aborting…", err), &err);
+                std::process::exit(exit_code());
+            });
+
+        say!();
+        let import_file = Self::ask(tr(
+            "File exported from another app to import, empty to skip: ",
+        ));
+        let import_format = if import_file.is_empty() {
+            None
+        } else {
+            Some(loop {
+                let answer = Self::ask(&text!(
+                    "Format of the file ({}) [{}]: ",
+                    IMPORT_FORMATS.join(", "),
+                    IMPORT_FORMATS[0]
+                ));
+                if answer.is_empty() {
+                    break IMPORT_FORMATS[0];
+                }
+                match IMPORT_FORMATS.iter().find(|format| **format == answer) {
+                    Some(format) => break *format,
+                    None => say!("Unknown format '{}'", answer),
+                }
+            })
+        };
+
+        self.create_database(password, |db| db.kdf(kdf));
+        if let Some(format) = import_format {
+            self.import_applications(&import_file, format, false, None);
+        }
+        self.print_location_hint(&default_location);
+        say!();
+    }
+
+    /// Creates a database with the options of `init`, instead of the first
+    /// save creating it with the defaults. An existing database is never
+    /// replaced.
+    fn init_database(&mut self, matches: &clap::ArgMatches) {
+        if let Some(location) = matches.value_of("LOCATION") {
+            self.database = Self::resolve_database_location(location);
+        }
+        let cipher =
+            match CipherKind::from_name(matches.value_of("cipher").unwrap_or("xchacha20-poly1305"))
+            {
+                Ok(cipher) => cipher,
+                Err(err) => return fail(&err),
+            };
+        let kdf = match matches.value_of("scrypt-cost").map(str::parse::<u8>) {
+            Some(Ok(log_n)) => match Kdf::scrypt(log_n) {
+                Ok(kdf) => kdf,
+                Err(err) => return fail(&err),
+            },
+            Some(Err(_)) => {
+                return fail(&Error::new(
+                    ErrorCode::InvalidArgument,
+                    "scrypt cost must be a number",
+                ))
+            }
+            None => Kdf::benchmark(self.unlock_time.unwrap_or(DEFAULT_UNLOCK_TIME)),
+        };
+        let indexed = matches.is_present("indexed");
+        let storage = match storage_for(&self.database) {
+            Ok(storage) => storage,
+            Err(err) => return fail(&err),
+        };
+        match storage.read() {
+            Ok(None) => {}
+            Ok(Some(_)) => {
+                return fail(&Error::new(
+                    ErrorCode::AlreadyExists,
+                    format!("Database {} already exists", self.database),
+                ));
+            }
+            Err(err) => return fail(&err),
+        }
+        if self.dry_run {
+            say!("Database would be created at {}.", self.database);
+            return;
+        }
+        let password = match Self::prompt_new_password("new database password", Some(&kdf)) {
+            Ok(password) => password,
+            Err(err) => return fail_with(&format!("{}. Aborting…", err), &err),
+        };
+        self.create_database(password, |db| db.cipher(cipher).indexed(indexed).kdf(kdf));
+        if let Some(file) = matches.value_of("import") {
+            self.import_applications(
+                file,
+                matches.value_of("from").unwrap_or("otpauth"),
+                false,
+                matches
+                    .value_of("on-duplicate")
+                    .map(Self::parse_duplicate_resolution),
+            );
+        }
+    }
+
+    /// Creates an empty database with the password, later commands of this
+    /// run use the password too instead of asking for it again.
+    fn create_database(
+        &mut self,
+        password: String,
+        options: impl FnOnce(JsonDatabaseBuilder) -> JsonDatabaseBuilder,
+    ) {
+        let storage = storage_for(&self.database).unwrap_or_else(|err| {
+            fail(&err);
+            std::process::exit(exit_code());
+        });
+        let new_password = password.clone();
+        let db = Self::build(options(
+            self.open_database(storage)
+                .new_secret(move || new_password.clone()),
+        ));
+        if let Err(err) = db.create() {
+            fail_with(&format!("Couldn't create database: {}", err), &err);
+            std::process::exit(exit_code());
+        }
+        say!("Database created at {}.", self.database);
+        self.setup_secret = Some(password);
+    }
+
+    // A database in another place is used only when it's given
+    fn print_location_hint(&self, default_location: &str) {
+        if self.database != default_location {
+            say!(
+                "Pass --database {} to use it, e.g. in an alias of totp.",
+                self.database
+            );
+        }
+    }
+
+    /// Reads the answer to the question, it's empty when the input ends.
+    fn ask(question: &str) -> String {
+        print!("{}", question);
+        io::stdout().flush().expect("Couldn't flush stdout");
+        let mut answer = String::new();
+        io::stdin()
+            .read_line(&mut answer)
+            .expect("Couldn't read answer");
+        String::from(answer.trim())
+    }
+
+    fn build(db: JsonDatabaseBuilder) -> JsonDatabase {
+        db.build().unwrap_or_else(|err| {
+            fail(&err);
+            std::process::exit(exit_code());
+        })
+    }
+
+    fn get_secret() -> String {
+        rpassword::prompt_password_stdout(tr("Enter your database pass: ")).unwrap()
+    }
+
+    fn get_second_secret() -> String {
+        rpassword::prompt_password_stdout(tr("Enter the passphrase of the second person: "))
+            .unwrap()
+    }
+
+    /// Asks for the password of a new database, which unlocks in
+    /// `unlock_time`. Exits if the passwords don't match or a weak password
+    /// isn't confirmed.
+    fn get_new_secret(unlock_time: Duration) -> String {
+        let kdf = Kdf::benchmark(unlock_time);
+        Self::prompt_new_password("new database password", Some(&kdf)).unwrap_or_else(|err| {
+            fail_with(&format!("{}. Aborting…", err), &err);
+            std::process::exit(exit_code());
+        })
+    }
+
+    /// Asks for a new password twice, `what` names it in the prompts. With
+    /// the key derivation `kdf` the password goes through, the user is told
+    /// how strong it is and has to confirm a weak one.
+    fn prompt_new_password(what: &str, kdf: Option<&Kdf>) -> Result<String, Error> {
+        ask_new_password(
+            |prompt| {
+                let text = match prompt {
+                    PasswordPrompt::Enter => text!("Enter the {}: ", what),
+                    PasswordPrompt::Repeat => text!("Repeat the {}: ", what),
+                    PasswordPrompt::Mismatch => {
+                        text!("Entries don't match, enter the {} again: ", what)
+                    }
+                };
+                let password = rpassword::prompt_password_stdout(&text).unwrap();
+                match kdf {
+                    Some(kdf) if prompt != PasswordPrompt::Repeat => {
+                        Self::check_strength(&password, kdf)?
+                    }
+                    _ => {}
+                }
+                Ok(password)
+            },
+            NEW_PASSWORD_ATTEMPTS,
+        )
+    }
+
+    fn check_strength(password: &str, kdf: &Kdf) -> Result<(), Error> {
+        let entropy = zxcvbn::zxcvbn(password, &[]);
+        let strength = match entropy.score() {
+            Score::Zero | Score::One => "very weak",
+            Score::Two => "weak",
+            Score::Three => "fair",
+            _ => "strong",
+        };
+        // Guessing is offline, on hardware built for it and slowed down only
+        // by the key derivation
+        let seconds = entropy.guesses() as f64 / kdf.guesses_per_second();
+        say!(
+            "Password strength: {}, it could be guessed in {}",
+            strength,
+            CrackTimeSeconds::Float(seconds)
+        );
+        if let Some(feedback) = entropy.feedback() {
+            if let Some(warning) = feedback.warning() {
+                say!("  {}", warning);
+            }
+            for suggestion in feedback.suggestions() {
+                say!("  {}", suggestion);
+            }
+        }
+        if entropy.score() < Score::Four
+            && !Self::confirm("Encrypted data can be guessed offline this fast. Use it anyway?")
+        {
+            return Err(Error::new(
+                ErrorCode::InvalidArgument,
+                "Weak password was rejected",
+            ));
+        }
+        Ok(())
+    }
+
+    // fn get_secret_from_storage() -> String { }
+
+    fn run() {
+        let matches = Self::get_cli_api_matches();
+        let mut database = matches.value_of("database").map_or_else(
+            || Self::get_database_path().to_string_lossy().into_owned(),
+            Self::resolve_database_location,
+        );
+        let (theme, problems) = Theme::from_env(matches.is_present("no-color"));
+        for problem in problems {
+            println!("{}", theme.warning(&problem));
+        }
+        // Keys and secrets shouldn't end up in core dumps of a crashed process
+        if !matches.is_present("allow-core-dumps") {
+            if let Err(err) = disable_core_dumps() {
+                println!("{}", theme.warning(err.message()));
+            }
+        }
+        if matches.is_present("follow-symlinks") {
+            database = Self::follow_symlinks(database);
+        }
+        let mut cli = Cli {
+            database,
+            read_only: matches.is_present("read-only"),
+            dry_run: matches.is_present("dry-run"),
+            group: match matches.value_of("group").map(str::parse::<usize>) {
+                Some(Ok(size)) => Some(size),
+                Some(Err(_)) => {
+                    say!("Group size must be a number");
+                    return;
+                }
+                None => None,
+            },
+            key_ttl: match matches.value_of("cache-key").map(|minutes| {
+                minutes
+                    .parse::<u64>()
+                    .ok()
+                    .and_then(|minutes| minutes.checked_mul(60))
+            }) {
+                Some(Some(secs)) => Some(Duration::from_secs(secs)),
+                Some(None) => {
+                    say!("Key cache time must be a number of minutes");
+                    return;
+                }
+                None => None,
+            },
+            unlock_time: match matches.value_of("unlock-time").map(str::parse::<u64>) {
+                Some(Ok(millis)) => Some(Duration::from_millis(millis)),
+                Some(Err(_)) => {
+                    say!("Unlock time must be a number of milliseconds");
+                    return;
+                }
+                None => None,
+            },
+            journal: matches.is_present("journal"),
+            exact_names: matches.is_present("exact-names"),
+            theme,
+            setup_secret: None,
+            cancellation: CancellationToken::new(),
+        };
+        match matches.subcommand() {
+            ("init", Some(sub_app)) => cli.init_database(sub_app),
+            (command, _) => {
+                cli.set_up_first_run(command);
+                cli.dispatch(&matches);
+            }
+        }
+    }
+
+    fn dispatch(&self, matches: &clap::ArgMatches) {
+        match matches.subcommand() {
+            ("dash", Some(_)) => {
+                self.show_dashboard();
+            }
+            ("list", Some(sub_app)) if sub_app.is_present("archived") => {
+                self.show_archived_applications(&Self::list_columns(sub_app));
+            }
+            ("list", Some(sub_app)) => {
+                self.show_applications_list(
+                    sub_app.value_of("page"),
+                    sub_app.value_of("per-page"),
+                    &Self::list_columns(sub_app),
+                );
+            }
+            ("search", Some(sub_app)) => {
+                self.search_applications(
+                    sub_app.value_of("QUERY").unwrap(),
+                    &Self::list_columns(sub_app),
+                );
+            }
+            // ("show-all", Some(_)) => {
+            //     self.show_applications_list(true);
+            // }
+            ("show", Some(sub_app)) => {
+                self.show_application(sub_app.value_of("APPNAME"), sub_app.is_present("reveal"));
+            }
+            ("login", Some(sub_app)) => {
+                self.login(
+                    sub_app.value_of("APPNAME"),
+                    sub_app.is_present("reveal"),
+                    sub_app.is_present("wait"),
+                );
+            }
+            ("add", Some(sub_app)) => {
+                let app_name: &str = sub_app
+                    .value_of("APPNAME")
+                    .expect("Couldn't read APPNAME for 'add' command");
+                let key: &str = sub_app.value_of("USERNAME").unwrap_or("");
+                self.create_application(
+                    app_name,
+                    key,
+                    sub_app.value_of("type").unwrap_or("totp"),
+                    sub_app.value_of("encoding").unwrap_or("base32"),
+                    sub_app.value_of("suite"),
+                    sub_app.value_of("epoch"),
+                    sub_app.is_present("hidden"),
+                    sub_app.is_present("protect"),
+                    sub_app
+                        .value_of("on-duplicate")
+                        .map(Self::parse_duplicate_resolution),
+                );
+            }
+            ("format", Some(sub_app)) => {
+                let app_name: &str = sub_app
+                    .value_of("APPNAME")
+                    .expect("Couldn't read APPNAME for 'format' command");
+                self.set_code_format(
+                    app_name,
+                    sub_app.value_of("alphabet"),
+                    sub_app.value_of("length"),
+                );
+            }
+            ("epoch", Some(sub_app)) => {
+                let app_name: &str = sub_app
+                    .value_of("APPNAME")
+                    .expect("Couldn't read APPNAME for 'epoch' command");
+                let epoch: &str = sub_app
+                    .value_of("SECONDS")
+                    .expect("Couldn't read SECONDS for 'epoch' command");
+                self.set_epoch(app_name, epoch);
+            }
+            ("archive", Some(sub_app)) => {
+                let app_name: &str = sub_app
+                    .value_of("APPNAME")
+                    .expect("Couldn't read APPNAME for 'archive' command");
+                self.archive_application(app_name, true);
+            }
+            ("hide", Some(sub_app)) => {
+                let app_name: &str = sub_app
+                    .value_of("APPNAME")
+                    .expect("Couldn't read APPNAME for 'hide' command");
+                self.hide_application(app_name, true);
+            }
+            ("icon", Some(sub_app)) => {
+                let app_name: &str = sub_app
+                    .value_of("APPNAME")
+                    .expect("Couldn't read APPNAME for 'icon' command");
+                self.set_icon(app_name, sub_app);
+            }
+            ("tag", Some(sub_app)) | ("untag", Some(sub_app)) => {
+                let app_name: &str = sub_app
+                    .value_of("APPNAME")
+                    .expect("Couldn't read APPNAME for 'tag' command");
+                let tags: Vec<String> = sub_app
+                    .values_of("TAG")
+                    .map(|tags| tags.map(String::from).collect())
+                    .unwrap_or_default();
+                self.tag_application(app_name, &tags, matches.subcommand_name() == Some("tag"));
+            }
+            ("share", Some(sub_app)) => {
+                let app_name: &str = sub_app
+                    .value_of("APPNAME")
+                    .expect("Couldn't read APPNAME for 'share' command");
+                self.share_application(app_name, sub_app.is_present("yes"));
+            }
+            ("handoff", Some(sub_app)) => self.hand_off_code(
+                sub_app.value_of("APPNAME"),
+                sub_app.is_present("json"),
+                sub_app.is_present("reveal"),
+            ),
+            ("receive", Some(sub_app)) => {
+                self.receive_application(
+                    sub_app.value_of("SHARE"),
+                    sub_app.value_of("as"),
+                    sub_app
+                        .value_of("on-duplicate")
+                        .map(Self::parse_duplicate_resolution),
+                );
+            }
+            ("provision", Some(sub_app)) => {
+                let app_name: &str = sub_app
+                    .value_of("APPNAME")
+                    .expect("Couldn't read APPNAME for 'provision' command");
+                let username: &str = sub_app.value_of("USERNAME").unwrap_or("");
+                self.provision_application(app_name, username);
+            }
+            ("reenroll", Some(sub_app)) => {
+                let app_name: &str = sub_app
+                    .value_of("APPNAME")
+                    .expect("Couldn't read APPNAME for 'reenroll' command");
+                self.reenroll_application(app_name, sub_app.is_present("yes"));
+            }
+            ("respond", Some(sub_app)) => {
+                let app_name: &str = sub_app
+                    .value_of("APPNAME")
+                    .expect("Couldn't read APPNAME for 'respond' command");
+                let challenge: &str = sub_app
+                    .value_of("CHALLENGE")
+                    .expect("Couldn't read CHALLENGE for 'respond' command");
+                self.respond_to_challenge(app_name, challenge);
+            }
+            ("reveal-secret", Some(sub_app)) => {
+                let app_name: &str = sub_app
+                    .value_of("APPNAME")
+                    .expect("Couldn't read APPNAME for 'reveal-secret' command");
+                self.reveal_secret(app_name, sub_app.is_present("yes"));
+            }
+            ("protect", Some(sub_app)) => {
+                let app_name: &str = sub_app
+                    .value_of("APPNAME")
+                    .expect("Couldn't read APPNAME for 'protect' command");
+                self.protect_application(app_name, true);
+            }
+            ("unprotect", Some(sub_app)) => {
+                let app_name: &str = sub_app
+                    .value_of("APPNAME")
+                    .expect("Couldn't read APPNAME for 'unprotect' command");
+                self.protect_application(app_name, false);
+            }
+            ("unarchive", Some(sub_app)) => {
+                let app_name: &str = sub_app
+                    .value_of("APPNAME")
+                    .expect("Couldn't read APPNAME for 'unarchive' command");
+                self.archive_application(app_name, false);
+            }
+            ("unhide", Some(sub_app)) => {
+                let app_name: &str = sub_app
+                    .value_of("APPNAME")
+                    .expect("Couldn't read APPNAME for 'unhide' command");
+                self.hide_application(app_name, false);
+            }
+            ("delete", Some(sub_app)) => {
+                let app_name: &str = sub_app
+                    .value_of("APPNAME")
+                    .expect("Couldn't read APPNAME for 'delete' command");
+                self.delete_application(app_name);
+            }
+            ("rename", Some(sub_app)) => {
+                let app_name: &str = sub_app
+                    .value_of("APPNAME")
+                    .expect("Couldn't read APPNAME for 'rename' command");
+                let new_name: &str = sub_app
+                    .value_of("NEWNAME")
+                    .expect("Couldn't read NEWNAME for 'rename' command");
+                self.rename_application(app_name, new_name);
+            }
+            ("rename-all", Some(sub_app)) => {
+                let rule = if let Some(prefix) = sub_app.value_of("prefix") {
+                    Ok(RenameRule::Prefix(String::from(prefix)))
+                } else if let Some(suffix) = sub_app.value_of("suffix") {
+                    Ok(RenameRule::Suffix(String::from(suffix)))
+                } else {
+                    RenameRule::regex(
+                        sub_app.value_of("regex").unwrap(),
+                        sub_app.value_of("replace").unwrap_or(""),
+                    )
+                };
+                match rule {
+                    Ok(rule) => self.rename_applications(&Self::selection_filter(sub_app), &rule),
+                    Err(err) => fail(&err),
+                }
+            }
+            ("eradicate", Some(_)) => {
+                self.eradicate_database();
+            }
+            ("sync", Some(sub_app)) => match sub_app.subcommand() {
+                ("pull", Some(sub_sync)) => self.sync_database(sub_sync, false),
+                ("push", Some(sub_sync)) => self.sync_database(sub_sync, true),
+                _ => println!("{}", sub_app.usage()),
+            },
+            ("history", Some(_)) => self.show_history(),
+            ("export", Some(sub_app)) => {
+                let file: &str = sub_app
+                    .value_of("FILE")
+                    .expect("Couldn't read FILE for 'export' command");
+                self.export_applications(
+                    file,
+                    &Self::selection_filter(sub_app),
+                    sub_app.value_of("format").unwrap_or("uris"),
+                    sub_app.is_present("qr"),
+                    sub_app.is_present("mask-secrets"),
+                    &sub_app
+                        .values_of("recipient")
+                        .map(|values| values.map(String::from).collect::<Vec<_>>())
+                        .unwrap_or_default(),
+                );
+            }
+            ("import", Some(sub_app)) => {
+                let file: &str = sub_app
+                    .value_of("FILE")
+                    .expect("Couldn't read FILE for 'import' command");
+                let on_duplicate = sub_app
+                    .value_of("on-duplicate")
+                    .map(Self::parse_duplicate_resolution);
+                self.import_applications(
+                    file,
+                    sub_app.value_of("from").unwrap_or("otpauth"),
+                    sub_app.is_present("json"),
+                    on_duplicate,
+                );
+            }
+            ("paper-backup", Some(sub_app)) => {
+                let output: &str = sub_app
+                    .value_of("OUTPUT")
+                    .expect("Couldn't read OUTPUT for 'paper-backup' command");
+                self.create_paper_backup(output);
+            }
+            ("paper-restore", Some(_)) => self.restore_paper_backup(),
+            ("salvage", Some(sub_app)) => {
+                let output: &str = sub_app
+                    .value_of("OUTPUT")
+                    .expect("Couldn't read OUTPUT for 'salvage' command");
+                self.salvage_database(output);
+            }
+            ("decoy", Some(_)) => self.create_decoy(),
+            ("change-password", Some(_)) => self.change_password(),
+            ("require-two-persons", Some(_)) => self.require_two_persons(),
+            ("split-key", Some(sub_app)) => {
+                let number = |name: &str| sub_app.value_of(name).unwrap_or("").parse::<u8>();
+                match (number("shares"), number("threshold")) {
+                    (Ok(shares), Ok(threshold)) => self.split_key(shares, threshold),
+                    _ => say!("Numbers of shares and the threshold must be from 1 to 255"),
+                }
+            }
+            ("recover-key", Some(_)) => self.recover_key(),
+            ("doctor", Some(sub_app)) => self.check_database(sub_app.is_present("json")),
+            ("stats", Some(sub_app)) => {
+                match sub_app.value_of("oldest").unwrap_or("5").parse::<usize>() {
+                    Ok(oldest) => self.show_stats(oldest, sub_app.is_present("json")),
+                    Err(_) => say!("Number of the oldest generators must be a number"),
+                }
+            }
+            ("fix-permissions", Some(_)) => self.fix_permissions(),
+            ("git", Some(sub_app)) => self.manage_git_history(sub_app),
+            ("recovery", Some(sub_app)) => self.manage_recovery_codes(sub_app),
+            ("alias", Some(sub_app)) => self.manage_aliases(sub_app),
+            ("tags", Some(sub_app)) => self.manage_tags(sub_app),
+            ("snapshot", Some(sub_app)) => self.manage_snapshots(sub_app),
+            _ => {
+                self.show_dashboard();
+            }
+        }
+    }
+
+    /// Local paths are made usable as they are, so `~/vault.json` works even
+    /// when the shell didn't expand it. URLs are left for `storage_for`.
+    fn resolve_database_location(location: &str) -> String {
+        if location.contains("://") {
+            String::from(location)
+        } else {
+            expand_home(location).to_string_lossy().into_owned()
+        }
+    }
+
+    /// Replaces a symlinked local database with its target, so the database
+    /// isn't refused as a symbolic link.
+    fn follow_symlinks(location: String) -> String {
+        if location.contains("://") {
+            return location;
+        }
+        match std::fs::canonicalize(&location) {
+            Ok(path) => path.to_string_lossy().into_owned(),
+            Err(_) => location,
+        }
+    }
+
+    fn get_database_path() -> PathBuf {
+        let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+        home.join(Path::new(CONFIG_PATH))
+    }
+
+    /// Returns the application with the given name, or the one picked
+    /// interactively when there's no name or it's ambiguous. Hidden ones
+    /// need `reveal`.
+    fn choose_application<'a>(
+        &self,
+        rustotpony: &'a RusTOTPony<JsonDatabase>,
+        name: Option<&str>,
+        reveal: bool,
+    ) -> Option<&'a GenApp> {
+        let name = match name {
+            Some(name) => String::from(name),
+            None => self.pick_application(rustotpony, None)?,
+        };
+        let found = match rustotpony.get_application(&name) {
+            Err(err) if err.code() == ErrorCode::Ambiguous && Self::can_pick() => {
+                say!("{}", err);
+                let name = self.pick_application(rustotpony, Some(err.get_candidates()))?;
+                rustotpony.get_application(&name)
+            }
+            found => found,
+        };
+        match found {
+            Ok(app) if app.is_hidden() && !reveal => {
+                say!("Application '{}' is hidden, use --reveal to show it", name);
+                None
+            }
+            Ok(app) => Some(app),
+            Err(err) => {
+                fail(&err);
+                None
+            }
+        }
+    }
+
+    fn can_pick() -> bool {
+        cfg!(feature = "picker") && io::stdin().is_terminal() && io::stderr().is_terminal()
+    }
+
+    /// Lets the user choose one of visible applications in the terminal, or
+    /// one of the given ones.
+    #[cfg(feature = "picker")]
+    fn pick_application(
+        &self,
+        rustotpony: &RusTOTPony<JsonDatabase>,
+        among: Option<&[String]>,
+    ) -> Option<String> {
+        if !Self::can_pick() {
+            say!("Name of the generator is required when not run in a terminal");
+            return None;
+        }
+        let apps = match rustotpony.get_applications() {
+            Ok(apps) => apps,
+            Err(err) => {
+                fail(&err);
+                return None;
+            }
+        };
+        let mut items: Vec<picker::Item> = apps
+            .iter()
+            .filter(|(name, app)| match among {
+                Some(names) => names.contains(name),
+                None => !app.is_hidden() && !app.is_archived(),
+            })
+            .map(|(name, app)| picker::Item {
+                name: name.clone(),
+                label: if app.get_username().is_empty() {
+                    name.clone()
+                } else {
+                    format!("{} ({})", name, app.get_username())
+                },
+            })
+            .collect();
+        items.sort_by(|a, b| a.name.cmp(&b.name));
+        match picker::pick(&items) {
+            Ok(name) => name,
+            Err(err) => {
+                fail(&err);
+                None
+            }
+        }
+    }
+
+    #[cfg(not(feature = "picker"))]
+    fn pick_application(
+        &self,
+        _rustotpony: &RusTOTPony<JsonDatabase>,
+        _among: Option<&[String]>,
+    ) -> Option<String> {
+        say!("Name of the generator is required, totp was built without the interactive picker");
+        None
+    }
+
+    fn confirm(question: &str) -> bool {
+        print!("{} [y/N] ", question);
+        io::stdout().flush().expect("Couldn't flush stdout");
+        let mut answer = String::new();
+        io::stdin()
+            .read_line(&mut answer)
+            .expect("Couldn't read answer");
+        let answer = answer.trim().to_lowercase();
+        answer == "y" || answer == "yes"
+    }
+
+    fn parse_duplicate_resolution(value: &str) -> DuplicateResolution {
+        match value {
+            "rename" => DuplicateResolution::Rename,
+            "replace" => DuplicateResolution::Replace,
+            "keep" => DuplicateResolution::KeepBoth,
+            _ => DuplicateResolution::Skip,
+        }
+    }
+
+    /// Asks what to do with the new application `name` when `existing`
+    /// already has its secret. Skips it when the input ends.
+    fn ask_duplicate_resolution(name: &str, existing: &str) -> DuplicateResolution {
+        loop {
+            print!(
+                "{}",
+                text!(
+                    "'{}' has the same secret as '{}': [s]kip, [r]ename '{}' to '{}', re[p]lace it, [k]eep both? ",
+                    name,
+                    existing,
+                    existing,
+                    name
+                )
+            );
+            io::stdout().flush().expect("Couldn't flush stdout");
+            let mut answer = String::new();
+            match io::stdin().read_line(&mut answer) {
+                Ok(0) | Err(_) => return DuplicateResolution::Skip,
+                Ok(_) => {}
+            }
+            match answer.trim().to_lowercase().as_str() {
+                "s" | "skip" => return DuplicateResolution::Skip,
+                "r" | "rename" => return DuplicateResolution::Rename,
+                "p" | "replace" => return DuplicateResolution::Replace,
+                "k" | "keep" => return DuplicateResolution::KeepBoth,
+                _ => {}
+            }
+        }
+    }
+
+    fn get_passphrase(name: &str) -> String {
+        rpassword::prompt_password_stdout(&text!("Enter passphrase for '{}': ", name)).unwrap()
+    }
+
+    fn get_new_passphrase(name: &str) -> Option<String> {
+        match Self::prompt_new_password(&format!("new passphrase for '{}'", name), None) {
+            Ok(passphrase) => Some(passphrase),
+            Err(err) => {
+                fail_with(&format!("{}. Aborting…", err), &err);
+                None
+            }
+        }
+    }
+
+    /// Filter of the `--name` and `--tag` options.
+    fn selection_filter(args: &clap::ArgMatches) -> ExportFilter {
+        ExportFilter {
+            names: args
+                .values_of("name")
+                .map(|names| names.map(String::from).collect())
+                .unwrap_or_default(),
+            tags: args
+                .values_of("tag")
+                .map(|tags| tags.map(String::from).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    fn read_lines() -> Vec<String> {
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            match io::stdin().read_line(&mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) if line.trim().is_empty() => break,
+                Ok(_) => lines.push(String::from(line.trim())),
+            }
+        }
+        lines
+    }
+
+    fn report_saved(&self, app: &RusTOTPony<JsonDatabase>, message: &str) {
+        if !app.is_dry_run() {
+            println!("{}", message);
+            return;
+        }
+        say!("Dry run, the database wasn't changed. The following changes would be saved:");
+        for change in app.get_changes() {
+            match change {
+                Change::Created(name) => say!("  create '{}'", name),
+                Change::Renamed(name, newname) => say!("  rename '{}' to '{}'", name, newname),
+                Change::Deleted(name) => say!("  delete '{}'", name),
+                Change::Hidden(name) => say!("  hide '{}'", name),
+                Change::Unhidden(name) => say!("  unhide '{}'", name),
+                Change::Archived(name) => say!("  archive '{}'", name),
+                Change::Unarchived(name) => say!("  unarchive '{}'", name),
+                Change::Updated(name, setting) => say!("  change {} of '{}'", setting, name),
+                Change::Protected(name) => say!("  protect '{}'", name),
+                Change::Unprotected(name) => say!("  unprotect '{}'", name),
+                Change::SecretRevealed(name) => say!("  reveal secret of '{}'", name),
+                Change::RecoveryCodesAdded(name, count) => {
+                    say!("  add {} recovery codes to '{}'", count, name)
+                }
+                Change::RecoveryCodeUsed(name) => say!("  use recovery code of '{}'", name),
+            }
+        }
+    }
+}
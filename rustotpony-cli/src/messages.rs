@@ -0,0 +1,55 @@
+use rustotpony_core::{locale_from_env, Catalog, Error};
+use std::path::Path;
+use std::sync::OnceLock;
+
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+/// Formats a message in the language of the user, like `format!` with
+/// `{}` placeholders only.
+macro_rules! text {
+    ($template:literal) => {
+        $crate::messages::catalog().format($template, &[])
+    };
+    ($template:literal, $($arg:expr),+ $(,)?) => {
+        $crate::messages::catalog().format($template, &[$(&$arg),+])
+    };
+}
+
+/// Prints a message in the language of the user, like `println!`.
+macro_rules! say {
+    () => {
+        println!()
+    };
+    ($($arg:tt)+) => {
+        println!("{}", text!($($arg)+))
+    };
+}
+
+/// Prints a message in the language of the user on stderr.
+macro_rules! esay {
+    ($($arg:tt)+) => {
+        eprintln!("{}", text!($($arg)+))
+    };
+}
+
+/// Loads the catalog of the locale set in the environment from the folder,
+/// messages stay in English without one. Returns a problem with the
+/// catalog, if there is one.
+pub fn init(dir: &Path) -> Option<Error> {
+    let locale = locale_from_env()?;
+    let (catalog, problem) = match Catalog::load(dir, &locale) {
+        Ok(catalog) => (catalog.unwrap_or_default(), None),
+        Err(err) => (Catalog::new(), Some(err)),
+    };
+    let _ = CATALOG.set(catalog);
+    problem
+}
+
+pub fn catalog() -> &'static Catalog {
+    CATALOG.get_or_init(Catalog::new)
+}
+
+/// Translation of a message without varying parts, e.g. help of an option.
+pub fn tr(message: &'static str) -> &'static str {
+    catalog().lookup(message)
+}
@@ -0,0 +1,190 @@
+use crossterm::cursor::{MoveToColumn, MoveUp};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, Clear, ClearType};
+use crossterm::{execute, queue};
+use rustotpony_core::{Error, ErrorCode};
+use std::io::{self, Write};
+use unicode_width::UnicodeWidthStr;
+
+// Number of matches shown below the prompt
+const VISIBLE: usize = 10;
+const PROMPT: &str = "> ";
+
+/// Entry to choose from.
+pub struct Item {
+    /// Returned when the item is chosen
+    pub name: String,
+    /// Shown in the list and matched against the typed text
+    pub label: String,
+}
+
+/// Lets the user choose an item by typing a part of its label, letters of
+/// the query have to appear in the label in the same order but not
+/// necessarily next to each other. Arrow keys move the selection, Enter
+/// chooses, Esc or Ctrl-C cancel. The list is drawn on stderr.
+pub fn pick(items: &[Item]) -> Result<Option<String>, Error> {
+    let _raw = RawMode::enable()?;
+    let mut stderr = io::stderr();
+    let mut query = String::new();
+    let mut selected = 0;
+    loop {
+        let matches = filter(items, &query);
+        selected = selected.min(matches.len().saturating_sub(1));
+        draw(&mut stderr, &query, &matches, items.len(), selected)
+            .map_err(|err| Error::new(ErrorCode::Io, err.to_string()))?;
+        let key = match event::read().map_err(|err| Error::new(ErrorCode::Io, err.to_string()))? {
+            Event::Key(key) if key.kind != KeyEventKind::Release => key,
+            _ => continue,
+        };
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => break,
+            KeyCode::Char('c') | KeyCode::Char('d') if ctrl => break,
+            KeyCode::Enter => {
+                clear(&mut stderr).map_err(|err| Error::new(ErrorCode::Io, err.to_string()))?;
+                return Ok(matches.get(selected).map(|item| item.name.clone()));
+            }
+            KeyCode::Up => selected = selected.saturating_sub(1),
+            KeyCode::Char('p') if ctrl => selected = selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Tab => selected += 1,
+            KeyCode::Char('n') if ctrl => selected += 1,
+            KeyCode::Backspace => {
+                query.pop();
+                selected = 0;
+            }
+            KeyCode::Char('u') if ctrl => {
+                query.clear();
+                selected = 0;
+            }
+            KeyCode::Char(c) if !ctrl => {
+                query.push(c);
+                selected = 0;
+            }
+            _ => {}
+        }
+    }
+    clear(&mut stderr).map_err(|err| Error::new(ErrorCode::Io, err.to_string()))?;
+    Ok(None)
+}
+
+/// Items matching the query, best matches first.
+fn filter<'a>(items: &'a [Item], query: &str) -> Vec<&'a Item> {
+    let mut scored: Vec<(i64, &Item)> = items
+        .iter()
+        .filter_map(|item| score(query, &item.label).map(|score| (score, item)))
+        .collect();
+    scored.sort_by(|(a_score, a), (b_score, b)| {
+        b_score.cmp(a_score).then_with(|| a.label.cmp(&b.label))
+    });
+    scored.into_iter().map(|(_, item)| item).collect()
+}
+
+/// Scores the text if all characters of the query appear in it in order,
+/// ignoring case. Runs of adjacent characters and characters starting words
+/// score higher, characters skipped in between lower the score.
+fn score(query: &str, text: &str) -> Option<i64> {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous: Option<usize> = None;
+    for c in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = position + text[position..].iter().position(|&t| t == c)?;
+        score += 1;
+        match previous {
+            Some(previous) if previous + 1 == found => score += 5,
+            Some(previous) => score -= (found - previous - 1).min(5) as i64,
+            None => score -= found.min(5) as i64,
+        }
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        previous = Some(found);
+        position = found + 1;
+    }
+    Some(score)
+}
+
+fn draw(
+    out: &mut impl Write,
+    query: &str,
+    matches: &[&Item],
+    total: usize,
+    selected: usize,
+) -> io::Result<()> {
+    let columns = match terminal::size() {
+        Ok((columns, _)) if columns > 0 => columns as usize,
+        _ => 80,
+    };
+    queue!(out, MoveToColumn(0), Clear(ClearType::FromCursorDown))?;
+    queue!(out, Print(PROMPT), Print(query))?;
+    // Keep the selection visible when it's below the first page
+    let first = (selected + 1).saturating_sub(VISIBLE);
+    let shown: Vec<&&Item> = matches.iter().skip(first).take(VISIBLE).collect();
+    for (idx, item) in shown.iter().enumerate() {
+        let label = truncate(&item.label, columns.saturating_sub(3));
+        queue!(out, Print("\r\n"))?;
+        if first + idx == selected {
+            queue!(
+                out,
+                SetAttribute(Attribute::Reverse),
+                Print(format!("> {}", label)),
+                SetAttribute(Attribute::Reset)
+            )?;
+        } else {
+            queue!(out, Print(format!("  {}", label)))?;
+        }
+    }
+    queue!(
+        out,
+        Print("\r\n"),
+        Print(format!("  {}/{}", matches.len(), total))
+    )?;
+    queue!(
+        out,
+        MoveUp(shown.len() as u16 + 1),
+        MoveToColumn((PROMPT.width() + query.width()) as u16)
+    )?;
+    out.flush()
+}
+
+fn clear(out: &mut impl Write) -> io::Result<()> {
+    execute!(out, MoveToColumn(0), Clear(ClearType::FromCursorDown))
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    if text.width() <= width {
+        return String::from(text);
+    }
+    let mut result = String::new();
+    for c in text.chars() {
+        if result.width() + c.to_string().width() >= width {
+            break;
+        }
+        result.push(c);
+    }
+    result.push('…');
+    result
+}
+
+/// Keeps the terminal in raw mode, so keys are read as they are pressed,
+/// and restores it when dropped.
+struct RawMode;
+
+impl RawMode {
+    fn enable() -> Result<RawMode, Error> {
+        terminal::enable_raw_mode().map_err(|err| {
+            Error::new(
+                ErrorCode::Io,
+                format!("Couldn't switch the terminal to raw mode: {}", err),
+            )
+        })?;
+        Ok(RawMode)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+    }
+}
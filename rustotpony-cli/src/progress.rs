@@ -0,0 +1,75 @@
+use rustotpony_core::{Progress, Task};
+use std::io::{self, IsTerminal, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Quick tasks would only flicker
+const SHOW_AFTER: Duration = Duration::from_millis(300);
+const WIDTH: usize = 20;
+
+/// Progress bar drawn over a single line of stderr, only in a terminal.
+/// It's cleared when the task is finished, so the output stays as it was.
+pub struct ProgressBar {
+    enabled: bool,
+    // Task being drawn, when it was started and whether the bar is shown
+    state: Mutex<Option<(Task, Instant, bool)>>,
+}
+
+impl ProgressBar {
+    pub fn new() -> ProgressBar {
+        ProgressBar {
+            enabled: io::stderr().is_terminal(),
+            state: Mutex::new(None),
+        }
+    }
+
+    pub fn show(&self, progress: &Progress) {
+        if !self.enabled {
+            return;
+        }
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        let (started, shown) = match *state {
+            Some((task, started, shown)) if task == progress.task => (started, shown),
+            _ => (Instant::now(), false),
+        };
+        if progress.is_finished() {
+            if shown {
+                eprint!("\r{:width$}\r", "", width = WIDTH + 40);
+                io::stderr().flush().ok();
+            }
+            *state = None;
+            return;
+        }
+        if started.elapsed() < SHOW_AFTER {
+            *state = Some((progress.task, started, shown));
+            return;
+        }
+        let line = match progress.fraction() {
+            Some(fraction) => {
+                let filled = (fraction * WIDTH as f64) as usize;
+                format!(
+                    "[{:-<width$}] {:>3}%",
+                    "=".repeat(filled),
+                    (fraction * 100.0) as u32,
+                    width = WIDTH
+                )
+            }
+            None => format!("[{:-<width$}]", "", width = WIDTH),
+        };
+        eprint!("\r{} {}", line, label(progress.task));
+        io::stderr().flush().ok();
+        *state = Some((progress.task, started, true));
+    }
+}
+
+fn label(task: Task) -> &'static str {
+    match task {
+        Task::KeyDerivation => "deriving key…   ",
+        Task::Decryption => "decrypting…     ",
+        Task::Encryption => "encrypting…     ",
+        Task::Reencryption => "re-encrypting…  ",
+        Task::Import => "importing…      ",
+        Task::Download => "downloading…    ",
+        Task::Upload => "uploading…      ",
+    }
+}
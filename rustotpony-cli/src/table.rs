@@ -0,0 +1,69 @@
+use unicode_width::UnicodeWidthStr;
+
+/// Text table with a header row. Every column is as wide as its widest cell
+/// on the terminal, so names with wide characters (e.g. CJK or emoji) don't
+/// shift the borders.
+pub struct Table {
+    headers: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(headers: &[&str]) -> Table {
+        Table {
+            headers: headers.iter().map(|header| cell(header)).collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    /// Adds a row, it's expected to have a cell for every column.
+    pub fn push_row(&mut self, row: Vec<String>) {
+        self.rows.push(row.iter().map(|text| cell(text)).collect());
+    }
+
+    pub fn render(&self) -> String {
+        let widths: Vec<usize> = self
+            .headers
+            .iter()
+            .enumerate()
+            .map(|(idx, header)| {
+                self.rows
+                    .iter()
+                    .filter_map(|row| row.get(idx))
+                    .fold(header.width(), |max, text| max.max(text.width()))
+            })
+            .collect();
+        let delimiter = format!(
+            "+{}+",
+            widths
+                .iter()
+                .map(|width| "-".repeat(width + 2))
+                .collect::<Vec<String>>()
+                .join("+")
+        );
+        let line = |row: &[String]| {
+            let cells: Vec<String> = widths
+                .iter()
+                .enumerate()
+                .map(|(idx, width)| pad(row.get(idx).map_or("", String::as_str), *width))
+                .collect();
+            format!("| {} |", cells.join(" | "))
+        };
+        let mut lines = vec![delimiter.clone(), line(&self.headers), delimiter.clone()];
+        lines.extend(self.rows.iter().map(|row| line(row)));
+        lines.push(delimiter);
+        lines.join("\n")
+    }
+}
+
+// Control characters would break the layout, they are shown as `?`
+fn cell(text: &str) -> String {
+    text.chars()
+        .map(|c| if c.is_control() { '?' } else { c })
+        .collect()
+}
+
+/// Pads the text with spaces to the width it takes on the terminal.
+pub fn pad(text: &str, width: usize) -> String {
+    format!("{}{}", text, " ".repeat(width.saturating_sub(text.width())))
+}
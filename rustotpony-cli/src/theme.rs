@@ -0,0 +1,122 @@
+use std::io::{self, IsTerminal};
+
+/// Environment variable overriding colors, e.g. `countdown=34:warning=1;33`.
+/// Every color is a list of SGR parameters of ANSI escape sequences.
+pub const COLORS_ENV: &str = "RUSTOTPONY_COLORS";
+const NO_COLOR_ENV: &str = "NO_COLOR";
+
+/// Part of the output which gets its own color.
+#[derive(Debug, Clone, Copy)]
+pub enum Role {
+    /// Progress bar of the dashboard
+    Countdown,
+    /// Progress bar during the last seconds of a code
+    Expiring,
+    /// Generated codes
+    Code,
+    Warning,
+}
+
+const ROLES: &[(&str, Role)] = &[
+    ("countdown", Role::Countdown),
+    ("expiring", Role::Expiring),
+    ("code", Role::Code),
+    ("warning", Role::Warning),
+];
+
+/// Colors of the output. Colors are off when `NO_COLOR` is set to anything
+/// but an empty string, with `--no-color` and when stdout isn't a terminal.
+pub struct Theme {
+    enabled: bool,
+    countdown: String,
+    expiring: String,
+    code: String,
+    warning: String,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            enabled: true,
+            countdown: String::from("32"),
+            expiring: String::from("31"),
+            code: String::from("1"),
+            warning: String::from("33"),
+        }
+    }
+}
+
+impl Theme {
+    /// Builds the theme from the environment. Returns it along with problems
+    /// of `RUSTOTPONY_COLORS`, entries with problems are ignored.
+    pub fn from_env(no_color: bool) -> (Theme, Vec<String>) {
+        let mut theme = Theme {
+            enabled: !no_color
+                && std::env::var_os(NO_COLOR_ENV).is_none_or(|value| value.is_empty())
+                && io::stdout().is_terminal(),
+            ..Theme::default()
+        };
+        let problems = match std::env::var(COLORS_ENV) {
+            Ok(colors) => theme.apply(&colors),
+            Err(_) => Vec::new(),
+        };
+        (theme, problems)
+    }
+
+    /// Wraps the text into escape sequences of the role's color.
+    pub fn paint(&self, role: Role, text: &str) -> String {
+        let color = self.color(role);
+        if !self.enabled || color.is_empty() {
+            return String::from(text);
+        }
+        format!("\x1B[{}m{}\x1B[0m", color, text)
+    }
+
+    /// Formats a warning for printing.
+    pub fn warning(&self, message: &str) -> String {
+        self.paint(Role::Warning, &format!("Warning: {}", message))
+    }
+
+    fn apply(&mut self, colors: &str) -> Vec<String> {
+        let mut problems = Vec::new();
+        for entry in colors.split(':').filter(|entry| !entry.trim().is_empty()) {
+            let (name, color) = entry.split_once('=').unwrap_or((entry, ""));
+            let role = match ROLES.iter().find(|(role, _)| *role == name.trim()) {
+                Some((_, role)) => *role,
+                None => {
+                    problems.push(format!("{} has an unknown entry '{}'", COLORS_ENV, name));
+                    continue;
+                }
+            };
+            // An empty color turns the role's color off
+            let color = color.trim();
+            if !color.chars().all(|c| c.is_ascii_digit() || c == ';') {
+                problems.push(format!(
+                    "{} has an invalid color '{}' for {}",
+                    COLORS_ENV, color, name
+                ));
+                continue;
+            }
+            *self.color_mut(role) = String::from(color);
+        }
+        problems
+    }
+
+    fn color(&self, role: Role) -> &str {
+        match role {
+            Role::Countdown => &self.countdown,
+            Role::Expiring => &self.expiring,
+            Role::Code => &self.code,
+            Role::Warning => &self.warning,
+        }
+    }
+
+    fn color_mut(&mut self, role: Role) -> &mut String {
+        match role {
+            Role::Countdown => &mut self.countdown,
+            Role::Expiring => &mut self.expiring,
+            Role::Code => &mut self.code,
+            Role::Warning => &mut self.warning,
+        }
+    }
+}
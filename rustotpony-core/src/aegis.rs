@@ -0,0 +1,352 @@
+use serde_derive::Serialize;
+
+use crate::crypto::{self, Hash};
+use crate::errors::{Error, ErrorCode};
+use crate::export::is_steam;
+use crate::random;
+use crate::{ExportReport, GenApp, Icon};
+
+// Parameters of scrypt Aegis uses for password slots
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+const KEY_SIZE: usize = 32;
+const NONCE_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+// Icon types Aegis shows
+const ICON_TYPES: &[&str] = &["image/png", "image/jpeg", "image/svg+xml"];
+
+#[derive(Serialize)]
+struct Vault {
+    version: u32,
+    header: Header,
+    /// Encrypted `Content`, base64 encoded
+    db: String,
+}
+
+#[derive(Serialize)]
+struct Header {
+    slots: Vec<Slot>,
+    params: KeyParams,
+}
+
+/// Master key encrypted with a key derived from the password
+#[derive(Serialize)]
+struct Slot {
+    #[serde(rename = "type")]
+    kind: u8,
+    uuid: String,
+    key: String,
+    key_params: KeyParams,
+    n: u32,
+    r: u32,
+    p: u32,
+    salt: String,
+    repaired: bool,
+}
+
+#[derive(Serialize)]
+struct KeyParams {
+    nonce: String,
+    tag: String,
+}
+
+#[derive(Serialize)]
+struct Content {
+    version: u32,
+    entries: Vec<Entry>,
+    groups: Vec<Group>,
+}
+
+#[derive(Serialize)]
+struct Entry {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    uuid: String,
+    name: String,
+    issuer: String,
+    note: String,
+    favorite: bool,
+    icon: Option<String>,
+    icon_mime: Option<String>,
+    icon_hash: Option<String>,
+    info: Info,
+    groups: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Info {
+    secret: String,
+    algo: &'static str,
+    digits: usize,
+    period: u64,
+}
+
+#[derive(Serialize)]
+struct Group {
+    uuid: String,
+    name: String,
+}
+
+impl ExportReport {
+    /// Writes the exported applications as an encrypted backup of the Aegis
+    /// authenticator, which restores it with the password. Tags become
+    /// Aegis groups, images of icons are kept. Fails if an application shows
+    /// codes Aegis can't generate.
+    pub fn to_aegis(&self, password: &str) -> Result<String, Error> {
+        let mut tags: Vec<&String> = self
+            .applications
+            .iter()
+            .flat_map(|app| app.get_tags())
+            .collect();
+        tags.sort();
+        tags.dedup();
+        let groups: Vec<Group> = tags
+            .into_iter()
+            .map(|tag| Group {
+                uuid: uuid(),
+                name: tag.clone(),
+            })
+            .collect();
+        let entries = self
+            .applications
+            .iter()
+            .map(|app| entry(app, &groups))
+            .collect::<Result<Vec<Entry>, Error>>()?;
+        let content = Content {
+            version: 3,
+            entries,
+            groups,
+        };
+        let data = serde_json::to_vec(&content).expect("Couldn't serialize Aegis entries");
+        seal(&data, password)
+    }
+}
+
+fn entry(app: &GenApp, groups: &[Group]) -> Result<Entry, Error> {
+    let kind = if is_steam(app, "Aegis")? {
+        "steam"
+    } else {
+        "totp"
+    };
+    let (icon, icon_mime, icon_hash) = match app.get_icon() {
+        Some(Icon::Image { mime, data }) if ICON_TYPES.contains(&mime.as_str()) => {
+            let bytes = base64::decode(data)
+                .map_err(|err| Error::new(ErrorCode::Damaged, err.to_string()))?;
+            (
+                Some(data.clone()),
+                Some(mime.clone()),
+                Some(Hash::Sha256.hex_digest(&bytes)),
+            )
+        }
+        _ => (None, None, None),
+    };
+    Ok(Entry {
+        kind,
+        uuid: uuid(),
+        // The same way as in otpauth URIs, so importing the backup back gives
+        // the same names
+        name: String::from(app.get_username()),
+        issuer: String::from(app.get_name()),
+        note: String::new(),
+        favorite: false,
+        icon,
+        icon_mime,
+        icon_hash,
+        info: Info {
+            secret: String::from(app.get_secret()),
+            algo: "SHA1",
+            digits: app.get_format().get_length(),
+            period: app.get_time_step().unwrap_or(30),
+        },
+        groups: groups
+            .iter()
+            .filter(|group| app.get_tags().contains(&group.name))
+            .map(|group| group.uuid.clone())
+            .collect(),
+    })
+}
+
+// Encrypts the data with a random master key, which is encrypted with the
+// key derived from the password
+fn seal(data: &[u8], password: &str) -> Result<String, Error> {
+    let mut master_key = [0; KEY_SIZE];
+    let mut salt = [0; KEY_SIZE];
+    let mut key_nonce = [0; NONCE_SIZE];
+    let mut db_nonce = [0; NONCE_SIZE];
+    random::fill(&mut master_key);
+    random::fill(&mut salt);
+    random::fill(&mut key_nonce);
+    random::fill(&mut db_nonce);
+
+    let params = scrypt::Params::new(SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P).map_err(|err| {
+        Error::new(
+            ErrorCode::InvalidArgument,
+            format!("Invalid scrypt parameters: {}", err),
+        )
+    })?;
+    let mut password_key = [0; KEY_SIZE];
+    scrypt::scrypt(password.as_bytes(), &salt, &params, &mut password_key).map_err(|err| {
+        Error::new(
+            ErrorCode::Other,
+            format!("Couldn't derive the key: {}", err),
+        )
+    })?;
+    let (key, key_tag) = split_tag(crypto::encrypt_gcm(&master_key, &password_key, &key_nonce)?);
+    let (db, db_tag) = split_tag(crypto::encrypt_gcm(data, &master_key, &db_nonce)?);
+
+    let vault = Vault {
+        version: 1,
+        header: Header {
+            slots: vec![Slot {
+                kind: 1,
+                uuid: uuid(),
+                key: crypto::to_hex(&key),
+                key_params: KeyParams {
+                    nonce: crypto::to_hex(&key_nonce),
+                    tag: crypto::to_hex(&key_tag),
+                },
+                n: 1 << SCRYPT_LOG_N,
+                r: SCRYPT_R,
+                p: SCRYPT_P,
+                salt: crypto::to_hex(&salt),
+                repaired: true,
+            }],
+            params: KeyParams {
+                nonce: crypto::to_hex(&db_nonce),
+                tag: crypto::to_hex(&db_tag),
+            },
+        },
+        db: base64::encode(db),
+    };
+    Ok(serde_json::to_string_pretty(&vault).expect("Couldn't serialize Aegis vault"))
+}
+
+fn split_tag(mut encrypted: Vec<u8>) -> (Vec<u8>, Vec<u8>) {
+    let tag = encrypted.split_off(encrypted.len() - TAG_SIZE);
+    (encrypted, tag)
+}
+
+/// Random UUID of version 4.
+fn uuid() -> String {
+    let mut bytes = [0u8; 16];
+    random::fill(&mut bytes);
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = crypto::to_hex(&bytes);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CodeFormat, Database, DatabaseContent, RusTOTPony, STEAM_ALPHABET};
+
+    struct Memory;
+
+    impl Database for Memory {
+        fn get_content(&self) -> Result<DatabaseContent, Error> {
+            Ok(DatabaseContent::default())
+        }
+
+        fn save_content(&self, _content: &DatabaseContent) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    fn report(steam: CodeFormat) -> ExportReport {
+        let mut totpony = RusTOTPony::with_content(Memory, DatabaseContent::default());
+        totpony
+            .create_application("GitHub", "octocat", "JBSWY3DPEHPK3PXP")
+            .unwrap();
+        totpony
+            .create_application("Steam", "gaben", "GEZDGNBVGY3TQOJQ")
+            .unwrap();
+        totpony.set_code_format("Steam", steam).unwrap();
+        totpony
+            .tag_application("GitHub", &[String::from("work")])
+            .unwrap();
+        let applications = ["GitHub", "Steam"]
+            .iter()
+            .map(|name| totpony.get_application(name).unwrap().clone())
+            .collect();
+        ExportReport {
+            applications,
+            skipped: Vec::new(),
+        }
+    }
+
+    fn from_hex(value: &serde_json::Value) -> Vec<u8> {
+        let hex = value.as_str().unwrap();
+        (0..hex.len())
+            .step_by(2)
+            .map(|idx| u8::from_str_radix(&hex[idx..idx + 2], 16).unwrap())
+            .collect()
+    }
+
+    // Decrypts the backup the way Aegis does
+    fn open(backup: &str, password: &str) -> Result<serde_json::Value, Error> {
+        let vault: serde_json::Value = serde_json::from_str(backup).unwrap();
+        let slot = &vault["header"]["slots"][0];
+        let number = |name: &str| slot[name].as_u64().unwrap() as u32;
+        let params =
+            scrypt::Params::new(number("n").trailing_zeros() as u8, number("r"), number("p"))
+                .unwrap();
+        let mut password_key = [0; KEY_SIZE];
+        scrypt::scrypt(
+            password.as_bytes(),
+            &from_hex(&slot["salt"]),
+            &params,
+            &mut password_key,
+        )
+        .unwrap();
+        let key = [from_hex(&slot["key"]), from_hex(&slot["key_params"]["tag"])].concat();
+        let master_key =
+            crypto::decrypt_gcm(&key, &password_key, &from_hex(&slot["key_params"]["nonce"]))?;
+        let params = &vault["header"]["params"];
+        let db = base64::decode(vault["db"].as_str().unwrap()).unwrap();
+        let content = crypto::decrypt_gcm(
+            &[db, from_hex(&params["tag"])].concat(),
+            &master_key,
+            &from_hex(&params["nonce"]),
+        )?;
+        Ok(serde_json::from_slice(&content).unwrap())
+    }
+
+    #[test]
+    fn backup_survives_round_trip() {
+        let steam = CodeFormat::with_alphabet(STEAM_ALPHABET, 5).unwrap();
+        let backup = report(steam).to_aegis("correct horse").unwrap();
+        let content = open(&backup, "correct horse").unwrap();
+        let entries = content["entries"].as_array().unwrap();
+        assert_eq!(entries[0]["type"], "totp");
+        assert_eq!(entries[0]["issuer"], "GitHub");
+        assert_eq!(entries[0]["name"], "octocat");
+        assert_eq!(entries[0]["info"]["secret"], "JBSWY3DPEHPK3PXP");
+        assert_eq!(entries[0]["groups"][0], content["groups"][0]["uuid"]);
+        assert_eq!(content["groups"][0]["name"], "work");
+        assert_eq!(entries[1]["type"], "steam");
+        assert_eq!(entries[1]["info"]["digits"], 5);
+    }
+
+    #[test]
+    fn wrong_password_is_rejected() {
+        let steam = CodeFormat::with_alphabet(STEAM_ALPHABET, 5).unwrap();
+        let backup = report(steam).to_aegis("correct horse").unwrap();
+        assert!(open(&backup, "wrong horse").is_err());
+    }
+
+    #[test]
+    fn custom_alphabet_is_unsupported() {
+        let custom = CodeFormat::with_alphabet("ABCDEFGH", 6).unwrap();
+        let err = report(custom).to_aegis("correct horse").unwrap_err();
+        assert_eq!(err.code(), ErrorCode::Unsupported);
+    }
+}
@@ -0,0 +1,99 @@
+use crate::errors::{Error, ErrorCode};
+use crate::names;
+use crate::{Change, Database, Operation, RusTOTPony};
+
+impl<DB: Database> RusTOTPony<DB> {
+    /// Adds a short name the application can be referred to by wherever a
+    /// name is taken, e.g. "aws" for "Amazon Web Services root". Aliases
+    /// can't take names of applications or aliases of other applications.
+    pub fn add_alias(&mut self, name: &str, alias: &str) -> Result<(), Error> {
+        let name = &self.resolve_name(name)?;
+        let alias = names::normalize(alias.trim());
+        if alias.is_empty() {
+            return Err(Error::new(
+                ErrorCode::InvalidArgument,
+                "Alias can't be empty",
+            ));
+        }
+        if let Some(taken) = self.find_taken_name(&alias)? {
+            return Err(Error::new(
+                ErrorCode::AlreadyExists,
+                format!("'{}' is already a name of an application", taken),
+            ));
+        }
+        if let Some(owner) = self.find_alias_owner(&alias)? {
+            return Err(Error::new(
+                ErrorCode::AlreadyExists,
+                format!("Alias '{}' is already used for '{}'", alias, owner),
+            ));
+        }
+        let app = match self.content_mut()?.applications.get_mut(name) {
+            Some(app) => app,
+            None => {
+                return Err(Error::new(
+                    ErrorCode::NotFound,
+                    format!("Application '{}' wasn't found", name),
+                ))
+            }
+        };
+        app.aliases.push(alias);
+        app.aliases.sort();
+        self.changed_aliases(name);
+        Ok(())
+    }
+
+    /// Removes the alias and returns the name of the application it
+    /// referred to.
+    pub fn remove_alias(&mut self, alias: &str) -> Result<String, Error> {
+        let name = self.find_alias_owner(alias)?.ok_or_else(|| {
+            Error::new(
+                ErrorCode::NotFound,
+                format!("Alias '{}' wasn't found", alias),
+            )
+        })?;
+        let key = self.name_key(alias);
+        let mut aliases = self.content()?.applications[&name].aliases.clone();
+        aliases.retain(|existing| self.name_key(existing) != key);
+        if let Some(app) = self.loaded_mut().applications.get_mut(&name) {
+            app.aliases = aliases;
+        }
+        self.changed_aliases(&name);
+        Ok(name)
+    }
+
+    /// Returns all aliases with names of their applications, sorted by alias.
+    pub fn get_aliases(&self) -> Result<Vec<(String, String)>, Error> {
+        let mut aliases: Vec<(String, String)> = self
+            .content()?
+            .applications
+            .iter()
+            .flat_map(|(name, app)| {
+                app.aliases
+                    .iter()
+                    .map(move |alias| (alias.clone(), name.clone()))
+            })
+            .collect();
+        aliases.sort();
+        Ok(aliases)
+    }
+
+    /// Returns the name of the application which has the alias, matched
+    /// the same way as names.
+    pub fn find_alias_owner(&self, alias: &str) -> Result<Option<String>, Error> {
+        let key = self.name_key(alias);
+        Ok(self
+            .content()?
+            .applications
+            .iter()
+            .filter(|(_, app)| app.aliases.iter().any(|a| self.name_key(a) == key))
+            .map(|(name, _)| name)
+            .min()
+            .cloned())
+    }
+
+    fn changed_aliases(&mut self, name: &str) {
+        self.mark_dirty(name);
+        self.record(Operation::Update, name, Some("aliases"));
+        self.push_change(Change::Updated(String::from(name), String::from("aliases")));
+    }
+}
@@ -0,0 +1,166 @@
+use pbkdf2::pbkdf2_hmac;
+use serde_derive::Serialize;
+use sha1::Sha1;
+
+use crate::crypto;
+use crate::errors::Error;
+use crate::export::is_steam;
+use crate::random;
+use crate::{ExportReport, GenApp};
+
+const KEY_SIZE: usize = 32;
+const SALT_SIZE: usize = 12;
+const NONCE_SIZE: usize = 12;
+// andOTP picks the number of PBKDF2 iterations at random from this range
+const MIN_ITERATIONS: u32 = 140_000;
+const MAX_ITERATIONS: u32 = 160_000;
+
+#[derive(Serialize)]
+struct Entry {
+    secret: String,
+    issuer: String,
+    label: String,
+    digits: usize,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    algorithm: &'static str,
+    thumbnail: &'static str,
+    last_used: u64,
+    used_frequency: u64,
+    period: u64,
+    tags: Vec<String>,
+}
+
+impl ExportReport {
+    /// Writes the exported applications as an encrypted backup of andOTP,
+    /// which restores it with the password. Tags are kept. Fails if an
+    /// application shows codes andOTP can't generate.
+    pub fn to_andotp(&self, password: &str) -> Result<Vec<u8>, Error> {
+        let entries = self
+            .applications
+            .iter()
+            .map(entry)
+            .collect::<Result<Vec<Entry>, Error>>()?;
+        let data = serde_json::to_vec(&entries).expect("Couldn't serialize andOTP entries");
+        seal(&data, password)
+    }
+}
+
+fn entry(app: &GenApp) -> Result<Entry, Error> {
+    Ok(Entry {
+        secret: String::from(app.get_secret()),
+        // The same way as in otpauth URIs, so importing the backup back gives
+        // the same names
+        issuer: String::from(app.get_name()),
+        label: String::from(app.get_username()),
+        digits: app.get_format().get_length(),
+        kind: if is_steam(app, "andOTP")? {
+            "STEAM"
+        } else {
+            "TOTP"
+        },
+        algorithm: "SHA1",
+        thumbnail: "Default",
+        last_used: 0,
+        used_frequency: 0,
+        period: app.get_time_step().unwrap_or(30),
+        tags: app.get_tags().to_vec(),
+    })
+}
+
+// The file is the number of iterations as a big-endian 32-bit integer, the
+// salt, the nonce and the data encrypted with the key derived from the
+// password
+fn seal(data: &[u8], password: &str) -> Result<Vec<u8>, Error> {
+    let iterations = random::number(MIN_ITERATIONS..=MAX_ITERATIONS);
+    let mut salt = [0; SALT_SIZE];
+    let mut nonce = [0; NONCE_SIZE];
+    random::fill(&mut salt);
+    random::fill(&mut nonce);
+    let mut key = [0; KEY_SIZE];
+    pbkdf2_hmac::<Sha1>(password.as_bytes(), &salt, iterations, &mut key);
+    let encrypted = crypto::encrypt_gcm(data, &key, &nonce)?;
+    Ok([&iterations.to_be_bytes()[..], &salt, &nonce, &encrypted].concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::ErrorCode;
+    use crate::{CodeFormat, Database, DatabaseContent, RusTOTPony, STEAM_ALPHABET};
+
+    struct Memory;
+
+    impl Database for Memory {
+        fn get_content(&self) -> Result<DatabaseContent, Error> {
+            Ok(DatabaseContent::default())
+        }
+
+        fn save_content(&self, _content: &DatabaseContent) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    fn report(steam: CodeFormat) -> ExportReport {
+        let mut totpony = RusTOTPony::with_content(Memory, DatabaseContent::default());
+        totpony
+            .create_application("GitHub", "octocat", "JBSWY3DPEHPK3PXP")
+            .unwrap();
+        totpony
+            .create_application("Steam", "gaben", "GEZDGNBVGY3TQOJQ")
+            .unwrap();
+        totpony.set_code_format("Steam", steam).unwrap();
+        totpony
+            .tag_application("GitHub", &[String::from("work")])
+            .unwrap();
+        let applications = ["GitHub", "Steam"]
+            .iter()
+            .map(|name| totpony.get_application(name).unwrap().clone())
+            .collect();
+        ExportReport {
+            applications,
+            skipped: Vec::new(),
+        }
+    }
+
+    // Decrypts the backup the way andOTP does
+    fn open(backup: &[u8], password: &str) -> Result<serde_json::Value, Error> {
+        let (iterations, rest) = backup.split_at(4);
+        let (salt, rest) = rest.split_at(SALT_SIZE);
+        let (nonce, encrypted) = rest.split_at(NONCE_SIZE);
+        let iterations = u32::from_be_bytes(iterations.try_into().unwrap());
+        assert!((MIN_ITERATIONS..=MAX_ITERATIONS).contains(&iterations));
+        let mut key = [0; KEY_SIZE];
+        pbkdf2_hmac::<Sha1>(password.as_bytes(), salt, iterations, &mut key);
+        let data = crypto::decrypt_gcm(encrypted, &key, nonce)?;
+        Ok(serde_json::from_slice(&data).unwrap())
+    }
+
+    #[test]
+    fn backup_survives_round_trip() {
+        let steam = CodeFormat::with_alphabet(STEAM_ALPHABET, 5).unwrap();
+        let backup = report(steam).to_andotp("correct horse").unwrap();
+        let entries = open(&backup, "correct horse").unwrap();
+        assert_eq!(entries[0]["type"], "TOTP");
+        assert_eq!(entries[0]["issuer"], "GitHub");
+        assert_eq!(entries[0]["label"], "octocat");
+        assert_eq!(entries[0]["secret"], "JBSWY3DPEHPK3PXP");
+        assert_eq!(entries[0]["tags"][0], "work");
+        assert_eq!(entries[1]["type"], "STEAM");
+        assert_eq!(entries[1]["digits"], 5);
+    }
+
+    #[test]
+    fn wrong_password_is_rejected() {
+        let steam = CodeFormat::with_alphabet(STEAM_ALPHABET, 5).unwrap();
+        let backup = report(steam).to_andotp("correct horse").unwrap();
+        assert!(open(&backup, "wrong horse").is_err());
+    }
+
+    #[test]
+    fn custom_alphabet_is_unsupported() {
+        let custom = CodeFormat::with_alphabet("ABCDEFGH", 6).unwrap();
+        let err = report(custom).to_andotp("correct horse").unwrap_err();
+        assert_eq!(err.code(), ErrorCode::Unsupported);
+    }
+}
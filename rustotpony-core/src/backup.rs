@@ -0,0 +1,53 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+use crate::errors::{Error, ErrorCode};
+
+/// What happens to the previous database file when it's overwritten.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackupPolicy {
+    /// The file is just overwritten.
+    #[default]
+    None,
+    /// The given number of previous versions is kept next to the file as
+    /// `<file>.bak.1`, `<file>.bak.2` and so on, the newest first. They are
+    /// encrypted like the database, with the password of their time.
+    KeepLast(usize),
+}
+
+impl BackupPolicy {
+    /// Copies the file before it's overwritten, shifting older copies and
+    /// dropping the oldest one. Nothing is copied for a new database.
+    pub(crate) fn back_up(self, path: &Path) -> Result<(), Error> {
+        let keep = match self {
+            BackupPolicy::None | BackupPolicy::KeepLast(0) => return Ok(()),
+            BackupPolicy::KeepLast(keep) => keep,
+        };
+        if !path.exists() {
+            return Ok(());
+        }
+        let failed = |err: std::io::Error| {
+            Error::new(
+                ErrorCode::Io,
+                format!("Couldn't back up the database: {}", err),
+            )
+        };
+        for idx in (1..keep).rev() {
+            match fs::rename(backup_path(path, idx), backup_path(path, idx + 1)) {
+                Err(err) if err.kind() != ErrorKind::NotFound => return Err(failed(err)),
+                _ => {}
+            }
+        }
+        fs::copy(path, backup_path(path, 1)).map_err(failed)?;
+        debug!(path = %path.display(), keep, "backed up database file");
+        Ok(())
+    }
+}
+
+fn backup_path(path: &Path, idx: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".bak.{}", idx));
+    PathBuf::from(name)
+}
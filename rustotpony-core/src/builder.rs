@@ -0,0 +1,256 @@
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::cancellation::CancellationToken;
+use crate::errors::{Error, ErrorCode};
+use crate::header::{CipherKind, Kdf};
+use crate::journal::Journal;
+use crate::kdf::DEFAULT_UNLOCK_TIME;
+use crate::keycache::KeyCache;
+use crate::progress::{Progress, ProgressCallback};
+use crate::{BackupPolicy, FileStorage, JsonDatabase, Storage};
+
+type SecretFn = Box<dyn Fn() -> String + Send + Sync>;
+
+/// Configures a `JsonDatabase`, made by `JsonDatabase::builder()`. Only the
+/// location and the password are required, everything else has defaults.
+pub struct JsonDatabaseBuilder {
+    storage: Option<Box<dyn Storage>>,
+    secret_fn: Option<SecretFn>,
+    new_secret_fn: Option<SecretFn>,
+    second_secret_fn: Option<SecretFn>,
+    cipher: CipherKind,
+    kdf: Option<Kdf>,
+    indexed: bool,
+    unlock_time: Duration,
+    backup_policy: BackupPolicy,
+    read_only: bool,
+    compression: bool,
+    key_ttl: Option<Duration>,
+    compact_after: Option<usize>,
+    progress: Option<ProgressCallback>,
+    cancellation: Option<CancellationToken>,
+}
+
+impl JsonDatabase {
+    pub fn builder() -> JsonDatabaseBuilder {
+        JsonDatabaseBuilder {
+            storage: None,
+            secret_fn: None,
+            new_secret_fn: None,
+            second_secret_fn: None,
+            cipher: CipherKind::XChaCha20Poly1305,
+            kdf: None,
+            indexed: false,
+            unlock_time: DEFAULT_UNLOCK_TIME,
+            backup_policy: BackupPolicy::None,
+            read_only: false,
+            compression: true,
+            key_ttl: None,
+            compact_after: None,
+            progress: None,
+            cancellation: None,
+        }
+    }
+}
+
+impl JsonDatabaseBuilder {
+    /// Keeps the database in a local file.
+    pub fn path(self, path: PathBuf) -> JsonDatabaseBuilder {
+        self.storage(Box::new(FileStorage::new(path)))
+    }
+
+    /// Keeps the database in any storage, e.g. one made by `storage_for`.
+    pub fn storage(mut self, storage: Box<dyn Storage>) -> JsonDatabaseBuilder {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// Sets the function asked for the password when the database is read
+    /// or saved.
+    pub fn secret<F>(mut self, secret_fn: F) -> JsonDatabaseBuilder
+    where
+        F: Fn() -> String + Send + Sync + 'static,
+    {
+        self.secret_fn = Some(Box::new(secret_fn));
+        self
+    }
+
+    /// Sets the function asked for the password when the database is saved
+    /// for the first time, instead of `secret`. Frontends use it to have
+    /// the password of a new database checked and typed twice.
+    pub fn new_secret<F>(mut self, new_secret_fn: F) -> JsonDatabaseBuilder
+    where
+        F: Fn() -> String + Send + Sync + 'static,
+    {
+        self.new_secret_fn = Some(Box::new(new_secret_fn));
+        self
+    }
+
+    /// Sets the function asked for the passphrase of the second person when
+    /// the database requires two, see `require_two_persons`. It isn't called
+    /// for other databases.
+    pub fn second_secret<F>(mut self, second_secret_fn: F) -> JsonDatabaseBuilder
+    where
+        F: Fn() -> String + Send + Sync + 'static,
+    {
+        self.second_secret_fn = Some(Box::new(second_secret_fn));
+        self
+    }
+
+    /// Cipher of new database files, XChaCha20-Poly1305 by default. Only
+    /// authenticated ciphers are accepted, AES-CBC is kept for existing
+    /// files, which are read with the cipher named in their header.
+    pub fn cipher(mut self, cipher: CipherKind) -> JsonDatabaseBuilder {
+        self.cipher = cipher;
+        self
+    }
+
+    /// Key derivation of new database files. By default scrypt parameters
+    /// are benchmarked when the database is created, see `unlock_time`.
+    /// Existing files keep the one named in their header.
+    pub fn kdf(mut self, kdf: Kdf) -> JsonDatabaseBuilder {
+        self.kdf = Some(kdf);
+        self
+    }
+
+    /// Encrypts every application of new database files on its own, behind
+    /// an encrypted index. Listing then decrypts only the index, a single
+    /// application is decrypted alone and saves encrypt only the changed
+    /// applications again. Existing files keep their layout.
+    pub fn indexed(mut self, indexed: bool) -> JsonDatabaseBuilder {
+        self.indexed = indexed;
+        self
+    }
+
+    /// How long deriving the key of a new database should take on this
+    /// machine, 250 ms by default. Longer times make guessing the password
+    /// harder.
+    pub fn unlock_time(mut self, unlock_time: Duration) -> JsonDatabaseBuilder {
+        self.unlock_time = unlock_time;
+        self
+    }
+
+    /// Controls copies of the previous file kept on every save. Backups are
+    /// possible only for databases in local files.
+    pub fn backup_policy(mut self, backup_policy: BackupPolicy) -> JsonDatabaseBuilder {
+        self.backup_policy = backup_policy;
+        self
+    }
+
+    /// Forbids any writes to the storage. Saving a read-only database fails
+    /// before the password is even asked, so it's safe to open a backup copy
+    /// for inspection.
+    pub fn read_only(mut self, read_only: bool) -> JsonDatabaseBuilder {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Gzips the vaults of new database files before encryption, it's
+    /// enabled by default. The header records it, existing files are read
+    /// and written as it says regardless of this setting.
+    pub fn compression(mut self, compression: bool) -> JsonDatabaseBuilder {
+        self.compression = compression;
+        self
+    }
+
+    /// Keeps the derived key (never the password) in memory for `ttl` after
+    /// the password was entered. Reads and saves within this time neither
+    /// ask for the password nor derive the key again. The key is wiped when
+    /// it expires, on `forget_key`, after a failed decryption and when the
+    /// database is dropped.
+    pub fn key_cache(mut self, ttl: Duration) -> JsonDatabaseBuilder {
+        self.key_ttl = Some(ttl);
+        self
+    }
+
+    /// Saves changes to a journal next to the database file instead of
+    /// rewriting the file. A save returns once its changes are on the disk,
+    /// and after `compact_after` saves (`DEFAULT_COMPACT_AFTER` suits most
+    /// programs) the journal is written into the file. A journal is
+    /// possible only for databases in local files.
+    pub fn journal(mut self, compact_after: usize) -> JsonDatabaseBuilder {
+        self.compact_after = Some(compact_after);
+        self
+    }
+
+    /// Registers a callback which is told the progress of slow operations:
+    /// key derivation, decryption and encryption of big databases and
+    /// encryption with a new key. It's called on the thread doing the
+    /// work, so user interfaces can show progress bars or spinners.
+    pub fn on_progress<F: Fn(&Progress) + Send + Sync + 'static>(
+        mut self,
+        callback: F,
+    ) -> JsonDatabaseBuilder {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Lets saves and `change_password` be cancelled with the token. They
+    /// fail with `CANCELLED` and leave the file as it was, unless it's
+    /// already being replaced.
+    pub fn cancellation(mut self, token: CancellationToken) -> JsonDatabaseBuilder {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Checks the configuration and makes the database. Nothing is read
+    /// yet, the file is opened on the first access.
+    pub fn build(self) -> Result<JsonDatabase, Error> {
+        let storage = self.storage.ok_or_else(|| {
+            Error::new(
+                ErrorCode::InvalidArgument,
+                "Location of the database isn't set",
+            )
+        })?;
+        let secret_fn = self.secret_fn.ok_or_else(|| {
+            Error::new(
+                ErrorCode::InvalidArgument,
+                "Password of the database isn't set",
+            )
+        })?;
+        if self.cipher == CipherKind::Aes256Cbc {
+            return Err(Error::new(
+                ErrorCode::Unsupported,
+                "AES-CBC isn't authenticated, it's kept only for existing databases",
+            ));
+        }
+        if self.kdf.as_ref().is_some_and(Kdf::is_two_persons) && self.second_secret_fn.is_none() {
+            return Err(Error::new(
+                ErrorCode::InvalidArgument,
+                "Passphrase of the second person is required by the key derivation",
+            ));
+        }
+        if self.backup_policy != BackupPolicy::None && storage.local_path().is_none() {
+            return Err(Error::new(
+                ErrorCode::Unsupported,
+                "Backups are kept only for databases in local files",
+            ));
+        }
+        if self.compact_after.is_some() && storage.local_path().is_none() {
+            return Err(Error::new(
+                ErrorCode::Unsupported,
+                "A journal is kept only for databases in local files",
+            ));
+        }
+        Ok(JsonDatabase {
+            storage,
+            secret_fn,
+            new_secret_fn: self.new_secret_fn,
+            second_secret_fn: self.second_secret_fn,
+            new_cipher: self.cipher,
+            new_kdf: self.kdf,
+            new_indexed: self.indexed,
+            unlock_time: self.unlock_time,
+            backup_policy: self.backup_policy,
+            read_only: self.read_only,
+            compression: self.compression,
+            key_cache: self.key_ttl.map(KeyCache::new),
+            journal: self.compact_after.map(Journal::new),
+            progress: self.progress,
+            cancellation: self.cancellation,
+            header: Mutex::new(None),
+        })
+    }
+}
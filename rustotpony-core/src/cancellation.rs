@@ -0,0 +1,45 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::errors::{Error, ErrorCode};
+use crate::JsonDatabase;
+
+/// Error returned by operations stopped with a `CancellationToken`
+pub const CANCELLED: &str = "Operation was cancelled";
+
+/// Flag shared with a long-running operation to stop it from another
+/// thread, e.g. a Ctrl-C handler. Clones share the flag.
+///
+/// Operations look at it between their steps and stop before the database
+/// file is replaced, so the file is either left untouched or has all the
+/// changes. Once the file is being replaced, the operation finishes.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Fails with `CANCELLED` if the token, when there is one, was cancelled.
+pub(crate) fn check(token: Option<&CancellationToken>) -> Result<(), Error> {
+    match token {
+        Some(token) if token.is_cancelled() => Err(Error::new(ErrorCode::Cancelled, CANCELLED)),
+        _ => Ok(()),
+    }
+}
+
+impl JsonDatabase {
+    pub(crate) fn check_cancelled(&self) -> Result<(), Error> {
+        check(self.cancellation.as_ref())
+    }
+}
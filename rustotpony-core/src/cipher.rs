@@ -0,0 +1,409 @@
+use crate::crypto;
+use crate::errors::{Error, ErrorCode};
+use crate::random;
+
+/// Encryption of the vaults in the database file. The cipher is named in
+/// the file header, see `CipherKind`, so new algorithms can be added
+/// without changing how the vaults are read and written.
+///
+/// A sealed vault is a random nonce (the IV of block modes) followed by the
+/// ciphertext. Vaults are streamed in chunks, see `VaultWriter`, so big
+/// databases are never held in memory decrypted as a whole.
+pub trait Cipher: Send + Sync {
+    /// Size of the random nonce in front of the ciphertext.
+    fn nonce_size(&self) -> usize;
+
+    /// How many bytes the ciphertext of a padded vault is longer than the
+    /// vault itself. Vaults are padded so that their ciphertexts fill
+    /// power-of-two sized buckets exactly.
+    fn overhead(&self) -> usize;
+
+    /// Whether a ciphertext of this length could have been produced by the
+    /// cipher, used to tell damaged files from wrong passwords.
+    fn is_valid_length(&self, length: usize) -> bool;
+
+    fn encrypt(&self, data: &[u8], key: &[u8], nonce: &[u8]) -> Result<Vec<u8>, Error>;
+
+    /// Reverse of `encrypt`, fails if the key is wrong. Authenticated
+    /// ciphers fail for changed data too.
+    fn decrypt(&self, data: &[u8], key: &[u8], nonce: &[u8]) -> Result<Vec<u8>, Error>;
+
+    /// Encrypts the data with a random nonce put in front of it.
+    fn seal(&self, data: &[u8], key: &[u8]) -> Vec<u8> {
+        let nonce = random::bytes(self.nonce_size());
+        let encrypted = self
+            .encrypt(data, key, &nonce)
+            .expect("Couldn't encrypt data");
+        [nonce, encrypted].concat()
+    }
+
+    /// Reverse of `seal`.
+    fn open(&self, sealed: &[u8], key: &[u8]) -> Result<Vec<u8>, Error> {
+        if sealed.len() < self.nonce_size() {
+            return Err(Error::new(ErrorCode::Damaged, "Database file is too short"));
+        }
+        let (nonce, data) = sealed.split_at(self.nonce_size());
+        self.decrypt(data, key, nonce)
+    }
+
+    /// Whether vaults are encrypted in chunks. Otherwise a vault is sealed
+    /// whole, like authenticated vaults of the first file format.
+    fn is_streamed(&self) -> bool {
+        true
+    }
+
+    /// How many bytes a sealed chunk is longer than the chunk, except the
+    /// last one, which is longer by `overhead`.
+    fn chunk_overhead(&self) -> usize;
+
+    /// Nonce of a chunk, derived from the nonce in front of the vault and
+    /// the ciphertext of the previous chunk.
+    fn chunk_nonce(&self, nonce: &[u8], previous: &[u8], index: u32, last: bool) -> Vec<u8>;
+
+    fn encrypt_chunk(
+        &self,
+        data: &[u8],
+        key: &[u8],
+        nonce: &[u8],
+        last: bool,
+    ) -> Result<Vec<u8>, Error>;
+
+    fn decrypt_chunk(
+        &self,
+        data: &[u8],
+        key: &[u8],
+        nonce: &[u8],
+        last: bool,
+    ) -> Result<Vec<u8>, Error>;
+}
+
+/// AES-256 in CBC mode with PKCS#7 padding, the cipher of all databases
+/// written before the cipher was selectable. It isn't authenticated, but a
+/// damaged block spoils only itself and the following one, so damaged
+/// databases can be salvaged.
+pub struct AesCbc;
+
+/// AES-256 in GCM mode, authenticated and fast on CPUs with AES
+/// instructions.
+pub struct AesGcm;
+
+/// XChaCha20-Poly1305, authenticated and fast without AES instructions.
+pub struct XChaCha20;
+
+/// Authenticated cipher sealing vaults whole, as files of the first format
+/// did.
+pub struct Whole(pub &'static dyn Cipher);
+
+const AES_BLOCK_SIZE: usize = 16;
+const AEAD_TAG_SIZE: usize = 16;
+
+impl Cipher for AesCbc {
+    fn nonce_size(&self) -> usize {
+        16
+    }
+
+    fn overhead(&self) -> usize {
+        // PKCS#7 padding always adds at least one byte
+        1
+    }
+
+    fn is_valid_length(&self, length: usize) -> bool {
+        length >= AES_BLOCK_SIZE && length.is_multiple_of(AES_BLOCK_SIZE)
+    }
+
+    fn encrypt(&self, data: &[u8], key: &[u8], nonce: &[u8]) -> Result<Vec<u8>, Error> {
+        crypto::encrypt(data, key, nonce)
+    }
+
+    fn decrypt(&self, data: &[u8], key: &[u8], nonce: &[u8]) -> Result<Vec<u8>, Error> {
+        crypto::decrypt(data, key, nonce)
+    }
+
+    // Chunks are whole blocks of a single CBC chain, so a streamed vault is
+    // the same as one encrypted at once
+    fn chunk_overhead(&self) -> usize {
+        0
+    }
+
+    fn chunk_nonce(&self, nonce: &[u8], previous: &[u8], index: u32, _: bool) -> Vec<u8> {
+        if index == 0 {
+            nonce.to_vec()
+        } else {
+            previous[previous.len() - AES_BLOCK_SIZE..].to_vec()
+        }
+    }
+
+    fn encrypt_chunk(
+        &self,
+        data: &[u8],
+        key: &[u8],
+        nonce: &[u8],
+        last: bool,
+    ) -> Result<Vec<u8>, Error> {
+        if last {
+            crypto::encrypt(data, key, nonce)
+        } else {
+            crypto::encrypt_blocks(data, key, nonce)
+        }
+    }
+
+    fn decrypt_chunk(
+        &self,
+        data: &[u8],
+        key: &[u8],
+        nonce: &[u8],
+        last: bool,
+    ) -> Result<Vec<u8>, Error> {
+        if last {
+            crypto::decrypt(data, key, nonce)
+        } else {
+            crypto::decrypt_blocks(data, key, nonce)
+        }
+    }
+}
+
+impl Cipher for AesGcm {
+    fn nonce_size(&self) -> usize {
+        12
+    }
+
+    fn overhead(&self) -> usize {
+        AEAD_TAG_SIZE
+    }
+
+    fn is_valid_length(&self, length: usize) -> bool {
+        length >= AEAD_TAG_SIZE
+    }
+
+    fn encrypt(&self, data: &[u8], key: &[u8], nonce: &[u8]) -> Result<Vec<u8>, Error> {
+        crypto::encrypt_gcm(data, key, nonce)
+    }
+
+    fn decrypt(&self, data: &[u8], key: &[u8], nonce: &[u8]) -> Result<Vec<u8>, Error> {
+        crypto::decrypt_gcm(data, key, nonce)
+    }
+
+    fn chunk_overhead(&self) -> usize {
+        AEAD_TAG_SIZE
+    }
+
+    fn chunk_nonce(&self, nonce: &[u8], _: &[u8], index: u32, last: bool) -> Vec<u8> {
+        aead_chunk_nonce(nonce, index, last)
+    }
+
+    fn encrypt_chunk(
+        &self,
+        data: &[u8],
+        key: &[u8],
+        nonce: &[u8],
+        _: bool,
+    ) -> Result<Vec<u8>, Error> {
+        self.encrypt(data, key, nonce)
+    }
+
+    fn decrypt_chunk(
+        &self,
+        data: &[u8],
+        key: &[u8],
+        nonce: &[u8],
+        _: bool,
+    ) -> Result<Vec<u8>, Error> {
+        self.decrypt(data, key, nonce)
+    }
+}
+
+impl Cipher for XChaCha20 {
+    fn nonce_size(&self) -> usize {
+        24
+    }
+
+    fn overhead(&self) -> usize {
+        AEAD_TAG_SIZE
+    }
+
+    fn is_valid_length(&self, length: usize) -> bool {
+        length >= AEAD_TAG_SIZE
+    }
+
+    fn encrypt(&self, data: &[u8], key: &[u8], nonce: &[u8]) -> Result<Vec<u8>, Error> {
+        crypto::encrypt_xchacha(data, key, nonce)
+    }
+
+    fn decrypt(&self, data: &[u8], key: &[u8], nonce: &[u8]) -> Result<Vec<u8>, Error> {
+        crypto::decrypt_xchacha(data, key, nonce)
+    }
+
+    fn chunk_overhead(&self) -> usize {
+        AEAD_TAG_SIZE
+    }
+
+    fn chunk_nonce(&self, nonce: &[u8], _: &[u8], index: u32, last: bool) -> Vec<u8> {
+        aead_chunk_nonce(nonce, index, last)
+    }
+
+    fn encrypt_chunk(
+        &self,
+        data: &[u8],
+        key: &[u8],
+        nonce: &[u8],
+        _: bool,
+    ) -> Result<Vec<u8>, Error> {
+        self.encrypt(data, key, nonce)
+    }
+
+    fn decrypt_chunk(
+        &self,
+        data: &[u8],
+        key: &[u8],
+        nonce: &[u8],
+        _: bool,
+    ) -> Result<Vec<u8>, Error> {
+        self.decrypt(data, key, nonce)
+    }
+}
+
+impl Cipher for Whole {
+    fn nonce_size(&self) -> usize {
+        self.0.nonce_size()
+    }
+
+    fn overhead(&self) -> usize {
+        self.0.overhead()
+    }
+
+    fn is_valid_length(&self, length: usize) -> bool {
+        self.0.is_valid_length(length)
+    }
+
+    fn encrypt(&self, data: &[u8], key: &[u8], nonce: &[u8]) -> Result<Vec<u8>, Error> {
+        self.0.encrypt(data, key, nonce)
+    }
+
+    fn decrypt(&self, data: &[u8], key: &[u8], nonce: &[u8]) -> Result<Vec<u8>, Error> {
+        self.0.decrypt(data, key, nonce)
+    }
+
+    fn is_streamed(&self) -> bool {
+        false
+    }
+
+    fn chunk_overhead(&self) -> usize {
+        self.0.chunk_overhead()
+    }
+
+    fn chunk_nonce(&self, nonce: &[u8], previous: &[u8], index: u32, last: bool) -> Vec<u8> {
+        self.0.chunk_nonce(nonce, previous, index, last)
+    }
+
+    fn encrypt_chunk(
+        &self,
+        data: &[u8],
+        key: &[u8],
+        nonce: &[u8],
+        last: bool,
+    ) -> Result<Vec<u8>, Error> {
+        self.0.encrypt_chunk(data, key, nonce, last)
+    }
+
+    fn decrypt_chunk(
+        &self,
+        data: &[u8],
+        key: &[u8],
+        nonce: &[u8],
+        last: bool,
+    ) -> Result<Vec<u8>, Error> {
+        self.0.decrypt_chunk(data, key, nonce, last)
+    }
+}
+
+// The last five bytes of the random nonce are replaced with the index of
+// the chunk and a flag of the last one, as in the STREAM construction, so
+// chunks can't be reordered, dropped or cut off unnoticed
+fn aead_chunk_nonce(nonce: &[u8], index: u32, last: bool) -> Vec<u8> {
+    let prefix = &nonce[..nonce.len() - 5];
+    [prefix, &index.to_be_bytes(), &[u8::from(last)]].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::CipherKind;
+
+    const KEY: [u8; 32] = [7; 32];
+    const WRONG_KEY: [u8; 32] = [8; 32];
+    const DATA: &[u8] = b"{\"applications\":{}}";
+
+    #[test]
+    fn sealed_data_survives_round_trip() {
+        for kind in CipherKind::ALL {
+            let cipher = kind.cipher();
+            let sealed = cipher.seal(DATA, &KEY);
+            assert!(cipher.is_valid_length(sealed.len() - cipher.nonce_size()));
+            assert_eq!(cipher.open(&sealed, &KEY).unwrap(), DATA, "{}", kind.name());
+        }
+    }
+
+    #[test]
+    fn wrong_key_is_rejected() {
+        for kind in CipherKind::ALL {
+            let cipher = kind.cipher();
+            let sealed = cipher.seal(DATA, &KEY);
+            let opened = cipher.open(&sealed, &WRONG_KEY);
+            if *kind == CipherKind::Aes256Cbc {
+                // Without authentication the padding may happen to be
+                // valid, the data is garbage then
+                assert!(!opened.is_ok_and(|opened| opened == DATA));
+            } else {
+                assert!(opened.is_err(), "{}", kind.name());
+            }
+        }
+    }
+
+    #[test]
+    fn changed_data_is_rejected_by_authenticated_ciphers() {
+        for cipher in [&AesGcm as &dyn Cipher, &XChaCha20] {
+            let mut sealed = cipher.seal(DATA, &KEY);
+            let last = sealed.len() - 1;
+            sealed[last] ^= 1;
+            assert!(cipher.open(&sealed, &KEY).is_err());
+        }
+    }
+
+    #[test]
+    fn chunks_survive_round_trip() {
+        for kind in CipherKind::ALL {
+            let cipher = kind.cipher();
+            let nonce = random::bytes(cipher.nonce_size());
+            // CBC chunks other than the last are whole blocks
+            let first = [1; 64];
+            let first_nonce = cipher.chunk_nonce(&nonce, &[], 0, false);
+            let sealed = cipher
+                .encrypt_chunk(&first, &KEY, &first_nonce, false)
+                .unwrap();
+            assert_eq!(sealed.len(), first.len() + cipher.chunk_overhead());
+            let last_nonce = cipher.chunk_nonce(&nonce, &sealed, 1, true);
+            let sealed_last = cipher.encrypt_chunk(DATA, &KEY, &last_nonce, true).unwrap();
+            assert_eq!(
+                cipher
+                    .decrypt_chunk(&sealed, &KEY, &first_nonce, false)
+                    .unwrap(),
+                first
+            );
+            assert_eq!(
+                cipher
+                    .decrypt_chunk(&sealed_last, &KEY, &last_nonce, true)
+                    .unwrap(),
+                DATA
+            );
+        }
+    }
+
+    #[test]
+    fn cipher_names_survive_round_trip() {
+        for kind in CipherKind::ALL {
+            assert_eq!(CipherKind::from_name(kind.name()).unwrap(), *kind);
+        }
+        let err = CipherKind::from_name("rot13").unwrap_err();
+        assert_eq!(err.code(), ErrorCode::Unsupported);
+    }
+}
@@ -0,0 +1,63 @@
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use std::io::{Read, Write};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Tells whether the decrypted payload starts with the gzip magic bytes. A
+/// vault of a compressed file which doesn't was decrypted with a wrong key.
+pub fn has_gzip_magic(data: &[u8]) -> bool {
+    data.starts_with(&GZIP_MAGIC)
+}
+
+/// Compresses everything written to it into the writer.
+pub fn encoder<W: Write>(writer: W) -> GzEncoder<W> {
+    GzEncoder::new(writer, Compression::best())
+}
+
+/// Decompresses as much of a damaged payload as possible.
+pub fn decompress_partial(data: &[u8]) -> Vec<u8> {
+    let mut decoder = GzDecoder::new(data);
+    let mut result = Vec::new();
+    let mut buffer = [0; 4096];
+    while let Ok(n) = decoder.read(&mut buffer) {
+        if n == 0 {
+            break;
+        }
+        result.extend_from_slice(&buffer[..n]);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut encoder = encoder(Vec::new());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn compressed_data_survives_round_trip() {
+        let data = br#"{"name": "GitHub", "secret": "JBSWY3DPEHPK3PXP"}"#.repeat(100);
+        let compressed = compress(&data);
+        assert!(has_gzip_magic(&compressed));
+        assert!(compressed.len() < data.len() / 10);
+        assert_eq!(decompress_partial(&compressed), data);
+    }
+
+    #[test]
+    fn truncated_data_is_decompressed_partially() {
+        let data: Vec<u8> = (0..20_000u32).flat_map(u32::to_be_bytes).collect();
+        let compressed = compress(&data);
+        let partial = decompress_partial(&compressed[..compressed.len() / 2]);
+        assert!(!partial.is_empty() && partial.len() < data.len());
+        assert!(data.starts_with(&partial));
+        assert!(decompress_partial(b"not gzip").is_empty());
+        assert!(!has_gzip_magic(b"{\"version\": 1}"));
+    }
+}
@@ -0,0 +1,214 @@
+use aes::cipher::block_padding::{NoPadding, Pkcs7};
+use aes::cipher::{BlockModeDecrypt, BlockModeEncrypt, KeyIvInit};
+use aes::Aes256;
+use aes_gcm::aead::Aead;
+use aes_gcm::{Aes256Gcm, Nonce};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use hmac::{EagerHash, Hmac, KeyInit, Mac};
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::errors::{Error, ErrorCode};
+
+/// Hash functions used by the generators and the database.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hash {
+    Md5,
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl Hash {
+    pub fn digest(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Hash::Md5 => Md5::digest(data).to_vec(),
+            Hash::Sha1 => Sha1::digest(data).to_vec(),
+            Hash::Sha256 => Sha256::digest(data).to_vec(),
+            Hash::Sha512 => Sha512::digest(data).to_vec(),
+        }
+    }
+
+    /// Lowercase hex representation of the digest.
+    pub fn hex_digest(self, data: &[u8]) -> String {
+        to_hex(&self.digest(data))
+    }
+
+    pub fn hmac(self, key: &[u8], data: &[u8]) -> Vec<u8> {
+        fn compute<D: EagerHash>(key: &[u8], data: &[u8]) -> Vec<u8> {
+            let mut hmac =
+                <Hmac<D> as KeyInit>::new_from_slice(key).expect("HMAC accepts keys of any size");
+            hmac.update(data);
+            hmac.finalize().into_bytes().to_vec()
+        }
+        match self {
+            Hash::Md5 => compute::<Md5>(key, data),
+            Hash::Sha1 => compute::<Sha1>(key, data),
+            Hash::Sha256 => compute::<Sha256>(key, data),
+            Hash::Sha512 => compute::<Sha512>(key, data),
+        }
+    }
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Compares secrets in time which doesn't depend on where they differ.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Encrypts the data with AES-256 in CBC mode with PKCS#7 padding.
+pub fn encrypt(data: &[u8], key: &[u8], iv: &[u8]) -> Result<Vec<u8>, Error> {
+    Ok(cbc::Encryptor::<Aes256>::new_from_slices(key, iv)
+        .map_err(|_| Error::new(ErrorCode::Internal, "Invalid key or IV length"))?
+        .encrypt_padded_vec::<Pkcs7>(data))
+}
+
+/// Reverse of `encrypt`, fails if the padding is broken which usually
+/// means that the key is wrong.
+pub fn decrypt(data: &[u8], key: &[u8], iv: &[u8]) -> Result<Vec<u8>, Error> {
+    cbc::Decryptor::<Aes256>::new_from_slices(key, iv)
+        .map_err(|_| Error::new(ErrorCode::Internal, "Invalid key or IV length"))?
+        .decrypt_padded_vec::<Pkcs7>(data)
+        .map_err(|_| Error::new(ErrorCode::Damaged, "Invalid padding"))
+}
+
+/// Encrypts whole blocks without padding, used for all but the last chunk
+/// of a streamed vault.
+pub fn encrypt_blocks(data: &[u8], key: &[u8], iv: &[u8]) -> Result<Vec<u8>, Error> {
+    if !data.len().is_multiple_of(16) {
+        return Err(Error::new(
+            ErrorCode::Damaged,
+            "Data isn't a whole number of blocks",
+        ));
+    }
+    Ok(cbc::Encryptor::<Aes256>::new_from_slices(key, iv)
+        .map_err(|_| Error::new(ErrorCode::Internal, "Invalid key or IV length"))?
+        .encrypt_padded_vec::<NoPadding>(data))
+}
+
+/// Decrypts whole blocks leaving the padding in place, used for recovering
+/// damaged data and for streamed vaults.
+pub fn decrypt_blocks(data: &[u8], key: &[u8], iv: &[u8]) -> Result<Vec<u8>, Error> {
+    cbc::Decryptor::<Aes256>::new_from_slices(key, iv)
+        .map_err(|_| Error::new(ErrorCode::Internal, "Invalid key or IV length"))?
+        .decrypt_padded_vec::<NoPadding>(data)
+        .map_err(|_| Error::new(ErrorCode::Damaged, "Data isn't a whole number of blocks"))
+}
+
+/// Encrypts the data with AES-256 in GCM mode, the way other authenticator
+/// apps encrypt their backups. Returns the ciphertext followed by the 16
+/// byte tag.
+pub fn encrypt_gcm(data: &[u8], key: &[u8], nonce: &[u8]) -> Result<Vec<u8>, Error> {
+    let nonce = Nonce::try_from(nonce)
+        .map_err(|_| Error::new(ErrorCode::Internal, "Invalid nonce length"))?;
+    <Aes256Gcm as KeyInit>::new_from_slice(key)
+        .map_err(|_| Error::new(ErrorCode::Internal, "Invalid key length"))?
+        .encrypt(&nonce, data)
+        .map_err(|_| Error::new(ErrorCode::Internal, "Couldn't encrypt data"))
+}
+
+/// Reverse of `encrypt_gcm`, fails if the tag doesn't match, i.e. the key
+/// is wrong or the data was changed.
+pub fn decrypt_gcm(data: &[u8], key: &[u8], nonce: &[u8]) -> Result<Vec<u8>, Error> {
+    let nonce = Nonce::try_from(nonce)
+        .map_err(|_| Error::new(ErrorCode::Internal, "Invalid nonce length"))?;
+    <Aes256Gcm as KeyInit>::new_from_slice(key)
+        .map_err(|_| Error::new(ErrorCode::Internal, "Invalid key length"))?
+        .decrypt(&nonce, data)
+        .map_err(|_| Error::new(ErrorCode::Damaged, "Authentication failed"))
+}
+
+/// Encrypts the data with XChaCha20-Poly1305. Returns the ciphertext
+/// followed by the 16 byte tag.
+pub fn encrypt_xchacha(data: &[u8], key: &[u8], nonce: &[u8]) -> Result<Vec<u8>, Error> {
+    let nonce = XNonce::try_from(nonce)
+        .map_err(|_| Error::new(ErrorCode::Internal, "Invalid nonce length"))?;
+    <XChaCha20Poly1305 as KeyInit>::new_from_slice(key)
+        .map_err(|_| Error::new(ErrorCode::Internal, "Invalid key length"))?
+        .encrypt(&nonce, data)
+        .map_err(|_| Error::new(ErrorCode::Internal, "Couldn't encrypt data"))
+}
+
+/// Reverse of `encrypt_xchacha`, fails if the tag doesn't match.
+pub fn decrypt_xchacha(data: &[u8], key: &[u8], nonce: &[u8]) -> Result<Vec<u8>, Error> {
+    let nonce = XNonce::try_from(nonce)
+        .map_err(|_| Error::new(ErrorCode::Internal, "Invalid nonce length"))?;
+    <XChaCha20Poly1305 as KeyInit>::new_from_slice(key)
+        .map_err(|_| Error::new(ErrorCode::Internal, "Invalid key length"))?
+        .decrypt(&nonce, data)
+        .map_err(|_| Error::new(ErrorCode::Damaged, "Authentication failed"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|idx| u8::from_str_radix(&hex[idx..idx + 2], 16).unwrap())
+            .collect()
+    }
+
+    // NIST SP 800-38A, F.2.5
+    const CBC_KEY: &str = "603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4";
+    const CBC_IV: &str = "000102030405060708090a0b0c0d0e0f";
+    const CBC_PLAINTEXT: &str = "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51";
+    const CBC_CIPHERTEXT: &str = "f58c4c04d6e5f1ba779eabfb5f7bfbd69cfc4e967edb808d679f777bc6702c7d";
+
+    #[test]
+    fn digests_match_known_values() {
+        assert_eq!(
+            Hash::Md5.hex_digest(b"abc"),
+            "900150983cd24fb0d6963f7d28e17f72"
+        );
+        assert_eq!(
+            Hash::Sha1.hex_digest(b"abc"),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            Hash::Sha256.hex_digest(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert!(Hash::Sha512
+            .hex_digest(b"abc")
+            .starts_with("ddaf35a193617abacc417349ae204131"));
+    }
+
+    // RFC 2202 and RFC 4231, test case 2
+    #[test]
+    fn hmacs_match_known_values() {
+        let hmac = |hash: Hash| to_hex(&hash.hmac(b"Jefe", b"what do ya want for nothing?"));
+        assert_eq!(hmac(Hash::Md5), "750c783e6ab0b503eaa86e310a5db738");
+        assert_eq!(hmac(Hash::Sha1), "effcdf6ae5eb2fa2d27416d5f184df9c259a7c79");
+        assert_eq!(
+            hmac(Hash::Sha256),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn cbc_matches_known_values() {
+        let (key, iv) = (from_hex(CBC_KEY), from_hex(CBC_IV));
+        let encrypted = encrypt_blocks(&from_hex(CBC_PLAINTEXT), &key, &iv).unwrap();
+        assert_eq!(to_hex(&encrypted), CBC_CIPHERTEXT);
+        let decrypted = decrypt_blocks(&encrypted, &key, &iv).unwrap();
+        assert_eq!(to_hex(&decrypted), CBC_PLAINTEXT);
+    }
+
+    #[test]
+    fn padded_cbc_survives_round_trip() {
+        let (key, iv) = (from_hex(CBC_KEY), from_hex(CBC_IV));
+        let encrypted = encrypt(b"JBSWY3DPEHPK3PXP", &key, &iv).unwrap();
+        assert_eq!(encrypted.len(), 32);
+        assert_eq!(decrypt(&encrypted, &key, &iv).unwrap(), b"JBSWY3DPEHPK3PXP");
+        // The padding may happen to be valid, the data is garbage then
+        let wrong = decrypt(&encrypted, &[0; 32], &iv);
+        assert!(!wrong.is_ok_and(|data| data == b"JBSWY3DPEHPK3PXP"));
+        assert!(encrypt_blocks(b"not a block", &key, &iv).is_err());
+    }
+}
@@ -0,0 +1,216 @@
+use serde::de::DeserializeOwned;
+
+use crate::cipher::Cipher;
+use crate::errors::{Error, ErrorCode};
+use crate::padding::MIN_BUCKET_SIZE;
+use crate::{random, stream};
+use crate::{DatabaseContent, JsonDatabase, JsonDatabaseSchema};
+
+/// Place of a vault in the database file.
+///
+/// Databases written by older versions contain a single vault which takes
+/// the whole file. Newer databases are split into two halves of equal size,
+/// each holding a vault encrypted with its own password followed by random
+/// filler. When there is no decoy vault, the second half is random data, so
+/// a database with a decoy looks exactly like a database without one.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Slot {
+    Whole,
+    Half(usize),
+}
+
+impl JsonDatabase {
+    /// Adds a decoy vault to the database. The decoy is opened instead of
+    /// the real applications when `password` is entered, and it can be used
+    /// as an ordinary database afterwards. An existing decoy vault is
+    /// replaced with an empty one.
+    pub fn create_decoy(&self, password: &str) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::new(
+                ErrorCode::ReadOnly,
+                "Database is opened in read-only mode",
+            ));
+        }
+        if self.requires_two_persons()? {
+            return Err(Error::new(
+                ErrorCode::Unsupported,
+                "Database requires two persons, a decoy would be opened by one",
+            ));
+        }
+        let key = self.database_key();
+        let decoy_key = Self::form_password_key(&self.kdf(), password);
+        if key == decoy_key {
+            return Err(Error::new(
+                ErrorCode::InvalidArgument,
+                "Decoy password must differ from the database password",
+            ));
+        }
+        let data = self
+            .read_vaults()?
+            .ok_or_else(|| Error::new(ErrorCode::NotFound, "There is no database yet"))?;
+        let (slot, mut schema): (Slot, JsonDatabaseSchema) = self.open_vault(&data, &key)?;
+        let own = match slot {
+            Slot::Half(idx) => idx,
+            Slot::Whole => 0,
+        };
+        // The real vault is sealed again, an old style vault may be of any
+        // size and wouldn't be found in a half of the file
+        self.replay_journal(&mut schema.content, &key)?;
+        let vault = self.seal(&schema.content, &key);
+        let decoy = self.seal(&DatabaseContent::default(), &decoy_key);
+        self.write_vaults(&join_halves(own, &vault, &decoy))?;
+        self.clear_journal(&schema.content, &key)
+    }
+}
+
+/// Finds the vault which can be decrypted with the key and returns its
+/// place with the content parsed as it's decrypted, see
+/// `stream::read_vault`.
+pub fn open<T: DeserializeOwned>(
+    cipher: &dyn Cipher,
+    data: &[u8],
+    key: &[u8],
+    compressed: bool,
+    report: &dyn Fn(u64, u64),
+) -> Result<(Slot, T), Error> {
+    if data.len() < cipher.nonce_size() {
+        return Err(Error::new(ErrorCode::Damaged, "Database file is too short"));
+    }
+    let mut error = Error::new(ErrorCode::Damaged, "Database file is too short");
+    for (slot, bytes) in slots(cipher, data) {
+        for length in vault_lengths(cipher, slot, bytes.len()) {
+            match stream::read_vault(cipher, &bytes[..length], key, compressed, report) {
+                Ok(value) => return Ok((slot, value)),
+                Err(err) => error = err,
+            }
+        }
+    }
+    Err(error)
+}
+
+/// Puts the vault into the half of the existing database file where the
+/// previous vault was found, keeping the other half intact.
+pub fn place(existing: &[u8], slot: Option<Slot>, vault: &[u8]) -> Vec<u8> {
+    match slot {
+        Some(Slot::Half(idx)) => replace_half(existing, idx, vault),
+        _ => join_halves(0, vault, &[]),
+    }
+}
+
+/// Puts the vault into the given half of the database file, keeping the
+/// other half intact.
+pub fn replace_half(existing: &[u8], idx: usize, vault: &[u8]) -> Vec<u8> {
+    let half = existing.len() / 2;
+    let other = if idx == 0 {
+        &existing[half..]
+    } else {
+        &existing[..half]
+    };
+    join_halves(idx, vault, other)
+}
+
+pub fn slots<'a>(cipher: &dyn Cipher, data: &'a [u8]) -> Vec<(Slot, &'a [u8])> {
+    let mut slots = Vec::new();
+    let half = data.len() / 2;
+    if data.len().is_multiple_of(2) && half >= cipher.nonce_size() + MIN_BUCKET_SIZE {
+        slots.push((Slot::Half(0), &data[..half]));
+        slots.push((Slot::Half(1), &data[half..]));
+    }
+    slots.push((Slot::Whole, data));
+    slots
+}
+
+/// Possible lengths of a vault in the slot. Vaults in halves are padded to
+/// power-of-two buckets, so only a few lengths have to be tried.
+fn vault_lengths(cipher: &dyn Cipher, slot: Slot, size: usize) -> Vec<usize> {
+    if slot == Slot::Whole {
+        return vec![size];
+    }
+    let mut lengths = Vec::new();
+    let mut bucket = MIN_BUCKET_SIZE;
+    while cipher.nonce_size() + bucket <= size {
+        lengths.push(cipher.nonce_size() + bucket);
+        bucket *= 2;
+    }
+    lengths
+}
+
+/// Builds a database file with the vault in the given half and the other
+/// content in the remaining one. Both halves are filled with random bytes
+/// up to the same size.
+fn join_halves(idx: usize, vault: &[u8], other: &[u8]) -> Vec<u8> {
+    let half = vault.len().max(other.len());
+    let fill = |bytes: &[u8]| [bytes, &random::bytes(half - bytes.len())].concat();
+    let (vault, other) = (fill(vault), fill(other));
+    if idx == 0 {
+        [vault, other].concat()
+    } else {
+        [other, vault].concat()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::ErrorCode;
+    use crate::header::Kdf;
+    use crate::{JsonDatabase, MemoryStorage, RusTOTPony};
+
+    fn database(storage: &MemoryStorage, password: &'static str) -> JsonDatabase {
+        JsonDatabase::builder()
+            .storage(Box::new(storage.clone()))
+            .secret(move || String::from(password))
+            .kdf(Kdf::scrypt(10).unwrap())
+            .build()
+            .unwrap()
+    }
+
+    fn names(storage: &MemoryStorage, password: &'static str) -> Vec<String> {
+        let mut names: Vec<String> = database(storage, password)
+            .load_content()
+            .unwrap()
+            .applications
+            .into_keys()
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn add(storage: &MemoryStorage, password: &'static str, name: &str) {
+        let mut totpony = RusTOTPony::new(database(storage, password));
+        totpony
+            .create_application(name, "octocat", "JBSWY3DPEHPK3PXP")
+            .unwrap();
+        totpony.flush().unwrap();
+    }
+
+    #[test]
+    fn decoy_password_opens_decoy() {
+        let storage = MemoryStorage::default();
+        add(&storage, "real", "GitHub");
+        let size = storage.get_bytes().unwrap().len();
+        database(&storage, "real").create_decoy("duress").unwrap();
+        // The second half was random filler, now it's the decoy
+        assert_eq!(storage.get_bytes().unwrap().len(), size);
+        assert!(names(&storage, "duress").is_empty());
+        add(&storage, "duress", "Weather");
+        assert_eq!(names(&storage, "real"), ["GitHub"]);
+        assert_eq!(names(&storage, "duress"), ["Weather"]);
+    }
+
+    #[test]
+    fn wrong_password_opens_neither_vault() {
+        let storage = MemoryStorage::default();
+        add(&storage, "real", "GitHub");
+        database(&storage, "real").create_decoy("duress").unwrap();
+        let err = database(&storage, "wrong").load_content().err().unwrap();
+        assert_eq!(err.code(), ErrorCode::WrongPassword);
+    }
+
+    #[test]
+    fn decoy_password_must_differ() {
+        let storage = MemoryStorage::default();
+        add(&storage, "real", "GitHub");
+        let err = database(&storage, "real").create_decoy("real").unwrap_err();
+        assert_eq!(err.code(), ErrorCode::InvalidArgument);
+    }
+}
@@ -0,0 +1,202 @@
+use serde_derive::Serialize;
+use std::collections::HashMap;
+
+use crate::errors::ErrorCode;
+use crate::header::{self, Header};
+use crate::history::verify_history;
+use crate::permissions::{check_write_target, permissions_problem};
+use crate::{GenApp, JsonDatabase, JsonDatabaseSchema, DATABASE_VERSION};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// Problem found by a database health check.
+#[derive(Serialize, Debug)]
+pub struct Finding {
+    pub severity: Severity,
+    /// Short stable identifier of the check, e.g. `invalid-secret`
+    pub check: &'static str,
+    pub message: String,
+}
+
+impl Finding {
+    fn error(check: &'static str, message: String) -> Finding {
+        Finding {
+            severity: Severity::Error,
+            check,
+            message,
+        }
+    }
+
+    fn warning(check: &'static str, message: String) -> Finding {
+        Finding {
+            severity: Severity::Warning,
+            check,
+            message,
+        }
+    }
+}
+
+impl JsonDatabase {
+    /// Runs health checks on the database and returns found problems.
+    /// Nothing is ever written. An empty result means the database is fine.
+    pub fn diagnose(&self) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        if let Some(problem) = self.storage.local_path().and_then(permissions_problem) {
+            findings.push(Finding::warning("permissions", problem));
+        }
+        if let Some(Err(problem)) = self.storage.local_path().map(check_write_target) {
+            findings.push(Finding::warning("path", problem.to_string()));
+        }
+
+        let data = match self.storage.read() {
+            Ok(Some(data)) => data,
+            Ok(None) => {
+                findings.push(Finding::warning(
+                    "missing",
+                    String::from("There is no database yet"),
+                ));
+                return findings;
+            }
+            Err(err) => {
+                findings.push(Finding::error("unreadable", err.to_string()));
+                return findings;
+            }
+        };
+        let (cipher, data) = match header::split(&data) {
+            Ok((header, vaults)) => (header.unwrap_or_else(Header::legacy).vault_cipher(), vaults),
+            Err(err) => {
+                findings.push(Finding::error("header", err.to_string()));
+                return findings;
+            }
+        };
+        if data.len() < cipher.nonce_size()
+            || !cipher.is_valid_length(data.len() - cipher.nonce_size())
+        {
+            findings.push(Finding::error(
+                "file-size",
+                format!(
+                    "Database file has unexpected size of {} bytes, it's either truncated or not a RusTOTPony database",
+                    data.len()
+                ),
+            ));
+            return findings;
+        }
+
+        let value: serde_json::Value = match self.decrypt_with_secret(data) {
+            Ok(value) => value,
+            Err(err) if err.code() == ErrorCode::Damaged => {
+                findings.push(Finding::error(
+                    "json",
+                    format!("Decrypted data isn't valid JSON: {}", err),
+                ));
+                return findings;
+            }
+            Err(err) => {
+                findings.push(Finding::error("decryption", err.to_string()));
+                return findings;
+            }
+        };
+        match value.get("version").and_then(serde_json::Value::as_u64) {
+            Some(version) if version > u64::from(DATABASE_VERSION) => {
+                findings.push(Finding::error(
+                    "version",
+                    format!(
+                        "Database version {} is newer than supported version {}",
+                        version, DATABASE_VERSION
+                    ),
+                ));
+                return findings;
+            }
+            Some(_) => {}
+            None => findings.push(Finding::error(
+                "version",
+                String::from("Database doesn't specify its version"),
+            )),
+        }
+        let schema: JsonDatabaseSchema = match serde_json::from_value(value) {
+            Ok(schema) => schema,
+            Err(err) => {
+                findings.push(Finding::error(
+                    "schema",
+                    format!("Database content doesn't match the schema: {}", err),
+                ));
+                return findings;
+            }
+        };
+
+        check_applications(&schema.content.applications, &mut findings);
+        if let Err(idx) = verify_history(&schema.content.history) {
+            findings.push(Finding::error(
+                "history",
+                format!(
+                    "History was tampered with, records starting from #{} can't be trusted",
+                    idx + 1
+                ),
+            ));
+        }
+        findings
+    }
+}
+
+fn check_applications(applications: &HashMap<String, GenApp>, findings: &mut Vec<Finding>) {
+    let mut keys: Vec<&String> = applications.keys().collect();
+    keys.sort();
+
+    let mut names: HashMap<String, &str> = HashMap::new();
+    let mut secrets: HashMap<&[u8], &str> = HashMap::new();
+    for key in keys {
+        let app = &applications[key];
+        if app.get_name() != key {
+            findings.push(Finding::warning(
+                "name-mismatch",
+                format!(
+                    "Application '{}' is stored under the name '{}'",
+                    app.get_name(),
+                    key
+                ),
+            ));
+        }
+        if let Some(other) = names.insert(key.to_lowercase(), key) {
+            findings.push(Finding::warning(
+                "duplicate-name",
+                format!(
+                    "Applications '{}' and '{}' differ only in letter case",
+                    other, key
+                ),
+            ));
+        }
+        // Secrets of protected applications can't be checked without the
+        // passphrase
+        if app.is_protected() {
+            continue;
+        }
+        match GenApp::base32_to_bytes(app.get_secret()) {
+            Some(ref bytes) if bytes == &app.secret_bytes => {}
+            Some(_) => findings.push(Finding::error(
+                "secret-mismatch",
+                format!(
+                    "Secret of application '{}' doesn't match its decoded form",
+                    key
+                ),
+            )),
+            None => findings.push(Finding::error(
+                "invalid-secret",
+                format!("Secret of application '{}' isn't valid base32", key),
+            )),
+        }
+        if let Some(other) = secrets.insert(&app.secret_bytes, key) {
+            findings.push(Finding::warning(
+                "duplicate-secret",
+                format!(
+                    "Applications '{}' and '{}' have the same secret",
+                    other, key
+                ),
+            ));
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use std::fmt;
+
+/// Kind of a failure with a stable number and name, for programs which
+/// handle errors without parsing the messages. Numbers and names are never
+/// changed or reused; new kinds get new numbers.
+///
+/// Every `Error` of the API has one, given where the error is raised. The
+/// C interface returns the numbers and `totp` exits with them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// An argument or an entered value is invalid
+    InvalidArgument,
+    /// Database couldn't be read or written for another reason
+    Database,
+    /// Application, alias, snapshot or database doesn't exist
+    NotFound,
+    /// Application is protected with an additional passphrase
+    Protected,
+    /// Algorithm, period, format or generator isn't supported
+    Unsupported,
+    /// Code didn't match
+    InvalidCode,
+    /// Unexpected internal error, e.g. a bug
+    Internal,
+    /// Password or passphrase is wrong
+    WrongPassword,
+    /// File is damaged or isn't a database
+    Damaged,
+    /// File was written by a newer version
+    NewerVersion,
+    /// Database is opened in read-only mode
+    ReadOnly,
+    /// Name, alias, snapshot or secret is already used
+    AlreadyExists,
+    /// Operation was cancelled with a `CancellationToken`
+    Cancelled,
+    /// File couldn't be read or written
+    Io,
+    /// Remote storage couldn't be reached
+    Network,
+    /// Remote database was changed since the last sync
+    SyncConflict,
+    /// Name matches several applications, see `Error::get_candidates`
+    Ambiguous,
+    /// Any other failure
+    Other,
+}
+
+impl ErrorCode {
+    /// All kinds, in the order of their numbers.
+    pub const ALL: &'static [ErrorCode] = &[
+        ErrorCode::InvalidArgument,
+        ErrorCode::Database,
+        ErrorCode::NotFound,
+        ErrorCode::Protected,
+        ErrorCode::Unsupported,
+        ErrorCode::InvalidCode,
+        ErrorCode::Internal,
+        ErrorCode::WrongPassword,
+        ErrorCode::Damaged,
+        ErrorCode::NewerVersion,
+        ErrorCode::ReadOnly,
+        ErrorCode::AlreadyExists,
+        ErrorCode::Cancelled,
+        ErrorCode::Io,
+        ErrorCode::Network,
+        ErrorCode::SyncConflict,
+        ErrorCode::Ambiguous,
+        ErrorCode::Other,
+    ];
+
+    /// Number of the kind, it's never 0, which means success.
+    pub fn code(self) -> i32 {
+        match self {
+            ErrorCode::InvalidArgument => 1,
+            ErrorCode::Database => 2,
+            ErrorCode::NotFound => 3,
+            ErrorCode::Protected => 4,
+            ErrorCode::Unsupported => 5,
+            ErrorCode::InvalidCode => 6,
+            ErrorCode::Internal => 7,
+            ErrorCode::WrongPassword => 8,
+            ErrorCode::Damaged => 9,
+            ErrorCode::NewerVersion => 10,
+            ErrorCode::ReadOnly => 11,
+            ErrorCode::AlreadyExists => 12,
+            ErrorCode::Cancelled => 13,
+            ErrorCode::Io => 14,
+            ErrorCode::Network => 15,
+            ErrorCode::SyncConflict => 16,
+            ErrorCode::Ambiguous => 17,
+            ErrorCode::Other => 99,
+        }
+    }
+
+    /// Name of the kind, e.g. `wrong-password`.
+    pub fn id(self) -> &'static str {
+        match self {
+            ErrorCode::InvalidArgument => "invalid-argument",
+            ErrorCode::Database => "database",
+            ErrorCode::NotFound => "not-found",
+            ErrorCode::Protected => "protected",
+            ErrorCode::Unsupported => "unsupported",
+            ErrorCode::InvalidCode => "invalid-code",
+            ErrorCode::Internal => "internal",
+            ErrorCode::WrongPassword => "wrong-password",
+            ErrorCode::Damaged => "damaged",
+            ErrorCode::NewerVersion => "newer-version",
+            ErrorCode::ReadOnly => "read-only",
+            ErrorCode::AlreadyExists => "already-exists",
+            ErrorCode::Cancelled => "cancelled",
+            ErrorCode::Io => "io",
+            ErrorCode::Network => "network",
+            ErrorCode::SyncConflict => "sync-conflict",
+            ErrorCode::Ambiguous => "ambiguous",
+            ErrorCode::Other => "other",
+        }
+    }
+
+    pub fn from_code(code: i32) -> Option<ErrorCode> {
+        ErrorCode::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.code() == code)
+    }
+}
+
+/// Error of the API: the kind of the failure and a message for people.
+/// Messages may be reworded, programs should look at `code`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    code: ErrorCode,
+    message: String,
+    candidates: Vec<String>,
+}
+
+impl Error {
+    pub fn new<M: Into<String>>(code: ErrorCode, message: M) -> Error {
+        Error {
+            code,
+            message: message.into(),
+            candidates: Vec::new(),
+        }
+    }
+
+    /// Error of a name which matches several applications, with their
+    /// names for letting the user choose.
+    pub fn ambiguous<M: Into<String>>(message: M, candidates: Vec<String>) -> Error {
+        Error {
+            code: ErrorCode::Ambiguous,
+            message: message.into(),
+            candidates,
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Names of the applications an ambiguous name matches, sorted. Empty
+    /// for other errors.
+    pub fn get_candidates(&self) -> &[String] {
+        &self.candidates
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Error {}
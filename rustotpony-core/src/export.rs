@@ -0,0 +1,231 @@
+use crate::errors::{Error, ErrorCode};
+use crate::{Database, GenApp, RusTOTPony, STEAM_ALPHABET};
+
+/// Selects the applications to export. An application is selected when its
+/// name matches one of the patterns and it has one of the tags, an empty
+/// list doesn't restrict anything.
+#[derive(Debug, Clone, Default)]
+pub struct ExportFilter {
+    /// Glob patterns of names, `*` matches any run of characters and `?` a
+    /// single character
+    pub names: Vec<String>,
+    pub tags: Vec<String>,
+}
+
+impl ExportFilter {
+    pub fn matches(&self, app: &GenApp) -> bool {
+        (self.names.is_empty() || self.names.iter().any(|pattern| glob(pattern, &app.name)))
+            && (self.tags.is_empty() || self.tags.iter().any(|tag| app.tags.contains(tag)))
+    }
+}
+
+/// Exported applications, unlocked, and applications which matched the
+/// filter but couldn't be exported, with the reason.
+#[derive(Debug, Default)]
+pub struct ExportReport {
+    pub applications: Vec<GenApp>,
+    pub skipped: Vec<(String, String)>,
+}
+
+impl ExportReport {
+    /// `otpauth://` URIs of the exported applications.
+    pub fn uris(&self) -> Vec<String> {
+        self.applications
+            .iter()
+            .map(GenApp::to_otpauth_uri)
+            .collect()
+    }
+}
+
+impl<DB: Database> RusTOTPony<DB> {
+    /// Returns the applications selected by the filter, sorted by name, for
+    /// writing them out in one of the export formats. `passphrase` is asked for passphrases of protected
+    /// applications, they are skipped when it returns `None`. Every export
+    /// is recorded in the history as a disclosure of the secret.
+    pub fn export_applications<F>(
+        &mut self,
+        filter: &ExportFilter,
+        mut passphrase: F,
+    ) -> Result<ExportReport, Error>
+    where
+        F: FnMut(&str) -> Option<String>,
+    {
+        let mut names: Vec<String> = self
+            .content()?
+            .applications
+            .iter()
+            .filter(|(_, app)| filter.matches(app))
+            .map(|(name, _)| name.clone())
+            .collect();
+        names.sort();
+        let mut report = ExportReport::default();
+        for name in names {
+            let app = &self.loaded_mut().applications[&name];
+            if let Err(err) = app.check_otpauth_uri() {
+                report.skipped.push((name, err.to_string()));
+                continue;
+            }
+            let passphrase = if app.is_protected() {
+                match passphrase(&name) {
+                    Some(passphrase) => Some(passphrase),
+                    None => {
+                        report
+                            .skipped
+                            .push((name, String::from("passphrase wasn't given")));
+                        continue;
+                    }
+                }
+            } else {
+                None
+            };
+            match self.disclose(&name, passphrase.as_deref(), Some("export")) {
+                Ok(app) => report.applications.push(app),
+                Err(err) => report.skipped.push((name, err.to_string())),
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// Tells whether the application shows Steam Guard codes rather than
+/// decimal ones. Other authenticator apps know only these two kinds, so
+/// applications with other alphabets can't be exported to `app`.
+pub(crate) fn is_steam(application: &GenApp, app: &str) -> Result<bool, Error> {
+    match application.get_format().get_alphabet() {
+        None => Ok(false),
+        Some(STEAM_ALPHABET) => Ok(true),
+        Some(_) => Err(Error::new(
+            ErrorCode::Unsupported,
+            format!(
+                "'{}' shows codes with a custom alphabet, {} can't generate them",
+                application.get_name(),
+                app
+            ),
+        )),
+    }
+}
+
+/// Matches the whole text against a pattern with `*` and `?` wildcards.
+fn glob(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position after the last `*` and the text position it was tried at
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                // Let the last `*` take one more character
+                Some((after, tried)) => {
+                    p = after;
+                    t = tried + 1;
+                    star = Some((after, tried + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::from_otp_field;
+    use crate::DatabaseContent;
+
+    struct Memory;
+
+    impl Database for Memory {
+        fn get_content(&self) -> Result<DatabaseContent, Error> {
+            Ok(DatabaseContent::default())
+        }
+
+        fn save_content(&self, _content: &DatabaseContent) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    fn totpony() -> RusTOTPony<Memory> {
+        let mut totpony = RusTOTPony::with_content(Memory, DatabaseContent::default());
+        for name in ["GitHub", "GitLab", "Bank"] {
+            totpony
+                .create_application(name, "octocat", "JBSWY3DPEHPK3PXP")
+                .unwrap();
+        }
+        totpony
+            .tag_application("GitLab", &[String::from("work")])
+            .unwrap();
+        totpony
+            .protect_application("Bank", "correct horse")
+            .unwrap();
+        totpony
+    }
+
+    fn names(report: &ExportReport) -> Vec<&str> {
+        report.applications.iter().map(GenApp::get_name).collect()
+    }
+
+    #[test]
+    fn filter_selects_by_names_and_tags() {
+        let mut totpony = totpony();
+        let filter = ExportFilter {
+            names: vec![String::from("Git*")],
+            tags: vec![],
+        };
+        let report = totpony.export_applications(&filter, |_| None).unwrap();
+        assert_eq!(names(&report), ["GitHub", "GitLab"]);
+        let filter = ExportFilter {
+            names: vec![String::from("Git?ab")],
+            tags: vec![String::from("work")],
+        };
+        let report = totpony.export_applications(&filter, |_| None).unwrap();
+        assert_eq!(names(&report), ["GitLab"]);
+        let filter = ExportFilter {
+            names: vec![String::from("Git")],
+            tags: vec![],
+        };
+        let report = totpony.export_applications(&filter, |_| None).unwrap();
+        assert!(report.applications.is_empty());
+    }
+
+    #[test]
+    fn exported_uris_survive_round_trip() {
+        let report = totpony()
+            .export_applications(&ExportFilter::default(), |_| {
+                Some(String::from("correct horse"))
+            })
+            .unwrap();
+        assert_eq!(names(&report), ["Bank", "GitHub", "GitLab"]);
+        for (uri, app) in report.uris().iter().zip(&report.applications) {
+            let imported = from_otp_field(uri, "", "").unwrap();
+            assert_eq!(imported.get_name(), app.get_name());
+            assert_eq!(imported.get_secret(), "JBSWY3DPEHPK3PXP");
+        }
+    }
+
+    #[test]
+    fn locked_applications_are_skipped() {
+        let mut totpony = totpony();
+        let report = totpony
+            .export_applications(&ExportFilter::default(), |_| None)
+            .unwrap();
+        assert_eq!(names(&report), ["GitHub", "GitLab"]);
+        assert_eq!(report.skipped[0].0, "Bank");
+        let report = totpony
+            .export_applications(&ExportFilter::default(), |_| {
+                Some(String::from("battery staple"))
+            })
+            .unwrap();
+        assert_eq!(names(&report), ["GitHub", "GitLab"]);
+        assert_eq!(report.skipped[0].0, "Bank");
+    }
+}
@@ -0,0 +1,147 @@
+use serde_derive::{Deserialize, Serialize};
+
+use crate::errors::{Error, ErrorCode};
+
+/// The largest decimal code which doesn't exceed the 31-bit value of the
+/// dynamic truncation from RFC 4226
+const MAX_DECIMAL_LENGTH: usize = 10;
+/// Symbols beyond the 64th are always the first one of the alphabet, as
+/// the generated value has 64 bits
+const MAX_LENGTH: usize = 64;
+
+/// Characters of codes shown by Steam Guard
+pub const STEAM_ALPHABET: &str = "23456789BCDFGHJKMNPQRTVWXY";
+
+/// How the generated value is turned into a code shown to the user.
+///
+/// Usual codes are decimal numbers of 6 or 8 digits. Some vendors use other
+/// alphabets, e.g. Steam Guard shows 5 characters of `STEAM_ALPHABET`. Such
+/// codes are built from the least significant symbol, the way Steam does.
+///
+/// Formats are checked by the constructors when they are read too, a
+/// database or a share can't make `format` fail.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "StoredFormat")]
+pub struct CodeFormat {
+    length: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    alphabet: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct StoredFormat {
+    length: usize,
+    #[serde(default)]
+    alphabet: Option<String>,
+}
+
+impl TryFrom<StoredFormat> for CodeFormat {
+    type Error = Error;
+
+    fn try_from(stored: StoredFormat) -> Result<CodeFormat, Error> {
+        match stored.alphabet {
+            None => CodeFormat::decimal(stored.length),
+            Some(alphabet) => CodeFormat::with_alphabet(&alphabet, stored.length),
+        }
+    }
+}
+
+impl Default for CodeFormat {
+    fn default() -> CodeFormat {
+        CodeFormat {
+            length: 6,
+            alphabet: None,
+        }
+    }
+}
+
+impl CodeFormat {
+    pub fn decimal(length: usize) -> Result<CodeFormat, Error> {
+        if length == 0 || length > MAX_DECIMAL_LENGTH {
+            return Err(Error::new(
+                ErrorCode::InvalidArgument,
+                format!("Code length must be between 1 and {}", MAX_DECIMAL_LENGTH),
+            ));
+        }
+        Ok(CodeFormat {
+            length,
+            alphabet: None,
+        })
+    }
+
+    pub fn with_alphabet(alphabet: &str, length: usize) -> Result<CodeFormat, Error> {
+        let mut chars: Vec<char> = alphabet.chars().collect();
+        chars.sort_unstable();
+        chars.dedup();
+        if chars.len() < 2 || chars.len() != alphabet.chars().count() {
+            return Err(Error::new(
+                ErrorCode::InvalidArgument,
+                "Alphabet must consist of at least two distinct characters",
+            ));
+        }
+        if length == 0 || length > MAX_LENGTH {
+            return Err(Error::new(
+                ErrorCode::InvalidArgument,
+                format!("Code length must be between 1 and {}", MAX_LENGTH),
+            ));
+        }
+        Ok(CodeFormat {
+            length,
+            alphabet: Some(String::from(alphabet)),
+        })
+    }
+
+    pub fn get_length(&self) -> usize {
+        self.length
+    }
+
+    pub fn get_alphabet(&self) -> Option<&str> {
+        self.alphabet.as_deref()
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == CodeFormat::default()
+    }
+
+    /// Splits the code into groups separated by spaces for display, e.g.
+    /// "123 456". Without a group size codes of even length from 6 characters
+    /// are split in halves, zero size keeps the code intact.
+    pub fn group(&self, code: &str, size: Option<usize>) -> String {
+        let size = match size {
+            Some(size) => size,
+            None if self.length >= 6 && self.length.is_multiple_of(2) => self.length / 2,
+            None => 0,
+        };
+        if size == 0 {
+            return String::from(code);
+        }
+        let chars: Vec<char> = code.chars().collect();
+        chars
+            .chunks(size)
+            .map(|group| group.iter().collect::<String>())
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    /// Formats the value of the dynamic truncation.
+    pub fn format(&self, value: u64) -> String {
+        match self.alphabet {
+            None => format!(
+                "{:0width$}",
+                value % 10_u64.pow(self.length as u32),
+                width = self.length
+            ),
+            Some(ref alphabet) => {
+                let chars: Vec<char> = alphabet.chars().collect();
+                let base = chars.len() as u64;
+                let mut value = value;
+                let mut code = String::new();
+                for _ in 0..self.length {
+                    code.push(chars[(value % base) as usize]);
+                    value /= base;
+                }
+                code
+            }
+        }
+    }
+}
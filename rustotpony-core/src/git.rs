@@ -0,0 +1,111 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::errors::{Error, ErrorCode};
+use crate::journal;
+
+const DEFAULT_REMOTE: &str = "origin";
+
+/// History of the encrypted database kept in a git repository.
+///
+/// The repository lives in the directory of the database file. Once it is
+/// initialized, every save of the database is committed, so any previous
+/// state can be restored with plain git tools. Only the encrypted file is
+/// ever added to the repository.
+pub struct GitHistory {
+    dir: PathBuf,
+    file_name: String,
+}
+
+impl GitHistory {
+    pub fn for_database(db_path: &Path) -> GitHistory {
+        let dir = match db_path.parent() {
+            Some(parent) if parent != Path::new("") => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let file_name = db_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        GitHistory { dir, file_name }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.dir.join(".git").exists()
+    }
+
+    /// Initializes the repository and commits the current database. The
+    /// journal has to be compacted first, the file alone is committed.
+    pub fn enable(&self, remote: Option<&str>) -> Result<(), Error> {
+        journal::check_no_journal(&self.dir.join(&self.file_name))?;
+        if !self.is_enabled() {
+            self.git(&["init", "--quiet"])?;
+        }
+        if self.git(&["config", "user.email"]).is_err() {
+            self.git(&["config", "user.email", "rustotpony@localhost"])?;
+            self.git(&["config", "user.name", "RusTOTPony"])?;
+        }
+        if let Some(url) = remote {
+            if self.git(&["remote", "get-url", DEFAULT_REMOTE]).is_ok() {
+                self.git(&["remote", "set-url", DEFAULT_REMOTE, url])?;
+            } else {
+                self.git(&["remote", "add", DEFAULT_REMOTE, url])?;
+            }
+        }
+        if self.dir.join(&self.file_name).exists() {
+            self.commit("Start tracking database history")?;
+        }
+        Ok(())
+    }
+
+    /// Commits the current state of the database file. Does nothing if the
+    /// file wasn't changed since the last commit.
+    pub fn commit(&self, message: &str) -> Result<(), Error> {
+        self.git(&["add", "--", &self.file_name])?;
+        if self
+            .git(&["diff", "--cached", "--quiet", "--", &self.file_name])
+            .is_ok()
+        {
+            return Ok(());
+        }
+        self.git(&["commit", "--quiet", "-m", message])?;
+        Ok(())
+    }
+
+    pub fn push(&self) -> Result<(), Error> {
+        journal::check_no_journal(&self.dir.join(&self.file_name))?;
+        self.git(&["push", "--quiet", DEFAULT_REMOTE, "HEAD"])?;
+        Ok(())
+    }
+
+    pub fn log(&self) -> Result<Vec<String>, Error> {
+        let output = self.git(&[
+            "log",
+            "--format=%h %ad %s",
+            "--date=iso",
+            "--",
+            &self.file_name,
+        ])?;
+        Ok(output.lines().map(String::from).collect())
+    }
+
+    fn git(&self, args: &[&str]) -> Result<String, Error> {
+        let output = Command::new("git")
+            .args(args)
+            .current_dir(&self.dir)
+            .output()
+            .map_err(|err| Error::new(ErrorCode::Io, format!("Couldn't run git: {}", err)))?;
+        if output.status.success() {
+            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+        } else {
+            Err(Error::new(
+                ErrorCode::Other,
+                format!(
+                    "git {} failed: {}",
+                    args[0],
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+            ))
+        }
+    }
+}
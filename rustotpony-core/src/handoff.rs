@@ -0,0 +1,127 @@
+use serde_derive::{Deserialize, Serialize};
+
+use crate::errors::{Error, ErrorCode};
+use crate::{datetime, GenApp};
+
+/// The current code of an application together with the moment it expires,
+/// meant to be shown to someone who has to log in right now, e.g. as a QR
+/// code. It never contains the secret, so it's useless once it expires.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CodeHandoff {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub username: String,
+    pub code: String,
+    /// Unix time the code stops being valid at.
+    pub expires: u64,
+}
+
+impl CodeHandoff {
+    /// Seconds the code stays valid at the given unix time, zero once it's
+    /// expired.
+    pub fn seconds_left(&self, timestamp: u64) -> u64 {
+        self.expires.saturating_sub(timestamp)
+    }
+
+    /// Human readable form, e.g. for a QR code read by a phone camera.
+    pub fn to_text(&self) -> String {
+        let account = if self.username.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{} ({})", self.name, self.username)
+        };
+        format!(
+            "{}: {} valid until {} UTC",
+            account,
+            self.code,
+            datetime::format_timestamp(self.expires)
+        )
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Couldn't serialize the code")
+    }
+}
+
+impl GenApp {
+    /// Makes a handoff of the code valid at the given unix time. Protected
+    /// applications have to be unlocked first, challenge-response ones have
+    /// no code to hand off.
+    pub fn hand_off(&self, timestamp: u64) -> Result<CodeHandoff, Error> {
+        if self.is_protected() {
+            return Err(Error::new(
+                ErrorCode::Protected,
+                format!("Application '{}' has to be unlocked", self.name),
+            ));
+        }
+        let seconds_left = self.seconds_left(timestamp).ok_or_else(|| {
+            Error::new(
+                ErrorCode::Unsupported,
+                format!(
+                    "Application '{}' requires a challenge, its codes can't be handed off",
+                    self.name
+                ),
+            )
+        })?;
+        Ok(CodeHandoff {
+            name: self.name.clone(),
+            username: self.username.clone(),
+            code: self.generate(timestamp),
+            expires: timestamp + seconds_left,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::from_otp_field;
+    use crate::GeneratorKind;
+
+    // Secret and the code at 59 seconds from RFC 6238
+    fn application() -> GenApp {
+        from_otp_field(
+            "otpauth://totp/Example:alice?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&digits=8",
+            "Example",
+            "alice",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn handoff_has_code_and_expiry() {
+        let handoff = application().hand_off(59).unwrap();
+        assert_eq!(handoff.code, "94287082");
+        assert_eq!(handoff.expires, 60);
+        assert_eq!(handoff.seconds_left(59), 1);
+        assert_eq!(handoff.seconds_left(61), 0);
+        assert_eq!(
+            handoff.to_text(),
+            "Example (alice): 94287082 valid until 1970-01-01 00:01:00 UTC"
+        );
+    }
+
+    #[test]
+    fn handoff_survives_round_trip_without_secret() {
+        let handoff = application().hand_off(59).unwrap();
+        let json = handoff.to_json();
+        assert!(!json.contains("GEZDGNBVGY3TQOJQ"));
+        assert_eq!(serde_json::from_str::<CodeHandoff>(&json).unwrap(), handoff);
+    }
+
+    #[test]
+    fn protected_and_challenge_codes_arent_handed_off() {
+        let mut protected = application();
+        protected.protected_secret = Some(String::from("sealed"));
+        protected.secret = String::new();
+        let err = protected.hand_off(59).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::Protected);
+        let mut ocra = application();
+        ocra.kind = GeneratorKind::Ocra {
+            suite: String::from("OCRA-1:HOTP-SHA1-6:QN08"),
+            counter: 0,
+        };
+        let err = ocra.hand_off(59).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::Unsupported);
+    }
+}
@@ -0,0 +1,48 @@
+use tracing::debug;
+
+use crate::errors::Error;
+#[cfg(unix)]
+use crate::errors::ErrorCode;
+
+/// Keeps keys and secrets out of core dumps. The core file size limit of the
+/// process is set to zero, and on Linux the process is also marked as not
+/// dumpable, which additionally prevents other processes of the same user
+/// from attaching a debugger. It can't be undone for the rest of the
+/// process, so it should be called before the database is opened.
+#[cfg(unix)]
+pub fn disable_core_dumps() -> Result<(), Error> {
+    let limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: setrlimit only reads the passed structure
+    if unsafe { libc::setrlimit(libc::RLIMIT_CORE, &limit) } != 0 {
+        return Err(Error::new(
+            ErrorCode::Io,
+            format!(
+                "Couldn't disable core dumps: {}",
+                std::io::Error::last_os_error()
+            ),
+        ));
+    }
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    // SAFETY: PR_SET_DUMPABLE takes a plain integer argument
+    if unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0) } != 0 {
+        return Err(Error::new(
+            ErrorCode::Io,
+            format!(
+                "Couldn't mark the process as not dumpable: {}",
+                std::io::Error::last_os_error()
+            ),
+        ));
+    }
+    debug!("disabled core dumps");
+    Ok(())
+}
+
+/// Core dumps are only disabled on Unix.
+#[cfg(not(unix))]
+pub fn disable_core_dumps() -> Result<(), Error> {
+    debug!("core dumps can't be disabled on this platform");
+    Ok(())
+}
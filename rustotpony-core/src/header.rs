@@ -0,0 +1,412 @@
+use crate::cipher::{AesCbc, AesGcm, Cipher, Whole, XChaCha20};
+use crate::errors::{Error, ErrorCode};
+use crate::kdf;
+
+/// First bytes of every database file written by this version
+pub const MAGIC: &[u8; 8] = b"RTPONY\0\x01";
+/// Newest version of the file layout, not of the JSON schema inside the
+/// vault
+pub const FORMAT_VERSION: u8 = 4;
+/// First version with authenticated vaults encrypted in chunks, vaults of
+/// AES-CBC are the same in all versions
+pub const STREAMED_VERSION: u8 = 2;
+/// Version of files with every application encrypted on its own, behind
+/// an encrypted index. A file keeps its layout, it's chosen when it's
+/// created.
+pub const INDEXED_VERSION: u8 = 3;
+/// First version with a byte of flags after the KDF ID, which tells whether
+/// the file is indexed and whether its vaults are compressed. Both are
+/// chosen when the file is created, vaults of older versions are never
+/// compressed.
+pub const FLAGS_VERSION: u8 = 4;
+
+const INDEXED_FLAG: u8 = 0x01;
+const COMPRESSED_FLAG: u8 = 0x02;
+
+static WHOLE_AES_GCM: Whole = Whole(&AesGcm);
+static WHOLE_XCHACHA20: Whole = Whole(&XChaCha20);
+
+/// Cleartext part of the database file.
+///
+/// Layout: magic bytes, format version, cipher ID, KDF ID, flags (since
+/// `FLAGS_VERSION`), length of the KDF parameters (two bytes, big-endian)
+/// and the parameters themselves. The encrypted vaults follow right after
+/// it. Files written by older versions have no header and start with the IV
+/// of the vault.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    pub version: u8,
+    pub cipher: CipherKind,
+    pub kdf: Kdf,
+    /// Applications are encrypted one by one, behind an index
+    pub indexed: bool,
+    /// The JSON in the vaults is gzipped before it's encrypted
+    pub compressed: bool,
+}
+
+/// Cipher named in the header, see `Cipher` for the algorithms.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CipherKind {
+    /// AES-256 in CBC mode with PKCS#7 padding and a random IV in front
+    Aes256Cbc,
+    /// AES-256 in GCM mode with a random 12 byte nonce in front
+    Aes256Gcm,
+    /// XChaCha20-Poly1305 with a random 24 byte nonce in front
+    XChaCha20Poly1305,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Kdf {
+    /// A single SHA-256 of the password, it has no parameters
+    Sha256,
+    /// SHA-256 of the SHA-256 hashes of two passphrases entered by
+    /// different persons, it has no parameters either. Databases which
+    /// required two persons before `TwoPersonsScrypt` keep it.
+    TwoPersons,
+    /// scrypt of the password with a random salt. The cost is picked by
+    /// `Kdf::benchmark` on the machine the database was created on.
+    Scrypt {
+        log_n: u8,
+        r: u32,
+        p: u32,
+        salt: Vec<u8>,
+    },
+    /// SHA-256 of the keys derived from the passphrases of two persons by
+    /// scrypt, each with the salt followed by the number of the person.
+    TwoPersonsScrypt {
+        log_n: u8,
+        r: u32,
+        p: u32,
+        salt: Vec<u8>,
+    },
+}
+
+impl CipherKind {
+    /// Every cipher, the default one of new files first.
+    pub const ALL: &'static [CipherKind] = &[
+        CipherKind::XChaCha20Poly1305,
+        CipherKind::Aes256Gcm,
+        CipherKind::Aes256Cbc,
+    ];
+
+    /// Name of the cipher, e.g. `xchacha20-poly1305`.
+    pub fn name(self) -> &'static str {
+        match self {
+            CipherKind::Aes256Cbc => "aes-256-cbc",
+            CipherKind::Aes256Gcm => "aes-256-gcm",
+            CipherKind::XChaCha20Poly1305 => "xchacha20-poly1305",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<CipherKind, Error> {
+        CipherKind::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.name() == name.to_lowercase())
+            .ok_or_else(|| {
+                let names: Vec<&str> = CipherKind::ALL.iter().map(|kind| kind.name()).collect();
+                Error::new(
+                    ErrorCode::Unsupported,
+                    format!(
+                        "Cipher '{}' isn't supported, only {} are supported",
+                        name,
+                        names.join(", ")
+                    ),
+                )
+            })
+    }
+
+    fn id(self) -> u8 {
+        match self {
+            CipherKind::Aes256Cbc => 1,
+            CipherKind::Aes256Gcm => 2,
+            CipherKind::XChaCha20Poly1305 => 3,
+        }
+    }
+
+    /// Implementation of the cipher.
+    pub fn cipher(self) -> &'static dyn Cipher {
+        match self {
+            CipherKind::Aes256Cbc => &AesCbc,
+            CipherKind::Aes256Gcm => &AesGcm,
+            CipherKind::XChaCha20Poly1305 => &XChaCha20,
+        }
+    }
+}
+
+impl TryFrom<u8> for CipherKind {
+    type Error = Error;
+
+    fn try_from(id: u8) -> Result<CipherKind, Error> {
+        match id {
+            1 => Ok(CipherKind::Aes256Cbc),
+            2 => Ok(CipherKind::Aes256Gcm),
+            3 => Ok(CipherKind::XChaCha20Poly1305),
+            _ => Err(Error::new(ErrorCode::NewerVersion, format!(
+                "Database is encrypted with an unknown cipher (ID {}), it was probably written by a newer version",
+                id
+            ))),
+        }
+    }
+}
+
+impl Kdf {
+    fn id(&self) -> u8 {
+        match self {
+            Kdf::Sha256 => 1,
+            Kdf::TwoPersons => 2,
+            Kdf::Scrypt { .. } => 3,
+            Kdf::TwoPersonsScrypt { .. } => 4,
+        }
+    }
+
+    // scrypt parameters are log N, r and p (big-endian) followed by the salt
+    pub(crate) fn params(&self) -> Vec<u8> {
+        match self {
+            Kdf::Sha256 | Kdf::TwoPersons => Vec::new(),
+            Kdf::Scrypt { log_n, r, p, salt } | Kdf::TwoPersonsScrypt { log_n, r, p, salt } => {
+                [&[*log_n][..], &r.to_be_bytes(), &p.to_be_bytes(), salt].concat()
+            }
+        }
+    }
+
+    pub(crate) fn parse(id: u8, params: &[u8]) -> Result<Kdf, Error> {
+        match id {
+            1 if params.is_empty() => Ok(Kdf::Sha256),
+            1 => Err(Error::new(ErrorCode::Damaged, "Database header is damaged: unexpected SHA-256 key derivation parameters")),
+            2 if params.is_empty() => Ok(Kdf::TwoPersons),
+            2 => Err(Error::new(ErrorCode::Damaged, "Database header is damaged: unexpected two-person key derivation parameters")),
+            3 | 4 => {
+                let damaged = || {
+                    Error::new(
+                        ErrorCode::Damaged,
+                        "Database header is damaged: invalid scrypt parameters",
+                    )
+                };
+                if params.len() != 9 + kdf::SALT_SIZE {
+                    return Err(damaged());
+                }
+                let log_n = params[0];
+                let r = u32::from_be_bytes([params[1], params[2], params[3], params[4]]);
+                let p = u32::from_be_bytes([params[5], params[6], params[7], params[8]]);
+                if !kdf::is_bounded(log_n, r, p) || scrypt::Params::new(log_n, r, p).is_err() {
+                    return Err(damaged());
+                }
+                let salt = params[9..].to_vec();
+                Ok(match id {
+                    3 => Kdf::Scrypt { log_n, r, p, salt },
+                    _ => Kdf::TwoPersonsScrypt { log_n, r, p, salt },
+                })
+            }
+            _ => Err(Error::new(ErrorCode::NewerVersion, format!(
+                "Database uses an unknown key derivation function (ID {}), it was probably written by a newer version",
+                id
+            ))),
+        }
+    }
+}
+
+impl Header {
+    /// Header implied by files of older versions, which have none. New
+    /// files get the cipher and key derivation set by the builder.
+    pub fn legacy() -> Header {
+        Header {
+            version: STREAMED_VERSION,
+            cipher: CipherKind::Aes256Cbc,
+            kdf: Kdf::Sha256,
+            indexed: false,
+            compressed: false,
+        }
+    }
+
+    /// Cipher of the vaults, authenticated vaults of the first format are
+    /// sealed whole.
+    pub fn vault_cipher(&self) -> &'static dyn Cipher {
+        match self.cipher {
+            CipherKind::Aes256Gcm if self.version < STREAMED_VERSION => &WHOLE_AES_GCM,
+            CipherKind::XChaCha20Poly1305 if self.version < STREAMED_VERSION => &WHOLE_XCHACHA20,
+            kind => kind.cipher(),
+        }
+    }
+
+    /// Version of the file written with this header. Authenticated vaults
+    /// of the first format keep it, the other half of the file may hold a
+    /// decoy vault which isn't sealed again.
+    pub fn write_version(&self) -> u8 {
+        match self.cipher {
+            CipherKind::Aes256Cbc => self.version.max(STREAMED_VERSION),
+            _ => self.version,
+        }
+    }
+
+    /// Whether the applications are encrypted one by one, behind an index.
+    pub fn is_indexed(&self) -> bool {
+        self.indexed
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let params = self.kdf.params();
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend([self.version, self.cipher.id(), self.kdf.id()]);
+        if self.version >= FLAGS_VERSION {
+            let flag = |set: bool, flag: u8| if set { flag } else { 0 };
+            bytes.push(flag(self.indexed, INDEXED_FLAG) | flag(self.compressed, COMPRESSED_FLAG));
+        }
+        bytes.extend((params.len() as u16).to_be_bytes());
+        bytes.extend(params);
+        bytes
+    }
+}
+
+/// Splits the database file into the header and the encrypted vaults. Files
+/// without the magic bytes are returned as they are, without a header.
+pub fn split(data: &[u8]) -> Result<(Option<Header>, &[u8]), Error> {
+    let rest = match data.strip_prefix(MAGIC.as_slice()) {
+        Some(rest) => rest,
+        None => return Ok((None, data)),
+    };
+    let truncated = || Error::new(ErrorCode::Damaged, "Database header is truncated");
+    let version = *rest.first().ok_or_else(truncated)?;
+    if version > FORMAT_VERSION {
+        return Err(Error::new(
+            ErrorCode::NewerVersion,
+            format!(
+                "Database file format version {} is newer than supported version {}",
+                version, FORMAT_VERSION
+            ),
+        ));
+    }
+    // Version, cipher ID, KDF ID, flags and the length of the parameters
+    let fixed_size = if version >= FLAGS_VERSION { 6 } else { 5 };
+    let (fixed, rest) = rest.split_at_checked(fixed_size).ok_or_else(truncated)?;
+    let cipher = CipherKind::try_from(fixed[1])?;
+    let flags = match version {
+        _ if version >= FLAGS_VERSION => fixed[3],
+        INDEXED_VERSION => INDEXED_FLAG,
+        _ => 0,
+    };
+    if flags & !(INDEXED_FLAG | COMPRESSED_FLAG) != 0 {
+        return Err(Error::new(
+            ErrorCode::NewerVersion,
+            "Database file uses unknown features, it was probably written by a newer version",
+        ));
+    }
+    let params_len = u16::from_be_bytes([fixed[fixed_size - 2], fixed[fixed_size - 1]]) as usize;
+    let (params, body) = rest.split_at_checked(params_len).ok_or_else(truncated)?;
+    let kdf = Kdf::parse(fixed[2], params)?;
+    Ok((
+        Some(Header {
+            version,
+            cipher,
+            kdf,
+            indexed: flags & INDEXED_FLAG != 0,
+            compressed: flags & COMPRESSED_FLAG != 0,
+        }),
+        body,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{JsonDatabase, MemoryStorage};
+
+    fn scrypt() -> Kdf {
+        Kdf::Scrypt {
+            log_n: 10,
+            r: 8,
+            p: 1,
+            salt: vec![5; kdf::SALT_SIZE],
+        }
+    }
+
+    #[test]
+    fn header_survives_round_trip() {
+        let headers = [
+            Header::legacy(),
+            Header {
+                version: INDEXED_VERSION,
+                cipher: CipherKind::Aes256Gcm,
+                kdf: Kdf::TwoPersons,
+                indexed: true,
+                compressed: false,
+            },
+            Header {
+                version: FORMAT_VERSION,
+                cipher: CipherKind::XChaCha20Poly1305,
+                kdf: scrypt(),
+                indexed: false,
+                compressed: true,
+            },
+        ];
+        for header in headers {
+            let data = [header.to_bytes(), b"vaults".to_vec()].concat();
+            let (parsed, vaults) = split(&data).unwrap();
+            assert_eq!(parsed, Some(header));
+            assert_eq!(vaults, b"vaults");
+        }
+    }
+
+    #[test]
+    fn file_without_magic_has_no_header() {
+        let data = [0x42; 48];
+        let (header, vaults) = split(&data).unwrap();
+        assert_eq!(header, None);
+        assert_eq!(vaults, &data[..]);
+    }
+
+    #[test]
+    fn damaged_and_newer_headers_are_rejected() {
+        let bytes = Header {
+            version: FORMAT_VERSION,
+            cipher: CipherKind::XChaCha20Poly1305,
+            kdf: scrypt(),
+            indexed: false,
+            compressed: false,
+        }
+        .to_bytes();
+        let changed = |idx: usize, value: u8| {
+            let mut bytes = bytes.clone();
+            bytes[idx] = value;
+            bytes
+        };
+        let cases = [
+            (bytes[..bytes.len() - 1].to_vec(), ErrorCode::Damaged),
+            (
+                changed(MAGIC.len(), FORMAT_VERSION + 1),
+                ErrorCode::NewerVersion,
+            ),
+            (changed(MAGIC.len() + 1, 9), ErrorCode::NewerVersion),
+            (changed(MAGIC.len() + 2, 9), ErrorCode::NewerVersion),
+            (changed(MAGIC.len() + 3, 0x80), ErrorCode::NewerVersion),
+            // scrypt demanding far more memory than any database needs
+            (changed(MAGIC.len() + 6, 40), ErrorCode::Damaged),
+        ];
+        for (data, code) in cases {
+            assert_eq!(split(&data).err().unwrap().code(), code, "{:?}", data);
+        }
+    }
+
+    #[test]
+    fn database_starts_with_header() {
+        let storage = MemoryStorage::default();
+        let database = |password: &'static str| {
+            JsonDatabase::builder()
+                .storage(Box::new(storage.clone()))
+                .secret(move || String::from(password))
+                .cipher(CipherKind::Aes256Gcm)
+                .kdf(scrypt())
+                .build()
+                .unwrap()
+        };
+        database("correct horse").create().unwrap();
+        let data = storage.get_bytes().unwrap();
+        let (header, _) = split(&data).unwrap();
+        let header = header.unwrap();
+        assert_eq!(header.cipher, CipherKind::Aes256Gcm);
+        assert_eq!(header.kdf, scrypt());
+        assert!(database("correct horse").load_content().is_ok());
+        let err = database("wrong horse").load_content().err().unwrap();
+        assert_eq!(err.code(), ErrorCode::WrongPassword);
+    }
+}
@@ -0,0 +1,98 @@
+use serde_derive::{Deserialize, Serialize};
+
+use crate::crypto::Hash;
+use crate::datetime;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Operation {
+    Create,
+    Rename,
+    Delete,
+    DeleteAll,
+    Hide,
+    Unhide,
+    Protect,
+    Unprotect,
+    RevealSecret,
+    AddRecoveryCodes,
+    UseRecoveryCode,
+    Update,
+    Archive,
+    Unarchive,
+}
+
+/// Entry of the audit log stored inside the encrypted database.
+///
+/// Every record contains a hash of itself and of the previous record, so
+/// removing or editing a record in the middle of the log breaks the chain
+/// and is reported by [`verify_history`].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HistoryRecord {
+    timestamp: u64,
+    operation: Operation,
+    name: String,
+    details: Option<String>,
+    hash: String,
+}
+
+impl HistoryRecord {
+    pub fn new(
+        previous: Option<&HistoryRecord>,
+        operation: Operation,
+        name: &str,
+        details: Option<&str>,
+    ) -> HistoryRecord {
+        let mut record = HistoryRecord {
+            timestamp: datetime::now(),
+            operation,
+            name: String::from(name),
+            details: details.map(String::from),
+            hash: String::new(),
+        };
+        record.hash = record.compute_hash(previous.map(|p| p.hash.as_str()));
+        record
+    }
+
+    pub fn get_timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn get_operation(&self) -> Operation {
+        self.operation
+    }
+
+    pub fn get_name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn get_details(&self) -> Option<&str> {
+        self.details.as_deref()
+    }
+
+    fn compute_hash(&self, previous_hash: Option<&str>) -> String {
+        Hash::Sha256.hex_digest(
+            format!(
+                "{}\n{}\n{:?}\n{}\n{}",
+                previous_hash.unwrap_or(""),
+                self.timestamp,
+                self.operation,
+                self.name,
+                self.details.as_deref().unwrap_or("")
+            )
+            .as_bytes(),
+        )
+    }
+}
+
+/// Checks the hash chain of the log. Returns the index of the first record
+/// which doesn't match its predecessors.
+pub fn verify_history(history: &[HistoryRecord]) -> Result<(), usize> {
+    let mut previous_hash: Option<&str> = None;
+    for (idx, record) in history.iter().enumerate() {
+        if record.compute_hash(previous_hash) != record.hash {
+            return Err(idx);
+        }
+        previous_hash = Some(record.hash.as_str());
+    }
+    Ok(())
+}
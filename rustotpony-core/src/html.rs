@@ -0,0 +1,211 @@
+use crate::datetime;
+use crate::{GenApp, Icon};
+
+// Printed pages are black and white, cards aren't split between pages
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#000}\
+h1{font-size:1.4em}\
+.card{border:1px solid #000;padding:1em;margin:0 0 1em;page-break-inside:avoid;break-inside:avoid;overflow:hidden}\
+.card svg{float:right;width:10em;height:10em;margin-left:1em}\
+.paper svg{width:18em;height:18em}\
+.card h2{margin:0 0 .5em;font-size:1.2em}\
+.card h2 img{width:1.5em;height:1.5em;vertical-align:middle;margin-right:.4em}\
+dt{font-weight:bold;float:left;clear:left;width:9em}\
+dd{margin:0 0 .3em 9em}\
+code{font-family:monospace;font-size:1.1em;word-break:break-all}";
+
+/// Renders an `otpauth://` URI as an SVG image of its QR code.
+pub type QrCodeRenderer = dyn Fn(&str) -> Option<String>;
+
+/// Options of the printable backup page.
+#[derive(Default)]
+pub struct HtmlBackup<'a> {
+    /// Secrets, URIs and recovery codes are left out of the text. QR codes,
+    /// if there are any, still carry the secrets.
+    pub mask_secrets: bool,
+    pub qr_code: Option<&'a QrCodeRenderer>,
+}
+
+impl HtmlBackup<'_> {
+    /// Renders the applications, e.g. the ones returned by
+    /// `export_applications`, into a single page which needs no other files
+    /// and can be printed and stored offline.
+    pub fn render(&self, applications: &[GenApp]) -> String {
+        let mut body = format!(
+            "<p>{} generators, created {} UTC. Keep this page as safe as the database password.</p>\n",
+            applications.len(),
+            datetime::format_timestamp(datetime::now())
+        );
+        for app in applications {
+            body.push_str(&self.render_card(app));
+        }
+        page("RusTOTPony backup", &body)
+    }
+
+    /// Renders the parts of a paper backup made by
+    /// `JsonDatabase::paper_backup`, every part as a QR code and as text for
+    /// typing it in if the code can't be scanned. Secrets aren't readable
+    /// without the database password, so nothing is masked.
+    pub fn render_paper(&self, parts: &[String]) -> String {
+        let mut body = format!(
+            "<p>Encrypted database in {} parts, created {} UTC. Restore it with \
+             <code>totp paper-restore</code> and the database password.</p>\n",
+            parts.len(),
+            datetime::format_timestamp(datetime::now())
+        );
+        for (idx, part) in parts.iter().enumerate() {
+            body.push_str("<div class=\"card paper\">\n");
+            if let Some(svg) = self.qr_code.and_then(|qr_code| qr_code(part)) {
+                body.push_str(&svg);
+                body.push('\n');
+            }
+            body.push_str(&format!(
+                "<h2>Part {} of {}</h2>\n<code>{}</code>\n</div>\n",
+                idx + 1,
+                parts.len(),
+                escape(part)
+            ));
+        }
+        page("RusTOTPony paper backup", &body)
+    }
+
+    fn render_card(&self, app: &GenApp) -> String {
+        let uri = app.to_otpauth_uri();
+        let mut card = String::from("<div class=\"card\">\n");
+        if let Some(svg) = self.qr_code.and_then(|qr_code| qr_code(&uri)) {
+            card.push_str(&svg);
+            card.push('\n');
+        }
+        // Images are embedded, addresses aren't loaded so the page works offline
+        let image = match app.get_icon().and_then(Icon::to_data_uri) {
+            Some(uri) => format!("<img src=\"{}\" alt=\"\">", escape(&uri)),
+            None => String::new(),
+        };
+        card.push_str(&format!("<h2>{}{}</h2>\n<dl>\n", image, escape(&app.name)));
+        let mut field = |title: &str, value: &str| {
+            card.push_str(&format!("<dt>{}</dt><dd>{}</dd>\n", title, value));
+        };
+        if !app.username.is_empty() {
+            field("Username", &escape(&app.username));
+        }
+        field("Digits", &app.format.get_length().to_string());
+        if !app.tags.is_empty() {
+            field("Tags", &escape(&app.tags.join(", ")));
+        }
+        match app.get_icon() {
+            Some(Icon::Name { name }) => field("Icon", &escape(name)),
+            Some(Icon::Url { url }) => field("Icon", &escape(url)),
+            _ => {}
+        }
+        if self.mask_secrets {
+            field("Secret", "<i>not printed</i>");
+        } else {
+            field(
+                "Secret",
+                &format!("<code>{}</code>", escape(&group(&app.secret))),
+            );
+            field("URI", &format!("<code>{}</code>", escape(&uri)));
+            if !app.recovery_codes.is_empty() {
+                let codes: Vec<String> = app
+                    .recovery_codes
+                    .iter()
+                    .map(|code| format!("<code>{}</code>", escape(code)))
+                    .collect();
+                field("Recovery codes", &codes.join("<br>"));
+            }
+        }
+        card.push_str("</dl>\n</div>\n");
+        card
+    }
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+         <style>{STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>\n{body}</body>\n</html>\n"
+    )
+}
+
+// Groups of four are easier to type in from paper
+fn group(secret: &str) -> String {
+    let chars: Vec<char> = secret.chars().collect();
+    chars
+        .chunks(4)
+        .map(|group| group.iter().collect::<String>())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::Error;
+    use crate::{Database, DatabaseContent, RusTOTPony};
+
+    struct Memory;
+
+    impl Database for Memory {
+        fn get_content(&self) -> Result<DatabaseContent, Error> {
+            Ok(DatabaseContent::default())
+        }
+
+        fn save_content(&self, _content: &DatabaseContent) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    fn application() -> GenApp {
+        let mut totpony = RusTOTPony::with_content(Memory, DatabaseContent::default());
+        totpony
+            .create_application("<script>alert(1)</script>", "octocat", "JBSWY3DPEHPK3PXP")
+            .unwrap();
+        totpony
+            .add_recovery_codes("<script>alert(1)</script>", &[String::from("1234-5678")])
+            .unwrap();
+        totpony
+            .get_application("<script>alert(1)</script>")
+            .unwrap()
+            .clone()
+    }
+
+    #[test]
+    fn secrets_are_printed_grouped() {
+        let html = HtmlBackup::default().render(&[application()]);
+        assert!(html.contains("<code>JBSW Y3DP EHPK 3PXP</code>"));
+        assert!(html.contains("otpauth://"));
+        assert!(html.contains("1234-5678"));
+    }
+
+    #[test]
+    fn masked_page_has_no_secrets() {
+        let backup = HtmlBackup {
+            mask_secrets: true,
+            ..HtmlBackup::default()
+        };
+        let html = backup.render(&[application()]);
+        assert!(!html.contains("JBSW"));
+        assert!(!html.contains("otpauth://"));
+        assert!(!html.contains("1234-5678"));
+    }
+
+    #[test]
+    fn names_are_escaped() {
+        let html = HtmlBackup::default().render(&[application()]);
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+    }
+}
@@ -0,0 +1,131 @@
+use serde_derive::{Deserialize, Serialize};
+
+use crate::errors::{Error, ErrorCode};
+use crate::{Change, Database, Operation, RusTOTPony};
+
+/// Largest image accepted as an icon, graphical apps show icons small.
+pub const MAX_ICON_SIZE: usize = 64 * 1024;
+const IMAGE_TYPES: &[&str] = &["image/png", "image/jpeg", "image/svg+xml", "image/webp"];
+
+/// Icon of an application. Codes don't depend on it, it's kept so icons
+/// aren't lost when generators go through graphical authenticator apps.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Icon {
+    /// Name of an icon in an icon pack, e.g. "github"
+    Name { name: String },
+    /// Address of an image, as given by the `image` parameter of
+    /// `otpauth://` URIs
+    Url { url: String },
+    /// Image itself, base64 encoded
+    Image { mime: String, data: String },
+}
+
+impl Icon {
+    pub fn named(name: &str) -> Result<Icon, Error> {
+        match name.trim() {
+            "" => Err(Error::new(
+                ErrorCode::InvalidArgument,
+                "Icon name can't be empty",
+            )),
+            name => Ok(Icon::Name {
+                name: String::from(name),
+            }),
+        }
+    }
+
+    pub fn from_url(url: &str) -> Result<Icon, Error> {
+        let url = url.trim();
+        if url.starts_with("data:") {
+            return Icon::from_data_uri(url);
+        }
+        if !url.starts_with("https://") && !url.starts_with("http://") {
+            return Err(Error::new(
+                ErrorCode::InvalidArgument,
+                format!("'{}' isn't an http(s) address of an image", url),
+            ));
+        }
+        Ok(Icon::Url {
+            url: String::from(url),
+        })
+    }
+
+    /// Takes an image of one of the types graphical apps show, PNG, JPEG,
+    /// SVG or WebP, up to `MAX_ICON_SIZE` bytes.
+    pub fn image(mime: &str, data: &[u8]) -> Result<Icon, Error> {
+        let mime = mime.trim().to_ascii_lowercase();
+        if !IMAGE_TYPES.contains(&mime.as_str()) {
+            return Err(Error::new(
+                ErrorCode::Unsupported,
+                format!(
+                    "Icons can be only {} images, not {}",
+                    IMAGE_TYPES.join(", "),
+                    mime
+                ),
+            ));
+        }
+        if data.len() > MAX_ICON_SIZE {
+            return Err(Error::new(
+                ErrorCode::InvalidArgument,
+                format!(
+                    "Icon is too large, {} bytes at most are allowed",
+                    MAX_ICON_SIZE
+                ),
+            ));
+        }
+        Ok(Icon::Image {
+            mime,
+            data: base64::encode(data),
+        })
+    }
+
+    /// Parses a `data:image/png;base64,...` URI.
+    pub fn from_data_uri(uri: &str) -> Result<Icon, Error> {
+        let invalid = || {
+            Error::new(
+                ErrorCode::InvalidArgument,
+                "Icon isn't a base64 encoded data URI",
+            )
+        };
+        let (header, data) = uri
+            .trim()
+            .strip_prefix("data:")
+            .and_then(|rest| rest.split_once(','))
+            .ok_or_else(invalid)?;
+        let mime = header.strip_suffix(";base64").ok_or_else(invalid)?;
+        let data = base64::decode(data).map_err(|_| invalid())?;
+        Icon::image(mime, &data)
+    }
+
+    /// Returns the image as a `data:` URI, e.g. for `<img>` tags.
+    pub fn to_data_uri(&self) -> Option<String> {
+        match self {
+            Icon::Image { mime, data } => Some(format!("data:{};base64,{}", mime, data)),
+            _ => None,
+        }
+    }
+}
+
+impl<DB: Database> RusTOTPony<DB> {
+    /// Sets or, with `None`, removes the icon of the application.
+    pub fn set_icon(&mut self, name: &str, icon: Option<Icon>) -> Result<(), Error> {
+        let name = &self.resolve_name(name)?;
+        let app = match self.content_mut()?.applications.get_mut(name) {
+            Some(app) => app,
+            None => {
+                return Err(Error::new(
+                    ErrorCode::NotFound,
+                    format!("Application '{}' wasn't found", name),
+                ))
+            }
+        };
+        if app.icon == icon {
+            return Ok(());
+        }
+        app.icon = icon;
+        self.mark_dirty(name);
+        self.record(Operation::Update, name, Some("icon"));
+        self.push_change(Change::Updated(String::from(name), String::from("icon")));
+        Ok(())
+    }
+}
@@ -0,0 +1,503 @@
+use serde_derive::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cancellation;
+use crate::errors::{Error, ErrorCode};
+use crate::progress::{Progress, Task};
+use crate::{Database, Event, GenApp, RusTOTPony, TOTP};
+
+/// What happens to an application when it's imported.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum ImportAction {
+    Add,
+    /// The same application with the same secret is already there
+    Skip,
+    /// Another application already has this name or uses it as an alias
+    NameConflict,
+    /// The secret is already used by the named application
+    SecretConflict {
+        existing: String,
+    },
+    /// The secret is already used by the named application and the entry
+    /// was skipped as requested
+    Duplicate {
+        existing: String,
+    },
+    /// The application using the same secret was renamed to the entry name
+    Renamed {
+        existing: String,
+    },
+    /// The application using the same secret was replaced by the entry
+    Replaced {
+        existing: String,
+    },
+    /// The entry couldn't be read
+    Invalid {
+        error: String,
+    },
+}
+
+/// What to do with a new application when its secret is already used by
+/// another one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DuplicateResolution {
+    /// Leave the existing application alone and drop the new one
+    Skip,
+    /// Give the existing application the name of the new one
+    Rename,
+    /// Delete the existing application and add the new one
+    Replace,
+    /// Add the new application anyway
+    KeepBoth,
+}
+
+/// Planned (or, after the import, taken) action for an imported entry.
+#[derive(Serialize, Debug, Clone)]
+pub struct ImportItem {
+    pub name: String,
+    #[serde(flatten)]
+    pub action: ImportAction,
+}
+
+/// Outcome of an import, nothing but the added applications is changed.
+#[derive(Serialize, Debug, Default)]
+pub struct ImportReport {
+    pub items: Vec<ImportItem>,
+}
+
+impl ImportReport {
+    pub fn count(&self, action: &ImportAction) -> usize {
+        self.items
+            .iter()
+            .filter(|item| &item.action == action)
+            .count()
+    }
+
+    pub fn added(&self) -> usize {
+        self.count(&ImportAction::Add)
+    }
+
+    /// Number of entries which changed the database, i.e. were added,
+    /// renamed an existing application or replaced one.
+    pub fn imported(&self) -> usize {
+        self.items
+            .iter()
+            .filter(|item| {
+                matches!(
+                    item.action,
+                    ImportAction::Add
+                        | ImportAction::Renamed { .. }
+                        | ImportAction::Replaced { .. }
+                )
+            })
+            .count()
+    }
+
+    /// Number of entries left out because the database already has them.
+    pub fn skipped(&self) -> usize {
+        self.items
+            .iter()
+            .filter(|item| {
+                matches!(
+                    item.action,
+                    ImportAction::Skip | ImportAction::Duplicate { .. }
+                )
+            })
+            .count()
+    }
+
+    /// Number of entries left out because they conflict with existing
+    /// applications.
+    pub fn conflicts(&self) -> usize {
+        self.items
+            .iter()
+            .filter(|item| {
+                matches!(
+                    item.action,
+                    ImportAction::NameConflict | ImportAction::SecretConflict { .. }
+                )
+            })
+            .count()
+    }
+
+    pub fn invalid(&self) -> usize {
+        self.items
+            .iter()
+            .filter(|item| matches!(item.action, ImportAction::Invalid { .. }))
+            .count()
+    }
+
+    /// Tells whether some entries weren't imported because they conflict
+    /// with existing applications or couldn't be read.
+    pub fn has_problems(&self) -> bool {
+        self.items.iter().any(|item| {
+            matches!(
+                item.action,
+                ImportAction::NameConflict
+                    | ImportAction::SecretConflict { .. }
+                    | ImportAction::Invalid { .. }
+            )
+        })
+    }
+}
+
+impl<DB: Database> RusTOTPony<DB> {
+    /// Decides what importing the entries would do without changing
+    /// anything. Entries are checked against the existing applications and
+    /// against each other, so a backup listing an application twice adds it
+    /// once. An entry whose name is taken by another application is named
+    /// `name:username` instead if that is free, the same goes for names used
+    /// as aliases. Importers pass entries they failed to parse as errors,
+    /// they are reported as invalid.
+    pub fn preview_import(&self, entries: &[Result<GenApp, Error>]) -> Result<ImportReport, Error> {
+        let applications = &self.content()?.applications;
+        // Names are compared the way lookups match them
+        let mut names: HashMap<String, &[u8]> = applications
+            .iter()
+            .map(|(name, app)| (self.name_key(name), &app.secret_bytes[..]))
+            .collect();
+        let aliases: HashSet<String> = applications
+            .values()
+            .flat_map(|app| app.aliases.iter().map(|alias| self.name_key(alias)))
+            .collect();
+        let mut secrets: HashMap<&[u8], String> = applications
+            .iter()
+            .filter(|(_, app)| !app.secret_bytes.is_empty())
+            .map(|(name, app)| (&app.secret_bytes[..], name.clone()))
+            .collect();
+        let mut report = ImportReport::default();
+        for (idx, entry) in entries.iter().enumerate() {
+            let app = match entry {
+                Ok(app) => app,
+                Err(error) => {
+                    report.items.push(ImportItem {
+                        name: format!("#{}", idx + 1),
+                        action: ImportAction::Invalid {
+                            error: error.to_string(),
+                        },
+                    });
+                    continue;
+                }
+            };
+            let mut name = String::from(app.get_name());
+            let mut name_key = self.name_key(&name);
+            // URIs name applications after their issuer, so another account
+            // at the same issuer takes the issuer and the account name
+            let taken = |key: &String| {
+                aliases.contains(key)
+                    || names
+                        .get(key)
+                        .is_some_and(|secret| *secret != &app.secret_bytes[..])
+            };
+            if taken(&name_key) && !app.get_username().is_empty() {
+                let qualified = format!("{}:{}", name, app.get_username());
+                let qualified_key = self.name_key(&qualified);
+                if !taken(&qualified_key) {
+                    name = qualified;
+                    name_key = qualified_key;
+                }
+            }
+            let action = match (names.get(&name_key), secrets.get(&app.secret_bytes[..])) {
+                _ if aliases.contains(&name_key) => ImportAction::NameConflict,
+                (Some(secret), _) if *secret == &app.secret_bytes[..] => ImportAction::Skip,
+                (Some(_), _) => ImportAction::NameConflict,
+                (None, Some(existing)) => {
+                    // Resolving the conflict may give the name to the entry,
+                    // later entries can't take it
+                    names.insert(name_key, &app.secret_bytes);
+                    ImportAction::SecretConflict {
+                        existing: existing.clone(),
+                    }
+                }
+                (None, None) => {
+                    names.insert(name_key, &app.secret_bytes);
+                    secrets.insert(&app.secret_bytes, name.clone());
+                    ImportAction::Add
+                }
+            };
+            report.items.push(ImportItem { name, action });
+        }
+        Ok(report)
+    }
+
+    /// Returns the name of an application which already uses the secret.
+    /// Secrets of protected applications are encrypted, they can't be
+    /// compared.
+    pub fn find_same_secret(&self, secret_bytes: &[u8]) -> Result<Option<String>, Error> {
+        Ok(self
+            .content()?
+            .applications
+            .iter()
+            .filter(|(_, app)| !app.secret_bytes.is_empty() && app.secret_bytes == secret_bytes)
+            .map(|(name, _)| name.clone())
+            .min())
+    }
+
+    /// Makes room for a new application named `name` whose secret is used
+    /// by the `existing` one. Returns whether the new application should be
+    /// added after all.
+    pub fn resolve_duplicate(
+        &mut self,
+        existing: &str,
+        name: &str,
+        resolution: DuplicateResolution,
+    ) -> Result<bool, Error> {
+        match resolution {
+            DuplicateResolution::Skip => Ok(false),
+            DuplicateResolution::Rename => self.rename_application(existing, name).map(|_| false),
+            DuplicateResolution::Replace => self.delete_application(existing).map(|_| true),
+            DuplicateResolution::KeepBoth => Ok(true),
+        }
+    }
+
+    /// Adds the entries which `preview_import` plans to add and returns the
+    /// report. Entries whose secret is already used by another application
+    /// are passed to `resolve` along with the name of that application.
+    /// Conflicting names and invalid entries are left out, changes are
+    /// saved with `flush` as usual. If an entry can't be added, none are.
+    pub fn import_applications<F>(
+        &mut self,
+        entries: Vec<Result<GenApp, Error>>,
+        mut resolve: F,
+    ) -> Result<ImportReport, Error>
+    where
+        F: FnMut(&GenApp, &str) -> DuplicateResolution,
+    {
+        let mut report = self.preview_import(&entries)?;
+        // Taken back if the import fails or is cancelled, so it's never
+        // saved halfway
+        let before = (
+            self.content()?.clone(),
+            self.changes.len(),
+            self.dirty.clone(),
+        );
+        if let Err(err) = self.import_planned(&mut report, entries, &mut resolve) {
+            let (content, changes, dirty) = before;
+            *self.loaded_mut() = content;
+            self.changes.truncate(changes);
+            self.dirty = dirty;
+            return Err(err);
+        }
+        Ok(report)
+    }
+
+    fn import_planned<F>(
+        &mut self,
+        report: &mut ImportReport,
+        entries: Vec<Result<GenApp, Error>>,
+        resolve: &mut F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(&GenApp, &str) -> DuplicateResolution,
+    {
+        // Names of applications renamed or replaced by earlier entries, the
+        // preview still refers to them by their old names
+        let mut moved: HashMap<String, String> = HashMap::new();
+        let total = entries.len() as u64;
+        for (idx, (item, entry)) in report.items.iter_mut().zip(entries).enumerate() {
+            cancellation::check(self.cancellation.as_ref())?;
+            self.notify(&Event::Progress(Progress::new(
+                Task::Import,
+                idx as u64,
+                Some(total),
+            )));
+            let mut app = match entry {
+                Ok(app) => app,
+                Err(_) => continue,
+            };
+            // The preview may have given the entry another name
+            app.name = item.name.clone();
+            match item.action.clone() {
+                ImportAction::Add => self.insert_application(app)?,
+                ImportAction::SecretConflict { existing: original } => {
+                    let existing = moved.get(&original).cloned().unwrap_or(original.clone());
+                    let resolution = resolve(&app, &existing);
+                    let name = String::from(app.get_name());
+                    if self.resolve_duplicate(&existing, &name, resolution)? {
+                        self.insert_application(app)?;
+                    }
+                    item.action = match resolution {
+                        DuplicateResolution::Skip => ImportAction::Duplicate { existing },
+                        DuplicateResolution::KeepBoth => ImportAction::Add,
+                        DuplicateResolution::Rename => {
+                            moved.insert(original, name);
+                            ImportAction::Renamed { existing }
+                        }
+                        DuplicateResolution::Replace => {
+                            moved.insert(original, name);
+                            ImportAction::Replaced { existing }
+                        }
+                    };
+                }
+                _ => {}
+            }
+        }
+        self.notify(&Event::Progress(Progress::new(
+            Task::Import,
+            total,
+            Some(total),
+        )));
+        Ok(())
+    }
+}
+
+/// Reads a folder with a file per account, e.g. made by scripts extracting
+/// accounts from Authy, and returns an entry for every `otpauth://` URI in
+/// the files for `import_applications`. Subfolders are read too, hidden
+/// files and symlinks to folders are skipped. Files without URIs are reported as invalid entries,
+/// errors name the file.
+pub fn parse_uri_folder(path: &Path) -> Result<Vec<Result<GenApp, Error>>, Error> {
+    let mut files = Vec::new();
+    collect_files(path, &mut files).map_err(|err| {
+        Error::new(
+            ErrorCode::Io,
+            format!("Couldn't read folder {}: {}", path.display(), err),
+        )
+    })?;
+    files.sort();
+    let mut entries = Vec::new();
+    for file in files {
+        let shown = file
+            .strip_prefix(path)
+            .unwrap_or(&file)
+            .display()
+            .to_string();
+        let text = match fs::read(&file).map(String::from_utf8) {
+            Ok(Ok(text)) => text,
+            Ok(Err(_)) => {
+                entries.push(Err(Error::new(
+                    ErrorCode::InvalidArgument,
+                    format!("{}: isn't a text file", shown),
+                )));
+                continue;
+            }
+            Err(err) => {
+                entries.push(Err(Error::new(
+                    ErrorCode::Io,
+                    format!("{}: {}", shown, err),
+                )));
+                continue;
+            }
+        };
+        let uris: Vec<&str> = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| line.starts_with("otpauth://"))
+            .collect();
+        if uris.is_empty() {
+            entries.push(Err(Error::new(
+                ErrorCode::InvalidArgument,
+                format!("{}: has no otpauth URI", shown),
+            )));
+        }
+        for uri in uris {
+            entries.push(
+                GenApp::from_otpauth_uri(uri)
+                    .map_err(|err| Error::new(err.code(), format!("{}: {}", shown, err))),
+            );
+        }
+    }
+    Ok(entries)
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_files(&path, files)?;
+        } else if file_type.is_symlink() && path.is_dir() {
+            // Links may point back up the tree or out of the folder
+            continue;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Builds an application from a one-time password field of another password
+/// manager or authenticator, which holds either an `otpauth://` URI or just
+/// a base32 secret. The name and the username of the item replace the ones
+/// in the URI unless they are empty.
+pub(crate) fn from_otp_field(otp: &str, name: &str, username: &str) -> Result<GenApp, Error> {
+    let otp = otp.trim();
+    let (name, username) = (name.trim(), username.trim());
+    let mut app = if otp.starts_with("otpauth://") {
+        GenApp::from_otpauth_uri(otp)?
+    } else if name.is_empty() {
+        return Err(Error::new(
+            ErrorCode::InvalidArgument,
+            "Entry has neither a name nor an otpauth URI",
+        ));
+    } else {
+        GenApp::from_totp(name, username, &TOTP::new(otp)?)
+    };
+    if !name.is_empty() {
+        app.name = String::from(name);
+    }
+    if !username.is_empty() {
+        app.username = String::from(username);
+    }
+    if app.issuer.is_empty() {
+        app.issuer = app.name.clone();
+    }
+    Ok(app)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DatabaseContent;
+
+    struct Memory;
+
+    impl Database for Memory {
+        fn get_content(&self) -> Result<DatabaseContent, Error> {
+            Ok(DatabaseContent::default())
+        }
+
+        fn save_content(&self, _content: &DatabaseContent) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn entry_named_like_alias_conflicts() {
+        let mut totpony = RusTOTPony::with_content(Memory, DatabaseContent::default());
+        totpony
+            .create_application("Amazon Web Services root", "", "JBSWY3DPEHPK3PXP")
+            .unwrap();
+        totpony
+            .add_alias("Amazon Web Services root", "aws")
+            .unwrap();
+        let entries = vec![
+            Ok(GenApp::from_totp(
+                "github",
+                "",
+                &TOTP::new("GEZDGNBVGY3TQOJQ").unwrap(),
+            )),
+            Ok(GenApp::from_totp(
+                "AWS",
+                "",
+                &TOTP::new("MFRGGZDFMZTWQ2LK").unwrap(),
+            )),
+        ];
+        let report = totpony
+            .import_applications(entries, |_, _| DuplicateResolution::Skip)
+            .unwrap();
+        assert_eq!(report.items[1].action, ImportAction::NameConflict);
+        assert!(totpony.get_application("github").is_ok());
+        assert_eq!(
+            totpony.find_alias_owner("aws").unwrap().unwrap(),
+            "Amazon Web Services root"
+        );
+    }
+}
@@ -0,0 +1,489 @@
+use serde::de::DeserializeOwned;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tracing::{debug, info};
+
+use crate::cipher::Cipher;
+use crate::crypto::Hash;
+use crate::decoy::{self, Slot};
+use crate::errors::{Error, ErrorCode};
+use crate::padding::MIN_BUCKET_SIZE;
+use crate::progress::Task;
+use crate::random;
+use crate::summary::{self, ApplicationSummary};
+use crate::{DatabaseContent, GenApp, HistoryRecord, JsonDatabase, JsonDatabaseSchema, Tombstone};
+
+// The locator holds the length of the index, padded so that it fills a
+// block of AES-CBC, the zero bytes also tell a wrong key
+const LOCATOR_SIZE: usize = 15;
+const LENGTH_SIZE: usize = 8;
+
+/// Index of a vault of format version 3, in which every application is
+/// encrypted on its own.
+///
+/// Layout of the vault: a sealed locator with the length of the index,
+/// the sealed index and the sealed applications, followed by random bytes
+/// up to a power-of-two sized bucket. The index is sealed with a key
+/// derived from the database key, each application with a key derived
+/// from it and the identifier of the application. Listing reads only the
+/// index, a single application can be decrypted alone and a save seals
+/// again only the applications which were changed.
+#[derive(Serialize, Deserialize)]
+struct Index {
+    version: u8,
+    entries: Vec<IndexEntry>,
+    #[serde(default)]
+    history: Vec<HistoryRecord>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tombstones: Vec<Tombstone>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct IndexEntry {
+    id: String,
+    /// Place of the sealed application after the index
+    offset: usize,
+    length: usize,
+    summary: ApplicationSummary,
+}
+
+impl IndexEntry {
+    /// The sealed application among the `entries` after the index, `None`
+    /// if a damaged entry points outside of them.
+    fn sealed<'a>(&self, entries: &'a [u8]) -> Option<&'a [u8]> {
+        entries.get(self.offset..self.offset.checked_add(self.length)?)
+    }
+}
+
+/// Index opened from a vault, with the sealed applications following it.
+struct OpenedIndex<'a> {
+    slot: Slot,
+    index: Index,
+    entries: &'a [u8],
+}
+
+impl JsonDatabase {
+    /// Finds the vault which can be decrypted with the key and returns its
+    /// place with the content parsed, in any layout of the vault.
+    pub(crate) fn open_vault<T: DeserializeOwned>(
+        &self,
+        data: &[u8],
+        key: &[u8],
+    ) -> Result<(Slot, T), Error> {
+        let header = self.header();
+        if !header.is_indexed() {
+            return decoy::open(
+                self.cipher(),
+                data,
+                key,
+                header.compressed,
+                &self.reporter(Task::Decryption),
+            );
+        }
+        let opened = open_index(self.cipher(), data, key)?;
+        let content = read_entries(
+            self.cipher(),
+            &opened,
+            key,
+            &self.reporter(Task::Decryption),
+        )?;
+        let schema = JsonDatabaseSchema {
+            version: opened.index.version,
+            content,
+        };
+        // Only callers needing something else than the schema pay for the
+        // conversion
+        let value = serde_json::to_value(&schema).expect("Couldn't serialize data to JSON");
+        let parsed = serde_json::from_value(value).map_err(|err| {
+            Error::new(
+                ErrorCode::Damaged,
+                format!("Couldn't parse JSON from database file: {}", err),
+            )
+        })?;
+        Ok((opened.slot, parsed))
+    }
+
+    /// Finds the vault which can be decrypted with the key, of an indexed
+    /// database only the index is decrypted.
+    pub(crate) fn find_vault(&self, data: &[u8], key: &[u8]) -> Result<Slot, Error> {
+        let header = self.header();
+        if header.is_indexed() {
+            open_index(self.cipher(), data, key).map(|opened| opened.slot)
+        } else {
+            let opened = decoy::open::<serde::de::IgnoredAny>(
+                self.cipher(),
+                data,
+                key,
+                header.compressed,
+                &|_, _| {},
+            );
+            opened.map(|(slot, _)| slot)
+        }
+    }
+
+    /// Summaries of the applications of an indexed database, read from the
+    /// index alone.
+    pub(crate) fn read_indexed_summaries(
+        &self,
+        data: &[u8],
+    ) -> Result<Vec<ApplicationSummary>, Error> {
+        let opened = open_index(self.cipher(), data, &self.database_key())?;
+        let mut summaries: Vec<ApplicationSummary> = opened
+            .index
+            .entries
+            .into_iter()
+            .map(|entry| entry.summary)
+            .collect();
+        summary::set_last_used(&mut summaries, &opened.index.history);
+        Ok(summaries)
+    }
+
+    /// Decrypts a single application of an indexed database, the others
+    /// stay sealed.
+    pub(crate) fn read_indexed_application(&self, name: &str) -> Result<Option<GenApp>, Error> {
+        let data = match self.read_vaults()? {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        let key = self.database_key();
+        let opened = open_index(self.cipher(), &data, &key)?;
+        match opened
+            .index
+            .entries
+            .iter()
+            .find(|entry| entry.summary.get_name() == name)
+        {
+            Some(entry) => open_entry(self.cipher(), entry, opened.entries, &key).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Writes the changes of the named applications into an indexed
+    /// database. The sealed records of the other applications are copied
+    /// as they are.
+    pub(crate) fn save_indexed_changes(
+        &self,
+        content: &DatabaseContent,
+        changed: &HashSet<String>,
+    ) -> Result<(), Error> {
+        let existing = match self.read_vaults()? {
+            Some(existing) => existing,
+            None => return self.save_database_file(content),
+        };
+        let key = self.database_key();
+        let opened = open_index(self.cipher(), &existing, &key)?;
+        let vault = seal_indexed(
+            self.cipher(),
+            content,
+            &key,
+            Some((&opened, changed)),
+            &self.reporter(Task::Encryption),
+        );
+        let data = decoy::place(&existing, Some(opened.slot), &vault);
+        self.write_vaults(&data)?;
+        info!(
+            applications = content.applications.len(),
+            changed = changed.len(),
+            "saved changed applications"
+        );
+        self.clear_journal(content, &key)
+    }
+}
+
+/// Seals the content into an indexed vault.
+pub(crate) fn seal(
+    cipher: &dyn Cipher,
+    content: &DatabaseContent,
+    key: &[u8],
+    report: &dyn Fn(u64, u64),
+) -> Vec<u8> {
+    seal_indexed(cipher, content, key, None, report)
+}
+
+fn index_key(key: &[u8]) -> Vec<u8> {
+    Hash::Sha256.hmac(key, b"index")
+}
+
+fn entry_key(key: &[u8], id: &str) -> Vec<u8> {
+    Hash::Sha256.hmac(key, format!("entry:{}", id).as_bytes())
+}
+
+fn locator_size(cipher: &dyn Cipher) -> usize {
+    cipher.nonce_size() + LOCATOR_SIZE + cipher.overhead()
+}
+
+// Tries the start of every slot, a vault always begins with its locator
+fn open_index<'a>(
+    cipher: &dyn Cipher,
+    data: &'a [u8],
+    key: &[u8],
+) -> Result<OpenedIndex<'a>, Error> {
+    let index_key = index_key(key);
+    for (slot, bytes) in decoy::slots(cipher, data) {
+        if let Some((index, entries)) = open_slot(cipher, bytes, &index_key) {
+            return Ok(OpenedIndex {
+                slot,
+                index,
+                entries,
+            });
+        }
+    }
+    Err(Error::new(
+        ErrorCode::WrongPassword,
+        "Couldn't decrypt data: wrong password or corrupted file",
+    ))
+}
+
+fn open_slot<'a>(
+    cipher: &dyn Cipher,
+    bytes: &'a [u8],
+    index_key: &[u8],
+) -> Option<(Index, &'a [u8])> {
+    let (locator, rest) = bytes.split_at_checked(locator_size(cipher))?;
+    let locator = cipher.open(locator, index_key).ok()?;
+    if locator.len() != LOCATOR_SIZE || locator[LENGTH_SIZE..].iter().any(|byte| *byte != 0) {
+        return None;
+    }
+    let mut length = [0u8; LENGTH_SIZE];
+    length.copy_from_slice(&locator[..LENGTH_SIZE]);
+    let length = usize::try_from(u64::from_be_bytes(length)).ok()?;
+    let (sealed, entries) = rest.split_at_checked(length)?;
+    let index = cipher.open(sealed, index_key).ok()?;
+    Some((serde_json::from_slice(&index).ok()?, entries))
+}
+
+fn open_entry(
+    cipher: &dyn Cipher,
+    entry: &IndexEntry,
+    entries: &[u8],
+    key: &[u8],
+) -> Result<GenApp, Error> {
+    let damaged = || {
+        Error::new(
+            ErrorCode::Damaged,
+            format!(
+                "Database is damaged: application '{}' can't be read",
+                entry.summary.get_name()
+            ),
+        )
+    };
+    let sealed = entry.sealed(entries).ok_or_else(damaged)?;
+    let data = cipher
+        .open(sealed, &entry_key(key, &entry.id))
+        .map_err(|_| damaged())?;
+    let app: GenApp = serde_json::from_slice(&data).map_err(|_| damaged())?;
+    if app.get_id() != entry.id || app.get_name() != entry.summary.get_name() {
+        return Err(damaged());
+    }
+    Ok(app)
+}
+
+fn read_entries(
+    cipher: &dyn Cipher,
+    opened: &OpenedIndex,
+    key: &[u8],
+    report: &dyn Fn(u64, u64),
+) -> Result<DatabaseContent, Error> {
+    let mut applications = HashMap::new();
+    let total = opened.index.entries.len() as u64;
+    for (idx, entry) in opened.index.entries.iter().enumerate() {
+        let app = open_entry(cipher, entry, opened.entries, key)?;
+        applications.insert(String::from(app.get_name()), app);
+        report(idx as u64 + 1, total);
+    }
+    Ok(DatabaseContent {
+        applications,
+        history: opened.index.history.clone(),
+        tombstones: opened.index.tombstones.clone(),
+    })
+}
+
+// Records of the previous vault are reused for applications which weren't
+// changed, they are sealed with the same keys
+fn seal_indexed(
+    cipher: &dyn Cipher,
+    content: &DatabaseContent,
+    key: &[u8],
+    previous: Option<(&OpenedIndex, &HashSet<String>)>,
+    report: &dyn Fn(u64, u64),
+) -> Vec<u8> {
+    let total = content.applications.len() as u64;
+    let mut apps: Vec<&GenApp> = content.applications.values().collect();
+    apps.sort_by(|a, b| a.get_name().cmp(b.get_name()));
+    let mut entries = Vec::new();
+    let mut sealed_entries = Vec::new();
+    let mut resealed = 0;
+    for app in apps {
+        let reused = previous.and_then(|(opened, changed)| {
+            if changed.contains(app.get_name()) {
+                return None;
+            }
+            let entry = opened.index.entries.iter().find(|entry| {
+                entry.id == app.get_id() && entry.summary.get_name() == app.get_name()
+            })?;
+            entry.sealed(opened.entries)
+        });
+        let sealed = match reused {
+            Some(sealed) => sealed.to_vec(),
+            None => {
+                resealed += 1;
+                let data = serde_json::to_vec(app).expect("Couldn't serialize data to JSON");
+                cipher.seal(&data, &entry_key(key, app.get_id()))
+            }
+        };
+        entries.push(IndexEntry {
+            id: String::from(app.get_id()),
+            offset: sealed_entries.len(),
+            length: sealed.len(),
+            summary: ApplicationSummary::from(app),
+        });
+        sealed_entries.extend(sealed);
+        report(entries.len() as u64, total);
+    }
+    debug!(
+        applications = entries.len(),
+        resealed, "sealed applications"
+    );
+
+    let index = Index {
+        version: crate::DATABASE_VERSION,
+        entries,
+        history: content.history.clone(),
+        tombstones: content.tombstones.clone(),
+    };
+    let index_key = index_key(key);
+    let index = serde_json::to_vec(&index).expect("Couldn't serialize data to JSON");
+    let sealed_index = cipher.seal(&index, &index_key);
+    let mut locator = (sealed_index.len() as u64).to_be_bytes().to_vec();
+    locator.resize(LOCATOR_SIZE, 0);
+    let mut vault = [
+        cipher.seal(&locator, &index_key),
+        sealed_index,
+        sealed_entries,
+    ]
+    .concat();
+    // Vaults fill buckets like the other layouts, see `padding::pad`
+    let bucket = (vault.len() - cipher.nonce_size())
+        .next_power_of_two()
+        .max(MIN_BUCKET_SIZE);
+    vault.extend(random::bytes(cipher.nonce_size() + bucket - vault.len()));
+    vault
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cipher::{AesCbc, AesGcm, XChaCha20};
+    use crate::header::Kdf;
+    use crate::import::from_otp_field;
+    use crate::{MemoryStorage, RusTOTPony};
+
+    const KEY: [u8; 32] = [7; 32];
+    const CIPHERS: [&dyn Cipher; 3] = [&AesCbc, &AesGcm, &XChaCha20];
+
+    fn content() -> DatabaseContent {
+        let mut content = DatabaseContent::default();
+        for name in ["GitHub", "GitLab"] {
+            let app = from_otp_field("JBSWY3DPEHPK3PXP", name, "octocat").unwrap();
+            content.applications.insert(String::from(name), app);
+        }
+        content
+    }
+
+    fn names(content: &DatabaseContent) -> Vec<&str> {
+        let mut names: Vec<&str> = content.applications.keys().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+
+    fn content_of(opened: &OpenedIndex) -> DatabaseContent {
+        read_entries(&XChaCha20, opened, &KEY, &|_, _| {}).unwrap()
+    }
+
+    #[test]
+    fn index_survives_round_trip() {
+        for cipher in CIPHERS {
+            let vault = seal(cipher, &content(), &KEY, &|_, _| {});
+            assert!((vault.len() - cipher.nonce_size()).is_power_of_two());
+            let opened = open_index(cipher, &vault, &KEY).unwrap();
+            assert!(opened.slot == Slot::Whole);
+            let read = read_entries(cipher, &opened, &KEY, &|_, _| {}).unwrap();
+            assert_eq!(names(&read), ["GitHub", "GitLab"]);
+        }
+    }
+
+    #[test]
+    fn wrong_key_is_rejected() {
+        for cipher in CIPHERS {
+            let vault = seal(cipher, &content(), &KEY, &|_, _| {});
+            let err = open_index(cipher, &vault, &[8; 32]).err().unwrap();
+            assert_eq!(err.code(), ErrorCode::WrongPassword);
+        }
+    }
+
+    #[test]
+    fn misplaced_entries_are_damaged() {
+        let vault = seal(&XChaCha20, &content(), &KEY, &|_, _| {});
+        let mut opened = open_index(&XChaCha20, &vault, &KEY).unwrap();
+        let (first, second) = opened.index.entries.split_at_mut(1);
+        std::mem::swap(&mut first[0].offset, &mut second[0].offset);
+        std::mem::swap(&mut first[0].length, &mut second[0].length);
+        let err = read_entries(&XChaCha20, &opened, &KEY, &|_, _| {})
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), ErrorCode::Damaged);
+        opened.index.entries[0].offset = usize::MAX;
+        let err = open_entry(&XChaCha20, &opened.index.entries[0], opened.entries, &KEY)
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), ErrorCode::Damaged);
+    }
+
+    #[test]
+    fn only_changed_applications_are_resealed() {
+        let vault = seal(&XChaCha20, &content(), &KEY, &|_, _| {});
+        let previous = open_index(&XChaCha20, &vault, &KEY).unwrap();
+        let changed = HashSet::from([String::from("GitLab")]);
+        let saved = seal_indexed(
+            &XChaCha20,
+            &content_of(&previous),
+            &KEY,
+            Some((&previous, &changed)),
+            &|_, _| {},
+        );
+        let opened = open_index(&XChaCha20, &saved, &KEY).unwrap();
+        let sealed = |opened: &OpenedIndex, idx: usize| {
+            opened.index.entries[idx]
+                .sealed(opened.entries)
+                .unwrap()
+                .to_vec()
+        };
+        assert_eq!(sealed(&opened, 0), sealed(&previous, 0));
+        assert_ne!(sealed(&opened, 1), sealed(&previous, 1));
+    }
+
+    #[test]
+    fn indexed_database_reads_single_application() {
+        let storage = MemoryStorage::default();
+        let database = |password: &'static str| {
+            JsonDatabase::builder()
+                .storage(Box::new(storage.clone()))
+                .secret(move || String::from(password))
+                .kdf(Kdf::scrypt(10).unwrap())
+                .indexed(true)
+                .build()
+                .unwrap()
+        };
+        let mut totpony = RusTOTPony::new(database("correct horse"));
+        totpony
+            .create_application("GitHub", "octocat", "JBSWY3DPEHPK3PXP")
+            .unwrap();
+        totpony.flush().unwrap();
+        let db = database("correct horse");
+        let app = db.read_indexed_application("GitHub").unwrap().unwrap();
+        assert_eq!(app.get_secret(), "JBSWY3DPEHPK3PXP");
+        assert!(db.read_indexed_application("GitLab").unwrap().is_none());
+        let err = database("battery staple").load_content().err().unwrap();
+        assert_eq!(err.code(), ErrorCode::WrongPassword);
+    }
+}
@@ -0,0 +1,590 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Seek, SeekFrom, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{debug, info};
+
+use crate::crypto::Hash;
+use crate::errors::{Error, ErrorCode};
+use crate::{padding, permissions, storage};
+use crate::{DatabaseContent, GenApp, HistoryRecord, JsonDatabase, JsonDatabaseSchema, Tombstone};
+
+/// How many journal records are kept before they are compacted into the
+/// database file, unless the builder says otherwise.
+pub const DEFAULT_COMPACT_AFTER: usize = 16;
+
+const LENGTH_SIZE: usize = 4;
+/// First bytes of journals tied to the database file, older journals
+/// start right with a record
+const MAGIC: &[u8; 8] = b"RTPJRNL\x01";
+/// First bytes of journals tied to the database file and to the file
+/// which is about to replace it
+const SAVING_MAGIC: &[u8; 8] = b"RTPJRNL\x02";
+const BASE_SIZE: usize = 32;
+
+/// Write-ahead journal of a database in a local file, kept next to it as
+/// `<file>.journal`. A save appends the changed applications as an
+/// encrypted record and waits until it's on the disk, which is quicker
+/// and safer than rewriting the whole file.
+///
+/// The journal starts with a SHA-256 hash of the database file its records
+/// apply to. Writes of the database point it at the new file, so a file
+/// replaced in another way, e.g. by a sync or a restored snapshot, makes
+/// the journal refused instead of replayed on top of other content. While
+/// the file is being replaced the journal holds the hashes of both files,
+/// a crash at any moment leaves it applicable to the file on the disk.
+///
+/// The journal is rewritten the way the database file is: next to it,
+/// synced and moved over it. Only new records are appended in place.
+pub(crate) struct Journal {
+    compact_after: usize,
+    /// Length of the history which is already saved
+    saved_history: Mutex<usize>,
+}
+
+/// Changes of one save, the applications are `None` when they were
+/// removed.
+#[derive(Serialize, Deserialize)]
+struct JournalRecord {
+    applications: Vec<(String, Option<GenApp>)>,
+    history_from: usize,
+    history: Vec<HistoryRecord>,
+    tombstones: Vec<Tombstone>,
+}
+
+impl Journal {
+    pub(crate) fn new(compact_after: usize) -> Journal {
+        Journal {
+            compact_after: compact_after.max(1),
+            saved_history: Mutex::new(0),
+        }
+    }
+
+    fn saved_history(&self) -> usize {
+        *self
+            .saved_history
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn set_saved_history(&self, length: usize) {
+        *self
+            .saved_history
+            .lock()
+            .unwrap_or_else(|err| err.into_inner()) = length;
+    }
+}
+
+impl JsonDatabase {
+    // A journal left by a program which had it enabled is applied even if
+    // this one doesn't use it
+    fn journal_path(&self) -> Option<PathBuf> {
+        Some(journal_path(self.storage.local_path()?))
+    }
+
+    // Hash of the database file as it's stored now
+    fn file_hash(&self) -> Result<Vec<u8>, Error> {
+        Ok(Hash::Sha256.digest(&self.storage.read()?.unwrap_or_default()))
+    }
+
+    /// Whether there are records which aren't compacted yet.
+    pub(crate) fn has_journal(&self) -> bool {
+        self.journal_path().is_some_and(|path| path.exists())
+    }
+
+    /// Appends the changes of the named applications to the journal, once
+    /// it returns they survive a crash. Records are compacted into the
+    /// database file when there are enough of them.
+    pub(crate) fn append_to_journal(
+        &self,
+        content: &DatabaseContent,
+        changed: &HashSet<String>,
+    ) -> Result<(), Error> {
+        self.check_cancelled()?;
+        let (journal, path) = match (&self.journal, self.journal_path()) {
+            (Some(journal), Some(path)) => (journal, path),
+            _ => return Err(Error::new(ErrorCode::NotFound, "Database has no journal")),
+        };
+        let mut names: Vec<&String> = changed.iter().collect();
+        names.sort();
+        let history_from = journal.saved_history().min(content.history.len());
+        let record = JournalRecord {
+            applications: names
+                .into_iter()
+                .map(|name| (name.clone(), content.applications.get(name).cloned()))
+                .collect(),
+            history_from,
+            history: content.history[history_from..].to_vec(),
+            tombstones: content.tombstones.clone(),
+        };
+        let mut data = serde_json::to_vec(&record).expect("Couldn't serialize data to JSON");
+        let cipher = self.cipher();
+        padding::pad(&mut data, cipher.overhead(), b' ');
+        let key = self.database_key();
+        let sealed = cipher.seal(&data, &key);
+
+        let file_hash = self.file_hash()?;
+        let existing = read_journal(&path)?;
+        let (bases, records) = split_journal(&existing);
+        if !bases.is_empty() && !bases.contains(&file_hash.as_slice()) {
+            return Err(mismatch(&path));
+        }
+        let mut header_size = existing.len() - records.len();
+        let records = split_records(records);
+        if bases.is_empty() {
+            // A new or an older journal gets the header first
+            write_journal(&path, &encode(&[&file_hash], &records))?;
+            header_size = MAGIC.len() + BASE_SIZE;
+        }
+        let failed = |err: std::io::Error| {
+            Error::new(ErrorCode::Io, format!("Couldn't write journal: {}", err))
+        };
+        permissions::check_write_target(&path)?;
+        let mut file = OpenOptions::new().write(true).open(&path).map_err(failed)?;
+        // A record torn by a crash was never acknowledged, it's dropped
+        let valid: usize = records.iter().map(|r| LENGTH_SIZE + r.len()).sum();
+        file.set_len((header_size + valid) as u64)
+            .and_then(|_| file.seek(SeekFrom::End(0)))
+            .map_err(failed)?;
+        file.write_all(&(sealed.len() as u32).to_be_bytes())
+            .and_then(|_| file.write_all(&sealed))
+            .and_then(|_| file.sync_data())
+            .map_err(failed)?;
+        journal.set_saved_history(content.history.len());
+        debug!(
+            path = %path.display(),
+            records = records.len() + 1,
+            "appended to journal"
+        );
+        // The changes are saved already, a cancelled save isn't compacted
+        if records.len() + 1 >= journal.compact_after && self.check_cancelled().is_ok() {
+            let existing = self.read_vaults()?.unwrap_or_default();
+            self.write_content(content, &existing, &key)?;
+        }
+        Ok(())
+    }
+
+    /// Applies the journal to the content read from the database file.
+    /// Records which can't be decrypted with the key belong to the other
+    /// vault of a database with a decoy, they are left alone. A journal
+    /// written for another file fails, one left by an interrupted save
+    /// applies to either file.
+    pub(crate) fn replay_journal(
+        &self,
+        content: &mut DatabaseContent,
+        key: &[u8],
+    ) -> Result<(), Error> {
+        let mut replayed = 0;
+        if let Some(path) = self.journal_path() {
+            let existing = read_journal(&path)?;
+            let (bases, records) = split_journal(&existing);
+            if !bases.is_empty() && !bases.contains(&self.file_hash()?.as_slice()) {
+                return Err(mismatch(&path));
+            }
+            for sealed in split_records(records) {
+                if let Some(record) = self.open_record(sealed, key) {
+                    record.apply(content);
+                    replayed += 1;
+                }
+            }
+        }
+        if replayed > 0 {
+            info!(records = replayed, "replayed journal");
+        }
+        if let Some(journal) = &self.journal {
+            journal.set_saved_history(content.history.len());
+        }
+        Ok(())
+    }
+
+    /// Called after the content was written to the database file. Records
+    /// of the vault opened by the key are in the file now, records of the
+    /// other vault are kept.
+    pub(crate) fn clear_journal(&self, content: &DatabaseContent, key: &[u8]) -> Result<(), Error> {
+        if let Some(journal) = &self.journal {
+            journal.set_saved_history(content.history.len());
+        }
+        let path = match self.journal_path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(()),
+        };
+        let existing = read_journal(&path)?;
+        let (_, records) = split_journal(&existing);
+        let kept: Vec<&[u8]> = split_records(records)
+            .into_iter()
+            .filter(|sealed| self.open_record(sealed, key).is_none())
+            .collect();
+        if kept.is_empty() {
+            remove_journal(&path).map_err(|err| {
+                Error::new(ErrorCode::Io, format!("Couldn't compact journal: {}", err))
+            })?;
+        } else {
+            write_journal(&path, &encode(&[&self.file_hash()?], &kept))?;
+        }
+        debug!(path = %path.display(), "compacted journal");
+        Ok(())
+    }
+
+    /// Writes the changes kept in the journal into the database file, e.g.
+    /// before the file is copied elsewhere. It's done on its own every few
+    /// saves.
+    pub fn compact_journal(&self) -> Result<(), Error> {
+        if !self.has_journal() {
+            return Ok(());
+        }
+        if self.read_only {
+            return Err(Error::new(
+                ErrorCode::ReadOnly,
+                "Database is opened in read-only mode",
+            ));
+        }
+        let existing = self
+            .read_vaults()?
+            .ok_or_else(|| Error::new(ErrorCode::NotFound, "There is no database yet"))?;
+        let key = self.database_key();
+        let mut schema: JsonDatabaseSchema = self.decrypt_with_key(&existing, &key)?;
+        self.replay_journal(&mut schema.content, &key)?;
+        self.write_content(&schema.content, &existing, &key)
+    }
+
+    /// Called before this database replaces its file with `file`. Until
+    /// `rebase_journal` the journal applies to both files.
+    pub(crate) fn prepare_journal(&self, file: &[u8]) -> Result<(), Error> {
+        let path = match self.journal_path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(()),
+        };
+        let file_hash = self.file_hash()?;
+        let existing = read_journal(&path)?;
+        let (bases, records) = split_journal(&existing);
+        if !bases.is_empty() && !bases.contains(&file_hash.as_slice()) {
+            return Err(mismatch(&path));
+        }
+        write_journal(
+            &path,
+            &encode(
+                &[&file_hash, &Hash::Sha256.digest(file)],
+                &split_records(records),
+            ),
+        )
+    }
+
+    /// Points the journal at the file just written by this database. Its
+    /// records still apply to the new file, which has the same content or
+    /// has them already.
+    pub(crate) fn rebase_journal(&self, file: &[u8]) -> Result<(), Error> {
+        let path = match self.journal_path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(()),
+        };
+        let existing = read_journal(&path)?;
+        let (_, records) = split_journal(&existing);
+        write_journal(
+            &path,
+            &encode(&[&Hash::Sha256.digest(file)], &split_records(records)),
+        )
+    }
+
+    fn open_record(&self, sealed: &[u8], key: &[u8]) -> Option<JournalRecord> {
+        let data = self.cipher().open(sealed, key).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+}
+
+impl JournalRecord {
+    // Replaying a record twice, after a crash during compaction, changes
+    // nothing
+    fn apply(self, content: &mut DatabaseContent) {
+        for (name, app) in self.applications {
+            match app {
+                Some(app) => content.applications.insert(name, app),
+                None => content.applications.remove(&name),
+            };
+        }
+        content.history.truncate(self.history_from);
+        content.history.extend(self.history);
+        content.tombstones = self.tombstones;
+    }
+}
+
+/// Journal of the database file at the path, it may not exist.
+pub(crate) fn journal_path(db_path: &Path) -> PathBuf {
+    let mut name = db_path.as_os_str().to_owned();
+    name.push(".journal");
+    PathBuf::from(name)
+}
+
+/// Fails if the database file has changes in its journal, which a copy of
+/// the file alone would lack.
+pub(crate) fn check_no_journal(db_path: &Path) -> Result<(), Error> {
+    if journal_path(db_path).exists() {
+        return Err(Error::new(ErrorCode::Database, format!(
+            "Database {} has changes in its journal which aren't in the file yet, compact the journal first",
+            db_path.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Removes the journal of a database file which was replaced, its records
+/// were written for the previous file.
+pub(crate) fn discard_journal(db_path: &Path) -> Result<(), Error> {
+    remove_journal(&journal_path(db_path))
+        .map_err(|err| Error::new(ErrorCode::Io, format!("Couldn't remove journal: {}", err)))
+}
+
+// The data is synced next to the journal and moved over it, so a crash
+// leaves either the previous journal or the new one
+fn write_journal(path: &Path, data: &[u8]) -> Result<(), Error> {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".tmp");
+    let temporary_path = PathBuf::from(name);
+    permissions::check_write_target(path)?;
+    permissions::check_write_target(&temporary_path)?;
+    let write = || {
+        match fs::remove_file(&temporary_path) {
+            Err(err) if err.kind() != ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(permissions::FILE_MODE);
+        let mut file = options.open(&temporary_path)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&temporary_path, path)?;
+        storage::sync_dir(path)
+    };
+    write().map_err(|err| Error::new(ErrorCode::Io, format!("Couldn't write journal: {}", err)))
+}
+
+fn remove_journal(path: &Path) -> std::io::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err),
+        Ok(()) => storage::sync_dir(path),
+    }
+}
+
+fn mismatch(path: &Path) -> Error {
+    Error::new(ErrorCode::Damaged, format!(
+        "Journal {} was written for another database file, which was replaced since; remove the journal to open the database",
+        path.display()
+    ))
+}
+
+fn read_journal(path: &Path) -> Result<Vec<u8>, Error> {
+    match fs::read(path) {
+        Ok(data) => Ok(data),
+        Err(ref err) if err.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(Error::new(
+            ErrorCode::Io,
+            format!("Couldn't read journal: {}", err),
+        )),
+    }
+}
+
+// Hashes of the database files the records apply to, if the journal has
+// them, and the records
+fn split_journal(data: &[u8]) -> (Vec<&[u8]>, &[u8]) {
+    for (magic, count) in [(MAGIC, 1), (SAVING_MAGIC, 2)] {
+        match data.strip_prefix(&magic[..]) {
+            Some(rest) if rest.len() >= count * BASE_SIZE => {
+                let (bases, records) = rest.split_at(count * BASE_SIZE);
+                return (bases.chunks(BASE_SIZE).collect(), records);
+            }
+            _ => {}
+        }
+    }
+    (Vec::new(), data)
+}
+
+fn encode(bases: &[&[u8]], records: &[&[u8]]) -> Vec<u8> {
+    let magic = if bases.len() > 1 { SAVING_MAGIC } else { MAGIC };
+    let mut data = [&magic[..], &bases.concat()].concat();
+    for record in records {
+        data.extend((record.len() as u32).to_be_bytes());
+        data.extend_from_slice(record);
+    }
+    data
+}
+
+// Records are prefixed with their length, an incomplete one at the end was
+// being written when the program stopped
+fn split_records(data: &[u8]) -> Vec<&[u8]> {
+    let mut records = Vec::new();
+    let mut rest = data;
+    while rest.len() >= LENGTH_SIZE {
+        let (length, tail) = rest.split_at(LENGTH_SIZE);
+        let length = u32::from_be_bytes([length[0], length[1], length[2], length[3]]) as usize;
+        if tail.len() < length {
+            break;
+        }
+        let (record, tail) = tail.split_at(length);
+        records.push(record);
+        rest = tail;
+    }
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Kdf, RusTOTPony};
+
+    struct Dir(PathBuf);
+
+    impl Drop for Dir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn temp_dir(name: &str) -> Dir {
+        let path = std::env::temp_dir().join(format!(
+            "rustotpony-journal-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Dir(path)
+    }
+
+    fn database(path: &Path) -> JsonDatabase {
+        JsonDatabase::builder()
+            .path(path.to_path_buf())
+            .secret(|| String::from("password"))
+            .kdf(Kdf::Sha256)
+            .journal(DEFAULT_COMPACT_AFTER)
+            .build()
+            .unwrap()
+    }
+
+    fn add(path: &Path, name: &str) {
+        let mut app = RusTOTPony::new(database(path));
+        app.create_application(name, "", "JBSWY3DPEHPK3PXP")
+            .unwrap();
+        app.flush().unwrap();
+    }
+
+    fn names(path: &Path) -> Vec<String> {
+        let mut names: Vec<String> = database(path)
+            .load_content()
+            .unwrap()
+            .applications
+            .into_keys()
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Database file with a journal holding a record of "b", and the file
+    /// compacting it writes.
+    struct Compaction {
+        db_path: PathBuf,
+        journal_path: PathBuf,
+        before: Vec<u8>,
+        journal: Vec<u8>,
+        after: Vec<u8>,
+    }
+
+    impl Compaction {
+        fn new(dir: &Dir) -> Compaction {
+            let db_path = dir.0.join("db.json");
+            add(&db_path, "a");
+            add(&db_path, "b");
+            let journal_path = journal_path(&db_path);
+            let before = fs::read(&db_path).unwrap();
+            let journal = fs::read(&journal_path).unwrap();
+            database(&db_path).compact_journal().unwrap();
+            assert!(!journal_path.exists());
+            let after = fs::read(&db_path).unwrap();
+            Compaction {
+                db_path,
+                journal_path,
+                before,
+                journal,
+                after,
+            }
+        }
+
+        fn records(&self) -> Vec<&[u8]> {
+            split_records(split_journal(&self.journal).1)
+        }
+
+        // State of the files when the program stopped
+        fn crash(&self, file: &[u8], bases: &[&[u8]]) {
+            fs::write(&self.db_path, file).unwrap();
+            fs::write(&self.journal_path, encode(bases, &self.records())).unwrap();
+        }
+
+        fn check_recovered(&self) {
+            assert_eq!(names(&self.db_path), ["a", "b"]);
+            add(&self.db_path, "c");
+            assert_eq!(names(&self.db_path), ["a", "b", "c"]);
+        }
+    }
+
+    #[test]
+    fn replays_after_crash_before_file_is_replaced() {
+        let dir = temp_dir("before-replace");
+        let compaction = Compaction::new(&dir);
+        let (before, after) = (
+            Hash::Sha256.digest(&compaction.before),
+            Hash::Sha256.digest(&compaction.after),
+        );
+        compaction.crash(&compaction.before, &[&before, &after]);
+        compaction.check_recovered();
+    }
+
+    #[test]
+    fn replays_after_crash_before_journal_is_rebased() {
+        let dir = temp_dir("before-rebase");
+        let compaction = Compaction::new(&dir);
+        let (before, after) = (
+            Hash::Sha256.digest(&compaction.before),
+            Hash::Sha256.digest(&compaction.after),
+        );
+        compaction.crash(&compaction.after, &[&before, &after]);
+        compaction.check_recovered();
+    }
+
+    #[test]
+    fn replays_after_crash_before_journal_is_cleared() {
+        let dir = temp_dir("before-clear");
+        let compaction = Compaction::new(&dir);
+        let after = Hash::Sha256.digest(&compaction.after);
+        compaction.crash(&compaction.after, &[&after]);
+        compaction.check_recovered();
+    }
+
+    #[test]
+    fn drops_record_torn_by_crash() {
+        let dir = temp_dir("torn");
+        let compaction = Compaction::new(&dir);
+        let before = Hash::Sha256.digest(&compaction.before);
+        compaction.crash(&compaction.before, &[&before]);
+        let mut journal = fs::read(&compaction.journal_path).unwrap();
+        journal.extend(100u32.to_be_bytes());
+        journal.extend([0; 10]);
+        fs::write(&compaction.journal_path, journal).unwrap();
+        compaction.check_recovered();
+    }
+
+    #[test]
+    fn refuses_journal_of_replaced_file() {
+        let dir = temp_dir("replaced");
+        let compaction = Compaction::new(&dir);
+        let before = Hash::Sha256.digest(&compaction.before);
+        compaction.crash(&compaction.after, &[&before]);
+        let err = database(&compaction.db_path).load_content().err().unwrap();
+        assert_eq!(err.code(), ErrorCode::Damaged);
+    }
+}
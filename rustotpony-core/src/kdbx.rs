@@ -0,0 +1,422 @@
+use keepass::config::KdfConfig;
+use keepass::db::{fields, CustomDataItem, CustomDataValue, EntryId, EntryRef, Times};
+use keepass::error::{DatabaseKeyError, DatabaseOpenError};
+use keepass::DatabaseKey;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tracing::{debug, info};
+
+use crate::errors::{Error, ErrorCode};
+use crate::import::from_otp_field;
+use crate::otpauth::carries_format;
+use crate::{CodeFormat, Database, DatabaseContent, FileStorage, GenApp, Storage};
+
+// Custom data of entries holding the generator properties KeePass has no
+// fields for, and of the database holding the history
+const APPLICATION_DATA: &str = "RusTOTPony";
+const HISTORY_DATA: &str = "RusTOTPony.History";
+// Argon2 parameters of new files, the same KeePassXC picks by default
+const ARGON2_MEMORY: u64 = 64 * 1024 * 1024;
+const ARGON2_ITERATIONS: u64 = 10;
+const ARGON2_PARALLELISM: u32 = 2;
+
+/// Database kept as a KeePass KDBX 4 file, so KeePassXC and other KeePass
+/// clients can open it and show the codes on machines without RusTOTPony.
+///
+/// Every application is an entry with its name as the title and an
+/// `otpauth://` URI in the `otp` field; tags are entry tags. Properties
+/// KeePass doesn't know about, e.g. aliases or recovery codes, are kept in
+/// custom data of the entry, along with the identifier of the application,
+/// so a renamed application keeps its entry. Entries without one-time
+/// passwords are left as they are when the database is saved, and entries
+/// moved to the recycle bin are treated as deleted.
+pub struct KdbxDatabase {
+    storage: Box<dyn Storage>,
+    secret_fn: Box<dyn Fn() -> String + Send + Sync>,
+}
+
+impl KdbxDatabase {
+    pub fn new<F>(path: PathBuf, secret_fn: F) -> KdbxDatabase
+    where
+        F: Fn() -> String + Send + Sync + 'static,
+    {
+        Self::with_storage(Box::new(FileStorage::new(path)), secret_fn)
+    }
+
+    pub fn with_storage<F>(storage: Box<dyn Storage>, secret_fn: F) -> KdbxDatabase
+    where
+        F: Fn() -> String + Send + Sync + 'static,
+    {
+        KdbxDatabase {
+            storage,
+            secret_fn: Box::new(secret_fn),
+        }
+    }
+
+    /// Reads and decrypts the database, like `get_content` without the
+    /// `Database` trait in scope.
+    pub fn load_content(&self) -> Result<DatabaseContent, Error> {
+        let Some((kdbx, _)) = self.open()? else {
+            info!("there is no database yet");
+            return Ok(DatabaseContent::default());
+        };
+        let mut content = DatabaseContent::default();
+        for entry in kdbx.iter_all_entries().filter(|entry| !is_recycled(entry)) {
+            if !is_application(&entry) {
+                continue;
+            }
+            let app = to_application(&entry).map_err(|err| {
+                Error::new(
+                    err.code(),
+                    format!(
+                        "Entry '{}' can't be read: {}",
+                        entry.get_title().unwrap_or(""),
+                        err
+                    ),
+                )
+            })?;
+            if content.applications.contains_key(&app.name) {
+                return Err(Error::new(ErrorCode::Database, format!(
+                    "There are several entries named '{}', rename them in KeePass to tell them apart",
+                    app.name
+                )));
+            }
+            content.applications.insert(app.name.clone(), app);
+        }
+        if let Some(history) = custom_string(&kdbx.meta.custom_data, HISTORY_DATA) {
+            content.history = serde_json::from_str(history).map_err(|_| {
+                Error::new(
+                    ErrorCode::Damaged,
+                    "Couldn't parse the history of the database",
+                )
+            })?;
+        }
+        info!(
+            applications = content.applications.len(),
+            "loaded KDBX database"
+        );
+        Ok(content)
+    }
+
+    // Password is asked once, the same key encrypts the saved file
+    fn open(&self) -> Result<Option<(keepass::Database, String)>, Error> {
+        let data = match self.storage.read()? {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        debug!(bytes = data.len(), "read KDBX file");
+        let password = (self.secret_fn)();
+        let kdbx = keepass::Database::parse(&data, key(&password)).map_err(|err| match err {
+            DatabaseOpenError::Key(DatabaseKeyError::IncorrectKey)
+            | DatabaseOpenError::Cryptography(_) => Error::new(
+                ErrorCode::WrongPassword,
+                "Couldn't open the KDBX file: wrong password",
+            ),
+            DatabaseOpenError::Io(_) => Error::new(
+                ErrorCode::Io,
+                format!("Couldn't read the KDBX file: {}", err),
+            ),
+            _ => Error::new(
+                ErrorCode::Damaged,
+                format!("Couldn't open the KDBX file: {}", err),
+            ),
+        })?;
+        Ok(Some((kdbx, password)))
+    }
+}
+
+impl Database for KdbxDatabase {
+    fn get_content(&self) -> Result<DatabaseContent, Error> {
+        self.load_content()
+    }
+
+    fn save_content(&self, content: &DatabaseContent) -> Result<(), Error> {
+        let (mut kdbx, password) = match self.open()? {
+            Some(opened) => opened,
+            None => (new_kdbx(), (self.secret_fn)()),
+        };
+        // Entries are updated in place, so groups and other fields set up in
+        // KeePass survive. They are found by the identifier in their
+        // properties, entries added in KeePass by the name they are read with
+        let names_by_id: HashMap<&str, &String> = content
+            .applications
+            .iter()
+            .filter(|(_, app)| !app.id.is_empty())
+            .map(|(name, app)| (app.id.as_str(), name))
+            .collect();
+        let mut existing: HashMap<String, EntryId> = HashMap::new();
+        let mut removed: Vec<EntryId> = Vec::new();
+        for entry in kdbx.iter_all_entries() {
+            if !is_application(&entry) {
+                continue;
+            }
+            let name = match stored_id(&entry) {
+                Some(id) => names_by_id.get(id.as_str()).map(|name| String::clone(name)),
+                None => to_application(&entry).ok().map(|app| app.name),
+            };
+            match name {
+                Some(name)
+                    if content.applications.contains_key(&name)
+                        && !is_recycled(&entry)
+                        && !existing.contains_key(&name) =>
+                {
+                    existing.insert(name, entry.id());
+                }
+                _ => removed.push(entry.id()),
+            }
+        }
+        for id in removed {
+            if let Some(entry) = kdbx.entry_mut(id) {
+                entry.remove();
+            }
+        }
+        let mut names: Vec<&String> = content.applications.keys().collect();
+        names.sort();
+        for name in names {
+            let app = &content.applications[name];
+            let id = match existing.get(name) {
+                Some(id) => *id,
+                None => kdbx.root_mut().add_entry().id(),
+            };
+            let mut entry = kdbx.entry_mut(id).expect("Entry has just been found");
+            fill_entry(&mut entry, app)?;
+        }
+        let history =
+            serde_json::to_string(&content.history).expect("Couldn't serialize the history");
+        kdbx.meta
+            .custom_data
+            .insert(String::from(HISTORY_DATA), string_item(history));
+
+        let mut data = Vec::new();
+        kdbx.save(&mut data, key(&password)).map_err(|err| {
+            Error::new(
+                ErrorCode::Io,
+                format!("Couldn't write the KDBX file: {}", err),
+            )
+        })?;
+        debug!(bytes = data.len(), "writing KDBX file");
+        self.storage.write(&data)
+    }
+
+    fn get_size(&self) -> Option<u64> {
+        let path = self.storage.local_path()?;
+        std::fs::metadata(path).ok().map(|metadata| metadata.len())
+    }
+}
+
+fn key(password: &str) -> DatabaseKey {
+    DatabaseKey::new().with_password(password)
+}
+
+fn new_kdbx() -> keepass::Database {
+    let mut kdbx = keepass::Database::new();
+    if let KdfConfig::Argon2 {
+        iterations,
+        memory,
+        parallelism,
+        ..
+    } = &mut kdbx.config.kdf_config
+    {
+        *iterations = ARGON2_ITERATIONS;
+        *memory = ARGON2_MEMORY;
+        *parallelism = ARGON2_PARALLELISM;
+    }
+    kdbx.meta.generator = Some(String::from("RusTOTPony"));
+    kdbx.meta.database_name = Some(String::from("RusTOTPony"));
+    kdbx
+}
+
+fn is_application(entry: &EntryRef) -> bool {
+    entry.get_raw_otp_value().is_some() || entry.custom_data.contains_key(APPLICATION_DATA)
+}
+
+fn is_recycled(entry: &EntryRef) -> bool {
+    let Some(bin) = entry.database().recycle_bin().map(|bin| bin.id()) else {
+        return false;
+    };
+    let kdbx = entry.database();
+    let mut group = Some(entry.parent().id());
+    while let Some(id) = group {
+        if id == bin {
+            return true;
+        }
+        group = kdbx
+            .group(id)
+            .and_then(|group| group.parent().map(|parent| parent.id()));
+    }
+    false
+}
+
+fn stored_id(entry: &EntryRef) -> Option<String> {
+    custom_string(&entry.custom_data, APPLICATION_DATA)
+        .and_then(|data| serde_json::from_str::<GenApp>(data).ok())
+        .map(|app| app.id)
+        .filter(|id| !id.is_empty())
+}
+
+// Fields shown by KeePass win over the stored properties, they could have
+// been edited there
+fn to_application(entry: &EntryRef) -> Result<GenApp, Error> {
+    let title = entry.get_title().unwrap_or("");
+    let username = entry.get_username().unwrap_or("");
+    let stored = custom_string(&entry.custom_data, APPLICATION_DATA)
+        .map(|data| {
+            serde_json::from_str::<GenApp>(data)
+                .map_err(|_| Error::new(ErrorCode::Damaged, "properties are damaged"))
+        })
+        .transpose()?;
+    let otp = entry
+        .get_raw_otp_value()
+        .filter(|otp| !otp.trim().is_empty());
+    let mut app = match (stored, otp) {
+        (Some(mut app), Some(otp)) => {
+            let generator = from_otp_field(otp, title, username)?;
+            app.secret = generator.secret;
+            app.secret_bytes = generator.secret_bytes;
+            app.format = field_format(&app.format, otp, generator.format);
+            app
+        }
+        (Some(app), None) => app,
+        (None, Some(otp)) => from_otp_field(otp, title, username)?,
+        (None, None) => {
+            return Err(Error::new(
+                ErrorCode::InvalidArgument,
+                "there is no one-time password",
+            ))
+        }
+    };
+    if !title.trim().is_empty() {
+        app.name = String::from(title.trim());
+    }
+    app.username = String::from(username.trim());
+    app.tags = entry.tags.clone();
+    // Formats are checked while parsing
+    app.check_kind().map_err(|err| {
+        Error::new(
+            ErrorCode::Damaged,
+            format!("properties are damaged: {}", err),
+        )
+    })?;
+    Ok(app)
+}
+
+// The otp field carries only decimal and Steam Guard codes, a bare secret
+// none at all. Files written before Steam Guard codes were marked in the
+// field have them there as decimal codes of the same length.
+fn field_format(stored: &CodeFormat, otp: &str, field: CodeFormat) -> CodeFormat {
+    let unmarked = stored.get_alphabet().is_some()
+        && field.get_alphabet().is_none()
+        && field.get_length() == stored.get_length();
+    if !otp.trim_start().starts_with("otpauth://") || unmarked || !carries_format(stored) {
+        stored.clone()
+    } else {
+        field
+    }
+}
+
+fn fill_entry(entry: &mut keepass::db::EntryMut, app: &GenApp) -> Result<(), Error> {
+    entry.set_unprotected(fields::TITLE, app.name.as_str());
+    entry.set_unprotected(fields::USERNAME, app.username.as_str());
+    // Protected secrets and generators other than TOTP can't be described
+    // by the otp field, KeePass clients see just the entry and the secret
+    // stays in the properties
+    let mut properties = app.clone();
+    if app.protected_secret.is_none() && app.check_otpauth_uri().is_ok() {
+        entry.set_protected(fields::OTP, app.to_otpauth_uri());
+        properties.secret.clear();
+        properties.secret_bytes.clear();
+    } else {
+        entry.fields.remove(fields::OTP);
+    }
+    entry.tags = app.tags.clone();
+    properties.tags.clear();
+    let data = serde_json::to_string(&properties)
+        .map_err(|err| Error::new(ErrorCode::Internal, err.to_string()))?;
+    entry
+        .custom_data
+        .insert(String::from(APPLICATION_DATA), string_item(data));
+    entry.times.last_modification = Some(Times::now());
+    Ok(())
+}
+
+fn custom_string<'a>(data: &'a HashMap<String, CustomDataItem>, key: &str) -> Option<&'a str> {
+    match data.get(key).and_then(|item| item.value.as_ref()) {
+        Some(CustomDataValue::String(value)) => Some(value.as_str()),
+        _ => None,
+    }
+}
+
+fn string_item(value: String) -> CustomDataItem {
+    CustomDataItem {
+        value: Some(CustomDataValue::String(value)),
+        last_modification_time: Some(Times::now()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemoryStorage, STEAM_ALPHABET, TOTP};
+
+    fn database(storage: &MemoryStorage, password: &'static str) -> KdbxDatabase {
+        KdbxDatabase::with_storage(Box::new(storage.clone()), move || String::from(password))
+    }
+
+    fn app(name: &str, format: CodeFormat) -> GenApp {
+        let mut app = GenApp::from_totp(name, "user", &TOTP::new("JBSWY3DPEHPK3PXP").unwrap());
+        app.format = format;
+        app
+    }
+
+    #[test]
+    fn formats_survive_round_trip() {
+        let storage = MemoryStorage::default();
+        let mut content = DatabaseContent::default();
+        for app in [
+            app("decimal", CodeFormat::decimal(8).unwrap()),
+            app(
+                "steam",
+                CodeFormat::with_alphabet(STEAM_ALPHABET, 5).unwrap(),
+            ),
+            app("custom", CodeFormat::with_alphabet("ABCDEF", 6).unwrap()),
+        ] {
+            content.applications.insert(app.name.clone(), app);
+        }
+        database(&storage, "password")
+            .save_content(&content)
+            .unwrap();
+        let loaded = database(&storage, "password").get_content().unwrap();
+        for (name, app) in content.applications.iter() {
+            assert_eq!(loaded.applications[name].format, app.format, "{}", name);
+            assert_eq!(loaded.applications[name].secret, app.secret, "{}", name);
+        }
+    }
+
+    #[test]
+    fn unmarked_steam_codes_keep_their_format() {
+        let steam = CodeFormat::with_alphabet(STEAM_ALPHABET, 5).unwrap();
+        let uri = "otpauth://totp/steam?secret=JBSWY3DPEHPK3PXP&digits=5";
+        assert_eq!(
+            field_format(&steam, uri, CodeFormat::decimal(5).unwrap()),
+            steam
+        );
+        assert_eq!(
+            field_format(&steam, uri, CodeFormat::decimal(6).unwrap()),
+            CodeFormat::decimal(6).unwrap()
+        );
+    }
+
+    #[test]
+    fn wrong_password_is_reported() {
+        let storage = MemoryStorage::default();
+        let mut content = DatabaseContent::default();
+        content
+            .applications
+            .insert(String::from("app"), app("app", CodeFormat::default()));
+        database(&storage, "password")
+            .save_content(&content)
+            .unwrap();
+        let err = database(&storage, "wrong").get_content().err().unwrap();
+        assert_eq!(err.code(), ErrorCode::WrongPassword);
+    }
+}
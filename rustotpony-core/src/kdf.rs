@@ -0,0 +1,264 @@
+use std::time::Duration;
+use tracing::debug;
+
+use crate::cipher::{Cipher, XChaCha20};
+use crate::datetime::Stopwatch;
+use crate::decoy::Slot;
+use crate::errors::{Error, ErrorCode};
+use crate::header::Kdf;
+use crate::random;
+use crate::{JsonDatabase, KEY_SIZE};
+
+/// How long unlocking a new database should take on the machine it's
+/// created on, unless the builder says otherwise.
+pub const DEFAULT_UNLOCK_TIME: Duration = Duration::from_millis(250);
+
+pub const SALT_SIZE: usize = 16;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+// scrypt takes 128 * r * 2^log_n bytes of memory: 1 MiB at least, so the
+// derivation is never trivial, and 1 GiB at most
+const MIN_LOG_N: u8 = 10;
+const MAX_LOG_N: u8 = 20;
+// Limits of parameters read from a file header, which may be crafted to
+// exhaust the memory or keep the derivation running for hours
+const MAX_R: u32 = 32;
+const MAX_P: u32 = 16;
+const MAX_MEMORY: u64 = 1 << 30;
+/// Cost of keys derived from the passphrases of shares and protected
+/// applications, 64 MiB of memory makes guessing them slow on any hardware.
+pub const PASSPHRASE_LOG_N: u8 = 16;
+// scrypt in the header and in front of data sealed with a passphrase
+const SCRYPT_ID: u8 = 3;
+const SCRYPT_PARAMS_SIZE: usize = 9 + SALT_SIZE;
+// Cost of the measured derivation, big enough to be timed reliably and
+// small enough to be quick on slow devices
+const PROBE_LOG_N: u8 = 12;
+// Offline guessing of a fast hash on hardware built for it, as estimated by
+// zxcvbn
+const FAST_GUESSES_PER_SECOND: f64 = 1e10;
+
+impl Kdf {
+    /// Whether the key is derived from the passphrases of two persons.
+    pub fn is_two_persons(&self) -> bool {
+        matches!(self, Kdf::TwoPersons | Kdf::TwoPersonsScrypt { .. })
+    }
+
+    /// Rough number of passwords an offline attacker tries per second
+    /// against keys derived this way, to tell how long guessing one takes.
+    /// scrypt is taken to cost as much as hashing the memory it fills
+    /// twice, 4 * r * p SHA-256 blocks per each of its 2^log_n steps.
+    pub fn guesses_per_second(&self) -> f64 {
+        match self {
+            Kdf::Scrypt { log_n, r, p, .. } | Kdf::TwoPersonsScrypt { log_n, r, p, .. } => {
+                let blocks = 4.0 * f64::from(*r) * f64::from(*p) * 2f64.powi(i32::from(*log_n));
+                FAST_GUESSES_PER_SECOND / blocks
+            }
+            Kdf::Sha256 | Kdf::TwoPersons => FAST_GUESSES_PER_SECOND,
+        }
+    }
+
+    /// Picks scrypt parameters which take about `target` to derive a key on
+    /// this machine, with a new random salt. The time doubles with every
+    /// step of the cost, so a single measurement is enough.
+    pub fn benchmark(target: Duration) -> Kdf {
+        let started = Stopwatch::start();
+        scrypt_key(
+            PROBE_LOG_N,
+            SCRYPT_R,
+            SCRYPT_P,
+            &[0; SALT_SIZE],
+            b"benchmark",
+        );
+        let probe = started.elapsed().max(Duration::from_micros(1));
+        let steps = (target.as_secs_f64() / probe.as_secs_f64()).log2().round();
+        let log_n = (f64::from(PROBE_LOG_N) + steps)
+            .clamp(f64::from(MIN_LOG_N), f64::from(MAX_LOG_N)) as u8;
+        debug!(
+            probe_us = probe.as_micros() as u64,
+            log_n, "benchmarked key derivation"
+        );
+        Kdf::Scrypt {
+            log_n,
+            r: SCRYPT_R,
+            p: SCRYPT_P,
+            salt: random::bytes(SALT_SIZE),
+        }
+    }
+
+    /// scrypt with the cost given as log2 of N instead of a benchmarked
+    /// one, e.g. to unlock equally fast on slower devices.
+    pub fn scrypt(log_n: u8) -> Result<Kdf, Error> {
+        if !(MIN_LOG_N..=MAX_LOG_N).contains(&log_n) {
+            return Err(Error::new(
+                ErrorCode::InvalidArgument,
+                format!("scrypt cost must be from {} to {}", MIN_LOG_N, MAX_LOG_N),
+            ));
+        }
+        Ok(Kdf::Scrypt {
+            log_n,
+            r: SCRYPT_R,
+            p: SCRYPT_P,
+            salt: random::bytes(SALT_SIZE),
+        })
+    }
+}
+
+impl JsonDatabase {
+    /// Derives the key of a single password, the passphrases of two
+    /// persons are combined by `form_two_persons_key`.
+    pub(crate) fn form_password_key(kdf: &Kdf, password: &str) -> [u8; KEY_SIZE] {
+        match kdf {
+            Kdf::Scrypt { log_n, r, p, salt } => {
+                let started = Stopwatch::start();
+                let key = scrypt_key(*log_n, *r, *p, salt, password.as_bytes());
+                debug!(
+                    elapsed_ms = started.elapsed().as_millis() as u64,
+                    "derived key with scrypt"
+                );
+                key
+            }
+            Kdf::Sha256 | Kdf::TwoPersons | Kdf::TwoPersonsScrypt { .. } => {
+                Self::form_secret_key(password)
+            }
+        }
+    }
+
+    /// Key derivation `change_password` uses for the new password, e.g. to
+    /// tell how fast it could be guessed.
+    pub fn password_kdf(&self) -> Result<Kdf, Error> {
+        self.new_password_kdf(&self.database_key())
+    }
+
+    /// Key derivation for a new password of the vault opened by `key`: the
+    /// one of the file, so a decoy keeps working, or a freshly benchmarked
+    /// one if two persons were required or a single fast hash was used by a
+    /// file which can't hold a decoy.
+    pub(crate) fn new_password_kdf(&self, key: &[u8; KEY_SIZE]) -> Result<Kdf, Error> {
+        let data = self
+            .read_vaults()?
+            .ok_or_else(|| Error::new(ErrorCode::NotFound, "There is no database yet"))?;
+        Ok(match self.kdf() {
+            kdf if kdf.is_two_persons() => Kdf::benchmark(self.unlock_time),
+            // The other half may be a decoy, which has to stay derived the
+            // same way, while a vault taking the whole file is alone
+            Kdf::Sha256 if self.find_vault(&data, key)? == Slot::Whole => {
+                Kdf::benchmark(self.unlock_time)
+            }
+            kdf => kdf,
+        })
+    }
+}
+
+/// Encrypts the data with a key derived from the passphrase by scrypt with
+/// a new random salt. The parameters and the salt, written as in the file
+/// header, are followed by the data sealed with XChaCha20-Poly1305, so a
+/// wrong passphrase and changed data are detected.
+pub(crate) fn seal_with_passphrase(data: &[u8], passphrase: &str) -> Vec<u8> {
+    let kdf = Kdf::scrypt(PASSPHRASE_LOG_N).expect("Passphrase cost is out of range");
+    let key = JsonDatabase::form_password_key(&kdf, passphrase);
+    [kdf.params(), XChaCha20.seal(data, &key)].concat()
+}
+
+/// Reverse of `seal_with_passphrase`.
+pub(crate) fn open_with_passphrase(sealed: &[u8], passphrase: &str) -> Result<Vec<u8>, Error> {
+    if sealed.len() < SCRYPT_PARAMS_SIZE {
+        return Err(Error::new(ErrorCode::Damaged, "Sealed data is too short"));
+    }
+    let (params, data) = sealed.split_at(SCRYPT_PARAMS_SIZE);
+    let kdf = Kdf::parse(SCRYPT_ID, params)?;
+    XChaCha20.open(data, &JsonDatabase::form_password_key(&kdf, passphrase))
+}
+
+/// Whether scrypt parameters read from a file header are within the
+/// limits of memory and time this version is willing to spend.
+pub(crate) fn is_bounded(log_n: u8, r: u32, p: u32) -> bool {
+    log_n <= MAX_LOG_N
+        && (1..=MAX_R).contains(&r)
+        && (1..=MAX_P).contains(&p)
+        && (128 * u64::from(r)) << log_n <= MAX_MEMORY
+}
+
+// Parameters are checked when the header is parsed
+pub(crate) fn scrypt_key(
+    log_n: u8,
+    r: u32,
+    p: u32,
+    salt: &[u8],
+    password: &[u8],
+) -> [u8; KEY_SIZE] {
+    let params = scrypt::Params::new(log_n, r, p).expect("Invalid scrypt parameters");
+    let mut key = [0; KEY_SIZE];
+    scrypt::scrypt(password, salt, &params, &mut key).expect("Invalid scrypt key length");
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|idx| u8::from_str_radix(&hex[idx..idx + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn scrypt_matches_rfc_7914() {
+        // The first half of the 64 bytes of the second test vector
+        let key = scrypt_key(10, 8, 16, b"NaCl", b"password");
+        assert_eq!(
+            key.to_vec(),
+            from_hex("fdbabe1c9d3472007856e7190d01e9fe7c6ad7cbc8237830e77376634b373162")
+        );
+    }
+
+    #[test]
+    fn sealed_data_survives_round_trip() {
+        let sealed = seal_with_passphrase(b"JBSWY3DPEHPK3PXP", "correct horse");
+        assert_eq!(
+            open_with_passphrase(&sealed, "correct horse").unwrap(),
+            b"JBSWY3DPEHPK3PXP"
+        );
+    }
+
+    #[test]
+    fn wrong_passphrase_and_damaged_data_are_rejected() {
+        let sealed = seal_with_passphrase(b"JBSWY3DPEHPK3PXP", "correct horse");
+        assert!(open_with_passphrase(&sealed, "battery staple").is_err());
+        let mut changed = sealed.clone();
+        *changed.last_mut().unwrap() ^= 1;
+        assert!(open_with_passphrase(&changed, "correct horse").is_err());
+        let err = open_with_passphrase(&sealed[..10], "correct horse").unwrap_err();
+        assert_eq!(err.code(), ErrorCode::Damaged);
+    }
+
+    #[test]
+    fn costs_are_kept_within_limits() {
+        assert!(Kdf::scrypt(MIN_LOG_N - 1).is_err());
+        assert!(Kdf::scrypt(MAX_LOG_N + 1).is_err());
+        let log_n = |kdf| match kdf {
+            Kdf::Scrypt { log_n, .. } => log_n,
+            _ => unreachable!(),
+        };
+        assert_eq!(log_n(Kdf::benchmark(Duration::ZERO)), MIN_LOG_N);
+        assert_eq!(
+            log_n(Kdf::benchmark(Duration::from_secs(1 << 20))),
+            MAX_LOG_N
+        );
+        assert!(is_bounded(MAX_LOG_N, SCRYPT_R, SCRYPT_P));
+        assert!(!is_bounded(MAX_LOG_N, MAX_R, SCRYPT_P));
+        assert!(!is_bounded(MIN_LOG_N, 0, SCRYPT_P));
+        assert!(!is_bounded(MIN_LOG_N, SCRYPT_R, MAX_P + 1));
+    }
+
+    #[test]
+    fn scrypt_slows_guessing_down() {
+        let fast = Kdf::Sha256.guesses_per_second();
+        let cheap = Kdf::scrypt(MIN_LOG_N).unwrap().guesses_per_second();
+        let costly = Kdf::scrypt(MAX_LOG_N).unwrap().guesses_per_second();
+        assert!(costly < cheap && cheap < fast);
+        assert_eq!(cheap / costly, 1024.0);
+    }
+}
@@ -0,0 +1,153 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tracing::debug;
+
+use crate::datetime::Stopwatch;
+use crate::KEY_SIZE;
+
+/// Derived database key kept in memory for a limited time, so the password
+/// isn't asked and the key isn't derived again for every read and save.
+pub struct KeyCache {
+    ttl: Duration,
+    entry: Mutex<Option<(Stopwatch, [u8; KEY_SIZE])>>,
+}
+
+impl KeyCache {
+    pub fn new(ttl: Duration) -> KeyCache {
+        KeyCache {
+            ttl,
+            entry: Mutex::new(None),
+        }
+    }
+
+    /// Returns the cached key, or derives a new one and caches it if there
+    /// is none or it has expired.
+    pub fn get_or_derive<F: FnOnce() -> [u8; KEY_SIZE]>(&self, derive: F) -> [u8; KEY_SIZE] {
+        let mut entry = self.entry.lock().unwrap_or_else(|err| err.into_inner());
+        match entry.as_mut() {
+            Some((cached_at, key)) if cached_at.elapsed() < self.ttl => return *key,
+            Some((_, key)) => {
+                debug!("cached key expired");
+                wipe(key);
+            }
+            None => {}
+        }
+        let key = derive();
+        *entry = Some((Stopwatch::start(), key));
+        key
+    }
+
+    /// Overwrites the cached key, the next access derives it again.
+    pub fn clear(&self) {
+        let mut entry = self.entry.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some((_, key)) = entry.as_mut() {
+            wipe(key);
+            debug!("wiped cached key");
+        }
+        *entry = None;
+    }
+}
+
+impl Drop for KeyCache {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+// Volatile writes aren't optimized away even though the key is never read
+// afterwards
+fn wipe(key: &mut [u8; KEY_SIZE]) {
+    for byte in key.iter_mut() {
+        // SAFETY: the pointer comes from a mutable reference
+        unsafe { std::ptr::write_volatile(byte, 0) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::ErrorCode;
+    use crate::header::Kdf;
+    use crate::{JsonDatabase, MemoryStorage};
+    use std::cell::Cell;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn key_is_derived_once_until_cleared() {
+        let cache = KeyCache::new(Duration::from_secs(60));
+        let derived = Cell::new(0);
+        let derive = |byte| {
+            derived.set(derived.get() + 1);
+            [byte; KEY_SIZE]
+        };
+        assert_eq!(cache.get_or_derive(|| derive(1)), [1; KEY_SIZE]);
+        assert_eq!(cache.get_or_derive(|| derive(2)), [1; KEY_SIZE]);
+        assert_eq!(derived.get(), 1);
+        cache.clear();
+        assert_eq!(cache.get_or_derive(|| derive(3)), [3; KEY_SIZE]);
+        assert_eq!(derived.get(), 2);
+    }
+
+    #[test]
+    fn expired_key_is_derived_again() {
+        let cache = KeyCache::new(Duration::ZERO);
+        assert_eq!(cache.get_or_derive(|| [1; KEY_SIZE]), [1; KEY_SIZE]);
+        assert_eq!(cache.get_or_derive(|| [2; KEY_SIZE]), [2; KEY_SIZE]);
+    }
+
+    #[test]
+    fn database_asks_password_once() {
+        let storage = MemoryStorage::default();
+        let asked = Arc::new(AtomicUsize::new(0));
+        let counter = asked.clone();
+        let database = JsonDatabase::builder()
+            .storage(Box::new(storage.clone()))
+            .secret(move || {
+                counter.fetch_add(1, Ordering::Relaxed);
+                String::from("correct horse")
+            })
+            .kdf(Kdf::scrypt(10).unwrap())
+            .key_cache(Duration::from_secs(60))
+            .build()
+            .unwrap();
+        database.create().unwrap();
+        database.load_content().unwrap();
+        database.load_content().unwrap();
+        assert_eq!(asked.load(Ordering::Relaxed), 1);
+        database.forget_key();
+        database.load_content().unwrap();
+        assert_eq!(asked.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn wrong_key_is_not_kept() {
+        let storage = MemoryStorage::default();
+        JsonDatabase::builder()
+            .storage(Box::new(storage.clone()))
+            .secret(|| String::from("correct horse"))
+            .kdf(Kdf::scrypt(10).unwrap())
+            .build()
+            .unwrap()
+            .create()
+            .unwrap();
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counter = attempts.clone();
+        let database = JsonDatabase::builder()
+            .storage(Box::new(storage.clone()))
+            .secret(move || {
+                // The first attempt is mistyped
+                match counter.fetch_add(1, Ordering::Relaxed) {
+                    0 => String::from("wrong horse"),
+                    _ => String::from("correct horse"),
+                }
+            })
+            .key_cache(Duration::from_secs(60))
+            .build()
+            .unwrap();
+        let err = database.load_content().err().unwrap();
+        assert_eq!(err.code(), ErrorCode::WrongPassword);
+        assert!(database.load_content().is_ok());
+    }
+}
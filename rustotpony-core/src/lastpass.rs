@@ -0,0 +1,180 @@
+use serde_derive::Deserialize;
+
+use crate::errors::{Error, ErrorCode};
+use crate::import::from_otp_field;
+use crate::{CodeFormat, GenApp};
+
+// Folders every LastPass Authenticator has, they don't say anything about
+// the accounts in them
+const BUILT_IN_FOLDERS: &[&str] = &["Favorites", "Other Accounts"];
+
+#[derive(Deserialize)]
+struct Export {
+    #[serde(default)]
+    accounts: Vec<Account>,
+    #[serde(default)]
+    folders: Vec<Folder>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Account {
+    #[serde(default)]
+    issuer_name: String,
+    #[serde(default)]
+    original_issuer_name: String,
+    #[serde(default)]
+    user_name: String,
+    #[serde(default)]
+    secret: String,
+    #[serde(default = "default_time_step")]
+    time_step: u64,
+    #[serde(default = "default_digits")]
+    digits: usize,
+    #[serde(default = "default_algorithm")]
+    algorithm: String,
+    #[serde(default)]
+    folder_data: Option<FolderData>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FolderData {
+    folder_id: i64,
+}
+
+#[derive(Deserialize)]
+struct Folder {
+    id: i64,
+    #[serde(default)]
+    name: String,
+}
+
+fn default_time_step() -> u64 {
+    30
+}
+
+fn default_digits() -> usize {
+    6
+}
+
+fn default_algorithm() -> String {
+    String::from("SHA1")
+}
+
+/// Reads the JSON export of LastPass Authenticator and returns an entry for
+/// every account in it for `import_applications`. Accounts are named by
+/// their issuers as shown in the app, folders other than the built-in ones
+/// become tags.
+pub fn parse_lastpass(data: &[u8]) -> Result<Vec<Result<GenApp, Error>>, Error> {
+    let export: Export = serde_json::from_slice(data).map_err(|err| {
+        Error::new(
+            ErrorCode::InvalidArgument,
+            format!("Couldn't parse the LastPass Authenticator export: {}", err),
+        )
+    })?;
+    let folder_name = |id: i64| {
+        export
+            .folders
+            .iter()
+            .find(|folder| folder.id == id)
+            .map(|folder| folder.name.trim())
+            .filter(|name| !name.is_empty() && !BUILT_IN_FOLDERS.contains(name))
+    };
+    Ok(export
+        .accounts
+        .iter()
+        .map(|account| {
+            if !account.algorithm.eq_ignore_ascii_case("SHA1") {
+                return Err(Error::new(
+                    ErrorCode::Unsupported,
+                    format!("Algorithm {} isn't supported", account.algorithm),
+                ));
+            }
+            if account.time_step != 30 {
+                return Err(Error::new(
+                    ErrorCode::Unsupported,
+                    format!("Period of {} seconds isn't supported", account.time_step),
+                ));
+            }
+            let name = if account.issuer_name.trim().is_empty() {
+                &account.user_name
+            } else {
+                &account.issuer_name
+            };
+            let mut app = from_otp_field(&account.secret, name, &account.user_name)?;
+            app.format = CodeFormat::decimal(account.digits)?;
+            if !account.original_issuer_name.trim().is_empty() {
+                app.issuer = String::from(account.original_issuer_name.trim());
+            }
+            let folder = account
+                .folder_data
+                .as_ref()
+                .and_then(|folder| folder_name(folder.folder_id));
+            if let Some(folder) = folder {
+                app.tags.push(String::from(folder));
+            }
+            Ok(app)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPORT: &str = r#"{
+        "folders": [
+            {"id": 0, "name": "Favorites"},
+            {"id": 1, "name": "Work"}
+        ],
+        "accounts": [
+            {
+                "issuerName": "GitHub",
+                "originalIssuerName": "GitHub Inc.",
+                "userName": "octocat",
+                "secret": "JBSWY3DPEHPK3PXP",
+                "digits": 8,
+                "folderData": {"folderId": 1}
+            },
+            {
+                "userName": "tanuki",
+                "secret": "GEZDGNBVGY3TQOJQ",
+                "folderData": {"folderId": 0}
+            },
+            {"issuerName": "Bank", "secret": "JBSWY3DPEHPK3PXP", "algorithm": "SHA256"},
+            {"issuerName": "Slow", "secret": "JBSWY3DPEHPK3PXP", "timeStep": 60}
+        ]
+    }"#;
+
+    #[test]
+    fn accounts_are_imported() {
+        let entries = parse_lastpass(EXPORT.as_bytes()).unwrap();
+        assert_eq!(entries.len(), 4);
+        let github = entries[0].as_ref().unwrap();
+        assert_eq!(github.get_name(), "GitHub");
+        assert_eq!(github.get_username(), "octocat");
+        assert_eq!(github.get_issuer(), "GitHub Inc.");
+        assert_eq!(github.get_format().get_length(), 8);
+        assert_eq!(github.get_tags(), ["Work"]);
+        let tanuki = entries[1].as_ref().unwrap();
+        assert_eq!(tanuki.get_name(), "tanuki");
+        assert_eq!(tanuki.get_format().get_length(), 6);
+        assert!(tanuki.get_tags().is_empty());
+    }
+
+    #[test]
+    fn unsupported_accounts_are_reported() {
+        let entries = parse_lastpass(EXPORT.as_bytes()).unwrap();
+        for entry in &entries[2..] {
+            let err = entry.as_ref().err().unwrap();
+            assert_eq!(err.code(), ErrorCode::Unsupported);
+        }
+    }
+
+    #[test]
+    fn damaged_export_is_rejected() {
+        let err = parse_lastpass(b"{\"accounts\": [").err().unwrap();
+        assert_eq!(err.code(), ErrorCode::InvalidArgument);
+    }
+}
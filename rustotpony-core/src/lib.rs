@@ -0,0 +1,1409 @@
+use serde::de::DeserializeOwned;
+use serde_derive::{Deserialize, Serialize};
+use tracing::{debug, info, info_span};
+
+use crate::crypto::Hash;
+use crate::header::Header;
+use crate::journal::Journal;
+use crate::keycache::KeyCache;
+use crate::progress::ProgressCallback;
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+mod aegis;
+mod aliases;
+mod andotp;
+mod backup;
+mod builder;
+mod cancellation;
+mod cipher;
+mod compression;
+mod crypto;
+mod datetime;
+mod decoy;
+mod doctor;
+mod errors;
+mod export;
+mod format;
+mod git;
+mod handoff;
+mod hardening;
+mod header;
+mod history;
+mod html;
+mod icon;
+mod import;
+mod index;
+mod journal;
+#[cfg(feature = "kdbx")]
+mod kdbx;
+mod kdf;
+mod keycache;
+mod lastpass;
+mod merge;
+mod messages;
+mod motp;
+mod names;
+#[cfg(feature = "async")]
+mod nonblocking;
+mod ocra;
+mod onepassword;
+mod otpauth;
+mod padding;
+mod paper;
+mod password;
+mod permissions;
+mod progress;
+mod protection;
+mod random;
+mod recipients;
+mod renaming;
+#[cfg(feature = "s3")]
+mod s3;
+mod salvage;
+#[cfg(feature = "ssh")]
+mod sftp;
+mod shamir;
+mod shared;
+mod sharing;
+mod snapshot;
+#[cfg(feature = "ssh")]
+mod ssh;
+mod stats;
+mod storage;
+mod stream;
+mod summary;
+mod tags;
+mod totp;
+mod twopersons;
+mod validation;
+#[cfg(feature = "webdav")]
+mod webdav;
+
+pub use backup::BackupPolicy;
+pub use builder::JsonDatabaseBuilder;
+pub use cancellation::{CancellationToken, CANCELLED};
+pub use cipher::Cipher;
+pub use datetime::format_timestamp;
+pub use doctor::{Finding, Severity};
+pub use errors::{Error, ErrorCode};
+pub use export::{ExportFilter, ExportReport};
+pub use format::{CodeFormat, STEAM_ALPHABET};
+pub use git::GitHistory;
+pub use handoff::CodeHandoff;
+pub use hardening::disable_core_dumps;
+pub use header::{CipherKind, Kdf};
+pub use history::{verify_history, HistoryRecord, Operation};
+pub use html::{HtmlBackup, QrCodeRenderer};
+pub use icon::{Icon, MAX_ICON_SIZE};
+pub use import::{parse_uri_folder, DuplicateResolution, ImportAction, ImportItem, ImportReport};
+pub use journal::DEFAULT_COMPACT_AFTER;
+#[cfg(feature = "kdbx")]
+pub use kdbx::KdbxDatabase;
+pub use kdf::{DEFAULT_UNLOCK_TIME, PASSPHRASE_LOG_N};
+pub use lastpass::parse_lastpass;
+pub use merge::{ConflictResolution, Tombstone};
+pub use messages::{locale_from_env, Catalog};
+pub use motp::MOTP;
+pub use names::NameMatching;
+#[cfg(feature = "async")]
+pub use nonblocking::{AsyncDatabase, BlockingDatabase, BoxFuture};
+pub use ocra::OCRA;
+pub use onepassword::parse_1password;
+pub use paper::PAPER_PART_SIZE;
+pub use password::{ask_new_password, PasswordPrompt, NEW_PASSWORD_ATTEMPTS};
+pub use permissions::{check_write_target, permissions_problem, restrict_permissions};
+pub use progress::{Progress, Task};
+pub use recipients::encrypt_to_recipients;
+pub use renaming::RenameRule;
+#[cfg(feature = "s3")]
+pub use s3::S3Storage;
+pub use salvage::SalvageReport;
+pub use shared::SharedRusTOTPony;
+pub use snapshot::{Snapshot, SnapshotStore};
+#[cfg(feature = "ssh")]
+pub use ssh::SshStorage;
+pub use stats::Stats;
+pub use storage::{expand_home, storage_for, FileStorage, MemoryStorage, Storage};
+pub use summary::{ApplicationSummary, Page};
+pub use totp::TOTP;
+#[cfg(feature = "webdav")]
+pub use webdav::{SyncOutcome, WebDavRemote};
+
+const DATABASE_VERSION: u8 = 1;
+// 160 bits, as recommended by RFC 4226
+const PROVISIONED_SECRET_SIZE: usize = 20;
+
+/// Modification of the applications made since the database was loaded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Created(String),
+    Renamed(String, String),
+    Deleted(String),
+    Hidden(String),
+    Unhidden(String),
+    Archived(String),
+    Unarchived(String),
+    Protected(String),
+    Unprotected(String),
+    SecretRevealed(String),
+    RecoveryCodesAdded(String, usize),
+    RecoveryCodeUsed(String),
+    /// Settings of the application were changed, the second field names them
+    Updated(String, String),
+}
+
+/// Event passed to callbacks registered with `RusTOTPony::on_change`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// Applications were changed in memory
+    Changed(Change),
+    /// A long-running operation, e.g. an import, made progress
+    Progress(Progress),
+    /// Changes were written to the database by `flush` or `flush_async`
+    Saved,
+}
+
+type Observer = Box<dyn Fn(&Event) + Send + Sync>;
+
+pub struct RusTOTPony<DB: Database> {
+    database: DB,
+    // Loaded on first use, listings can do without secrets
+    content: OnceLock<DatabaseContent>,
+    changes: Vec<Change>,
+    // Names of applications changed since the database was loaded
+    dirty: HashSet<String>,
+    observers: Vec<Observer>,
+    dry_run: bool,
+    name_matching: NameMatching,
+    cancellation: Option<CancellationToken>,
+}
+
+impl<DB: Database> RusTOTPony<DB> {
+    pub fn new(db: DB) -> RusTOTPony<DB> {
+        RusTOTPony {
+            content: OnceLock::new(),
+            database: db,
+            changes: Vec::new(),
+            dirty: HashSet::new(),
+            observers: Vec::new(),
+            dry_run: false,
+            name_matching: NameMatching::default(),
+            cancellation: None,
+        }
+    }
+
+    /// Creates an instance with content which was already loaded from the
+    /// database, e.g. asynchronously.
+    pub fn with_content(db: DB, content: DatabaseContent) -> RusTOTPony<DB> {
+        let instance = RusTOTPony::new(db);
+        let mut content = content;
+        content.assign_ids();
+        let _ = instance.content.set(content);
+        instance
+    }
+
+    /// In dry-run mode all operations work as usual, but `flush` never writes
+    /// anything. Use `get_changes` to find out what would have been saved.
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Lets imports and `flush` be cancelled with the token. A cancelled
+    /// import takes back the entries added so far, a cancelled `flush`
+    /// doesn't save anything; both fail with `CANCELLED`. Merges change
+    /// only the memory, they are cancelled by cancelling the `flush`.
+    pub fn set_cancellation(&mut self, token: CancellationToken) {
+        self.cancellation = Some(token);
+    }
+
+    /// Registers a callback which is called after every change of the
+    /// applications and after they are saved, so user interfaces and sync
+    /// don't have to poll for changes.
+    pub fn on_change<F: Fn(&Event) + Send + Sync + 'static>(&mut self, callback: F) {
+        self.observers.push(Box::new(callback));
+    }
+
+    /// Returns all changes made since the database was loaded.
+    pub fn get_changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    pub fn create_application(
+        &mut self,
+        name: &str,
+        username: &str,
+        secret: &str,
+    ) -> Result<(), Error> {
+        let totp = TOTP::new(secret)?;
+        self.create_totp_application(name, username, &totp)
+    }
+
+    /// Creates an application with a secret given in any encoding.
+    pub fn create_totp_application(
+        &mut self,
+        name: &str,
+        username: &str,
+        totp: &TOTP,
+    ) -> Result<(), Error> {
+        self.insert_application(GenApp::from_totp(name, username, totp))
+    }
+
+    pub fn create_motp_application(
+        &mut self,
+        name: &str,
+        username: &str,
+        motp: &MOTP,
+    ) -> Result<(), Error> {
+        self.insert_application(GenApp::from_motp(name, username, motp))
+    }
+
+    /// Creates an application with a new random secret and returns its
+    /// `otpauth://` URI, which can be handed to a user for enrollment.
+    pub fn provision(&mut self, name: &str, username: &str) -> Result<String, Error> {
+        let secret_bytes = random::bytes(PROVISIONED_SECRET_SIZE);
+        let app = GenApp::from_totp(name, username, &TOTP::from_bytes(secret_bytes));
+        let uri = app.to_otpauth_uri();
+        self.insert_application(app)?;
+        Ok(uri)
+    }
+
+    /// Adds an application with all its settings, e.g. one recovered from
+    /// another database.
+    pub fn insert_application(&mut self, mut app: GenApp) -> Result<(), Error> {
+        app.name = names::normalize(&app.name);
+        let name = app.name.clone();
+        // A copy of an application, e.g. received from another database, is
+        // a different application here
+        if app.id.is_empty()
+            || self
+                .content()?
+                .applications
+                .values()
+                .any(|a| a.id == app.id)
+        {
+            app.id = merge::new_id();
+        }
+        if let Some(taken) = self.find_taken_name(&name)? {
+            if taken == name {
+                Err(Error::new(
+                    ErrorCode::AlreadyExists,
+                    format!("Application with name '{}' already exists!", name),
+                ))
+            } else {
+                Err(Error::new(ErrorCode::AlreadyExists, format!(
+                    "Application '{}' already exists, names differing only in case or Unicode composition aren't allowed",
+                    taken
+                )))
+            }
+        } else if let Some(owner) = self.find_alias_owner(&name)? {
+            Err(Error::new(
+                ErrorCode::AlreadyExists,
+                format!("'{}' is an alias of '{}'", name, owner),
+            ))
+        } else {
+            self.loaded_mut().applications.insert(name.clone(), app);
+            self.mark_dirty(&name);
+            self.record(Operation::Create, &name, None);
+            self.push_change(Change::Created(name));
+            Ok(())
+        }
+    }
+
+    pub fn delete_application(&mut self, name: &str) -> Result<(), Error> {
+        let name = &self.resolve_name(name)?;
+        if let Some(app) = self.content_mut()?.applications.remove(name) {
+            self.loaded_mut().bury(&app);
+            self.mark_dirty(name);
+            self.record(Operation::Delete, name, None);
+            self.push_change(Change::Deleted(String::from(name)));
+            Ok(())
+        } else {
+            Err(Error::new(
+                ErrorCode::NotFound,
+                format!("Application with the name '{}' doesn't exist", name),
+            ))
+        }
+    }
+
+    pub fn rename_application(&mut self, name: &str, newname: &str) -> Result<(), Error> {
+        let name = &self.resolve_name(name)?;
+        let newname = &names::normalize(newname);
+        // Changing the case of a name is fine
+        match self.find_taken_name(newname)? {
+            Some(taken) if taken != *name => {
+                return Err(Error::new(
+                    ErrorCode::AlreadyExists,
+                    format!("Application with name '{}' already exists!", taken),
+                ));
+            }
+            _ => {}
+        }
+        match self.find_alias_owner(newname)? {
+            Some(owner) if owner != *name => {
+                return Err(Error::new(
+                    ErrorCode::AlreadyExists,
+                    format!("'{}' is an alias of '{}'", newname, owner),
+                ));
+            }
+            _ => {}
+        }
+        if let Some(mut app) = self.content_mut()?.applications.remove(name) {
+            app.name = String::from(newname);
+            self.loaded_mut()
+                .applications
+                .insert(String::from(newname), app);
+            self.mark_dirty(name);
+            self.mark_dirty(newname);
+            self.record(Operation::Rename, name, Some(newname));
+            self.push_change(Change::Renamed(String::from(name), String::from(newname)));
+            Ok(())
+        } else {
+            Err(Error::new(
+                ErrorCode::NotFound,
+                format!("Application '{}' wasn't found", name),
+            ))
+        }
+    }
+
+    /// Changes how codes of the application are shown.
+    pub fn set_code_format(&mut self, name: &str, format: CodeFormat) -> Result<(), Error> {
+        let name = &self.resolve_name(name)?;
+        match self.content_mut()?.applications.get_mut(name) {
+            Some(app) => app.format = format,
+            None => {
+                return Err(Error::new(
+                    ErrorCode::NotFound,
+                    format!("Application '{}' wasn't found", name),
+                ))
+            }
+        }
+        self.mark_dirty(name);
+        self.record(Operation::Update, name, Some("format"));
+        self.push_change(Change::Updated(String::from(name), String::from("format")));
+        Ok(())
+    }
+
+    /// Changes the unix time from which time steps of a TOTP application
+    /// are counted.
+    pub fn set_epoch(&mut self, name: &str, epoch: u64) -> Result<(), Error> {
+        let name = &self.resolve_name(name)?;
+        if epoch > datetime::now() {
+            return Err(Error::new(
+                ErrorCode::InvalidArgument,
+                "Epoch can't be in the future",
+            ));
+        }
+        match self.content_mut()?.applications.get_mut(name) {
+            Some(app) if !app.kind.is_totp() => {
+                return Err(Error::new(
+                    ErrorCode::Unsupported,
+                    "Only TOTP generators have an epoch",
+                ))
+            }
+            Some(app) => app.epoch = epoch,
+            None => {
+                return Err(Error::new(
+                    ErrorCode::NotFound,
+                    format!("Application '{}' wasn't found", name),
+                ))
+            }
+        }
+        self.mark_dirty(name);
+        self.record(Operation::Update, name, Some("epoch"));
+        self.push_change(Change::Updated(String::from(name), String::from("epoch")));
+        Ok(())
+    }
+
+    /// Hidden applications are left out of listings and the dashboard, they
+    /// are shown only when requested explicitly by name.
+    pub fn set_hidden(&mut self, name: &str, hidden: bool) -> Result<(), Error> {
+        let name = &self.resolve_name(name)?;
+        let app = match self.content_mut()?.applications.get_mut(name) {
+            Some(app) => app,
+            None => {
+                return Err(Error::new(
+                    ErrorCode::NotFound,
+                    format!("Application '{}' wasn't found", name),
+                ))
+            }
+        };
+        if app.hidden == hidden {
+            return Ok(());
+        }
+        app.hidden = hidden;
+        self.mark_dirty(name);
+        if hidden {
+            self.record(Operation::Hide, name, None);
+            self.push_change(Change::Hidden(String::from(name)));
+        } else {
+            self.record(Operation::Unhide, name, None);
+            self.push_change(Change::Unhidden(String::from(name)));
+        }
+        Ok(())
+    }
+
+    /// Archived applications are left out of listings and the dashboard like
+    /// hidden ones, for accounts which are closed but may be needed again.
+    pub fn set_archived(&mut self, name: &str, archived: bool) -> Result<(), Error> {
+        let name = &self.resolve_name(name)?;
+        let app = match self.content_mut()?.applications.get_mut(name) {
+            Some(app) => app,
+            None => {
+                return Err(Error::new(
+                    ErrorCode::NotFound,
+                    format!("Application '{}' wasn't found", name),
+                ))
+            }
+        };
+        if app.archived == archived {
+            return Ok(());
+        }
+        app.archived = archived;
+        self.mark_dirty(name);
+        if archived {
+            self.record(Operation::Archive, name, None);
+            self.push_change(Change::Archived(String::from(name)));
+        } else {
+            self.record(Operation::Unarchive, name, None);
+            self.push_change(Change::Unarchived(String::from(name)));
+        }
+        Ok(())
+    }
+
+    /// Stores recovery codes of the application. Blank lines and codes which
+    /// are already stored are skipped, the number of added codes is returned.
+    pub fn add_recovery_codes(&mut self, name: &str, codes: &[String]) -> Result<usize, Error> {
+        let name = &self.resolve_name(name)?;
+        let app = match self.content_mut()?.applications.get_mut(name) {
+            Some(app) => app,
+            None => {
+                return Err(Error::new(
+                    ErrorCode::NotFound,
+                    format!("Application '{}' wasn't found", name),
+                ))
+            }
+        };
+        let mut added = 0;
+        for code in codes.iter().map(|code| code.trim()) {
+            if !code.is_empty() && !app.recovery_codes.iter().any(|c| c == code) {
+                app.recovery_codes.push(String::from(code));
+                added += 1;
+            }
+        }
+        if added > 0 {
+            self.mark_dirty(name);
+            self.record(Operation::AddRecoveryCodes, name, Some(&added.to_string()));
+            self.push_change(Change::RecoveryCodesAdded(String::from(name), added));
+        }
+        Ok(added)
+    }
+
+    /// Removes a recovery code of the application and returns it. When no
+    /// code is given, the oldest one is taken.
+    pub fn use_recovery_code(&mut self, name: &str, code: Option<&str>) -> Result<String, Error> {
+        let name = &self.resolve_name(name)?;
+        let app = match self.content_mut()?.applications.get_mut(name) {
+            Some(app) => app,
+            None => {
+                return Err(Error::new(
+                    ErrorCode::NotFound,
+                    format!("Application '{}' wasn't found", name),
+                ))
+            }
+        };
+        let idx = match code {
+            Some(code) => app
+                .recovery_codes
+                .iter()
+                .position(|c| c == code.trim())
+                .ok_or_else(|| {
+                    Error::new(
+                        ErrorCode::NotFound,
+                        format!("Application '{}' has no such recovery code", name),
+                    )
+                })?,
+            None if app.recovery_codes.is_empty() => {
+                return Err(Error::new(
+                    ErrorCode::NotFound,
+                    format!("Application '{}' has no recovery codes left", name),
+                ))
+            }
+            None => 0,
+        };
+        let code = app.recovery_codes.remove(idx);
+        self.mark_dirty(name);
+        self.record(Operation::UseRecoveryCode, name, None);
+        self.push_change(Change::RecoveryCodeUsed(String::from(name)));
+        Ok(code)
+    }
+
+    pub fn get_applications(&self) -> Result<&HashMap<String, GenApp>, Error> {
+        let content = self.content()?;
+        if content.applications.is_empty() {
+            Err(Error::new(ErrorCode::NotFound, "There are no applications"))
+        } else {
+            Ok(&content.applications)
+        }
+    }
+
+    pub fn get_application(&self, name: &str) -> Result<&GenApp, Error> {
+        let name = self.resolve_name(name)?;
+        if let Some(app) = self.content()?.applications.get(&name) {
+            Ok(app)
+        } else {
+            Err(Error::new(
+                ErrorCode::NotFound,
+                format!("Application '{}' wasn't found", name),
+            ))
+        }
+    }
+
+    /// Returns a copy of the application. Unless the content is loaded
+    /// already, an application given by its exact name is read alone, so
+    /// indexed databases don't decrypt the other applications.
+    pub fn load_application(&self, name: &str) -> Result<GenApp, Error> {
+        if self.content.get().is_none() {
+            if let Some(app) = self.database.get_application(name)? {
+                return Ok(app);
+            }
+        }
+        self.get_application(name).cloned()
+    }
+
+    /// Returns the secret of the application and records the disclosure in
+    /// the history. Passphrase is required for protected applications.
+    pub fn reveal_secret(&mut self, name: &str, passphrase: Option<&str>) -> Result<String, Error> {
+        let app = self.disclose(name, passphrase, None)?;
+        Ok(app.secret)
+    }
+
+    /// Returns an `otpauth://` URI for setting the application up on another
+    /// device. It contains the secret, so it's recorded in the history like
+    /// `reveal_secret`.
+    pub fn reenroll(&mut self, name: &str, passphrase: Option<&str>) -> Result<String, Error> {
+        self.get_application(name)?.check_otpauth_uri()?;
+        let app = self.disclose(name, passphrase, Some("reenroll"))?;
+        Ok(app.to_otpauth_uri())
+    }
+
+    pub fn delete_all_applications(&mut self) -> Result<(), Error> {
+        let mut names: Vec<String> = self.content()?.applications.keys().cloned().collect();
+        names.sort();
+        for name in names.iter() {
+            self.mark_dirty(name);
+        }
+        for name in names {
+            self.push_change(Change::Deleted(name));
+        }
+        let apps = std::mem::take(&mut self.loaded_mut().applications);
+        for app in apps.values() {
+            self.loaded_mut().bury(app);
+        }
+        self.record(Operation::DeleteAll, "", None);
+        Ok(())
+    }
+
+    /// Returns the audit log, the oldest record first.
+    pub fn get_history(&self) -> Result<&[HistoryRecord], Error> {
+        Ok(&self.content()?.history)
+    }
+
+    pub fn flush(&mut self) -> Result<(), Error> {
+        if !self.start_flush()? {
+            return Ok(());
+        }
+        self.database.save_changes(self.content()?, &self.dirty)?;
+        self.finish_flush();
+        Ok(())
+    }
+
+    // Tells whether changes should be saved, shared with `flush_async`
+    fn start_flush(&self) -> Result<bool, Error> {
+        if self.dry_run {
+            return Ok(false);
+        }
+        cancellation::check(self.cancellation.as_ref())?;
+        Ok(true)
+    }
+
+    fn finish_flush(&mut self) {
+        self.dirty.clear();
+        self.notify(&Event::Saved);
+    }
+
+    fn disclose(
+        &mut self,
+        name: &str,
+        passphrase: Option<&str>,
+        details: Option<&str>,
+    ) -> Result<GenApp, Error> {
+        let name = &self.resolve_name(name)?;
+        let app = self.get_application(name)?;
+        let app = match passphrase {
+            Some(passphrase) => app.unlock(passphrase)?,
+            None if app.is_protected() => {
+                return Err(Error::new(
+                    ErrorCode::Protected,
+                    format!(
+                        "Application '{}' is protected, its passphrase is required",
+                        name
+                    ),
+                ))
+            }
+            None => app.clone(),
+        };
+        self.record(Operation::RevealSecret, name, details);
+        self.push_change(Change::SecretRevealed(String::from(name)));
+        Ok(app)
+    }
+
+    fn content(&self) -> Result<&DatabaseContent, Error> {
+        if let Some(content) = self.content.get() {
+            return Ok(content);
+        }
+        let mut content = self.database.get_content()?;
+        content.assign_ids();
+        Ok(self.content.get_or_init(|| content))
+    }
+
+    fn content_mut(&mut self) -> Result<&mut DatabaseContent, Error> {
+        self.content()?;
+        Ok(self.loaded_mut())
+    }
+
+    // Changes follow a successful `content`, so the content is loaded
+    fn loaded_mut(&mut self) -> &mut DatabaseContent {
+        self.content.get_mut().expect("Content isn't loaded")
+    }
+
+    fn push_change(&mut self, change: Change) {
+        self.notify(&Event::Changed(change.clone()));
+        self.changes.push(change);
+    }
+
+    fn notify(&self, event: &Event) {
+        for observer in self.observers.iter() {
+            observer(event);
+        }
+    }
+
+    // Every change of an application goes through here, so it's also where
+    // the modification time is kept
+    fn mark_dirty(&mut self, name: &str) {
+        if let Some(app) = self.loaded_mut().applications.get_mut(name) {
+            app.touch();
+        }
+        self.dirty.insert(String::from(name));
+    }
+
+    fn record(&mut self, operation: Operation, name: &str, details: Option<&str>) {
+        let content = self.loaded_mut();
+        let record = HistoryRecord::new(content.history.last(), operation, name, details);
+        content.history.push(record);
+    }
+}
+
+pub trait Database {
+    /// Reads the applications.
+    fn get_content(&self) -> Result<DatabaseContent, Error>;
+    fn save_content(&self, content: &DatabaseContent) -> Result<(), Error>;
+
+    /// Saves the content when only the named applications were changed (or
+    /// removed) since it was loaded. Backends keeping a record per
+    /// application can write just these records and the history, by
+    /// default the whole content is saved.
+    fn save_changes(
+        &self,
+        content: &DatabaseContent,
+        _changed: &HashSet<String>,
+    ) -> Result<(), Error> {
+        self.save_content(content)
+    }
+
+    /// Reads the applications without their secrets.
+    fn get_summaries(&self) -> Result<Vec<ApplicationSummary>, Error> {
+        Ok(summary::summarize(&self.get_content()?))
+    }
+
+    /// Reads a single application. Backends encrypting applications one by
+    /// one decrypt only its record, by default the whole content is read.
+    fn get_application(&self, name: &str) -> Result<Option<GenApp>, Error> {
+        Ok(self.get_content()?.applications.remove(name))
+    }
+
+    /// Size of the stored database in bytes, `None` if it's unknown or
+    /// expensive to find out.
+    fn get_size(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl Database for JsonDatabase {
+    fn get_content(&self) -> Result<DatabaseContent, Error> {
+        self.load_content()
+    }
+
+    fn save_content(&self, content: &DatabaseContent) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::new(
+                ErrorCode::ReadOnly,
+                "Database is opened in read-only mode",
+            ));
+        }
+        self.save_database_file(content)
+    }
+
+    fn save_changes(
+        &self,
+        content: &DatabaseContent,
+        changed: &HashSet<String>,
+    ) -> Result<(), Error> {
+        match self.storage.local_path() {
+            // The journal is applied to the file, a new database is written
+            // whole
+            Some(path) if self.journal.is_some() && !self.read_only && path.exists() => {
+                self.append_to_journal(content, changed)
+            }
+            _ if !self.read_only && self.header().is_indexed() => {
+                self.save_indexed_changes(content, changed)
+            }
+            _ => self.save_content(content),
+        }
+    }
+
+    fn get_summaries(&self) -> Result<Vec<ApplicationSummary>, Error> {
+        // Summaries are read from the file alone, which lacks the changes
+        // in the journal
+        if self.has_journal() {
+            return Ok(summary::summarize(&self.get_content()?));
+        }
+        self.read_summaries()
+    }
+
+    fn get_application(&self, name: &str) -> Result<Option<GenApp>, Error> {
+        if self.has_journal() || !self.header().is_indexed() {
+            return Ok(self.get_content()?.applications.remove(name));
+        }
+        self.read_indexed_application(name)
+    }
+
+    fn get_size(&self) -> Option<u64> {
+        let path = self.storage.local_path()?;
+        std::fs::metadata(path).ok().map(|metadata| metadata.len())
+    }
+}
+
+/// Layout of the decrypted database. Content is borrowed when saving, so
+/// the applications don't have to be copied for serialization.
+#[derive(Serialize, Deserialize)]
+struct JsonDatabaseSchema<C = DatabaseContent> {
+    version: u8,
+    content: C,
+}
+
+/// Everything stored in a database.
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct DatabaseContent {
+    pub applications: HashMap<String, GenApp>,
+    #[serde(default)]
+    pub history: Vec<HistoryRecord>,
+    /// Deleted applications, for merging copies of the database
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tombstones: Vec<Tombstone>,
+}
+
+pub struct JsonDatabase {
+    storage: Box<dyn Storage>,
+    secret_fn: Box<dyn Fn() -> String + Send + Sync>,
+    new_secret_fn: Option<Box<dyn Fn() -> String + Send + Sync>>,
+    second_secret_fn: Option<Box<dyn Fn() -> String + Send + Sync>>,
+    /// Cipher and key derivation of new files, the latter is benchmarked
+    /// to take `unlock_time` if it isn't set
+    new_cipher: CipherKind,
+    new_kdf: Option<Kdf>,
+    new_indexed: bool,
+    unlock_time: Duration,
+    backup_policy: BackupPolicy,
+    read_only: bool,
+    compression: bool,
+    key_cache: Option<KeyCache>,
+    journal: Option<Journal>,
+    progress: Option<ProgressCallback>,
+    cancellation: Option<CancellationToken>,
+    /// Header of the file, known once it's read
+    header: Mutex<Option<Header>>,
+}
+
+const IV_SIZE: usize = 16;
+// Size of an AES block, ciphertext length is always a multiple of it
+const BLOCK_SIZE: usize = 16;
+const KEY_SIZE: usize = 32;
+impl JsonDatabase {
+    /// Wipes the cached key, the password is asked again on the next access.
+    pub fn forget_key(&self) {
+        if let Some(cache) = &self.key_cache {
+            cache.clear();
+        }
+    }
+
+    /// Key of the database, either cached or derived from the password.
+    fn database_key(&self) -> [u8; KEY_SIZE] {
+        self.derive_key(&self.secret_fn)
+    }
+
+    fn derive_key(&self, secret_fn: &dyn Fn() -> String) -> [u8; KEY_SIZE] {
+        let derive = || match self.kdf() {
+            kdf if kdf.is_two_persons() => {
+                let first = secret_fn();
+                let second = self
+                    .second_secret_fn
+                    .as_ref()
+                    .map_or_else(String::new, |f| f());
+                self.report(Task::KeyDerivation, 0, None);
+                let key = Self::form_two_persons_key(&kdf, &first, &second);
+                self.report(Task::KeyDerivation, 1, Some(1));
+                key
+            }
+            kdf => {
+                let password = secret_fn();
+                self.report(Task::KeyDerivation, 0, None);
+                let key = Self::form_password_key(&kdf, &password);
+                self.report(Task::KeyDerivation, 1, Some(1));
+                key
+            }
+        };
+        match &self.key_cache {
+            Some(cache) => cache.get_or_derive(derive),
+            None => derive(),
+        }
+    }
+
+    fn form_secret_key(input: &str) -> [u8; KEY_SIZE] {
+        let started = datetime::Stopwatch::start();
+        let mut res: [u8; KEY_SIZE] = [0; KEY_SIZE];
+        res.copy_from_slice(&Hash::Sha256.digest(input.as_bytes()));
+        debug!(
+            elapsed_us = started.elapsed().as_micros() as u64,
+            "derived key"
+        );
+        res
+    }
+
+    /// Reads and decrypts the database, like `get_content` without the
+    /// `Database` trait in scope.
+    pub fn load_content(&self) -> Result<DatabaseContent, Error> {
+        self.read_database_file().map(|schema| schema.content)
+    }
+
+    /// Creates an empty database with the cipher, key derivation and layout
+    /// set by the builder. Unlike the first save it fails if the database
+    /// exists already, so an existing one is never replaced.
+    pub fn create(&self) -> Result<(), Error> {
+        if self.storage.read()?.is_some() {
+            return Err(Error::new(
+                ErrorCode::AlreadyExists,
+                "Database already exists",
+            ));
+        }
+        self.save_content(&DatabaseContent::default())
+    }
+
+    fn read_database_file(&self) -> Result<JsonDatabaseSchema, Error> {
+        let _span = info_span!("load_database").entered();
+        let started = datetime::Stopwatch::start();
+        let data = match self.read_vaults()? {
+            Some(d) => d,
+            None => {
+                info!("there is no database yet");
+                return Ok(Self::get_empty_schema());
+            }
+        };
+        debug!(bytes = data.len(), "read database");
+        let key = self.database_key();
+        let mut schema: JsonDatabaseSchema = self.decrypt_with_key(&data, &key)?;
+        self.replay_journal(&mut schema.content, &key)?;
+        info!(
+            version = schema.version,
+            applications = schema.content.applications.len(),
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "loaded database"
+        );
+        Ok(schema)
+    }
+
+    /// Reads the database file and returns the encrypted vaults following
+    /// its header. A file without a header has to look like a database of
+    /// an older version, otherwise it's not a database at all.
+    fn read_vaults(&self) -> Result<Option<Vec<u8>>, Error> {
+        match self.storage.read()? {
+            Some(data) => self.strip_header(&data).map(|vaults| Some(vaults.to_vec())),
+            None => Ok(None),
+        }
+    }
+
+    fn strip_header<'a>(&self, data: &'a [u8]) -> Result<&'a [u8], Error> {
+        let (header, vaults) = header::split(data)?;
+        // Files without a header were written with the defaults of older
+        // versions
+        self.set_header(header.clone().unwrap_or_else(Header::legacy));
+        match header {
+            Some(header) => debug!(version = header.version, "read database header"),
+            None if !has_legacy_size(vaults) => {
+                return Err(Error::new(
+                    ErrorCode::Damaged,
+                    "File isn't a RusTOTPony database",
+                ));
+            }
+            None => debug!("database has no header"),
+        }
+        Ok(vaults)
+    }
+
+    /// Writes the vaults with the header of the current format in front.
+    /// The previous file is backed up first if the policy says so.
+    fn write_vaults(&self, vaults: &[u8]) -> Result<(), Error> {
+        // The last moment a save can be cancelled, the file is replaced
+        // at once
+        self.check_cancelled()?;
+        let header = self.header();
+        let header = Header {
+            version: header.write_version(),
+            ..header
+        };
+        if let Some(path) = self.storage.local_path() {
+            self.backup_policy.back_up(path)?;
+        }
+        let data = [&header.to_bytes(), vaults].concat();
+        self.prepare_journal(&data)?;
+        self.storage.write(&data)?;
+        self.rebase_journal(&data)
+    }
+
+    /// Header of the database file, it's read if it's unknown yet. New
+    /// databases get the cipher and key derivation set by the builder, the
+    /// header is kept, so the key is derived with the same salt as long as
+    /// the database is opened.
+    fn header(&self) -> Header {
+        if let Some(header) = self
+            .header
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone()
+        {
+            return header;
+        }
+        // Unreadable files fail later with a better message
+        let _ = self.read_vaults();
+        let mut header = self.header.lock().unwrap_or_else(|err| err.into_inner());
+        header
+            .get_or_insert_with(|| Header {
+                cipher: self.new_cipher,
+                kdf: self
+                    .new_kdf
+                    .clone()
+                    .unwrap_or_else(|| Kdf::benchmark(self.unlock_time)),
+                version: header::FORMAT_VERSION,
+                indexed: self.new_indexed,
+                // Indexed applications are encrypted one by one, each of
+                // them too small to be worth compressing
+                compressed: self.compression && !self.new_indexed,
+            })
+            .clone()
+    }
+
+    fn set_header(&self, header: Header) {
+        *self.header.lock().unwrap_or_else(|err| err.into_inner()) = Some(header);
+    }
+
+    /// Key derivation of the database.
+    fn kdf(&self) -> Kdf {
+        self.header().kdf
+    }
+
+    fn set_kdf(&self, kdf: Kdf) {
+        self.set_header(Header {
+            kdf,
+            ..self.header()
+        });
+    }
+
+    /// Cipher the vaults of the database are encrypted with.
+    fn cipher(&self) -> &'static dyn Cipher {
+        self.header().vault_cipher()
+    }
+
+    fn decrypt_with_secret<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T, Error> {
+        self.decrypt_with_key(data, &self.database_key())
+    }
+
+    fn decrypt_with_key<T: DeserializeOwned>(&self, data: &[u8], key: &[u8]) -> Result<T, Error> {
+        let result = self.open_vault(data, key).map(|(_, value)| value);
+        if result.is_err() {
+            self.forget_key();
+        }
+        result
+    }
+
+    fn decrypt_data(cipher: &dyn Cipher, data: &[u8], key: &[u8]) -> Result<String, Error> {
+        let decrypted = cipher.open(data, key).map_err(|_| {
+            Error::new(
+                ErrorCode::WrongPassword,
+                "Couldn't decrypt data: wrong password or corrupted file",
+            )
+        })?;
+        String::from_utf8(decrypted)
+            .map_err(|_| Error::new(ErrorCode::Damaged, "Decrypted data isn't valid UTF-8"))
+    }
+
+    fn save_database_file(&self, content: &DatabaseContent) -> Result<(), Error> {
+        let _span = info_span!("save_database").entered();
+        let started = datetime::Stopwatch::start();
+        let existing = self.read_vaults()?;
+        let key = match (&existing, &self.new_secret_fn) {
+            (None, Some(new_secret_fn)) => self.derive_key(new_secret_fn),
+            _ => self.database_key(),
+        };
+        self.write_content(content, &existing.unwrap_or_default(), &key)?;
+        info!(
+            applications = content.applications.len(),
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "saved database"
+        );
+        Ok(())
+    }
+
+    // Puts the sealed content in place of the vault opened by the key,
+    // everything in the journal is in the file afterwards
+    fn write_content(
+        &self,
+        content: &DatabaseContent,
+        existing: &[u8],
+        key: &[u8],
+    ) -> Result<(), Error> {
+        let vault = self.seal(content, key);
+        let data = decoy::place(existing, self.find_vault(existing, key).ok(), &vault);
+        self.write_vaults(&data)?;
+        debug!(bytes = data.len(), "wrote vaults");
+        self.clear_journal(content, key)
+    }
+
+    // Serializes, compresses, pads and encrypts the content
+    fn seal(&self, content: &DatabaseContent, key: &[u8]) -> Vec<u8> {
+        if self.header().is_indexed() {
+            return index::seal(
+                self.cipher(),
+                content,
+                key,
+                &self.reporter(Task::Encryption),
+            );
+        }
+        let schema = JsonDatabaseSchema {
+            version: DATABASE_VERSION,
+            content,
+        };
+        stream::write_vault(
+            self.cipher(),
+            &schema,
+            key,
+            self.header().compressed,
+            &self.reporter(Task::Encryption),
+        )
+    }
+
+    fn get_empty_schema() -> JsonDatabaseSchema {
+        JsonDatabaseSchema {
+            version: DATABASE_VERSION,
+            content: DatabaseContent::default(),
+        }
+    }
+}
+
+/// Databases of older versions are a bare IV followed by whole blocks.
+fn has_legacy_size(data: &[u8]) -> bool {
+    data.len() >= IV_SIZE + BLOCK_SIZE && (data.len() - IV_SIZE).is_multiple_of(BLOCK_SIZE)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GenApp {
+    /// Identifier kept across renames, see `get_id`
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    id: String,
+    /// Unix time of the last change
+    #[serde(default, skip_serializing_if = "is_zero")]
+    modified_at: u64,
+    /// Times of the earlier changes, the oldest first, to tell a later
+    /// version of the application from one changed in another copy
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    previous: Vec<u64>,
+    /// Identifier of the application this is a conflicting version of
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    conflict_of: Option<String>,
+    name: String,
+    secret: String,
+    username: String,
+    secret_bytes: Vec<u8>,
+    #[serde(default)]
+    hidden: bool,
+    /// Secret encrypted with an additional passphrase, `secret` and
+    /// `secret_bytes` are empty when it's set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    protected_secret: Option<String>,
+    /// Backup codes given by the service along with the secret
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    recovery_codes: Vec<String>,
+    #[serde(default, skip_serializing_if = "CodeFormat::is_default")]
+    format: CodeFormat,
+    #[serde(default, skip_serializing_if = "GeneratorKind::is_totp")]
+    kind: GeneratorKind,
+    /// Start of time steps of TOTP generators
+    #[serde(default, skip_serializing_if = "is_zero")]
+    epoch: u64,
+    /// Labels for picking applications out, e.g. for an export
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    /// Service which issued the secret, as given by an `otpauth://` URI
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    issuer: String,
+    /// Other names the application can be referred to by
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    aliases: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    icon: Option<Icon>,
+    /// Decommissioned application, kept in case it's needed again
+    #[serde(default, skip_serializing_if = "is_false")]
+    archived: bool,
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// Algorithm used to generate codes of an application.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum GeneratorKind {
+    #[default]
+    Totp,
+    /// Mobile-OTP, the secret is used as a string
+    Motp { pin: String },
+    /// OCRA challenge-response, the counter is used by suites with `C`
+    Ocra {
+        suite: String,
+        #[serde(default)]
+        counter: u64,
+    },
+}
+
+impl GeneratorKind {
+    pub fn is_totp(&self) -> bool {
+        *self == GeneratorKind::Totp
+    }
+}
+
+impl GenApp {
+    fn from_totp(name: &str, username: &str, totp: &TOTP) -> Self {
+        GenApp {
+            id: merge::new_id(),
+            modified_at: datetime::now(),
+            previous: Vec::new(),
+            conflict_of: None,
+            name: String::from(name),
+            secret: totp.get_secret_base32(),
+            username: String::from(username),
+            secret_bytes: totp.get_secret_bytes().to_vec(),
+            hidden: false,
+            protected_secret: None,
+            recovery_codes: Vec::new(),
+            format: totp.get_format().clone(),
+            kind: GeneratorKind::Totp,
+            epoch: totp.get_epoch(),
+            tags: Vec::new(),
+            issuer: String::new(),
+            aliases: Vec::new(),
+            icon: None,
+            archived: false,
+        }
+    }
+
+    fn from_motp(name: &str, username: &str, motp: &MOTP) -> Self {
+        let totp = TOTP::from_bytes(motp.get_secret().as_bytes().to_vec());
+        let mut app = Self::from_totp(name, username, &totp);
+        app.kind = GeneratorKind::Motp {
+            pin: String::from(motp.get_pin()),
+        };
+        app
+    }
+
+    fn from_ocra(name: &str, username: &str, ocra: &OCRA) -> Self {
+        let totp = TOTP::from_bytes(ocra.get_secret_bytes().to_vec())
+            .with_format(CodeFormat::decimal(ocra.get_digits()).unwrap_or_default());
+        let mut app = Self::from_totp(name, username, &totp);
+        app.kind = GeneratorKind::Ocra {
+            suite: String::from(ocra.get_suite()),
+            counter: ocra.get_counter(),
+        };
+        app
+    }
+
+    pub fn get_name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    /// Returns the application under another name, e.g. before adding a
+    /// received application whose name is taken.
+    pub fn with_name(mut self, name: &str) -> GenApp {
+        self.name = String::from(name);
+        self
+    }
+
+    pub fn get_secret(&self) -> &str {
+        self.secret.as_str()
+    }
+
+    pub fn get_username(&self) -> &str {
+        self.username.as_str()
+    }
+
+    /// Issuer of the secret, empty unless the application was set up from
+    /// an `otpauth://` URI.
+    pub fn get_issuer(&self) -> &str {
+        self.issuer.as_str()
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.hidden
+    }
+
+    pub fn get_recovery_codes(&self) -> &[String] {
+        &self.recovery_codes
+    }
+
+    /// Tags of the application, sorted.
+    pub fn get_tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Aliases of the application, sorted.
+    pub fn get_aliases(&self) -> &[String] {
+        &self.aliases
+    }
+
+    pub fn is_archived(&self) -> bool {
+        self.archived
+    }
+
+    pub fn get_icon(&self) -> Option<&Icon> {
+        self.icon.as_ref()
+    }
+
+    /// Protected applications have to be unlocked before generating codes.
+    /// Challenge-response applications have no code without a challenge,
+    /// an empty string is returned for them.
+    pub fn get_code(&self) -> String {
+        self.generate(datetime::now())
+    }
+
+    /// Returns the code valid at the given unix time, see `get_code`.
+    pub fn generate(&self, timestamp: u64) -> String {
+        match self.kind {
+            GeneratorKind::Totp => self.get_totp().generate(timestamp),
+            GeneratorKind::Motp { ref pin } => {
+                MOTP::new(&String::from_utf8_lossy(&self.secret_bytes), pin)
+                    .map(|motp| motp.generate(timestamp))
+                    .unwrap_or_default()
+            }
+            GeneratorKind::Ocra { .. } => String::new(),
+        }
+    }
+
+    /// Returns how many seconds a code is valid, `None` for challenge-response
+    /// applications, whose codes don't depend on time.
+    pub fn get_time_step(&self) -> Option<u64> {
+        match self.kind {
+            GeneratorKind::Totp => Some(totp::TIME_STEP),
+            GeneratorKind::Motp { .. } => Some(motp::TIME_STEP),
+            GeneratorKind::Ocra { .. } => None,
+        }
+    }
+
+    /// Returns how many seconds the code generated at the given unix time
+    /// stays valid.
+    pub fn seconds_left(&self, timestamp: u64) -> Option<u64> {
+        let step = self.get_time_step()?;
+        let elapsed = match self.kind {
+            GeneratorKind::Totp => timestamp.saturating_sub(self.epoch),
+            _ => timestamp,
+        };
+        Some(step - elapsed % step)
+    }
+
+    /// Checks a code entered by a user. Codes of the previous and the next
+    /// time step are accepted too, so slightly wrong clocks don't matter.
+    /// Challenge-response applications never match.
+    pub fn verify(&self, code: &str, timestamp: u64) -> bool {
+        let step = match self.get_time_step() {
+            Some(step) => step,
+            None => return false,
+        };
+        let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+        !code.is_empty()
+            && [timestamp.saturating_sub(step), timestamp, timestamp + step]
+                .iter()
+                .any(|&time| self.generate(time).eq_ignore_ascii_case(&code))
+    }
+
+    /// Returns the challenge-response generator of an OCRA application.
+    pub fn get_ocra(&self) -> Option<Result<OCRA, Error>> {
+        match self.kind {
+            GeneratorKind::Ocra { ref suite, counter } => Some(
+                OCRA::new(self.secret_bytes.clone(), suite).map(|ocra| ocra.with_counter(counter)),
+            ),
+            _ => None,
+        }
+    }
+
+    pub fn get_kind(&self) -> &GeneratorKind {
+        &self.kind
+    }
+
+    /// Checks the generator of an application read from outside the
+    /// database, e.g. a share, before it's used.
+    pub(crate) fn check_kind(&self) -> Result<(), Error> {
+        match self.kind {
+            GeneratorKind::Motp { ref pin } if pin.is_empty() => {
+                Err(Error::new(ErrorCode::InvalidArgument, "PIN is empty"))
+            }
+            GeneratorKind::Ocra { ref suite, .. } => OCRA::new(Vec::new(), suite).map(|_| ()),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn get_totp(&self) -> TOTP {
+        TOTP::from_bytes(self.secret_bytes.clone())
+            .with_format(self.format.clone())
+            .with_epoch(self.epoch)
+    }
+
+    pub fn get_format(&self) -> &CodeFormat {
+        &self.format
+    }
+
+    pub fn get_epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    fn base32_to_bytes(secret: &str) -> Option<Vec<u8>> {
+        base32::decode(base32::Alphabet::RFC4648 { padding: false }, secret)
+    }
+}
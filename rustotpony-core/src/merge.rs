@@ -0,0 +1,547 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::crypto::{to_hex, Hash};
+use crate::errors::{Error, ErrorCode};
+use crate::history::Operation;
+use crate::{datetime, random, Change, Database, DatabaseContent, GenApp, RusTOTPony};
+
+const ID_SIZE: usize = 16;
+// Enough to recognize versions from copies which weren't merged for a
+// long time, yet small next to the secret
+const MAX_PREVIOUS_VERSIONS: usize = 32;
+const CONFLICT_SUFFIX: &str = " (conflict)";
+
+/// Record of a deleted application, kept so that merging a copy of the
+/// database made before the deletion doesn't bring the application back.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Tombstone {
+    id: String,
+    deleted_at: u64,
+}
+
+impl Tombstone {
+    pub fn get_id(&self) -> &str {
+        self.id.as_str()
+    }
+
+    pub fn get_deleted_at(&self) -> u64 {
+        self.deleted_at
+    }
+}
+
+/// How `resolve_conflict` settles a conflict found by a merge.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConflictResolution {
+    /// The conflicting version is dropped.
+    KeepOriginal,
+    /// The conflicting version replaces the original one, which keeps its
+    /// name.
+    KeepConflicting,
+}
+
+pub(crate) fn new_id() -> String {
+    to_hex(&random::bytes(ID_SIZE))
+}
+
+impl GenApp {
+    /// Identifier which stays the same when the application is renamed, so
+    /// its versions in two copies of the database can be told apart from
+    /// different applications of the same name.
+    pub fn get_id(&self) -> &str {
+        self.id.as_str()
+    }
+
+    /// Unix time of the last change of the application, 0 if it wasn't
+    /// changed since timestamps were introduced.
+    pub fn get_modified_at(&self) -> u64 {
+        self.modified_at
+    }
+
+    /// Identifier of the application this is a conflicting version of,
+    /// see `DatabaseContent::merge`.
+    pub fn get_conflict_of(&self) -> Option<&str> {
+        self.conflict_of.as_deref()
+    }
+
+    // The new time is later than all versions the application was made
+    // from, even if they were changed within the same second
+    pub(crate) fn touch(&mut self) {
+        let modified_at = self.modified_at;
+        let latest = self.previous.last().copied().unwrap_or(0).max(modified_at);
+        self.modified_at = datetime::now().max(latest + 1);
+        self.absorb(&[modified_at]);
+    }
+
+    // Both versions were changed since they parted, unless one of them is
+    // an earlier version of the other. Versions from before modification
+    // times were kept are earlier than any other.
+    fn conflicts_with(&self, other: &GenApp) -> bool {
+        let is_earlier =
+            |a: &GenApp, b: &GenApp| a.modified_at == 0 || b.previous.contains(&a.modified_at);
+        !is_earlier(self, other)
+            && !is_earlier(other, self)
+            && settings_json(self) != settings_json(other)
+    }
+
+    // Makes the given times earlier versions of this one
+    fn absorb(&mut self, times: &[u64]) {
+        let current = self.modified_at;
+        self.previous
+            .extend(times.iter().filter(|time| **time != 0 && **time != current));
+        self.previous.sort_unstable();
+        self.previous.dedup();
+        let excess = self.previous.len().saturating_sub(MAX_PREVIOUS_VERSIONS);
+        self.previous.drain(..excess);
+    }
+
+    // The identifier is derived from the version, so merging the same
+    // versions again finds the copy made before
+    fn conflicting_copy(&self, original_id: &str) -> GenApp {
+        let mut copy = self.clone();
+        copy.id = to_hex(&Hash::Sha256.digest(app_json(self).as_bytes())[..ID_SIZE]);
+        copy.name = format!("{}{}", self.name, CONFLICT_SUFFIX);
+        copy.conflict_of = Some(String::from(original_id));
+        copy
+    }
+
+    // Versions changed at the same second are ordered by their content, so
+    // both copies pick the same one
+    fn is_newer_than(&self, other: &GenApp) -> bool {
+        let version = |app: &GenApp| (app.modified_at, serde_json::to_string(app).ok());
+        version(self) > version(other)
+    }
+}
+
+impl DatabaseContent {
+    /// Gives identifiers to applications created before they were
+    /// introduced. They are derived from the name, so all copies of such a
+    /// database agree on them.
+    pub(crate) fn assign_ids(&mut self) {
+        for (name, app) in self.applications.iter_mut() {
+            if app.id.is_empty() {
+                app.id = to_hex(&Hash::Sha256.digest(name.as_bytes())[..ID_SIZE]);
+            }
+        }
+    }
+
+    /// Leaves a tombstone of a deleted application, later than its last
+    /// change.
+    pub(crate) fn bury(&mut self, app: &GenApp) {
+        self.tombstones.retain(|tombstone| tombstone.id != app.id);
+        self.tombstones.push(Tombstone {
+            id: app.id.clone(),
+            deleted_at: datetime::now().max(app.modified_at + 1),
+        });
+    }
+
+    /// Merges another copy of the database into this one. Of two versions
+    /// of an application the later one wins if it was made from the other.
+    /// When an application was changed in both copies, the later version
+    /// wins and the other one is kept as a separate application with
+    /// " (conflict)" added to its name, see `resolve_conflict`. An
+    /// application deleted in either copy stays deleted unless it was
+    /// changed after the deletion. Different applications which got the
+    /// same name in the two copies are told apart by a part of their
+    /// identifier added to the name. Applications merged in either
+    /// direction come out the same; the history of this copy is kept.
+    pub fn merge(&mut self, other: &DatabaseContent) {
+        let mut other = other.clone();
+        other.assign_ids();
+        self.assign_ids();
+
+        let mut deleted: HashMap<String, u64> = HashMap::new();
+        for tombstone in self.tombstones.iter().chain(other.tombstones.iter()) {
+            let deleted_at = deleted.entry(tombstone.id.clone()).or_insert(0);
+            *deleted_at = (*deleted_at).max(tombstone.deleted_at);
+        }
+
+        let mut apps: BTreeMap<String, GenApp> = BTreeMap::new();
+        let all_apps = self
+            .applications
+            .drain()
+            .chain(other.applications)
+            .map(|(_, app)| app);
+        for app in all_apps {
+            let kept = match apps.remove(&app.id) {
+                Some(kept) => kept,
+                None => {
+                    apps.insert(app.id.clone(), app);
+                    continue;
+                }
+            };
+            let (mut newer, older) = if app.is_newer_than(&kept) {
+                (app, kept)
+            } else {
+                (kept, app)
+            };
+            if newer.conflicts_with(&older) {
+                let copy = older.conflicting_copy(&newer.id);
+                if !apps.contains_key(&copy.id) {
+                    apps.insert(copy.id.clone(), copy);
+                }
+            } else {
+                newer.absorb(&older.previous);
+                newer.absorb(&[older.modified_at]);
+            }
+            apps.insert(newer.id.clone(), newer);
+        }
+        apps.retain(|id, app| deleted.get(id).is_none_or(|at| app.modified_at > *at));
+        deleted.retain(|id, _| !apps.contains_key(id));
+
+        let mut apps: Vec<GenApp> = apps.into_values().collect();
+        apps.sort_by(|a, b| (&a.name, &a.id).cmp(&(&b.name, &b.id)));
+        for mut app in apps {
+            if self.applications.contains_key(&app.name) {
+                app.name = unused_name(&self.applications, &app.name, &app.id);
+                // The renamed version has to be later than the one in the
+                // copy, or merging it again would find a conflict. A time
+                // derived from the version keeps the merge deterministic.
+                let modified_at = app.modified_at;
+                app.modified_at += 1;
+                app.absorb(&[modified_at]);
+            }
+            self.applications.insert(app.name.clone(), app);
+        }
+        self.tombstones = deleted
+            .into_iter()
+            .map(|(id, deleted_at)| Tombstone { id, deleted_at })
+            .collect();
+        self.tombstones.sort_by(|a, b| a.id.cmp(&b.id));
+    }
+}
+
+impl<DB: Database> RusTOTPony<DB> {
+    /// Merges another copy of the database, e.g. one changed on another
+    /// device, see `DatabaseContent::merge`. The changes of this copy are
+    /// returned and recorded in the history; `flush` saves them.
+    pub fn merge(&mut self, other: &DatabaseContent) -> Result<Vec<Change>, Error> {
+        let before: HashMap<String, (String, String)> = self
+            .content()?
+            .applications
+            .iter()
+            .map(|(name, app)| (app.id.clone(), (name.clone(), app_json(app))))
+            .collect();
+        self.loaded_mut().merge(other);
+
+        let mut changes = Vec::new();
+        let mut seen = HashSet::new();
+        for (name, app) in self.loaded_mut().applications.iter() {
+            seen.insert(app.id.as_str());
+            match before.get(&app.id) {
+                None => changes.push(Change::Created(name.clone())),
+                Some((old_name, json)) => {
+                    if old_name != name {
+                        changes.push(Change::Renamed(old_name.clone(), name.clone()));
+                    }
+                    if *json != app_json(&app.clone().with_name(old_name)) {
+                        changes.push(Change::Updated(name.clone(), String::from("merge")));
+                    }
+                }
+            }
+        }
+        for (id, (name, _)) in before.iter() {
+            if !seen.contains(id.as_str()) {
+                changes.push(Change::Deleted(name.clone()));
+            }
+        }
+        changes.sort_by_key(|change| format!("{:?}", change));
+
+        for change in changes.iter() {
+            let (operation, name, details) = match change {
+                Change::Created(name) => (Operation::Create, name, Some("merge")),
+                Change::Deleted(name) => (Operation::Delete, name, Some("merge")),
+                Change::Renamed(name, newname) => {
+                    self.dirty.insert(newname.clone());
+                    (Operation::Rename, name, Some(newname.as_str()))
+                }
+                Change::Updated(name, _) => (Operation::Update, name, Some("merge")),
+                _ => continue,
+            };
+            let name = name.clone();
+            let details = details.map(String::from);
+            // Unlike other changes, a merge keeps the modification times
+            self.dirty.insert(name.clone());
+            self.record(operation, &name, details.as_deref());
+            self.push_change(change.clone());
+        }
+        Ok(changes)
+    }
+
+    /// Returns names of the conflicting versions kept by merges, sorted.
+    pub fn get_conflicts(&self) -> Result<Vec<String>, Error> {
+        let mut names: Vec<String> = self
+            .content()?
+            .applications
+            .iter()
+            .filter(|(_, app)| app.conflict_of.is_some())
+            .map(|(name, _)| name.clone())
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    /// Settles a conflict found by a merge, given the name of the
+    /// conflicting version. Either way only one version is left, and merging
+    /// the copies again doesn't bring the conflict back.
+    pub fn resolve_conflict(
+        &mut self,
+        name: &str,
+        resolution: ConflictResolution,
+    ) -> Result<(), Error> {
+        let name = self.resolve_name(name)?;
+        let conflict = self.get_application(&name)?.clone();
+        let original_id = conflict.conflict_of.clone().ok_or_else(|| {
+            Error::new(
+                ErrorCode::InvalidArgument,
+                format!("Application '{}' isn't a conflicting version", name),
+            )
+        })?;
+        let original = self
+            .loaded_mut()
+            .applications
+            .iter()
+            .find(|(_, app)| app.id == original_id)
+            .map(|(name, _)| name.clone());
+        let original = match original {
+            Some(original) => original,
+            // The original was deleted since, the conflicting version is
+            // all that's left of the application
+            None if resolution == ConflictResolution::KeepConflicting => {
+                if let Some(app) = self.loaded_mut().applications.get_mut(&name) {
+                    app.conflict_of = None;
+                }
+                self.mark_dirty(&name);
+                self.record(Operation::Update, &name, Some("conflict"));
+                self.push_change(Change::Updated(name, String::from("conflict")));
+                return Ok(());
+            }
+            None => return self.delete_application(&name),
+        };
+
+        if let Some(app) = self.loaded_mut().applications.get_mut(&original) {
+            let mut times = [app.previous.as_slice(), &conflict.previous].concat();
+            times.extend([app.modified_at, conflict.modified_at]);
+            if resolution == ConflictResolution::KeepConflicting {
+                let id = app.id.clone();
+                *app = conflict.clone().with_name(&original);
+                app.id = id;
+                app.conflict_of = None;
+            }
+            app.absorb(&times);
+        }
+        self.mark_dirty(&original);
+        self.record(Operation::Update, &original, Some("conflict"));
+        self.push_change(Change::Updated(original, String::from("conflict")));
+        self.delete_application(&name)
+    }
+}
+
+// Name of an application whose name is taken by another one: a part of its
+// identifier is added, a longer one if that's taken too, and a number after
+// the whole identifier if even that is
+fn unused_name(applications: &HashMap<String, GenApp>, name: &str, id: &str) -> String {
+    let mut lengths: Vec<usize> = (8..id.len()).step_by(8).collect();
+    lengths.push(id.len());
+    lengths
+        .into_iter()
+        .filter_map(|length| id.get(..length))
+        .map(|part| format!("{} ({})", name, part))
+        .chain((2..).map(|number| format!("{} ({} {})", name, id, number)))
+        .find(|candidate| !applications.contains_key(candidate))
+        .expect("Names with numbers never run out")
+}
+
+fn app_json(app: &GenApp) -> String {
+    serde_json::to_string(app).unwrap_or_default()
+}
+
+// Everything but the modification times
+fn settings_json(app: &GenApp) -> String {
+    let mut app = app.clone();
+    app.modified_at = 0;
+    app.previous = Vec::new();
+    app_json(&app)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TOTP;
+
+    const SECRET: &str = "JBSWY3DPEHPK3PXP";
+
+    // Copies of the database are kept in memory only
+    struct Memory;
+
+    impl Database for Memory {
+        fn get_content(&self) -> Result<DatabaseContent, Error> {
+            Ok(DatabaseContent::default())
+        }
+
+        fn save_content(&self, _content: &DatabaseContent) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    fn app() -> GenApp {
+        GenApp::from_totp("", "", &TOTP::new(SECRET).unwrap())
+    }
+
+    fn copy(content: &DatabaseContent) -> RusTOTPony<Memory> {
+        RusTOTPony::with_content(Memory, content.clone())
+    }
+
+    fn content(copy: &RusTOTPony<Memory>) -> DatabaseContent {
+        copy.content().unwrap().clone()
+    }
+
+    fn database(names: &[&str]) -> DatabaseContent {
+        let mut database = copy(&DatabaseContent::default());
+        for name in names {
+            database.create_application(name, "", SECRET).unwrap();
+        }
+        content(&database)
+    }
+
+    fn merged(content: &DatabaseContent, other: &DatabaseContent) -> DatabaseContent {
+        let mut content = content.clone();
+        content.merge(other);
+        content
+    }
+
+    fn names(content: &DatabaseContent) -> Vec<&str> {
+        let mut names: Vec<&str> = content.applications.keys().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+
+    // Applications with all their settings and times
+    fn applications(content: &DatabaseContent) -> BTreeMap<&str, String> {
+        content
+            .applications
+            .iter()
+            .map(|(name, app)| (name.as_str(), app_json(app)))
+            .collect()
+    }
+
+    // Copies of a database with "github" changed differently in each
+    fn conflicting() -> (DatabaseContent, DatabaseContent) {
+        let base = database(&["github"]);
+        let (mut first, mut second) = (copy(&base), copy(&base));
+        first.set_hidden("github", true).unwrap();
+        second.set_archived("github", true).unwrap();
+        (content(&first), content(&second))
+    }
+
+    #[test]
+    fn merges_the_same_in_both_directions() {
+        let base = database(&["github", "gitlab", "google"]);
+        let (mut first, mut second) = (copy(&base), copy(&base));
+        first.set_hidden("github", true).unwrap();
+        first.delete_application("gitlab").unwrap();
+        first.create_application("aws", "", SECRET).unwrap();
+        second.set_archived("github", true).unwrap();
+        second.set_hidden("google", true).unwrap();
+        second.create_application("aws", "", SECRET).unwrap();
+        let (first, second) = (content(&first), content(&second));
+
+        let (one, other) = (merged(&first, &second), merged(&second, &first));
+        assert_eq!(applications(&one), applications(&other));
+        assert_eq!(one.tombstones, other.tombstones);
+        assert_eq!(names(&one).len(), 5);
+        assert!(!one.applications.contains_key("gitlab"));
+        assert!(one.applications["google"].is_hidden());
+    }
+
+    #[test]
+    fn change_after_deletion_brings_application_back() {
+        let base = database(&["github"]);
+        let (mut deleted, mut changed) = (copy(&base), copy(&base));
+        deleted.delete_application("github").unwrap();
+        changed.set_hidden("github", true).unwrap();
+        let deleted = content(&deleted);
+        let mut changed = content(&changed);
+        let deleted_at = deleted.tombstones[0].get_deleted_at();
+
+        // A change made at the same time as the deletion loses
+        changed.applications.get_mut("github").unwrap().modified_at = deleted_at;
+        assert!(names(&merged(&deleted, &changed)).is_empty());
+        assert!(names(&merged(&changed, &deleted)).is_empty());
+
+        changed.applications.get_mut("github").unwrap().modified_at = deleted_at + 1;
+        for content in [merged(&deleted, &changed), merged(&changed, &deleted)] {
+            assert_eq!(names(&content), ["github"]);
+            assert!(content.applications["github"].is_hidden());
+            assert!(content.tombstones.is_empty());
+        }
+    }
+
+    #[test]
+    fn conflict_is_kept_once() {
+        let (first, second) = conflicting();
+        let content = merged(&first, &second);
+        assert_eq!(names(&content), ["github", "github (conflict)"]);
+        let conflict = &content.applications["github (conflict)"];
+        assert_eq!(
+            conflict.get_conflict_of(),
+            Some(content.applications["github"].get_id())
+        );
+
+        let again = merged(&merged(&content, &first), &second);
+        assert_eq!(applications(&again), applications(&content));
+        let both = merged(&content, &merged(&second, &first));
+        assert_eq!(applications(&both), applications(&content));
+    }
+
+    #[test]
+    fn resolved_conflict_stays_resolved() {
+        let (first, second) = conflicting();
+        let conflicted = merged(&first, &second);
+        let original = conflicted.applications["github"].clone();
+        let conflict = conflicted.applications["github (conflict)"].clone();
+        let resolutions = [
+            (ConflictResolution::KeepOriginal, &original),
+            (ConflictResolution::KeepConflicting, &conflict),
+        ];
+        for (resolution, kept) in resolutions {
+            let mut resolved = copy(&conflicted);
+            resolved
+                .resolve_conflict("github (conflict)", resolution)
+                .unwrap();
+            assert!(resolved.get_conflicts().unwrap().is_empty());
+            let resolved = content(&resolved);
+            assert_eq!(names(&resolved), ["github"]);
+            let app = &resolved.applications["github"];
+            assert_eq!(app.get_id(), original.get_id());
+            assert_eq!(app.get_conflict_of(), None);
+            assert_eq!(app.is_hidden(), kept.is_hidden());
+            assert_eq!(app.is_archived(), kept.is_archived());
+
+            for other in [&first, &second, &conflicted] {
+                assert_eq!(names(&merged(&resolved, other)), ["github"]);
+                assert_eq!(names(&merged(other, &resolved)), ["github"]);
+            }
+        }
+    }
+
+    #[test]
+    fn renamed_application_takes_a_free_name() {
+        let id = "0123456789abcdef0123456789abcdef";
+        let mut applications = HashMap::new();
+        for name in ["aws", "aws (01234567)"] {
+            applications.insert(String::from(name), app());
+        }
+        assert_eq!(
+            unused_name(&applications, "aws", id),
+            "aws (0123456789abcdef)"
+        );
+        applications.insert(String::from("aws (0123456789abcdef)"), app());
+        applications.insert(String::from("aws (0123456789abcdef01234567)"), app());
+        applications.insert(format!("aws ({})", id), app());
+        assert_eq!(
+            unused_name(&applications, "aws", id),
+            format!("aws ({} 2)", id)
+        );
+    }
+}
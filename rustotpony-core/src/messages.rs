@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use crate::errors::{Error, ErrorCode};
+
+/// Translations of user-facing messages into a language.
+///
+/// A catalog is a JSON object which maps English messages to their
+/// translations, e.g. `{"Application '{}' wasn't found": "Anwendung '{}'
+/// wurde nicht gefunden"}`. Varying parts of the messages are written as
+/// `{}`; translations can take them in another order with `{0}`, `{1}` and
+/// so on. Messages without a translation stay in English.
+///
+/// Errors of the library are formatted messages, `translate` finds their
+/// templates in the catalog, so programs can show them in the language of
+/// the user too.
+#[derive(Debug, Clone, Default)]
+pub struct Catalog {
+    locale: Option<String>,
+    messages: HashMap<String, String>,
+    // Templates with varying parts, the longest ones are tried first
+    templates: Vec<(Vec<String>, String)>,
+}
+
+impl Catalog {
+    /// Catalog without translations, every message stays in English.
+    pub fn new() -> Catalog {
+        Catalog::default()
+    }
+
+    pub fn parse(locale: &str, json: &str) -> Result<Catalog, Error> {
+        let messages: HashMap<String, String> = serde_json::from_str(json).map_err(|err| {
+            Error::new(
+                ErrorCode::InvalidArgument,
+                format!("Catalog of '{}' isn't valid: {}", locale, err),
+            )
+        })?;
+        let mut templates: Vec<(Vec<String>, String)> = messages
+            .iter()
+            .filter(|(template, _)| template.contains("{}"))
+            .map(|(template, translation)| {
+                let parts = template.split("{}").map(String::from).collect();
+                (parts, translation.clone())
+            })
+            .filter(|(parts, _): &(Vec<String>, String)| parts.iter().any(|part| !part.is_empty()))
+            .collect();
+        templates.sort_by_key(|(parts, _)| {
+            std::cmp::Reverse(parts.iter().map(String::len).sum::<usize>())
+        });
+        Ok(Catalog {
+            locale: Some(String::from(locale)),
+            messages,
+            templates,
+        })
+    }
+
+    /// Reads the catalog of the locale from the folder, e.g. `de.json` for
+    /// `de_DE.UTF-8` if there is no `de_DE.json`. Returns `None` if the
+    /// folder has no catalog of the language.
+    pub fn load(dir: &Path, locale: &str) -> Result<Option<Catalog>, Error> {
+        for candidate in candidates(locale) {
+            let path = dir.join(format!("{}.json", candidate));
+            match fs::read_to_string(&path) {
+                Ok(json) => return Catalog::parse(&candidate, &json).map(Some),
+                Err(err) if err.kind() == ErrorKind::NotFound => continue,
+                Err(err) => {
+                    return Err(Error::new(
+                        ErrorCode::Io,
+                        format!("Couldn't read {}: {}", path.display(), err),
+                    ))
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Locale of the catalog, `None` for English.
+    pub fn locale(&self) -> Option<&str> {
+        self.locale.as_deref()
+    }
+
+    /// Translation of a message or a template, the message itself if there
+    /// is none.
+    pub fn lookup<'a>(&'a self, message: &'a str) -> &'a str {
+        self.messages.get(message).map_or(message, String::as_str)
+    }
+
+    /// Translates the template and puts the arguments in place of its
+    /// varying parts.
+    pub fn format(&self, template: &str, args: &[&dyn Display]) -> String {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        fill(self.lookup(template), &args)
+    }
+
+    /// Translates a formatted message, e.g. an error returned by the
+    /// library. The varying parts are translated as well, errors often
+    /// wrap other errors.
+    pub fn translate(&self, message: &str) -> String {
+        if let Some(translation) = self.messages.get(message) {
+            return translation.clone();
+        }
+        for (parts, translation) in self.templates.iter() {
+            if let Some(args) = split_message(message, parts) {
+                let args: Vec<String> = args.into_iter().map(|arg| self.translate(arg)).collect();
+                return fill(translation, &args);
+            }
+        }
+        String::from(message)
+    }
+}
+
+/// Locale of messages set in the environment (`LC_ALL`, `LC_MESSAGES` or
+/// `LANG`), `None` for the default and English ones.
+pub fn locale_from_env() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .filter(|value| !matches!(value.as_str(), "C" | "POSIX") && !value.starts_with("C."))
+        .filter(|value| !value.starts_with("en"))
+}
+
+// Names of catalogs for the locale, from the most specific one
+fn candidates(locale: &str) -> Vec<String> {
+    let name = locale.split(['.', '@']).next().unwrap_or(locale);
+    let mut names = vec![String::from(name)];
+    if let Some((language, _)) = name.split_once(['_', '-']) {
+        names.push(String::from(language));
+    }
+    names
+}
+
+// Arguments of the message formatted from the template split at `{}`
+fn split_message<'a>(message: &'a str, parts: &[String]) -> Option<Vec<&'a str>> {
+    let (first, rest) = parts.split_first()?;
+    let (last, middle) = rest.split_last()?;
+    let mut remaining = message.strip_prefix(first.as_str())?;
+    remaining = remaining.strip_suffix(last.as_str())?;
+    let mut args = Vec::new();
+    for part in middle {
+        // Parts between arguments can't be empty, `{}{}` can't be split
+        if part.is_empty() {
+            return None;
+        }
+        let (arg, rest) = remaining.split_once(part.as_str())?;
+        args.push(arg);
+        remaining = rest;
+    }
+    args.push(remaining);
+    Some(args)
+}
+
+// Puts the arguments in place of `{}` (in order) and `{N}`
+fn fill(template: &str, args: &[String]) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut next = 0;
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            result.push_str(&rest[..1]);
+            rest = &rest[2..];
+            continue;
+        }
+        let end = match rest.find('}') {
+            Some(end) if rest.starts_with('{') => end,
+            _ => {
+                result.push_str(&rest[..1]);
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        let index = match &rest[1..end] {
+            "" => {
+                next += 1;
+                Some(next - 1)
+            }
+            number => number.parse::<usize>().ok(),
+        };
+        match index.and_then(|index| args.get(index)) {
+            Some(arg) => result.push_str(arg),
+            None => result.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    result
+}
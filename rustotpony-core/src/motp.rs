@@ -0,0 +1,52 @@
+use crate::crypto::Hash;
+use crate::datetime;
+use crate::errors::{Error, ErrorCode};
+
+pub const TIME_STEP: u64 = 10;
+const CODE_LENGTH: usize = 6;
+
+/// Mobile-OTP generator, still required by some legacy VPN appliances.
+///
+/// The code is the beginning of the MD5 hex digest of the time in 10 second
+/// steps, the init secret and the PIN, all concatenated as strings.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MOTP {
+    secret: String,
+    pin: String,
+}
+
+impl MOTP {
+    pub fn new(secret: &str, pin: &str) -> Result<MOTP, Error> {
+        if secret.is_empty() {
+            return Err(Error::new(ErrorCode::InvalidArgument, "Secret is empty"));
+        }
+        if pin.is_empty() {
+            return Err(Error::new(ErrorCode::InvalidArgument, "PIN is empty"));
+        }
+        Ok(MOTP {
+            secret: String::from(secret),
+            pin: String::from(pin),
+        })
+    }
+
+    pub fn get_secret(&self) -> &str {
+        &self.secret
+    }
+
+    pub fn get_pin(&self) -> &str {
+        &self.pin
+    }
+
+    /// Returns the code valid at the given unix time.
+    pub fn generate(&self, timestamp: u64) -> String {
+        let mut code = Hash::Md5
+            .hex_digest(format!("{}{}{}", timestamp / TIME_STEP, self.secret, self.pin).as_bytes());
+        code.truncate(CODE_LENGTH);
+        code
+    }
+
+    pub fn now(&self) -> String {
+        self.generate(datetime::now())
+    }
+}
@@ -0,0 +1,190 @@
+use unicode_normalization::UnicodeNormalization;
+
+use crate::errors::{Error, ErrorCode};
+use crate::{Database, RusTOTPony};
+
+/// How names given to lookups are matched against names of applications.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum NameMatching {
+    /// "github" finds "GitHub", and names differing only in case can't be
+    /// given to two applications
+    #[default]
+    CaseInsensitive,
+    /// Names are compared as they are, e.g. to tell apart applications
+    /// created before names were matched case-insensitively
+    Exact,
+}
+
+impl<DB: Database> RusTOTPony<DB> {
+    pub fn set_name_matching(&mut self, matching: NameMatching) {
+        self.name_matching = matching;
+    }
+
+    /// Returns the name of the application the given name refers to. An
+    /// exact match always wins, otherwise the name is matched as configured
+    /// with `set_name_matching`. Either way, names which are the same text
+    /// in different Unicode forms (e.g. typed on macOS and on Linux) match.
+    /// Names matching several applications are rejected with an
+    /// `ErrorCode::Ambiguous` error listing them.
+    ///
+    /// A name which matches no application is looked up among aliases, then
+    /// among usernames and issuers, so an application can be found by the
+    /// email it was registered with. Failing that, it's taken as the
+    /// beginning of a name, so "gith" finds "GitHub" unless another name
+    /// starts the same way. Hidden applications are found only by their
+    /// name, they are never among the matches of anything else.
+    pub fn resolve_name(&self, name: &str) -> Result<String, Error> {
+        let candidates = self.find_name_candidates(name)?;
+        match candidates.as_slice() {
+            [] => Err(Error::new(
+                ErrorCode::NotFound,
+                format!("Application '{}' wasn't found", name),
+            )),
+            [found] => Ok(found.clone()),
+            _ => Err(Error::ambiguous(
+                format!(
+                    "Name '{}' matches several applications: {}, use the exact name",
+                    name,
+                    candidates
+                        .iter()
+                        .map(|candidate| format!("'{}'", candidate))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                ),
+                candidates,
+            )),
+        }
+    }
+
+    /// Returns the sorted names of applications the given name may refer to,
+    /// as matched by `resolve_name`. More than one name means the given one
+    /// is ambiguous, e.g. for letting the user choose.
+    pub fn find_name_candidates(&self, name: &str) -> Result<Vec<String>, Error> {
+        let applications = &self.content()?.applications;
+        if applications.contains_key(name) {
+            return Ok(vec![String::from(name)]);
+        }
+        let normalized = normalize(name);
+        if applications.contains_key(&normalized) {
+            return Ok(vec![normalized]);
+        }
+        let key = self.name_key(name);
+        let mut candidates: Vec<&String> = applications
+            .keys()
+            .filter(|candidate| self.name_key(candidate) == key)
+            .collect();
+        // Hidden applications are found only by their name
+        let visible = || applications.iter().filter(|(_, app)| !app.hidden);
+        if candidates.is_empty() {
+            if let Some(owner) = self.find_alias_owner(name)? {
+                if !applications[&owner].hidden {
+                    return Ok(vec![owner]);
+                }
+            }
+        }
+        if candidates.is_empty() && !key.is_empty() {
+            candidates = visible()
+                .filter(|(_, app)| {
+                    self.name_key(&app.username) == key || self.name_key(&app.issuer) == key
+                })
+                .map(|(candidate, _)| candidate)
+                .collect();
+        }
+        if candidates.is_empty() && !key.is_empty() {
+            candidates = visible()
+                .map(|(candidate, _)| candidate)
+                .filter(|candidate| self.name_key(candidate).starts_with(&key))
+                .collect();
+        }
+        candidates.sort();
+        Ok(candidates.into_iter().cloned().collect())
+    }
+
+    /// Returns the name of an application which already takes the name, i.e.
+    /// has the same name or one which differs only in case.
+    pub fn find_taken_name(&self, name: &str) -> Result<Option<String>, Error> {
+        let key = self.name_key(name);
+        Ok(self
+            .content()?
+            .applications
+            .keys()
+            .filter(|candidate| self.name_key(candidate) == key)
+            .min()
+            .cloned())
+    }
+
+    /// Form of the name in which names referring to the same application
+    /// are equal.
+    pub(crate) fn name_key(&self, name: &str) -> String {
+        match self.name_matching {
+            NameMatching::CaseInsensitive => normalize(&name.to_lowercase()),
+            NameMatching::Exact => normalize(name),
+        }
+    }
+}
+
+/// Brings the name to Unicode normalization form C, the composed form most
+/// systems produce, in which new names are stored.
+pub(crate) fn normalize(name: &str) -> String {
+    name.nfc().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DatabaseContent;
+
+    struct Memory;
+
+    impl Database for Memory {
+        fn get_content(&self) -> Result<DatabaseContent, Error> {
+            Ok(DatabaseContent::default())
+        }
+
+        fn save_content(&self, _content: &DatabaseContent) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    fn totpony() -> RusTOTPony<Memory> {
+        let mut totpony = RusTOTPony::with_content(Memory, DatabaseContent::default());
+        for (name, username) in [("github", "octocat"), ("gitlab", "octocat")] {
+            totpony
+                .create_application(name, username, "JBSWY3DPEHPK3PXP")
+                .unwrap();
+        }
+        totpony.add_alias("gitlab", "lab").unwrap();
+        totpony
+    }
+
+    #[test]
+    fn prefix_resolves_unique_name() {
+        let mut totpony = totpony();
+        totpony
+            .create_application("bitbucket", "", "JBSWY3DPEHPK3PXP")
+            .unwrap();
+        assert_eq!(totpony.resolve_name("bit").unwrap(), "bitbucket");
+        assert_eq!(totpony.resolve_name("lab").unwrap(), "gitlab");
+        let err = totpony.resolve_name("git").unwrap_err();
+        assert_eq!(err.code(), ErrorCode::Ambiguous);
+        assert_eq!(err.get_candidates(), ["github", "gitlab"]);
+    }
+
+    #[test]
+    fn hidden_application_is_found_only_by_name() {
+        let mut totpony = totpony();
+        totpony.set_hidden("gitlab", true).unwrap();
+        assert_eq!(totpony.resolve_name("gitlab").unwrap(), "gitlab");
+        assert_eq!(totpony.resolve_name("GitLab").unwrap(), "gitlab");
+        assert_eq!(totpony.resolve_name("git").unwrap(), "github");
+        assert_eq!(totpony.resolve_name("octocat").unwrap(), "github");
+        assert_eq!(
+            totpony.resolve_name("lab").unwrap_err().code(),
+            ErrorCode::NotFound
+        );
+        totpony.set_hidden("github", true).unwrap();
+        let err = totpony.resolve_name("git").unwrap_err();
+        assert_eq!(err.code(), ErrorCode::NotFound);
+        assert!(!err.message().contains("github"));
+    }
+}
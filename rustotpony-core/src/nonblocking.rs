@@ -0,0 +1,314 @@
+use tokio::task::{self, JoinHandle};
+
+use std::collections::HashSet;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use crate::errors::{Error, ErrorCode};
+use crate::{ApplicationSummary, Database, DatabaseContent, GenApp, RusTOTPony};
+
+pub type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// Database which loads and saves its content without blocking the calling
+/// thread, for programs running on an async executor.
+pub trait AsyncDatabase: Send + Sync {
+    fn load_content(&self) -> BoxFuture<Result<DatabaseContent, Error>>;
+    fn store_content(&self, content: DatabaseContent) -> BoxFuture<Result<(), Error>>;
+
+    /// Saves the content when only the named applications were changed,
+    /// see `Database::save_changes`. By default the whole content is saved.
+    fn store_changes(
+        &self,
+        content: DatabaseContent,
+        _changed: HashSet<String>,
+    ) -> BoxFuture<Result<(), Error>> {
+        self.store_content(content)
+    }
+}
+
+/// Wraps an ordinary database and runs its I/O and key derivation on the
+/// blocking thread pool of tokio. It can be used as a synchronous database
+/// as well. Password prompts of the wrapped database are called from that
+/// pool too.
+pub struct BlockingDatabase<DB> {
+    inner: Arc<DB>,
+}
+
+impl<DB> BlockingDatabase<DB> {
+    pub fn new(db: DB) -> BlockingDatabase<DB> {
+        BlockingDatabase {
+            inner: Arc::new(db),
+        }
+    }
+}
+
+impl<DB: Database + Send + Sync + 'static> AsyncDatabase for BlockingDatabase<DB> {
+    fn load_content(&self) -> BoxFuture<Result<DatabaseContent, Error>> {
+        let db = self.inner.clone();
+        Box::pin(Blocking::new(move || db.get_content()))
+    }
+
+    fn store_content(&self, content: DatabaseContent) -> BoxFuture<Result<(), Error>> {
+        let db = self.inner.clone();
+        Box::pin(Blocking::new(move || db.save_content(&content)))
+    }
+
+    fn store_changes(
+        &self,
+        content: DatabaseContent,
+        changed: HashSet<String>,
+    ) -> BoxFuture<Result<(), Error>> {
+        let db = self.inner.clone();
+        Box::pin(Blocking::new(move || db.save_changes(&content, &changed)))
+    }
+}
+
+// Wrapping must not change how the database is read and written
+impl<DB: Database> Database for BlockingDatabase<DB> {
+    fn get_content(&self) -> Result<DatabaseContent, Error> {
+        self.inner.get_content()
+    }
+
+    fn save_content(&self, content: &DatabaseContent) -> Result<(), Error> {
+        self.inner.save_content(content)
+    }
+
+    fn save_changes(
+        &self,
+        content: &DatabaseContent,
+        changed: &HashSet<String>,
+    ) -> Result<(), Error> {
+        self.inner.save_changes(content, changed)
+    }
+
+    fn get_summaries(&self) -> Result<Vec<ApplicationSummary>, Error> {
+        self.inner.get_summaries()
+    }
+
+    fn get_application(&self, name: &str) -> Result<Option<GenApp>, Error> {
+        self.inner.get_application(name)
+    }
+
+    fn get_size(&self) -> Option<u64> {
+        self.inner.get_size()
+    }
+}
+
+type Job<T> = Box<dyn FnOnce() -> Result<T, Error> + Send>;
+
+// Blocking task started on the first poll, so futures can be created
+// outside of a runtime. A panic in the task becomes an error.
+enum Blocking<T> {
+    Created(Option<Job<T>>),
+    Started(JoinHandle<Result<T, Error>>),
+}
+
+impl<T> Blocking<T> {
+    fn new<F: FnOnce() -> Result<T, Error> + Send + 'static>(job: F) -> Blocking<T> {
+        Blocking::Created(Some(Box::new(job)))
+    }
+}
+
+impl<T: Send + 'static> Future for Blocking<T> {
+    type Output = Result<T, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        if let Blocking::Created(ref mut job) = *self {
+            let job = job.take().expect("Task is already started");
+            *self = Blocking::Started(task::spawn_blocking(job));
+        }
+        let handle = match *self {
+            Blocking::Started(ref mut handle) => handle,
+            Blocking::Created(_) => unreachable!(),
+        };
+        match Pin::new(handle).poll(cx) {
+            Poll::Ready(Ok(result)) => Poll::Ready(result),
+            Poll::Ready(Err(err)) => Poll::Ready(Err(Error::new(
+                ErrorCode::Internal,
+                format!("Database task failed: {}", err),
+            ))),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+// RusTOTPony created once the content is loaded
+struct Opening<DB> {
+    database: Option<DB>,
+    loading: BoxFuture<Result<DatabaseContent, Error>>,
+}
+
+impl<DB: Database + Unpin> Future for Opening<DB> {
+    type Output = Result<RusTOTPony<DB>, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        match self.loading.as_mut().poll(cx) {
+            Poll::Ready(Ok(content)) => {
+                let database = self.database.take().expect("Database is already opened");
+                Poll::Ready(Ok(RusTOTPony::with_content(database, content)))
+            }
+            Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<DB: Database + AsyncDatabase + Unpin + 'static> RusTOTPony<DB> {
+    /// Loads the database without blocking. Once it's opened, all
+    /// operations work on the content in memory, use `flush_async` to save
+    /// changes.
+    pub fn open(db: DB) -> BoxFuture<Result<RusTOTPony<DB>, Error>> {
+        let loading = db.load_content();
+        Box::pin(Opening {
+            database: Some(db),
+            loading,
+        })
+    }
+
+    /// Saves changes like `flush` without blocking.
+    pub fn flush_async(&mut self) -> impl Future<Output = Result<(), Error>> + '_ {
+        let saving = match self.start_flush() {
+            Ok(true) => self.content().map(|content| {
+                self.database
+                    .store_changes(content.clone(), self.dirty.clone())
+            }),
+            Ok(false) => return Flushing::Done(Some(Ok(()))),
+            Err(err) => Err(err),
+        };
+        match saving {
+            Ok(saving) => Flushing::Saving(self, saving),
+            Err(err) => Flushing::Done(Some(Err(err))),
+        }
+    }
+}
+
+// Saving started by `flush_async`, finished the same way as by `flush`
+enum Flushing<'a, DB: Database> {
+    Saving(&'a mut RusTOTPony<DB>, BoxFuture<Result<(), Error>>),
+    Done(Option<Result<(), Error>>),
+}
+
+impl<DB: Database> Future for Flushing<'_, DB> {
+    type Output = Result<(), Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let result = match *self {
+            Flushing::Saving(_, ref mut saving) => match saving.as_mut().poll(cx) {
+                Poll::Ready(result) => result,
+                Poll::Pending => return Poll::Pending,
+            },
+            Flushing::Done(ref mut result) => {
+                return Poll::Ready(result.take().expect("Future is polled after completion"))
+            }
+        };
+        if let (Flushing::Saving(ref mut totpony, _), Ok(())) = (&mut *self, &result) {
+            totpony.finish_flush();
+        }
+        *self = Flushing::Done(None);
+        Poll::Ready(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Event;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+
+    // Records which methods were called
+    #[derive(Default)]
+    struct Recording {
+        saved: Mutex<Vec<HashSet<String>>>,
+        whole: AtomicBool,
+    }
+
+    impl Database for Recording {
+        fn get_content(&self) -> Result<DatabaseContent, Error> {
+            Ok(DatabaseContent::default())
+        }
+
+        fn save_content(&self, _content: &DatabaseContent) -> Result<(), Error> {
+            self.whole.store(true, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn save_changes(
+            &self,
+            _content: &DatabaseContent,
+            changed: &HashSet<String>,
+        ) -> Result<(), Error> {
+            self.saved.lock().unwrap().push(changed.clone());
+            Ok(())
+        }
+
+        fn get_size(&self) -> Option<u64> {
+            Some(42)
+        }
+    }
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn wrapped_database_is_used_the_same_way() {
+        let db = BlockingDatabase::new(Recording::default());
+        assert_eq!(db.get_size(), Some(42));
+        let mut totpony = runtime().block_on(RusTOTPony::open(db)).unwrap();
+        totpony
+            .create_application("github", "", "JBSWY3DPEHPK3PXP")
+            .unwrap();
+        totpony.flush().unwrap();
+        let inner = &totpony.database.inner;
+        assert_eq!(inner.saved.lock().unwrap().len(), 1);
+        assert!(!inner.whole.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn async_flush_saves_changes_like_flush() {
+        let runtime = runtime();
+        let db = BlockingDatabase::new(Recording::default());
+        let mut totpony = runtime.block_on(RusTOTPony::open(db)).unwrap();
+        let saved = Arc::new(AtomicBool::new(false));
+        let observed = saved.clone();
+        totpony.on_change(move |event| {
+            if matches!(event, Event::Saved) {
+                observed.store(true, Ordering::SeqCst);
+            }
+        });
+        totpony
+            .create_application("github", "", "JBSWY3DPEHPK3PXP")
+            .unwrap();
+        runtime.block_on(totpony.flush_async()).unwrap();
+        assert!(saved.load(Ordering::SeqCst));
+        assert!(totpony.dirty.is_empty());
+        let inner = &totpony.database.inner;
+        assert_eq!(
+            *inner.saved.lock().unwrap(),
+            vec![HashSet::from([String::from("github")])]
+        );
+        assert!(!inner.whole.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn cancelled_async_flush_saves_nothing() {
+        let runtime = runtime();
+        let db = BlockingDatabase::new(Recording::default());
+        let mut totpony = runtime.block_on(RusTOTPony::open(db)).unwrap();
+        totpony
+            .create_application("github", "", "JBSWY3DPEHPK3PXP")
+            .unwrap();
+        let token = crate::CancellationToken::new();
+        token.cancel();
+        totpony.set_cancellation(token);
+        let err = runtime.block_on(totpony.flush_async()).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::Cancelled);
+        assert!(!totpony.dirty.is_empty());
+        assert!(totpony.database.inner.saved.lock().unwrap().is_empty());
+    }
+}
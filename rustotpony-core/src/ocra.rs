@@ -0,0 +1,474 @@
+use rustotpony_otp::{truncate, Code};
+
+use crate::crypto::Hash;
+use crate::datetime;
+use crate::errors::{Error, ErrorCode};
+use crate::{Change, Database, GenApp, GeneratorKind, Operation, RusTOTPony};
+
+// Questions are always padded to this size in the hashed message
+const QUESTION_SIZE: usize = 128;
+
+fn parse_hash(name: &str) -> Option<Hash> {
+    match name {
+        "SHA1" => Some(Hash::Sha1),
+        "SHA256" => Some(Hash::Sha256),
+        "SHA512" => Some(Hash::Sha512),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum QuestionType {
+    Alphanumeric,
+    Numeric,
+    Hex,
+}
+
+/// Parsed OCRA suite, e.g. `OCRA-1:HOTP-SHA1-6:C-QN08-PSHA1`.
+#[derive(Debug, Clone, PartialEq)]
+struct Suite {
+    hash: Hash,
+    digits: usize,
+    counter: bool,
+    question: (QuestionType, usize),
+    pin: Option<Hash>,
+    time_step: Option<u64>,
+}
+
+impl Suite {
+    fn parse(suite: &str) -> Result<Suite, Error> {
+        let invalid = |reason: &str| {
+            Error::new(
+                ErrorCode::InvalidArgument,
+                format!("Invalid OCRA suite '{}': {}", suite, reason),
+            )
+        };
+        let parts: Vec<&str> = suite.split(':').collect();
+        if parts.len() != 3 || parts[0] != "OCRA-1" {
+            return Err(invalid("expected OCRA-1:<function>:<inputs>"));
+        }
+        let function: Vec<&str> = parts[1].split('-').collect();
+        if function.len() != 3 || function[0] != "HOTP" {
+            return Err(invalid("crypto function must be HOTP-SHA<n>-<digits>"));
+        }
+        let hash = parse_hash(function[1]).ok_or_else(|| invalid("unknown hash function"))?;
+        let digits = match function[2].parse() {
+            Ok(digits) if (4..=10).contains(&digits) => digits,
+            _ => return Err(invalid("number of digits must be between 4 and 10")),
+        };
+        let mut result = Suite {
+            hash,
+            digits,
+            counter: false,
+            question: (QuestionType::Numeric, 0),
+            pin: None,
+            time_step: None,
+        };
+        for input in parts[2].split('-') {
+            match input.chars().next() {
+                Some('C') if input == "C" => result.counter = true,
+                Some('Q') => {
+                    result.question = parse_question(input).ok_or_else(|| invalid(input))?
+                }
+                Some('P') if input.starts_with("PSHA") => {
+                    result.pin = Some(parse_hash(&input[1..]).ok_or_else(|| invalid(input))?)
+                }
+                Some('T') => {
+                    result.time_step = Some(parse_time_step(input).ok_or_else(|| invalid(input))?)
+                }
+                Some('S') => return Err(invalid("session information isn't supported")),
+                _ => return Err(invalid(&format!("unknown data input '{}'", input))),
+            }
+        }
+        if result.question.1 == 0 {
+            return Err(invalid("challenge question (Q) is required"));
+        }
+        Ok(result)
+    }
+}
+
+// Inputs are sliced by bytes, anything but ASCII is invalid anyway
+fn parse_question(input: &str) -> Option<(QuestionType, usize)> {
+    if input.len() != 4 || !input.is_ascii() {
+        return None;
+    }
+    let kind = match &input[1..2] {
+        "A" => QuestionType::Alphanumeric,
+        "N" => QuestionType::Numeric,
+        "H" => QuestionType::Hex,
+        _ => return None,
+    };
+    match input[2..].parse() {
+        Ok(length) if (4..=64).contains(&length) => Some((kind, length)),
+        _ => None,
+    }
+}
+
+fn parse_time_step(input: &str) -> Option<u64> {
+    if !input.is_ascii() {
+        return None;
+    }
+    let (number, unit) = input[1..].split_at(input.len().saturating_sub(2));
+    let number: u64 = number.parse().ok()?;
+    match unit {
+        "S" if (1..=59).contains(&number) => Some(number),
+        "M" if (1..=59).contains(&number) => Some(number * 60),
+        "H" if (1..=48).contains(&number) => Some(number * 3600),
+        _ => None,
+    }
+}
+
+/// Challenge-response generator as described in RFC 6287, used by some banks
+/// for signing transactions.
+///
+/// The suite defines the algorithm and which data besides the challenge
+/// goes into the response: a counter (`C`), a hashed PIN (`PSHA1`) or the
+/// current time (`T30S`). Session information (`S`) isn't supported.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct OCRA {
+    secret: Vec<u8>,
+    suite: String,
+    parsed: Suite,
+    counter: u64,
+}
+
+impl OCRA {
+    pub fn new(secret: Vec<u8>, suite: &str) -> Result<OCRA, Error> {
+        let suite = suite.trim().to_uppercase();
+        Ok(OCRA {
+            secret,
+            parsed: Suite::parse(&suite)?,
+            suite,
+            counter: 0,
+        })
+    }
+
+    pub fn with_counter(mut self, counter: u64) -> OCRA {
+        self.counter = counter;
+        self
+    }
+
+    pub fn get_secret_bytes(&self) -> &[u8] {
+        &self.secret
+    }
+
+    pub fn get_suite(&self) -> &str {
+        &self.suite
+    }
+
+    pub fn get_counter(&self) -> u64 {
+        self.counter
+    }
+
+    pub fn get_digits(&self) -> usize {
+        self.parsed.digits
+    }
+
+    /// Whether the counter has to be advanced after each response.
+    pub fn uses_counter(&self) -> bool {
+        self.parsed.counter
+    }
+
+    pub fn uses_pin(&self) -> bool {
+        self.parsed.pin.is_some()
+    }
+
+    /// Returns the response to the challenge at the given unix time. The PIN
+    /// is required only by suites with a `P` data input.
+    pub fn respond(
+        &self,
+        challenge: &str,
+        pin: Option<&str>,
+        timestamp: u64,
+    ) -> Result<String, Error> {
+        let mut message = self.suite.as_bytes().to_vec();
+        message.push(0);
+        if self.parsed.counter {
+            message.extend_from_slice(&self.counter.to_be_bytes());
+        }
+        message.extend(self.encode_question(challenge)?);
+        if let Some(hash) = self.parsed.pin {
+            let pin = pin.ok_or_else(|| {
+                Error::new(
+                    ErrorCode::InvalidArgument,
+                    "PIN is required by the OCRA suite",
+                )
+            })?;
+            message.extend(hash.digest(pin.as_bytes()));
+        }
+        if let Some(step) = self.parsed.time_step {
+            message.extend_from_slice(&(timestamp / step).to_be_bytes());
+        }
+        let hmac = self.parsed.hash.hmac(&self.secret, &message);
+        Ok(Code::new(truncate(&hmac), self.parsed.digits as u32).to_string())
+    }
+
+    pub fn respond_now(&self, challenge: &str, pin: Option<&str>) -> Result<String, Error> {
+        self.respond(challenge, pin, datetime::now())
+    }
+
+    /// Converts the challenge to bytes as the suite defines, numeric and hex
+    /// challenges are stored as hex digits padded on the right. The length
+    /// from the suite isn't enforced, mutual challenge-response concatenates
+    /// client and server challenges.
+    fn encode_question(&self, challenge: &str) -> Result<Vec<u8>, Error> {
+        let challenge = challenge.trim();
+        if challenge.is_empty() {
+            return Err(Error::new(ErrorCode::InvalidArgument, "Challenge is empty"));
+        }
+        let mut question: Vec<u8> = match self.parsed.question.0 {
+            QuestionType::Alphanumeric => challenge.as_bytes().to_vec(),
+            QuestionType::Numeric => {
+                if !challenge.chars().all(|c| c.is_ascii_digit()) {
+                    return Err(Error::new(
+                        ErrorCode::InvalidArgument,
+                        "Challenge must consist of decimal digits",
+                    ));
+                }
+                nibbles_to_bytes(&decimal_to_nibbles(challenge))
+            }
+            QuestionType::Hex => nibbles_to_bytes(
+                &challenge
+                    .chars()
+                    .map(|c| c.to_digit(16).map(|d| d as u8))
+                    .collect::<Option<Vec<u8>>>()
+                    .ok_or_else(|| {
+                        Error::new(
+                            ErrorCode::InvalidArgument,
+                            "Challenge must consist of hex digits",
+                        )
+                    })?,
+            ),
+        };
+        if question.len() > QUESTION_SIZE {
+            return Err(Error::new(
+                ErrorCode::InvalidArgument,
+                "Challenge is too long",
+            ));
+        }
+        question.resize(QUESTION_SIZE, 0);
+        Ok(question)
+    }
+}
+
+fn nibbles_to_bytes(nibbles: &[u8]) -> Vec<u8> {
+    nibbles
+        .chunks(2)
+        .map(|pair| pair[0] << 4 | pair.get(1).cloned().unwrap_or(0))
+        .collect()
+}
+
+/// Hex digits of a decimal number of any length, the most significant first.
+fn decimal_to_nibbles(number: &str) -> Vec<u8> {
+    // Little-endian base-16 digits
+    let mut nibbles: Vec<u8> = Vec::new();
+    for digit in number.bytes().map(|b| u32::from(b - b'0')) {
+        let mut carry = digit;
+        for nibble in nibbles.iter_mut() {
+            let value = u32::from(*nibble) * 10 + carry;
+            *nibble = (value % 16) as u8;
+            carry = value / 16;
+        }
+        while carry > 0 {
+            nibbles.push((carry % 16) as u8);
+            carry /= 16;
+        }
+    }
+    if nibbles.is_empty() {
+        nibbles.push(0);
+    }
+    nibbles.reverse();
+    nibbles
+}
+
+impl<DB: Database> RusTOTPony<DB> {
+    pub fn create_ocra_application(
+        &mut self,
+        name: &str,
+        username: &str,
+        ocra: &OCRA,
+    ) -> Result<(), Error> {
+        self.insert_application(GenApp::from_ocra(name, username, ocra))
+    }
+
+    /// Computes the response of a challenge-response application. Counter
+    /// based suites advance the stored counter, the database has to be
+    /// flushed afterwards. Passphrase is required for protected applications.
+    pub fn respond_to_challenge(
+        &mut self,
+        name: &str,
+        challenge: &str,
+        pin: Option<&str>,
+        passphrase: Option<&str>,
+    ) -> Result<String, Error> {
+        let name = &self.resolve_name(name)?;
+        let app = self.get_application(name)?;
+        let app = match passphrase {
+            Some(passphrase) => app.unlock(passphrase)?,
+            None if app.is_protected() => {
+                return Err(Error::new(
+                    ErrorCode::Protected,
+                    format!(
+                        "Application '{}' is protected, its passphrase is required",
+                        name
+                    ),
+                ))
+            }
+            None => app.clone(),
+        };
+        let ocra = app.get_ocra().ok_or_else(|| {
+            Error::new(
+                ErrorCode::Unsupported,
+                format!(
+                    "Application '{}' isn't a challenge-response generator",
+                    name
+                ),
+            )
+        })??;
+        let response = ocra.respond_now(challenge, pin)?;
+        if ocra.uses_counter() {
+            if let Some(app) = self.loaded_mut().applications.get_mut(name) {
+                app.kind = GeneratorKind::Ocra {
+                    suite: String::from(ocra.get_suite()),
+                    counter: ocra.get_counter() + 1,
+                };
+            }
+            self.mark_dirty(name);
+            self.record(Operation::Update, name, Some("counter"));
+            self.push_change(Change::Updated(String::from(name), String::from("counter")));
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Keys, PIN and time of the test vectors in RFC 6287, Appendix C
+    const SEED: &[u8] = b"12345678901234567890";
+    const SEED32: &[u8] = b"12345678901234567890123456789012";
+    const SEED64: &[u8] = b"1234567890123456789012345678901234567890123456789012345678901234";
+    const PIN: &str = "1234";
+    const TIME: u64 = 0x132d0b6 * 60;
+
+    fn respond(
+        seed: &[u8],
+        suite: &str,
+        counter: u64,
+        question: &str,
+        pin: Option<&str>,
+    ) -> String {
+        OCRA::new(seed.to_vec(), suite)
+            .unwrap()
+            .with_counter(counter)
+            .respond(question, pin, TIME)
+            .unwrap()
+    }
+
+    fn numeric(digit: usize) -> String {
+        digit.to_string().repeat(8)
+    }
+
+    #[test]
+    fn one_way_challenge_response() {
+        let expected = [
+            "237653", "243178", "653583", "740991", "608993", "388898", "816933", "224598",
+            "750600", "294470",
+        ];
+        for (idx, code) in expected.iter().enumerate() {
+            let suite = "OCRA-1:HOTP-SHA1-6:QN08";
+            assert_eq!(respond(SEED, suite, 0, &numeric(idx), None), *code);
+        }
+
+        let expected = [
+            "65347737", "86775851", "78192410", "71565254", "10104329", "65983500", "70069104",
+            "91771096", "75011558", "08522129",
+        ];
+        for (counter, code) in expected.iter().enumerate() {
+            let suite = "OCRA-1:HOTP-SHA256-8:C-QN08-PSHA1";
+            let response = respond(SEED32, suite, counter as u64, "12345678", Some(PIN));
+            assert_eq!(response, *code);
+        }
+
+        let expected = ["83238735", "01501458", "17957585", "86776967", "86807031"];
+        for (idx, code) in expected.iter().enumerate() {
+            let suite = "OCRA-1:HOTP-SHA256-8:QN08-PSHA1";
+            assert_eq!(respond(SEED32, suite, 0, &numeric(idx), Some(PIN)), *code);
+        }
+
+        let expected = [
+            "07016083", "63947962", "70123924", "25341727", "33203315", "34205738", "44343969",
+            "51946085", "20403879", "31409299",
+        ];
+        for (idx, code) in expected.iter().enumerate() {
+            let suite = "OCRA-1:HOTP-SHA512-8:C-QN08";
+            assert_eq!(
+                respond(SEED64, suite, idx as u64, &numeric(idx), None),
+                *code
+            );
+        }
+
+        let expected = ["95209754", "55907591", "22048402", "24218844", "36209546"];
+        for (idx, code) in expected.iter().enumerate() {
+            let suite = "OCRA-1:HOTP-SHA512-8:QN08-T1M";
+            assert_eq!(respond(SEED64, suite, 0, &numeric(idx), None), *code);
+        }
+    }
+
+    #[test]
+    fn mutual_challenge_response() {
+        let server = ["28247970", "01984843", "65387857", "03351211", "83412541"];
+        let client = ["15510767", "90175646", "33777207", "95285278", "28934924"];
+        for idx in 0..5 {
+            let suite = "OCRA-1:HOTP-SHA256-8:QA08";
+            let question = format!("CLI2222{}SRV1111{}", idx, idx);
+            assert_eq!(respond(SEED32, suite, 0, &question, None), server[idx]);
+            let question = format!("SRV1111{}CLI2222{}", idx, idx);
+            assert_eq!(respond(SEED32, suite, 0, &question, None), client[idx]);
+        }
+
+        let server = ["79496648", "76831980", "12250499", "90856481", "12761449"];
+        let client = ["18806276", "70020315", "01600026", "18951020", "32528969"];
+        for idx in 0..5 {
+            let question = format!("CLI2222{}SRV1111{}", idx, idx);
+            let suite = "OCRA-1:HOTP-SHA512-8:QA08";
+            assert_eq!(respond(SEED64, suite, 0, &question, None), server[idx]);
+            let question = format!("SRV1111{}CLI2222{}", idx, idx);
+            let suite = "OCRA-1:HOTP-SHA512-8:QA08-PSHA1";
+            assert_eq!(respond(SEED64, suite, 0, &question, Some(PIN)), client[idx]);
+        }
+    }
+
+    #[test]
+    fn plain_signature() {
+        let expected = ["53095496", "04110475", "31331128", "76028668", "46554205"];
+        for (idx, code) in expected.iter().enumerate() {
+            let suite = "OCRA-1:HOTP-SHA256-8:QA08";
+            let question = format!("SIG1{}000", idx);
+            assert_eq!(respond(SEED32, suite, 0, &question, None), *code);
+        }
+
+        let expected = ["77537423", "31970405", "10235557", "95213541", "65360607"];
+        for (idx, code) in expected.iter().enumerate() {
+            let suite = "OCRA-1:HOTP-SHA512-8:QA10-T1M";
+            let question = format!("SIG1{}00000", idx);
+            assert_eq!(respond(SEED64, suite, 0, &question, None), *code);
+        }
+    }
+
+    #[test]
+    fn suites_with_other_characters_are_rejected() {
+        for suite in [
+            "OCRA-1:HOTP-SHA1-6:QÄ08",
+            "OCRA-1:HOTP-SHA1-6:QN08-TÄ",
+            "OCRA-1:HOTP-SHA1-6:Q€",
+        ] {
+            assert_eq!(
+                OCRA::new(SEED.to_vec(), suite).unwrap_err().code(),
+                ErrorCode::InvalidArgument
+            );
+        }
+    }
+}
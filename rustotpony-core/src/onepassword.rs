@@ -0,0 +1,305 @@
+use serde_derive::Deserialize;
+use serde_json::Value;
+use std::io::{Cursor, Read};
+
+use crate::errors::{Error, ErrorCode};
+use crate::import::from_otp_field;
+use crate::GenApp;
+
+// Export of the items inside a `.1pux` archive
+const EXPORT_DATA: &str = "export.data";
+// Columns of 1Password CSV exports which may hold one-time passwords
+const OTP_COLUMNS: &[&str] = &["otpauth", "one-time password", "otp", "totp"];
+
+#[derive(Deserialize)]
+struct Export {
+    #[serde(default)]
+    accounts: Vec<Account>,
+}
+
+#[derive(Deserialize)]
+struct Account {
+    #[serde(default)]
+    vaults: Vec<Vault>,
+}
+
+#[derive(Deserialize)]
+struct Vault {
+    #[serde(default)]
+    attrs: VaultAttrs,
+    #[serde(default)]
+    items: Vec<Item>,
+}
+
+#[derive(Deserialize, Default)]
+struct VaultAttrs {
+    #[serde(default)]
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct Item {
+    #[serde(default)]
+    state: String,
+    #[serde(default)]
+    overview: Overview,
+    #[serde(default)]
+    details: Details,
+}
+
+#[derive(Deserialize, Default)]
+struct Overview {
+    #[serde(default)]
+    title: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct Details {
+    #[serde(default)]
+    login_fields: Vec<LoginField>,
+    #[serde(default)]
+    sections: Vec<Section>,
+}
+
+#[derive(Deserialize)]
+struct LoginField {
+    #[serde(default)]
+    designation: String,
+    #[serde(default)]
+    value: String,
+}
+
+#[derive(Deserialize)]
+struct Section {
+    #[serde(default)]
+    fields: Vec<Field>,
+}
+
+#[derive(Deserialize)]
+struct Field {
+    #[serde(default)]
+    value: Value,
+}
+
+/// Reads a 1Password export, either a `.1pux` archive (or the
+/// `export.data` file from it) or a CSV file, and returns an entry for
+/// every one-time password in it for `import_applications`. Items are
+/// named by their titles, login usernames become usernames. Vaults of
+/// `.1pux` exports become tags and archived items are archived.
+pub fn parse_1password(data: &[u8]) -> Result<Vec<Result<GenApp, Error>>, Error> {
+    if data.starts_with(b"PK") {
+        let mut archive = zip::ZipArchive::new(Cursor::new(data)).map_err(|err| {
+            Error::new(
+                ErrorCode::Io,
+                format!("Couldn't open the 1PUX archive: {}", err),
+            )
+        })?;
+        let mut file = archive.by_name(EXPORT_DATA).map_err(|_| {
+            Error::new(
+                ErrorCode::Damaged,
+                format!("1PUX archive has no {}", EXPORT_DATA),
+            )
+        })?;
+        let mut text = Vec::new();
+        file.read_to_end(&mut text).map_err(|err| {
+            Error::new(
+                ErrorCode::Io,
+                format!("Couldn't read {}: {}", EXPORT_DATA, err),
+            )
+        })?;
+        parse_export_data(&text)
+    } else if data.trim_ascii_start().starts_with(b"{") {
+        parse_export_data(data)
+    } else {
+        parse_csv(data)
+    }
+}
+
+fn parse_export_data(data: &[u8]) -> Result<Vec<Result<GenApp, Error>>, Error> {
+    let export: Export = serde_json::from_slice(data).map_err(|err| {
+        Error::new(
+            ErrorCode::InvalidArgument,
+            format!("Couldn't parse the 1Password export: {}", err),
+        )
+    })?;
+    let mut entries = Vec::new();
+    for vault in export.accounts.iter().flat_map(|account| &account.vaults) {
+        for item in vault.items.iter().filter(|item| item.state != "deleted") {
+            let username = item
+                .details
+                .login_fields
+                .iter()
+                .find(|field| field.designation == "username")
+                .map_or("", |field| field.value.as_str());
+            let otps = item
+                .details
+                .sections
+                .iter()
+                .flat_map(|section| &section.fields)
+                .filter_map(|field| field.value.get("totp").and_then(Value::as_str));
+            for otp in otps {
+                entries.push(
+                    from_otp_field(otp, &item.overview.title, username).map(|mut app| {
+                        if !vault.attrs.name.is_empty() {
+                            app.tags.push(vault.attrs.name.clone());
+                        }
+                        app.archived = item.state == "archived";
+                        app
+                    }),
+                );
+            }
+        }
+    }
+    Ok(entries)
+}
+
+fn parse_csv(data: &[u8]) -> Result<Vec<Result<GenApp, Error>>, Error> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(data);
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|err| {
+            Error::new(
+                ErrorCode::Io,
+                format!("Couldn't read the CSV header: {}", err),
+            )
+        })?
+        .iter()
+        .map(|header| header.trim().to_lowercase())
+        .collect();
+    let column = |names: &[&str]| {
+        headers
+            .iter()
+            .position(|header| names.contains(&header.as_str()))
+    };
+    let otp_column = column(OTP_COLUMNS).ok_or_else(|| {
+        Error::new(
+            ErrorCode::InvalidArgument,
+            "CSV file has no column of one-time passwords, e.g. OTPAuth",
+        )
+    })?;
+    let title_column = column(&["title", "name"]);
+    let username_column = column(&["username"]);
+    let archived_column = column(&["archived"]);
+    let mut entries = Vec::new();
+    for (idx, record) in reader.records().enumerate() {
+        let record = match record {
+            Ok(record) => record,
+            Err(err) => {
+                entries.push(Err(Error::new(
+                    ErrorCode::InvalidArgument,
+                    format!("Row {} can't be read: {}", idx + 2, err),
+                )));
+                continue;
+            }
+        };
+        let cell =
+            |column: Option<usize>| column.and_then(|column| record.get(column)).unwrap_or("");
+        let otp = cell(Some(otp_column));
+        if otp.trim().is_empty() {
+            continue;
+        }
+        entries.push(
+            from_otp_field(otp, cell(title_column), cell(username_column)).map(|mut app| {
+                app.archived = cell(archived_column).trim().eq_ignore_ascii_case("true");
+                app
+            }),
+        );
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const EXPORT: &str = r#"{"accounts": [{"vaults": [{
+        "attrs": {"name": "Work"},
+        "items": [
+            {
+                "overview": {"title": "GitHub"},
+                "details": {
+                    "loginFields": [{"designation": "username", "value": "octocat"}],
+                    "sections": [{"fields": [{"value": {"totp": "JBSWY3DPEHPK3PXP"}}]}]
+                }
+            },
+            {
+                "state": "archived",
+                "overview": {"title": "GitLab"},
+                "details": {"sections": [{"fields": [
+                    {"value": {"totp": "otpauth://totp/GitLab:tanuki?secret=GEZDGNBVGY3TQOJQ&digits=8"}}
+                ]}]}
+            },
+            {
+                "state": "deleted",
+                "overview": {"title": "Gone"},
+                "details": {"sections": [{"fields": [{"value": {"totp": "JBSWY3DPEHPK3PXP"}}]}]}
+            },
+            {"overview": {"title": "Bank"}, "details": {}}
+        ]
+    }]}]}"#;
+
+    fn applications(data: &[u8]) -> Vec<GenApp> {
+        parse_1password(data)
+            .unwrap()
+            .into_iter()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    fn check_export(apps: &[GenApp]) {
+        assert_eq!(apps.len(), 2);
+        assert_eq!(apps[0].get_name(), "GitHub");
+        assert_eq!(apps[0].get_username(), "octocat");
+        assert_eq!(apps[0].get_secret(), "JBSWY3DPEHPK3PXP");
+        assert_eq!(apps[0].get_tags(), ["Work"]);
+        assert!(!apps[0].is_archived());
+        assert_eq!(apps[1].get_name(), "GitLab");
+        assert_eq!(apps[1].get_username(), "tanuki");
+        assert_eq!(apps[1].get_format().get_length(), 8);
+        assert!(apps[1].is_archived());
+    }
+
+    #[test]
+    fn export_data_is_imported() {
+        check_export(&applications(EXPORT.as_bytes()));
+    }
+
+    #[test]
+    fn archive_is_imported() {
+        let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        archive
+            .start_file(EXPORT_DATA, zip::write::SimpleFileOptions::default())
+            .unwrap();
+        archive.write_all(EXPORT.as_bytes()).unwrap();
+        let data = archive.finish().unwrap().into_inner();
+        check_export(&applications(&data));
+    }
+
+    #[test]
+    fn csv_is_imported() {
+        let csv = "Title,Username,OTPAuth,Archived\n\
+                   GitHub,octocat,JBSWY3DPEHPK3PXP,false\n\
+                   Bank,me,,false\n\
+                   GitLab,,otpauth://totp/GitLab:tanuki?secret=GEZDGNBVGY3TQOJQ,true\n";
+        let apps = applications(csv.as_bytes());
+        assert_eq!(apps.len(), 2);
+        assert_eq!(apps[0].get_username(), "octocat");
+        assert_eq!(apps[1].get_username(), "tanuki");
+        assert!(apps[1].is_archived());
+    }
+
+    #[test]
+    fn invalid_secrets_and_files_are_reported() {
+        let csv = "Title,OTPAuth\nGitHub,not base32!\n";
+        let entries = parse_1password(csv.as_bytes()).unwrap();
+        assert!(entries[0].is_err());
+        let err = parse_1password(b"Title,Password\nGitHub,hunter2\n")
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), ErrorCode::InvalidArgument);
+        let err = parse_1password(b"PK\x03\x04 damaged").err().unwrap();
+        assert_eq!(err.code(), ErrorCode::Io);
+    }
+}
@@ -0,0 +1,248 @@
+use crate::errors::{Error, ErrorCode};
+use crate::{CodeFormat, GenApp, Icon, STEAM_ALPHABET, TOTP};
+
+const SCHEME: &str = "otpauth://totp/";
+
+impl GenApp {
+    /// Builds an `otpauth://` URI, which authenticator apps accept as text or
+    /// QR code. Protected applications have to be unlocked first.
+    ///
+    /// The name of the application is used as the issuer, the username as
+    /// the account name. An icon given by address goes to the `image`
+    /// parameter, images themselves are left out to keep QR codes small.
+    /// Steam Guard codes are marked with `encoder=steam`, the way Aegis and
+    /// KeePassXC mark them.
+    pub fn to_otpauth_uri(&self) -> String {
+        let label = if self.username.is_empty() {
+            encode(&self.name)
+        } else {
+            format!("{}:{}", encode(&self.name), encode(&self.username))
+        };
+        let image = match &self.icon {
+            Some(Icon::Url { url }) => format!("&image={}", encode(url)),
+            _ => String::new(),
+        };
+        let encoder = match self.format.get_alphabet() {
+            Some(STEAM_ALPHABET) => "&encoder=steam",
+            _ => "",
+        };
+        format!(
+            "otpauth://totp/{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period=30{}{}",
+            label,
+            self.secret,
+            encode(&self.name),
+            self.format.get_length(),
+            encoder,
+            image
+        )
+    }
+
+    /// Tells why the application can't be described by an `otpauth://`
+    /// URI, if it can't.
+    pub fn check_otpauth_uri(&self) -> Result<(), Error> {
+        if !self.kind.is_totp() {
+            return Err(Error::new(
+                ErrorCode::Unsupported,
+                "Only TOTP generators can be set up with an otpauth URI",
+            ));
+        }
+        if self.epoch != 0 {
+            return Err(Error::new(
+                ErrorCode::InvalidArgument,
+                "otpauth URIs can't carry a custom epoch, the generator can't be set up with one",
+            ));
+        }
+        if !carries_format(&self.format) {
+            return Err(Error::new(
+                ErrorCode::Unsupported,
+                "otpauth URIs can't carry a custom alphabet, the generator can't be set up with one",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl GenApp {
+    /// Parses an `otpauth://totp/` URI, e.g. one exported by another
+    /// authenticator app. The issuer becomes the name of the application
+    /// and the account name its username, the same way `to_otpauth_uri`
+    /// builds them. The issuer is also kept on its own, so it can still be
+    /// searched for after the application is renamed. Only SHA-1 codes with
+    /// a 30 second period can be generated, URIs with other parameters are
+    /// rejected. `encoder=steam` selects Steam Guard codes.
+    pub fn from_otpauth_uri(uri: &str) -> Result<GenApp, Error> {
+        let rest = uri.trim().strip_prefix(SCHEME).ok_or_else(|| {
+            Error::new(
+                ErrorCode::Unsupported,
+                format!("Only {} URIs are supported", SCHEME),
+            )
+        })?;
+        let (label, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let label = decode(label)?;
+        let (label_issuer, account) = match label.split_once(':') {
+            Some((issuer, account)) => (Some(issuer.trim()), account.trim()),
+            None => (None, label.trim()),
+        };
+        let mut secret = None;
+        let mut issuer = None;
+        let mut icon = None;
+        let mut digits = None;
+        let mut steam = false;
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = decode(value)?;
+            match key.to_ascii_lowercase().as_str() {
+                "secret" => secret = Some(value),
+                "issuer" => issuer = Some(value),
+                // A broken icon isn't worth rejecting the generator
+                "image" => icon = Icon::from_url(&value).ok(),
+                "digits" => {
+                    digits = Some(value.parse().map_err(|_| {
+                        Error::new(
+                            ErrorCode::InvalidArgument,
+                            format!("Invalid number of digits '{}'", value),
+                        )
+                    })?);
+                }
+                "encoder" if value.eq_ignore_ascii_case("steam") => steam = true,
+                "encoder" => {
+                    return Err(Error::new(
+                        ErrorCode::Unsupported,
+                        format!("Encoder {} isn't supported", value),
+                    ));
+                }
+                "algorithm" if !value.eq_ignore_ascii_case("SHA1") => {
+                    return Err(Error::new(
+                        ErrorCode::Unsupported,
+                        format!("Algorithm {} isn't supported", value),
+                    ));
+                }
+                "period" if value != "30" => {
+                    return Err(Error::new(
+                        ErrorCode::Unsupported,
+                        format!("Period of {} seconds isn't supported", value),
+                    ));
+                }
+                _ => {}
+            }
+        }
+        let format = if steam {
+            CodeFormat::with_alphabet(STEAM_ALPHABET, digits.unwrap_or(5))?
+        } else {
+            CodeFormat::decimal(digits.unwrap_or(6))?
+        };
+        let secret =
+            secret.ok_or_else(|| Error::new(ErrorCode::InvalidArgument, "URI has no secret"))?;
+        // Without a username the label is just the name of the application
+        let (name, username) = match issuer.as_deref().or(label_issuer) {
+            Some(issuer) if !issuer.is_empty() && issuer != account => (issuer, account),
+            Some(issuer) if !issuer.is_empty() => (issuer, ""),
+            _ => (account, ""),
+        };
+        if name.is_empty() {
+            return Err(Error::new(
+                ErrorCode::InvalidArgument,
+                "URI has neither an issuer nor an account name",
+            ));
+        }
+        let mut app = GenApp::from_totp(name, username, &TOTP::new(&secret)?);
+        app.format = format;
+        app.issuer = String::from(issuer.as_deref().or(label_issuer).unwrap_or_default());
+        app.icon = icon;
+        Ok(app)
+    }
+}
+
+/// Tells whether an `otpauth://` URI can describe codes of the format:
+/// decimal ones and Steam Guard ones.
+pub(crate) fn carries_format(format: &CodeFormat) -> bool {
+    matches!(format.get_alphabet(), None | Some(STEAM_ALPHABET))
+}
+
+/// Percent-encodes everything except unreserved characters of RFC 3986.
+fn encode(value: &str) -> String {
+    let mut result = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                result.push(byte as char)
+            }
+            _ => result.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    result
+}
+
+/// Reverses percent-encoding, `+` is left as it is.
+fn decode(value: &str) -> Result<String, Error> {
+    let bytes = value.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        if bytes[idx] == b'%' {
+            let byte = value
+                .get(idx + 1..idx + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| {
+                    Error::new(
+                        ErrorCode::InvalidArgument,
+                        format!("Invalid percent-encoding in '{}'", value),
+                    )
+                })?;
+            result.push(byte);
+            idx += 3;
+        } else {
+            result.push(bytes[idx]);
+            idx += 1;
+        }
+    }
+    String::from_utf8(result).map_err(|_| {
+        Error::new(
+            ErrorCode::InvalidArgument,
+            format!("'{}' isn't valid UTF-8", value),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn steam_app() -> GenApp {
+        let mut app = GenApp::from_totp("Steam", "gabe", &TOTP::new("JBSWY3DPEHPK3PXP").unwrap());
+        app.format = CodeFormat::with_alphabet(STEAM_ALPHABET, 5).unwrap();
+        app
+    }
+
+    #[test]
+    fn steam_application_survives_round_trip() {
+        let app = steam_app();
+        app.check_otpauth_uri().unwrap();
+        let uri = app.to_otpauth_uri();
+        assert!(uri.contains("encoder=steam"));
+        let parsed = GenApp::from_otpauth_uri(&uri).unwrap();
+        assert_eq!(parsed.format, app.format);
+        assert_eq!(parsed.secret, app.secret);
+        assert_eq!(parsed.name, app.name);
+        assert_eq!(parsed.username, app.username);
+    }
+
+    #[test]
+    fn decimal_application_survives_round_trip() {
+        let mut app = GenApp::from_totp("GitHub", "", &TOTP::new("JBSWY3DPEHPK3PXP").unwrap());
+        app.format = CodeFormat::decimal(8).unwrap();
+        let parsed = GenApp::from_otpauth_uri(&app.to_otpauth_uri()).unwrap();
+        assert_eq!(parsed.format, app.format);
+        assert!(!parsed.to_otpauth_uri().contains("encoder"));
+    }
+
+    #[test]
+    fn custom_alphabet_has_no_uri() {
+        let mut app = steam_app();
+        app.format = CodeFormat::with_alphabet("ABCDEF", 6).unwrap();
+        assert_eq!(
+            app.check_otpauth_uri().unwrap_err().code(),
+            ErrorCode::Unsupported
+        );
+    }
+}
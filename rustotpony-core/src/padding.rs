@@ -0,0 +1,98 @@
+use crate::cipher::Cipher;
+use crate::stream::SEALED_CHUNK_SIZE;
+
+/// Smallest size bucket of the encrypted payload
+pub const MIN_BUCKET_SIZE: usize = 4096;
+
+/// Pads the payload, so that its encrypted form fills a power-of-two sized
+/// bucket of at least 4 KiB. The size of the database file then only tells
+/// roughly how many applications it contains.
+///
+/// JSON is padded with trailing whitespace and compressed data with zero
+/// bytes after the gzip stream, the `filler`. Both are ignored when reading,
+/// so padded databases stay readable by older versions.
+pub fn pad(data: &mut Vec<u8>, overhead: usize, filler: u8) {
+    // Ciphers add padding or a tag, see `Cipher::overhead`
+    let bucket = (data.len() + overhead)
+        .next_power_of_two()
+        .max(MIN_BUCKET_SIZE);
+    data.resize(bucket - overhead, filler);
+}
+
+/// Length a vault encrypted in chunks is padded to, so that it fills a
+/// bucket like the payload padded by `pad`. Every chunk but the last adds
+/// `Cipher::chunk_overhead` bytes.
+pub fn padded_length(length: usize, cipher: &dyn Cipher) -> usize {
+    let chunk_size = SEALED_CHUNK_SIZE - cipher.chunk_overhead();
+    let chunks = length.div_ceil(chunk_size).max(1);
+    let sealed = length + cipher.overhead() + cipher.chunk_overhead() * (chunks - 1);
+    let bucket = sealed.next_power_of_two().max(MIN_BUCKET_SIZE);
+    let chunks = (bucket / SEALED_CHUNK_SIZE).max(1);
+    bucket - cipher.overhead() - cipher.chunk_overhead() * (chunks - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cipher::{AesCbc, AesGcm, Whole, XChaCha20};
+    use crate::errors::ErrorCode;
+    use crate::{crypto, random, stream};
+
+    const KEY: [u8; 32] = [7; 32];
+    const CIPHERS: [&dyn Cipher; 4] = [&AesCbc, &AesGcm, &XChaCha20, &Whole(&XChaCha20)];
+
+    // Incompressible, so compressed vaults span several chunks too
+    fn payload(length: usize) -> serde_json::Value {
+        serde_json::json!({ "data": crypto::to_hex(&random::bytes(length / 2)) })
+    }
+
+    fn seal(cipher: &dyn Cipher, value: &serde_json::Value, compressed: bool) -> Vec<u8> {
+        stream::write_vault(cipher, value, &KEY, compressed, &|_, _| {})
+    }
+
+    #[test]
+    fn vaults_fill_buckets() {
+        for cipher in CIPHERS {
+            for compressed in [false, true] {
+                for length in [0, 3000, 150_000] {
+                    let vault = seal(cipher, &payload(length), compressed);
+                    let bucket = vault.len() - cipher.nonce_size();
+                    assert!(bucket.is_power_of_two(), "{} bytes", length);
+                    assert!(bucket >= MIN_BUCKET_SIZE, "{} bytes", length);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn padded_vaults_survive_round_trip() {
+        for cipher in CIPHERS {
+            for compressed in [false, true] {
+                let value = payload(150_000);
+                let vault = seal(cipher, &value, compressed);
+                let opened: serde_json::Value =
+                    stream::read_vault(cipher, &vault, &KEY, compressed, &|_, _| {}).unwrap();
+                assert_eq!(opened, value);
+            }
+        }
+    }
+
+    #[test]
+    fn wrong_key_is_rejected() {
+        for cipher in CIPHERS {
+            for compressed in [false, true] {
+                let vault = seal(cipher, &payload(3000), compressed);
+                let err = stream::read_vault::<serde_json::Value>(
+                    cipher,
+                    &vault,
+                    &[8; 32],
+                    compressed,
+                    &|_, _| {},
+                )
+                .err()
+                .unwrap();
+                assert_eq!(err.code(), ErrorCode::WrongPassword);
+            }
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::BTreeMap;
+
+use crate::crypto::{self, Hash};
+use crate::errors::{Error, ErrorCode};
+use crate::journal;
+use crate::{JsonDatabase, JsonDatabaseSchema};
+
+const PREFIX: &str = "RTPB1";
+/// Bytes of the database file carried by one part. Together with the
+/// sequence header it fits a QR code which is still easy to scan from paper.
+pub const PAPER_PART_SIZE: usize = 600;
+const CHECKSUM_SIZE: usize = 4;
+
+impl JsonDatabase {
+    /// Splits the database file, encrypted as it is, into parts for QR codes
+    /// printed on paper. Every part reads like
+    /// `RTPB1:2/5:<file checksum>:<part checksum>:<base32 data>`, which uses
+    /// only characters of the compact alphanumeric QR mode. The password
+    /// isn't asked, the parts are as safe as the database file.
+    pub fn paper_backup(&self, part_size: usize) -> Result<Vec<String>, Error> {
+        if self.has_journal() {
+            return Err(Error::new(ErrorCode::Database, "Database has changes in its journal which aren't in the file yet, compact it first"));
+        }
+        let data = self
+            .storage
+            .read()?
+            .ok_or_else(|| Error::new(ErrorCode::NotFound, "There is no database yet"))?;
+        self.strip_header(&data)?;
+        let chunks: Vec<&[u8]> = data.chunks(part_size.max(1)).collect();
+        let file_checksum = checksum(&data);
+        Ok(chunks
+            .iter()
+            .enumerate()
+            .map(|(idx, chunk)| {
+                format!(
+                    "{}:{}/{}:{}:{}:{}",
+                    PREFIX,
+                    idx + 1,
+                    chunks.len(),
+                    file_checksum,
+                    checksum(chunk),
+                    base32::encode(base32::Alphabet::RFC4648 { padding: false }, chunk)
+                )
+            })
+            .collect())
+    }
+
+    /// Reassembles a database from the parts of a paper backup, in any
+    /// order. The parts are checked, decrypted with the password to make
+    /// sure it's the right backup, and only then written to the storage,
+    /// which has to be empty. Returns the number of restored applications.
+    pub fn restore_paper_backup(&self, parts: &[String]) -> Result<usize, Error> {
+        if self.read_only {
+            return Err(Error::new(
+                ErrorCode::ReadOnly,
+                "Database is opened in read-only mode",
+            ));
+        }
+        if self.storage.read()?.is_some() {
+            return Err(Error::new(
+                ErrorCode::AlreadyExists,
+                "Database already exists, a backup can only be restored into a new one",
+            ));
+        }
+        let data = join(parts)?;
+        let schema: JsonDatabaseSchema = self.decrypt_with_secret(self.strip_header(&data)?)?;
+        self.storage.write(&data)?;
+        // A journal left next to the removed database doesn't belong to
+        // the restored one
+        if let Some(path) = self.storage.local_path() {
+            journal::discard_journal(path)?;
+        }
+        Ok(schema.content.applications.len())
+    }
+}
+
+struct Part {
+    idx: usize,
+    total: usize,
+    file_checksum: String,
+    data: Vec<u8>,
+}
+
+fn parse(text: &str) -> Result<Part, Error> {
+    let invalid = || {
+        Error::new(
+            ErrorCode::InvalidArgument,
+            format!("'{}' isn't a part of a paper backup", shorten(text)),
+        )
+    };
+    let fields: Vec<&str> = text.trim().split(':').collect();
+    if fields.len() != 5 || fields[0] != PREFIX {
+        return Err(invalid());
+    }
+    let (idx, total) = fields[1].split_once('/').ok_or_else(invalid)?;
+    let (idx, total): (usize, usize) = match (idx.parse(), total.parse()) {
+        (Ok(idx), Ok(total)) if idx >= 1 && idx <= total => (idx, total),
+        _ => return Err(invalid()),
+    };
+    let data = base32::decode(base32::Alphabet::RFC4648 { padding: false }, fields[4]).ok_or_else(
+        || {
+            Error::new(
+                ErrorCode::Damaged,
+                format!("Part {} of {} is damaged", idx, total),
+            )
+        },
+    )?;
+    if !fields[3].eq_ignore_ascii_case(&checksum(&data)) {
+        return Err(Error::new(
+            ErrorCode::Damaged,
+            format!(
+                "Part {} of {} is damaged, its checksum doesn't match",
+                idx, total
+            ),
+        ));
+    }
+    Ok(Part {
+        idx,
+        total,
+        file_checksum: fields[2].to_ascii_uppercase(),
+        data,
+    })
+}
+
+fn join(texts: &[String]) -> Result<Vec<u8>, Error> {
+    let mut parts: BTreeMap<usize, Part> = BTreeMap::new();
+    let mut first: Option<(usize, String)> = None;
+    for text in texts.iter().filter(|text| !text.trim().is_empty()) {
+        let part = parse(text)?;
+        match &first {
+            None => first = Some((part.total, part.file_checksum.clone())),
+            Some((total, file_checksum))
+                if *total != part.total || *file_checksum != part.file_checksum =>
+            {
+                return Err(Error::new(
+                    ErrorCode::InvalidArgument,
+                    format!(
+                        "Part {} of {} belongs to another backup",
+                        part.idx, part.total
+                    ),
+                ));
+            }
+            Some(_) => {}
+        }
+        // The same QR code scanned twice is fine
+        parts.insert(part.idx, part);
+    }
+    let (total, file_checksum) =
+        first.ok_or_else(|| Error::new(ErrorCode::NotFound, "There are no parts"))?;
+    let missing: Vec<String> = (1..=total)
+        .filter(|idx| !parts.contains_key(idx))
+        .map(|idx| idx.to_string())
+        .collect();
+    if !missing.is_empty() {
+        return Err(Error::new(
+            ErrorCode::InvalidArgument,
+            format!("Parts {} of {} are missing", missing.join(", "), total),
+        ));
+    }
+    let data: Vec<u8> = parts.into_values().flat_map(|part| part.data).collect();
+    if checksum(&data) != file_checksum {
+        return Err(Error::new(
+            ErrorCode::Damaged,
+            "Reassembled backup doesn't match its checksum",
+        ));
+    }
+    Ok(data)
+}
+
+/// Uppercase, so it stays in the alphanumeric QR mode.
+fn checksum(data: &[u8]) -> String {
+    crypto::to_hex(&Hash::Sha256.digest(data)[..CHECKSUM_SIZE]).to_ascii_uppercase()
+}
+
+fn shorten(text: &str) -> String {
+    match text.char_indices().nth(20) {
+        Some((idx, _)) => format!("{}…", &text[..idx]),
+        None => String::from(text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::Kdf;
+    use crate::{MemoryStorage, RusTOTPony};
+
+    fn database(storage: &MemoryStorage, password: &'static str) -> JsonDatabase {
+        JsonDatabase::builder()
+            .storage(Box::new(storage.clone()))
+            .secret(move || String::from(password))
+            .kdf(Kdf::scrypt(10).unwrap())
+            .build()
+            .unwrap()
+    }
+
+    fn backup() -> (MemoryStorage, Vec<String>) {
+        let storage = MemoryStorage::default();
+        let mut totpony = RusTOTPony::new(database(&storage, "correct horse"));
+        totpony
+            .create_application("GitHub", "octocat", "JBSWY3DPEHPK3PXP")
+            .unwrap();
+        totpony.flush().unwrap();
+        let parts = database(&storage, "correct horse")
+            .paper_backup(PAPER_PART_SIZE)
+            .unwrap();
+        (storage, parts)
+    }
+
+    #[test]
+    fn backup_survives_round_trip() {
+        let (original, mut parts) = backup();
+        assert!(parts.len() > 1);
+        parts.reverse();
+        let restored = MemoryStorage::default();
+        let count = database(&restored, "correct horse")
+            .restore_paper_backup(&parts)
+            .unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(restored.get_bytes(), original.get_bytes());
+    }
+
+    #[test]
+    fn wrong_password_restores_nothing() {
+        let (_, parts) = backup();
+        let restored = MemoryStorage::default();
+        let err = database(&restored, "wrong horse")
+            .restore_paper_backup(&parts)
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::WrongPassword);
+        assert!(restored.get_bytes().is_none());
+    }
+
+    #[test]
+    fn damaged_and_missing_parts_are_rejected() {
+        let (_, parts) = backup();
+        let mut damaged = parts.clone();
+        let last = damaged[0].pop().unwrap();
+        damaged[0].push(if last == 'A' { 'B' } else { 'A' });
+        let restore = |parts: &[String]| {
+            database(&MemoryStorage::default(), "correct horse")
+                .restore_paper_backup(parts)
+                .unwrap_err()
+                .code()
+        };
+        assert_eq!(restore(&damaged), ErrorCode::Damaged);
+        assert_eq!(restore(&parts[1..]), ErrorCode::InvalidArgument);
+    }
+
+    #[test]
+    fn existing_database_isnt_replaced() {
+        let (storage, parts) = backup();
+        let err = database(&storage, "correct horse")
+            .restore_paper_backup(&parts)
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::AlreadyExists);
+    }
+}
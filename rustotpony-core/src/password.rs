@@ -0,0 +1,110 @@
+use crate::crypto;
+use crate::decoy::{self, Slot};
+use crate::errors::{Error, ErrorCode};
+use crate::header::Kdf;
+use crate::progress::Task;
+use crate::{JsonDatabase, JsonDatabaseSchema, KEY_SIZE};
+
+/// Number of times a new password can be mistyped before giving up
+pub const NEW_PASSWORD_ATTEMPTS: u32 = 3;
+
+// Decrypting, encrypting and writing the file, see `Task::Reencryption`
+const REENCRYPTION_STEPS: u64 = 3;
+
+/// What a frontend is asked to prompt for while a new password is set.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PasswordPrompt {
+    /// The new password, entered for the first time
+    Enter,
+    /// The same password once more
+    Repeat,
+    /// The new password again, after the previous two entries didn't match
+    Mismatch,
+}
+
+/// Asks for a new password twice and returns it once both entries match.
+/// Mistyped entries are asked again up to `attempts` times. The `ask`
+/// function shows the prompt and reads the answer, it can cancel the whole
+/// flow by returning an error, e.g. when the user rejects a weak password.
+///
+/// All frontends use it for database passwords, decoy passwords and
+/// passphrases of protected applications, so they behave the same.
+pub fn ask_new_password<F>(mut ask: F, attempts: u32) -> Result<String, Error>
+where
+    F: FnMut(PasswordPrompt) -> Result<String, Error>,
+{
+    let mut prompt = PasswordPrompt::Enter;
+    for _ in 0..attempts {
+        let password = ask(prompt)?;
+        let confirmation = ask(PasswordPrompt::Repeat)?;
+        if crypto::constant_time_eq(password.as_bytes(), confirmation.as_bytes()) {
+            return Ok(password);
+        }
+        prompt = PasswordPrompt::Mismatch;
+    }
+    Err(Error::new(
+        ErrorCode::InvalidArgument,
+        format!("Passwords didn't match {} times", attempts),
+    ))
+}
+
+impl JsonDatabase {
+    /// Encrypts the database with a new password. The decoy vault, if there
+    /// is one, stays as it is, so the new password must differ from the
+    /// decoy password. A database which required two persons is opened by
+    /// the single password afterwards, as is an old database whose password
+    /// went through a single fast hash, see `password_kdf`.
+    pub fn change_password(&self, new_password: &str) -> Result<(), Error> {
+        let kdf = self.password_kdf()?;
+        self.replace_key(
+            &self.database_key(),
+            &Self::form_password_key(&kdf, new_password),
+            kdf,
+        )
+    }
+
+    /// Encrypts the vault opened by `key` with a new key derived by `kdf`.
+    pub(crate) fn replace_key(
+        &self,
+        key: &[u8; KEY_SIZE],
+        new_key: &[u8; KEY_SIZE],
+        kdf: Kdf,
+    ) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::new(
+                ErrorCode::ReadOnly,
+                "Database is opened in read-only mode",
+            ));
+        }
+        let data = self
+            .read_vaults()?
+            .ok_or_else(|| Error::new(ErrorCode::NotFound, "There is no database yet"))?;
+        self.check_cancelled()?;
+        self.report(Task::Reencryption, 0, Some(REENCRYPTION_STEPS));
+        let (slot, mut schema): (Slot, JsonDatabaseSchema) = self.open_vault(&data, key)?;
+        if self.find_vault(&data, new_key).is_ok() {
+            return Err(Error::new(
+                ErrorCode::InvalidArgument,
+                "New password must differ from the current and the decoy passwords",
+            ));
+        }
+        self.replay_journal(&mut schema.content, key)?;
+        self.check_cancelled()?;
+        self.report(Task::Reencryption, 1, Some(REENCRYPTION_STEPS));
+        let vault = self.seal(&schema.content, new_key);
+        self.check_cancelled()?;
+        self.report(Task::Reencryption, 2, Some(REENCRYPTION_STEPS));
+        // A decoy would be opened by a single person, so it's dropped when
+        // two persons become required
+        let data = match slot {
+            Slot::Half(idx) if !kdf.is_two_persons() => decoy::replace_half(&data, idx, &vault),
+            _ => decoy::place(&[], None, &vault),
+        };
+        self.set_kdf(kdf);
+        self.write_vaults(&data)?;
+        self.clear_journal(&schema.content, key)?;
+        self.report(Task::Reencryption, 3, Some(REENCRYPTION_STEPS));
+        self.forget_key();
+        Ok(())
+    }
+}
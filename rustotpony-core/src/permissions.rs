@@ -0,0 +1,133 @@
+use std::path::Path;
+
+use crate::errors::{Error, ErrorCode};
+
+/// Mode of database files, readable and writable only by the owner
+#[cfg(unix)]
+pub const FILE_MODE: u32 = 0o600;
+/// Mode of directories created for database files
+#[cfg(unix)]
+pub const DIR_MODE: u32 = 0o700;
+
+/// Tells whether other users can access the database file. Only Unix
+/// permission bits are checked.
+#[cfg(unix)]
+pub fn permissions_problem(path: &Path) -> Option<String> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = std::fs::metadata(path).ok()?.permissions().mode();
+    if mode & 0o077 != 0 {
+        Some(format!(
+            "Database file {} is accessible by other users (mode {:o})",
+            path.display(),
+            mode & 0o777
+        ))
+    } else {
+        None
+    }
+}
+
+#[cfg(not(unix))]
+pub fn permissions_problem(_: &Path) -> Option<String> {
+    None
+}
+
+/// Checks that writing to the path changes only the database file itself.
+/// A symbolic link could redirect the write anywhere, and a file with more
+/// than one hard link would be changed under its other names too. Missing
+/// files are fine, they are created.
+pub fn check_write_target(path: &Path) -> Result<(), Error> {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => {
+            return Err(Error::new(
+                ErrorCode::Io,
+                format!("Couldn't inspect {}: {}", path.display(), err),
+            ))
+        }
+    };
+    if metadata.file_type().is_symlink() {
+        return Err(Error::new(
+            ErrorCode::Io,
+            format!(
+                "Database file {} is a symbolic link, refusing to write through it",
+                path.display()
+            ),
+        ));
+    }
+    if !metadata.is_file() {
+        return Err(Error::new(
+            ErrorCode::Io,
+            format!("Database file {} isn't a regular file", path.display()),
+        ));
+    }
+    match hard_links(&metadata) {
+        links if links > 1 => Err(Error::new(
+            ErrorCode::Io,
+            format!(
+                "Database file {} has {} hard links, refusing to write through them",
+                path.display(),
+                links
+            ),
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(unix)]
+fn hard_links(metadata: &std::fs::Metadata) -> u64 {
+    std::os::unix::fs::MetadataExt::nlink(metadata)
+}
+
+// Link count isn't available on stable Rust elsewhere
+#[cfg(not(unix))]
+fn hard_links(_: &std::fs::Metadata) -> u64 {
+    1
+}
+
+/// Makes the database file accessible only by its owner.
+#[cfg(unix)]
+pub fn restrict_permissions(path: &Path) -> Result<(), Error> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(FILE_MODE)).map_err(|err| {
+        Error::new(
+            ErrorCode::Io,
+            format!("Couldn't change permissions of {}: {}", path.display(), err),
+        )
+    })
+}
+
+/// Replaces inherited ACL entries of the database file with full control
+/// for the current user only.
+#[cfg(windows)]
+pub fn restrict_permissions(path: &Path) -> Result<(), Error> {
+    use std::process::Command;
+
+    let user = std::env::var("USERNAME")
+        .map_err(|_| Error::new(ErrorCode::Other, "Couldn't determine the current user"))?;
+    let output = Command::new("icacls")
+        .arg(path)
+        .args(["/inheritance:r", "/grant:r"])
+        .arg(format!("{}:F", user))
+        .output()
+        .map_err(|err| Error::new(ErrorCode::Io, format!("Couldn't run icacls: {}", err)))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(Error::new(
+            ErrorCode::Io,
+            format!(
+                "Couldn't change permissions of {}: {}",
+                path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ))
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn restrict_permissions(_: &Path) -> Result<(), Error> {
+    Ok(())
+}
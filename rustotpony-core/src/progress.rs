@@ -0,0 +1,71 @@
+use crate::JsonDatabase;
+
+/// Long-running operation whose progress is reported to callbacks, see
+/// `JsonDatabaseBuilder::on_progress` and `Event::Progress`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Task {
+    /// Deriving the key from the password, its progress is unknown until
+    /// it's finished
+    KeyDerivation,
+    /// Decrypting the vault, in bytes of the vault or, for indexed
+    /// databases, in applications
+    Decryption,
+    /// Encrypting the vault, in bytes of the JSON or, for indexed
+    /// databases, in applications
+    Encryption,
+    /// Encrypting the database with a new key, e.g. when the password is
+    /// changed and an old database moves to the current key derivation.
+    /// Its steps are decrypting, encrypting and writing the file.
+    Reencryption,
+    /// Adding imported entries, in entries
+    Import,
+    /// Downloading the database, in bytes
+    Download,
+    /// Uploading the database, in bytes
+    Upload,
+}
+
+/// Progress of a task. `done` and `total` are counted in units of the
+/// task; the total is unknown for some tasks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    pub task: Task,
+    pub done: u64,
+    pub total: Option<u64>,
+}
+
+pub(crate) type ProgressCallback = Box<dyn Fn(&Progress) + Send + Sync>;
+
+impl Progress {
+    pub fn new(task: Task, done: u64, total: Option<u64>) -> Progress {
+        Progress { task, done, total }
+    }
+
+    /// Finished part of the task from 0 to 1, `None` if the total is
+    /// unknown.
+    pub fn fraction(&self) -> Option<f64> {
+        match self.total {
+            Some(0) => Some(1.0),
+            Some(total) => Some((self.done as f64 / total as f64).min(1.0)),
+            None => None,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.total.is_some_and(|total| self.done >= total)
+    }
+}
+
+impl JsonDatabase {
+    /// Passes the progress to the callback set by the builder.
+    pub(crate) fn report(&self, task: Task, done: u64, total: Option<u64>) {
+        if let Some(callback) = &self.progress {
+            callback(&Progress::new(task, done, total));
+        }
+    }
+
+    /// Callback for `stream` and `decoy`, which count bytes of the task.
+    pub(crate) fn reporter(&self, task: Task) -> impl Fn(u64, u64) + '_ {
+        move |done, total| self.report(task, done, Some(total))
+    }
+}
@@ -0,0 +1,191 @@
+use crate::cipher::AesCbc;
+use crate::errors::{Error, ErrorCode};
+use crate::kdf;
+use crate::{Change, Database, GenApp, JsonDatabase, Operation, RusTOTPony};
+
+// Secrets protected with a key derived by scrypt and sealed with
+// XChaCha20-Poly1305, secrets without it were encrypted by older versions
+// with AES-CBC and a SHA-256 of the passphrase
+const SCRYPT_PREFIX: &str = "scrypt:";
+
+impl GenApp {
+    /// Protected applications keep their secret encrypted with an additional
+    /// passphrase, use `unlock` to get a copy which can generate codes.
+    pub fn is_protected(&self) -> bool {
+        self.protected_secret.is_some()
+    }
+
+    /// Returns a copy of the application with the secret decrypted.
+    pub fn unlock(&self, passphrase: &str) -> Result<GenApp, Error> {
+        let sealed = match self.protected_secret {
+            Some(ref sealed) => sealed,
+            None => return Ok(self.clone()),
+        };
+        let wrong_passphrase = || {
+            Error::new(
+                ErrorCode::WrongPassword,
+                format!("Wrong passphrase for application '{}'", self.name),
+            )
+        };
+        let secret = match sealed.strip_prefix(SCRYPT_PREFIX) {
+            Some(sealed) => base64::decode(sealed)
+                .ok()
+                .and_then(|data| kdf::open_with_passphrase(&data, passphrase).ok())
+                .and_then(|secret| String::from_utf8(secret).ok()),
+            None => base64::decode(sealed).ok().and_then(|data| {
+                JsonDatabase::decrypt_data(
+                    &AesCbc,
+                    &data,
+                    &JsonDatabase::form_secret_key(passphrase),
+                )
+                .ok()
+            }),
+        }
+        .ok_or_else(wrong_passphrase)?;
+        let secret_bytes = GenApp::base32_to_bytes(&secret).ok_or_else(wrong_passphrase)?;
+        let mut app = self.clone();
+        app.secret = secret;
+        app.secret_bytes = secret_bytes;
+        app.protected_secret = None;
+        Ok(app)
+    }
+
+    // The key is derived with scrypt and a random salt kept with the
+    // secret, the secret is authenticated
+    fn protect(&mut self, passphrase: &str) {
+        let sealed = kdf::seal_with_passphrase(self.secret.as_bytes(), passphrase);
+        self.protected_secret = Some(format!("{}{}", SCRYPT_PREFIX, base64::encode(sealed)));
+        self.secret = String::new();
+        self.secret_bytes = Vec::new();
+    }
+}
+
+impl<DB: Database> RusTOTPony<DB> {
+    /// Encrypts the secret of the application once more with the passphrase,
+    /// so it's unusable without the passphrase even when the database is open.
+    pub fn protect_application(&mut self, name: &str, passphrase: &str) -> Result<(), Error> {
+        let name = &self.resolve_name(name)?;
+        match self.content_mut()?.applications.get_mut(name) {
+            Some(ref app) if app.is_protected() => {
+                return Err(Error::new(
+                    ErrorCode::AlreadyExists,
+                    format!("Application '{}' is already protected", name),
+                ))
+            }
+            Some(app) => app.protect(passphrase),
+            None => {
+                return Err(Error::new(
+                    ErrorCode::NotFound,
+                    format!("Application '{}' wasn't found", name),
+                ))
+            }
+        }
+        self.mark_dirty(name);
+        self.record(Operation::Protect, name, None);
+        self.push_change(Change::Protected(String::from(name)));
+        Ok(())
+    }
+
+    pub fn unprotect_application(&mut self, name: &str, passphrase: &str) -> Result<(), Error> {
+        let name = &self.resolve_name(name)?;
+        let unlocked = match self.content()?.applications.get(name) {
+            Some(app) if app.is_protected() => app.unlock(passphrase)?,
+            Some(_) => {
+                return Err(Error::new(
+                    ErrorCode::InvalidArgument,
+                    format!("Application '{}' isn't protected", name),
+                ))
+            }
+            None => {
+                return Err(Error::new(
+                    ErrorCode::NotFound,
+                    format!("Application '{}' wasn't found", name),
+                ))
+            }
+        };
+        self.loaded_mut()
+            .applications
+            .insert(String::from(name), unlocked);
+        self.mark_dirty(name);
+        self.record(Operation::Unprotect, name, None);
+        self.push_change(Change::Unprotected(String::from(name)));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cipher::Cipher;
+    use crate::DatabaseContent;
+
+    struct Memory;
+
+    impl Database for Memory {
+        fn get_content(&self) -> Result<DatabaseContent, Error> {
+            Ok(DatabaseContent::default())
+        }
+
+        fn save_content(&self, _content: &DatabaseContent) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    fn protected() -> RusTOTPony<Memory> {
+        let mut totpony = RusTOTPony::with_content(Memory, DatabaseContent::default());
+        totpony
+            .create_application("GitHub", "octocat", "JBSWY3DPEHPK3PXP")
+            .unwrap();
+        totpony
+            .protect_application("GitHub", "correct horse")
+            .unwrap();
+        totpony
+    }
+
+    #[test]
+    fn protected_secret_survives_round_trip() {
+        let totpony = protected();
+        let app = totpony.get_application("GitHub").unwrap();
+        assert!(app.is_protected());
+        assert_eq!(app.get_secret(), "");
+        let unlocked = app.unlock("correct horse").unwrap();
+        assert_eq!(unlocked.get_secret(), "JBSWY3DPEHPK3PXP");
+        assert!(!unlocked.is_protected());
+    }
+
+    #[test]
+    fn wrong_passphrase_is_rejected() {
+        let mut totpony = protected();
+        let app = totpony.get_application("GitHub").unwrap();
+        let err = app.unlock("wrong horse").unwrap_err();
+        assert_eq!(err.code(), ErrorCode::WrongPassword);
+        let err = totpony
+            .unprotect_application("GitHub", "wrong horse")
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::WrongPassword);
+        assert!(totpony.get_application("GitHub").unwrap().is_protected());
+    }
+
+    #[test]
+    fn unprotect_restores_secret() {
+        let mut totpony = protected();
+        totpony
+            .unprotect_application("GitHub", "correct horse")
+            .unwrap();
+        let app = totpony.get_application("GitHub").unwrap();
+        assert!(!app.is_protected());
+        assert_eq!(app.get_secret(), "JBSWY3DPEHPK3PXP");
+    }
+
+    #[test]
+    fn secrets_protected_by_older_versions_are_unlocked() {
+        let mut app = protected().get_application("GitHub").unwrap().clone();
+        let key = JsonDatabase::form_secret_key("correct horse");
+        let sealed = AesCbc.seal(b"JBSWY3DPEHPK3PXP", &key);
+        app.protected_secret = Some(base64::encode(sealed));
+        let unlocked = app.unlock("correct horse").unwrap();
+        assert_eq!(unlocked.get_secret(), "JBSWY3DPEHPK3PXP");
+        let err = app.unlock("wrong horse").unwrap_err();
+        assert_eq!(err.code(), ErrorCode::WrongPassword);
+    }
+}
@@ -0,0 +1,31 @@
+use std::ops::RangeInclusive;
+
+/// Fills the buffer with random bytes of the operating system, or of the
+/// Web Crypto API in browsers. Every IV, nonce, salt, key and generated
+/// secret comes from here.
+pub fn fill(bytes: &mut [u8]) {
+    getrandom::fill(bytes).expect("Random number generator of the system failed");
+}
+
+pub fn bytes(size: usize) -> Vec<u8> {
+    let mut bytes = vec![0; size];
+    fill(&mut bytes);
+    bytes
+}
+
+/// Returns a uniformly distributed number of the range.
+pub fn number(range: RangeInclusive<u32>) -> u32 {
+    let (low, high) = range.into_inner();
+    let span = u64::from(high - low) + 1;
+    // Numbers above the last whole multiple of the span are rejected, so
+    // the remainder isn't biased towards small values
+    let limit = (1u64 << 32) / span * span;
+    loop {
+        let mut bytes = [0; 4];
+        fill(&mut bytes);
+        let value = u64::from(u32::from_be_bytes(bytes));
+        if value < limit {
+            return low + (value % span) as u32;
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use age::armor::{ArmoredWriter, Format};
+use std::io::Write;
+
+use crate::errors::{Error, ErrorCode};
+
+/// Encrypts exported data, e.g. the URIs or the HTML page, to the public
+/// keys of age recipients (`age1…`) and returns it ASCII armored. Any of
+/// the recipients can open it with their identity, e.g. by `age -d -i key`,
+/// so a backup of a team can be restored by every designated admin without
+/// a shared password.
+pub fn encrypt_to_recipients(data: &[u8], recipients: &[String]) -> Result<Vec<u8>, Error> {
+    let recipients = recipients
+        .iter()
+        .map(|recipient| {
+            recipient
+                .trim()
+                .parse::<age::x25519::Recipient>()
+                .map_err(|err| {
+                    Error::new(
+                        ErrorCode::InvalidArgument,
+                        format!("Invalid recipient '{}': {}", recipient, err),
+                    )
+                })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    if recipients.is_empty() {
+        return Err(Error::new(
+            ErrorCode::InvalidArgument,
+            "At least one recipient is required",
+        ));
+    }
+    let encryptor = age::Encryptor::with_recipients(
+        recipients
+            .iter()
+            .map(|recipient| recipient as &dyn age::Recipient),
+    )
+    .map_err(|err| {
+        Error::new(
+            ErrorCode::Other,
+            format!("Couldn't encrypt to the recipients: {}", err),
+        )
+    })?;
+    let write = || -> std::io::Result<Vec<u8>> {
+        let armored = ArmoredWriter::wrap_output(Vec::new(), Format::AsciiArmor)?;
+        let mut writer = encryptor.wrap_output(armored)?;
+        writer.write_all(data)?;
+        writer.finish()?.finish()
+    };
+    write().map_err(|err| {
+        Error::new(
+            ErrorCode::Other,
+            format!("Couldn't encrypt to the recipients: {}", err),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use age::armor::ArmoredReader;
+    use age::x25519::Identity;
+    use std::io::Read;
+
+    fn decrypt(data: &[u8], identity: &Identity) -> Result<Vec<u8>, age::DecryptError> {
+        let decryptor = age::Decryptor::new(ArmoredReader::new(data))?;
+        let mut reader = decryptor.decrypt(std::iter::once(identity as &dyn age::Identity))?;
+        let mut opened = Vec::new();
+        reader.read_to_end(&mut opened)?;
+        Ok(opened)
+    }
+
+    #[test]
+    fn every_recipient_can_decrypt() {
+        let identities = [Identity::generate(), Identity::generate()];
+        let recipients: Vec<String> = identities
+            .iter()
+            .map(|identity| identity.to_public().to_string())
+            .collect();
+        let data = b"otpauth://totp/GitHub?secret=JBSWY3DPEHPK3PXP";
+        let sealed = encrypt_to_recipients(data, &recipients).unwrap();
+        assert!(sealed.starts_with(b"-----BEGIN AGE ENCRYPTED FILE-----"));
+        for identity in &identities {
+            assert_eq!(decrypt(&sealed, identity).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn other_identity_cant_decrypt() {
+        let recipient = Identity::generate().to_public().to_string();
+        let sealed = encrypt_to_recipients(b"secret", &[recipient]).unwrap();
+        assert!(decrypt(&sealed, &Identity::generate()).is_err());
+    }
+
+    #[test]
+    fn invalid_recipients_are_rejected() {
+        for recipients in [vec![], vec![String::from("age1invalid")]] {
+            let err = encrypt_to_recipients(b"secret", &recipients).err().unwrap();
+            assert_eq!(err.code(), ErrorCode::InvalidArgument);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use regex::Regex;
+use std::collections::HashMap;
+
+use crate::errors::{Error, ErrorCode};
+use crate::names;
+use crate::{Change, Database, ExportFilter, Operation, RusTOTPony};
+
+/// How new names are made from the current ones in bulk renames.
+#[derive(Debug, Clone)]
+pub enum RenameRule {
+    /// Puts the text before the name, e.g. "work/"
+    Prefix(String),
+    /// Puts the text after the name
+    Suffix(String),
+    /// Replaces every match of the pattern, the replacement can refer to
+    /// groups as `$1` or `${name}`
+    Replace(Regex, String),
+}
+
+impl RenameRule {
+    pub fn regex(pattern: &str, replacement: &str) -> Result<RenameRule, Error> {
+        let regex = Regex::new(pattern).map_err(|err| {
+            Error::new(
+                ErrorCode::InvalidArgument,
+                format!("Invalid pattern '{}': {}", pattern, err),
+            )
+        })?;
+        Ok(RenameRule::Replace(regex, String::from(replacement)))
+    }
+
+    pub fn apply(&self, name: &str) -> String {
+        match self {
+            RenameRule::Prefix(prefix) => format!("{}{}", prefix, name),
+            RenameRule::Suffix(suffix) => format!("{}{}", name, suffix),
+            RenameRule::Replace(regex, replacement) => {
+                regex.replace_all(name, replacement.as_str()).into_owned()
+            }
+        }
+    }
+}
+
+impl<DB: Database> RusTOTPony<DB> {
+    /// Returns the names of the applications selected by the filter paired
+    /// with the names the rule gives them, sorted. Applications the rule
+    /// doesn't change are left out. Fails when a new name is empty, taken by
+    /// an application which isn't renamed or by an alias, or given to
+    /// several applications.
+    pub fn plan_renames(
+        &self,
+        filter: &ExportFilter,
+        rule: &RenameRule,
+    ) -> Result<Vec<(String, String)>, Error> {
+        let mut renames: Vec<(String, String)> = self
+            .content()?
+            .applications
+            .iter()
+            .filter(|(_, app)| filter.matches(app))
+            .map(|(name, _)| (name.clone(), names::normalize(rule.apply(name).trim())))
+            .filter(|(name, newname)| name != newname)
+            .collect();
+        renames.sort();
+        let mut targets: HashMap<String, &str> = HashMap::new();
+        for (name, newname) in &renames {
+            if newname.is_empty() {
+                return Err(Error::new(
+                    ErrorCode::InvalidArgument,
+                    format!("'{}' would get an empty name", name),
+                ));
+            }
+            if let Some(other) = targets.insert(self.name_key(newname), name) {
+                return Err(Error::new(
+                    ErrorCode::AlreadyExists,
+                    format!(
+                        "Both '{}' and '{}' would be renamed to '{}'",
+                        other, name, newname
+                    ),
+                ));
+            }
+            // Names freed by other renames of the batch can be reused
+            match self.find_taken_name(newname)? {
+                Some(taken)
+                    if taken != *name && !renames.iter().any(|(renamed, _)| *renamed == taken) =>
+                {
+                    return Err(Error::new(
+                        ErrorCode::AlreadyExists,
+                        format!("Application with name '{}' already exists!", taken),
+                    ));
+                }
+                _ => {}
+            }
+            match self.find_alias_owner(newname)? {
+                Some(owner) if owner != *name => {
+                    return Err(Error::new(
+                        ErrorCode::AlreadyExists,
+                        format!("'{}' is an alias of '{}'", newname, owner),
+                    ));
+                }
+                _ => {}
+            }
+        }
+        Ok(renames)
+    }
+
+    /// Renames all applications selected by the filter with the rule, see
+    /// `plan_renames`. Either all of them are renamed or none, the renames
+    /// are returned.
+    pub fn rename_applications(
+        &mut self,
+        filter: &ExportFilter,
+        rule: &RenameRule,
+    ) -> Result<Vec<(String, String)>, Error> {
+        let renames = self.plan_renames(filter, rule)?;
+        // All applications are taken out first, so names can be swapped
+        let apps: Vec<_> = renames
+            .iter()
+            .filter_map(|(name, _)| self.loaded_mut().applications.remove(name))
+            .collect();
+        for ((name, newname), mut app) in renames.iter().zip(apps) {
+            app.name = newname.clone();
+            self.loaded_mut().applications.insert(newname.clone(), app);
+            self.mark_dirty(name);
+            self.mark_dirty(newname);
+            self.record(Operation::Rename, name, Some(newname));
+            self.push_change(Change::Renamed(name.clone(), newname.clone()));
+        }
+        Ok(renames)
+    }
+}
@@ -0,0 +1,235 @@
+use tracing::debug;
+
+use std::env;
+use std::io::Read;
+
+use crate::crypto::{to_hex, Hash};
+use crate::datetime::{self, DateTime};
+use crate::errors::{Error, ErrorCode};
+use crate::storage::Storage;
+
+const DEFAULT_REGION: &str = "us-east-1";
+const VERSIONS_SUFFIX: &str = ".versions";
+
+/// Database storage in an S3-compatible object storage (AWS S3, MinIO, etc.).
+///
+/// Every write first uploads the database as a new timestamped object under
+/// `<key>.versions/` and then copies it over `<key>` on the server side, so
+/// previous states of the database stay available even when bucket
+/// versioning is disabled.
+pub struct S3Storage {
+    endpoint: String,
+    region: String,
+    bucket: String,
+    key: String,
+    access_key: String,
+    secret_key: String,
+}
+
+impl S3Storage {
+    pub fn new(
+        endpoint: &str,
+        region: &str,
+        bucket: &str,
+        key: &str,
+        access_key: &str,
+        secret_key: &str,
+    ) -> S3Storage {
+        S3Storage {
+            endpoint: String::from(endpoint.trim_end_matches('/')),
+            region: String::from(region),
+            bucket: String::from(bucket),
+            key: String::from(key.trim_start_matches('/')),
+            access_key: String::from(access_key),
+            secret_key: String::from(secret_key),
+        }
+    }
+
+    /// Reads credentials from the standard `AWS_ACCESS_KEY_ID`,
+    /// `AWS_SECRET_ACCESS_KEY` and `AWS_REGION` variables. A custom endpoint
+    /// (e.g. a MinIO server) can be set with `RUSTOTPONY_S3_ENDPOINT`.
+    pub fn from_env(bucket: &str, key: &str) -> Result<S3Storage, Error> {
+        let access_key = env::var("AWS_ACCESS_KEY_ID")
+            .map_err(|_| Error::new(ErrorCode::InvalidArgument, "AWS_ACCESS_KEY_ID is not set"))?;
+        let secret_key = env::var("AWS_SECRET_ACCESS_KEY").map_err(|_| {
+            Error::new(
+                ErrorCode::InvalidArgument,
+                "AWS_SECRET_ACCESS_KEY is not set",
+            )
+        })?;
+        let region = env::var("AWS_REGION").unwrap_or_else(|_| String::from(DEFAULT_REGION));
+        let endpoint = env::var("RUSTOTPONY_S3_ENDPOINT")
+            .unwrap_or_else(|_| format!("https://s3.{}.amazonaws.com", region));
+        Ok(S3Storage::new(
+            &endpoint,
+            &region,
+            bucket,
+            key,
+            &access_key,
+            &secret_key,
+        ))
+    }
+
+    fn object_path(&self, key: &str) -> String {
+        let encoded_key: Vec<String> = key.split('/').map(uri_encode).collect();
+        format!("/{}/{}", uri_encode(&self.bucket), encoded_key.join("/"))
+    }
+
+    fn host(&self) -> &str {
+        let without_scheme = match self.endpoint.find("://") {
+            Some(idx) => &self.endpoint[idx + 3..],
+            None => &self.endpoint,
+        };
+        without_scheme.split('/').next().unwrap_or(without_scheme)
+    }
+
+    fn request(
+        &self,
+        method: &str,
+        key: &str,
+        payload: &[u8],
+        extra_headers: &[(&str, String)],
+    ) -> ureq::Request {
+        let path = self.object_path(key);
+        let (amz_date, date_stamp) = Self::timestamps();
+        let payload_hash = sha256_hex(payload);
+
+        let mut headers: Vec<(String, String)> = vec![
+            (String::from("host"), String::from(self.host())),
+            (String::from("x-amz-content-sha256"), payload_hash.clone()),
+            (String::from("x-amz-date"), amz_date.clone()),
+        ];
+        for (name, value) in extra_headers {
+            headers.push((name.to_lowercase(), value.clone()));
+        }
+        headers.sort();
+
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+            .collect();
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<&str>>()
+            .join(";");
+        let canonical_request = format!(
+            "{}\n{}\n\n{}\n{}\n{}",
+            method, path, canonical_headers, signed_headers, payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date_stamp, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            sha256_hex(canonical_request.as_bytes())
+        );
+
+        let signing_key = [
+            date_stamp.as_str(),
+            self.region.as_str(),
+            "s3",
+            "aws4_request",
+        ]
+        .iter()
+        .fold(
+            format!("AWS4{}", self.secret_key).into_bytes(),
+            |key, part| hmac_sha256(&key, part.as_bytes()),
+        );
+        let signature = to_hex(&hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+
+        let mut request = ureq::request(method, &format!("{}{}", self.endpoint, path)).set(
+            "Authorization",
+            &format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                self.access_key, scope, signed_headers, signature
+            ),
+        );
+        for (name, value) in headers.iter().filter(|(name, _)| name != "host") {
+            request = request.set(name, value);
+        }
+        request
+    }
+
+    /// Returns the current time formatted as `YYYYMMDDTHHMMSSZ` and `YYYYMMDD`.
+    fn timestamps() -> (String, String) {
+        let dt = DateTime::from_timestamp(datetime::now());
+        let date_stamp = format!("{:04}{:02}{:02}", dt.year, dt.month, dt.day);
+        let amz_date = format!(
+            "{}T{:02}{:02}{:02}Z",
+            date_stamp, dt.hour, dt.minute, dt.second
+        );
+        (amz_date, date_stamp)
+    }
+}
+
+impl Storage for S3Storage {
+    fn read(&self) -> Result<Option<Vec<u8>>, Error> {
+        debug!(bucket = %self.bucket, key = %self.key, "downloading database from S3");
+        let response = match self.request("GET", &self.key, b"", &[]).call() {
+            Ok(r) => r,
+            Err(ureq::Error::Status(404, _)) => return Ok(None),
+            Err(err) => {
+                return Err(Error::new(
+                    ErrorCode::Network,
+                    format!("Couldn't download database from S3: {}", err),
+                ))
+            }
+        };
+        let mut data = Vec::new();
+        response
+            .into_reader()
+            .read_to_end(&mut data)
+            .map_err(|err| {
+                Error::new(
+                    ErrorCode::Network,
+                    format!("Couldn't download database from S3: {}", err),
+                )
+            })?;
+        Ok(Some(data))
+    }
+
+    fn write(&self, data: &[u8]) -> Result<(), Error> {
+        debug!(bucket = %self.bucket, key = %self.key, bytes = data.len(), "uploading database to S3");
+        let (version, _) = Self::timestamps();
+        let version_key = format!("{}{}/{}", self.key, VERSIONS_SUFFIX, version);
+        self.request("PUT", &version_key, data, &[])
+            .send_bytes(data)
+            .map_err(|err| {
+                Error::new(
+                    ErrorCode::Network,
+                    format!("Couldn't upload database to S3: {}", err),
+                )
+            })?;
+        let copy_source = self.object_path(&version_key);
+        self.request("PUT", &self.key, b"", &[("x-amz-copy-source", copy_source)])
+            .send_bytes(b"")
+            .map_err(|err| {
+                Error::new(
+                    ErrorCode::Network,
+                    format!("Couldn't update database in S3: {}", err),
+                )
+            })?;
+        Ok(())
+    }
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    Hash::Sha256.hex_digest(data)
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    Hash::Sha256.hmac(key, data)
+}
+
+fn uri_encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
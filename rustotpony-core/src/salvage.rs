@@ -0,0 +1,269 @@
+use tracing::{info, info_span};
+
+use crate::cipher::AesCbc;
+use crate::compression;
+use crate::crypto;
+use crate::decoy::{self, Slot};
+use crate::errors::{Error, ErrorCode};
+use crate::header::{self, CipherKind};
+use crate::{GenApp, JsonDatabase, BLOCK_SIZE, IV_SIZE};
+
+/// Result of recovering a damaged database.
+pub struct SalvageReport {
+    pub applications: Vec<GenApp>,
+    /// Descriptions of entries which were found but couldn't be recovered
+    pub damaged: Vec<String>,
+}
+
+impl JsonDatabase {
+    /// Recovers as many applications as possible from a database which can't
+    /// be opened normally, e.g. because the file was truncated or partially
+    /// overwritten. Nothing is written, the caller decides where to put the
+    /// recovered applications.
+    pub fn salvage(&self) -> Result<SalvageReport, Error> {
+        let _span = info_span!("salvage").entered();
+        let data = self
+            .storage
+            .read()?
+            .ok_or_else(|| Error::new(ErrorCode::NotFound, "There is no database to salvage"))?;
+        let (header, data) = header::split(&data)?;
+        // Authenticated ciphers reveal nothing of a damaged vault
+        if header
+            .as_ref()
+            .is_some_and(|header| header.cipher != CipherKind::Aes256Cbc)
+        {
+            return Err(Error::new(
+                ErrorCode::Unsupported,
+                "Only databases encrypted with AES-CBC can be salvaged",
+            ));
+        }
+        let compressed = header.as_ref().is_some_and(|header| header.compressed);
+        if header.is_some_and(|header| header.is_indexed()) {
+            return Err(Error::new(
+                ErrorCode::InvalidArgument,
+                "Indexed databases can't be salvaged, their applications are encrypted one by one",
+            ));
+        }
+        if data.len() < IV_SIZE + BLOCK_SIZE {
+            return Err(Error::new(
+                ErrorCode::Damaged,
+                "Database file is too short, there is nothing to salvage",
+            ));
+        }
+        let key = self.database_key();
+        let mut plain = decrypt_region(data, &key);
+        if compressed {
+            plain = compression::decompress_partial(&plain);
+        }
+        // A vault in the second half of the file is decrypted as a part of
+        // the whole file too, but a compressed one has to be inflated
+        // separately
+        for (slot, half) in decoy::slots(&AesCbc, data) {
+            if compressed && slot == Slot::Half(1) {
+                plain.extend(compression::decompress_partial(&decrypt_region(half, &key)));
+            }
+        }
+        let text = String::from_utf8_lossy(&plain);
+
+        let mut report = SalvageReport {
+            applications: Vec::new(),
+            damaged: Vec::new(),
+        };
+        // Applications are the objects with a name and a secret, whatever
+        // the order of their fields; history records have no secret
+        for (start, _) in text.match_indices('{') {
+            let fragment = &text[start..];
+            let (keys, end) = scan_object(fragment);
+            let has = |wanted: &str| keys.iter().any(|(key, _)| *key == wanted);
+            if !has("name") || !(has("secret") || has("secret_bytes")) {
+                continue;
+            }
+            let parsed = end
+                .ok_or_else(|| Error::new(ErrorCode::Damaged, "entry is truncated"))
+                .and_then(|end| {
+                    serde_json::from_str::<GenApp>(&fragment[..end])
+                        .map_err(|err| Error::new(ErrorCode::Damaged, err.to_string()))
+                })
+                .and_then(|app| match GenApp::base32_to_bytes(app.get_secret()) {
+                    Some(ref bytes) if bytes == &app.secret_bytes => Ok(app),
+                    _ => Err(Error::new(ErrorCode::Damaged, "secret is damaged")),
+                });
+            match parsed {
+                Ok(app) => report.applications.push(app),
+                Err(err) => {
+                    report
+                        .damaged
+                        .push(format!("{}: {}", fragment_name(fragment, &keys), err))
+                }
+            }
+        }
+        if report.applications.is_empty() && report.damaged.is_empty() {
+            return Err(Error::new(
+                ErrorCode::WrongPassword,
+                "No applications were found, probably the password is wrong",
+            ));
+        }
+        info!(
+            recovered = report.applications.len(),
+            damaged = report.damaged.len(),
+            "salvaged database"
+        );
+        Ok(report)
+    }
+}
+
+fn decrypt_region(data: &[u8], key: &[u8]) -> Vec<u8> {
+    // Ignore an incomplete trailing block and the padding, a damaged
+    // block in CBC mode only affects itself and the following block
+    let usable_length = IV_SIZE + (data.len() - IV_SIZE) / BLOCK_SIZE * BLOCK_SIZE;
+    crypto::decrypt_blocks(&data[IV_SIZE..usable_length], key, &data[..IV_SIZE]).unwrap_or_default()
+}
+
+/// Returns the keys of the JSON object at the beginning of the text, with
+/// the positions of their values, and the length of the object if it's
+/// complete.
+fn scan_object(text: &str) -> (Vec<(&str, usize)>, Option<usize>) {
+    let mut keys = Vec::new();
+    let mut depth = 0;
+    // Start of the string being read and the last string of the object
+    let mut string_start = None;
+    let mut last_string = None;
+    let mut escaped = false;
+    for (idx, c) in text.char_indices() {
+        if let Some(start) = string_start {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => {
+                    string_start = None;
+                    if depth == 1 {
+                        last_string = Some(&text[start..idx]);
+                    }
+                }
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => string_start = Some(idx + 1),
+            ':' if depth == 1 => {
+                if let Some(key) = last_string.take() {
+                    keys.push((key, idx + 1));
+                }
+            }
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return (keys, Some(idx + 1));
+                }
+            }
+            _ => {}
+        }
+    }
+    (keys, None)
+}
+
+fn fragment_name(fragment: &str, keys: &[(&str, usize)]) -> String {
+    let rest = match keys.iter().find(|(key, _)| *key == "name") {
+        Some((_, position)) => &fragment[*position..],
+        None => return String::from("entry with unreadable name"),
+    };
+    let mut stream = serde_json::Deserializer::from_str(rest).into_iter::<String>();
+    match stream.next() {
+        Some(Ok(name)) => format!("'{}'", name),
+        _ => String::from("entry with unreadable name"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cipher::Cipher;
+    use crate::header::Kdf;
+    use crate::import::from_otp_field;
+    use crate::{DatabaseContent, JsonDatabaseSchema, MemoryStorage, RusTOTPony};
+
+    // JSON of a database written before headers, which was encrypted with
+    // AES-CBC alone
+    fn legacy_json() -> String {
+        let mut content = DatabaseContent::default();
+        for name in ["GitHub", "GitLab"] {
+            let app = from_otp_field("JBSWY3DPEHPK3PXP", name, "octocat").unwrap();
+            content.applications.insert(String::from(name), app);
+        }
+        let schema = JsonDatabaseSchema {
+            version: crate::DATABASE_VERSION,
+            content,
+        };
+        serde_json::to_string(&schema).unwrap()
+    }
+
+    fn database(storage: &MemoryStorage, password: &'static str) -> JsonDatabase {
+        JsonDatabase::builder()
+            .storage(Box::new(storage.clone()))
+            .secret(move || String::from(password))
+            .kdf(Kdf::scrypt(10).unwrap())
+            .build()
+            .unwrap()
+    }
+
+    fn seal(json: &str) -> Vec<u8> {
+        AesCbc.seal(
+            json.as_bytes(),
+            &JsonDatabase::form_secret_key("correct horse"),
+        )
+    }
+
+    #[test]
+    fn intact_database_is_salvaged_whole() {
+        let storage = MemoryStorage::new(seal(&legacy_json()));
+        let report = database(&storage, "correct horse").salvage().unwrap();
+        let mut names: Vec<&str> = report.applications.iter().map(GenApp::get_name).collect();
+        names.sort();
+        assert_eq!(names, ["GitHub", "GitLab"]);
+        assert!(report.damaged.is_empty());
+    }
+
+    #[test]
+    fn truncated_application_is_reported_damaged() {
+        let json = legacy_json();
+        // Cut the file inside the last application, after its secret
+        let cut = json.rfind("secret_bytes").unwrap();
+        let sealed = seal(&json);
+        let storage =
+            MemoryStorage::new(sealed[..IV_SIZE + cut / BLOCK_SIZE * BLOCK_SIZE].to_vec());
+        let report = database(&storage, "correct horse").salvage().unwrap();
+        assert_eq!(report.applications.len(), 1);
+        assert_eq!(report.damaged.len(), 1);
+        let recovered = report.applications[0].get_name();
+        let lost = if recovered == "GitHub" {
+            "GitLab"
+        } else {
+            "GitHub"
+        };
+        assert_eq!(report.damaged[0], format!("'{}': entry is truncated", lost));
+    }
+
+    #[test]
+    fn wrong_password_salvages_nothing() {
+        let storage = MemoryStorage::new(seal(&legacy_json()));
+        let err = database(&storage, "battery staple")
+            .salvage()
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), ErrorCode::WrongPassword);
+    }
+
+    #[test]
+    fn authenticated_database_isnt_salvaged() {
+        let storage = MemoryStorage::default();
+        let mut totpony = RusTOTPony::new(database(&storage, "correct horse"));
+        totpony
+            .create_application("GitHub", "octocat", "JBSWY3DPEHPK3PXP")
+            .unwrap();
+        totpony.flush().unwrap();
+        let err = database(&storage, "correct horse").salvage().err().unwrap();
+        assert_eq!(err.code(), ErrorCode::Unsupported);
+    }
+}
@@ -0,0 +1,493 @@
+use std::io::{Read, Write};
+
+use crate::errors::{Error, ErrorCode};
+
+// Version 3 of the protocol, the one spoken by OpenSSH
+// (draft-ietf-secsh-filexfer-02)
+const VERSION: u32 = 3;
+
+const FXP_INIT: u8 = 1;
+const FXP_VERSION: u8 = 2;
+const FXP_OPEN: u8 = 3;
+const FXP_CLOSE: u8 = 4;
+const FXP_READ: u8 = 5;
+const FXP_WRITE: u8 = 6;
+const FXP_REMOVE: u8 = 13;
+const FXP_MKDIR: u8 = 14;
+const FXP_STAT: u8 = 17;
+const FXP_RENAME: u8 = 18;
+const FXP_EXTENDED: u8 = 200;
+const FXP_STATUS: u8 = 101;
+const FXP_HANDLE: u8 = 102;
+const FXP_DATA: u8 = 103;
+const FXP_ATTRS: u8 = 105;
+
+const FXF_READ: u32 = 0x01;
+const FXF_WRITE: u32 = 0x02;
+const FXF_CREAT: u32 = 0x08;
+const FXF_TRUNC: u32 = 0x10;
+const FXF_EXCL: u32 = 0x20;
+
+const ATTR_PERMISSIONS: u32 = 0x04;
+
+const FX_OK: u32 = 0;
+const FX_EOF: u32 = 1;
+const FX_NO_SUCH_FILE: u32 = 2;
+
+// Replaces the target like rename(2), plain RENAME fails if it exists
+const POSIX_RENAME: &str = "posix-rename@openssh.com";
+// Servers are only required to handle packets up to 34000 bytes
+const CHUNK: usize = 32 * 1024;
+// Anything longer isn't a response to our requests
+const MAX_PACKET: usize = 256 * 1024;
+
+/// Client side of an SFTP session over a pair of streams, e.g. stdout and
+/// stdin of `ssh -s host sftp`. Requests are sent one at a time.
+pub(crate) struct SftpSession<R: Read, W: Write> {
+    reader: R,
+    writer: W,
+    next_id: u32,
+    posix_rename: bool,
+}
+
+enum Response {
+    Status(u32, String),
+    Handle(Vec<u8>),
+    Data(Vec<u8>),
+    Attrs,
+}
+
+impl<R: Read, W: Write> SftpSession<R, W> {
+    /// Negotiates the protocol version with the server.
+    pub(crate) fn start(reader: R, writer: W) -> Result<SftpSession<R, W>, Error> {
+        let mut session = SftpSession {
+            reader,
+            writer,
+            next_id: 1,
+            posix_rename: false,
+        };
+        let mut init = vec![FXP_INIT];
+        put_u32(&mut init, VERSION);
+        session.send(&init)?;
+        let packet = session.receive()?;
+        let mut reader = PacketReader::new(&packet);
+        if reader.u8()? != FXP_VERSION || reader.u32()? < VERSION {
+            return Err(protocol_error("the server doesn't speak SFTP version 3"));
+        }
+        while !reader.is_empty() {
+            let name = reader.string()?;
+            reader.string()?;
+            if name == POSIX_RENAME.as_bytes() {
+                session.posix_rename = true;
+            }
+        }
+        Ok(session)
+    }
+
+    /// Returns the content of the file, `None` if there is no such file.
+    pub(crate) fn read_file(&mut self, path: &str) -> Result<Option<Vec<u8>>, Error> {
+        let handle = match self.open(path, FXF_READ, None)? {
+            Some(handle) => handle,
+            None => return Ok(None),
+        };
+        let mut content = Vec::new();
+        loop {
+            let mut request = self.request(FXP_READ);
+            put_bytes(&mut request, &handle);
+            put_u64(&mut request, content.len() as u64);
+            put_u32(&mut request, CHUNK as u32);
+            match self.call(request)? {
+                Response::Data(data) => content.extend_from_slice(&data),
+                Response::Status(FX_EOF, _) => break,
+                response => return Err(failure(path, response)),
+            }
+        }
+        self.close(path, &handle)?;
+        Ok(Some(content))
+    }
+
+    /// Replaces the file with `data`. It's written to `<path>.tmp` first,
+    /// so an interrupted transfer doesn't leave a truncated file behind.
+    /// Missing folders are created accessible only by the owner, the same
+    /// as the file.
+    pub(crate) fn write_file(&mut self, path: &str, data: &[u8]) -> Result<(), Error> {
+        let mut folder = String::new();
+        let components: Vec<&str> = path.split('/').collect();
+        for (idx, component) in components[..components.len() - 1].iter().enumerate() {
+            if idx > 0 {
+                folder.push('/');
+            }
+            folder.push_str(component);
+            if !component.is_empty() && !self.exists(&folder)? {
+                self.mkdir(&folder)?;
+            }
+        }
+        let temporary = format!("{}.tmp", path);
+        // A temporary file left by another write would keep its permissions
+        self.remove(&temporary)?;
+        let handle = self
+            .open(
+                &temporary,
+                FXF_WRITE | FXF_CREAT | FXF_TRUNC | FXF_EXCL,
+                Some(0o600),
+            )?
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorCode::NotFound,
+                    format!("Couldn't create {}", temporary),
+                )
+            })?;
+        for (idx, chunk) in data.chunks(CHUNK).enumerate() {
+            let mut request = self.request(FXP_WRITE);
+            put_bytes(&mut request, &handle);
+            put_u64(&mut request, (idx * CHUNK) as u64);
+            put_bytes(&mut request, chunk);
+            self.expect_ok(&temporary, request)?;
+        }
+        self.close(&temporary, &handle)?;
+        self.rename(&temporary, path)
+    }
+
+    fn open(
+        &mut self,
+        path: &str,
+        flags: u32,
+        mode: Option<u32>,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let mut request = self.request(FXP_OPEN);
+        put_bytes(&mut request, path.as_bytes());
+        put_u32(&mut request, flags);
+        put_attrs(&mut request, mode);
+        match self.call(request)? {
+            Response::Handle(handle) => Ok(Some(handle)),
+            Response::Status(FX_NO_SUCH_FILE, _) => Ok(None),
+            response => Err(failure(path, response)),
+        }
+    }
+
+    fn close(&mut self, path: &str, handle: &[u8]) -> Result<(), Error> {
+        let mut request = self.request(FXP_CLOSE);
+        put_bytes(&mut request, handle);
+        self.expect_ok(path, request)
+    }
+
+    fn exists(&mut self, path: &str) -> Result<bool, Error> {
+        let mut request = self.request(FXP_STAT);
+        put_bytes(&mut request, path.as_bytes());
+        match self.call(request)? {
+            Response::Attrs => Ok(true),
+            Response::Status(FX_NO_SUCH_FILE, _) => Ok(false),
+            response => Err(failure(path, response)),
+        }
+    }
+
+    fn remove(&mut self, path: &str) -> Result<(), Error> {
+        let mut request = self.request(FXP_REMOVE);
+        put_bytes(&mut request, path.as_bytes());
+        match self.call(request)? {
+            Response::Status(FX_OK, _) | Response::Status(FX_NO_SUCH_FILE, _) => Ok(()),
+            response => Err(failure(path, response)),
+        }
+    }
+
+    fn mkdir(&mut self, path: &str) -> Result<(), Error> {
+        let mut request = self.request(FXP_MKDIR);
+        put_bytes(&mut request, path.as_bytes());
+        put_attrs(&mut request, Some(0o700));
+        self.expect_ok(path, request)
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> Result<(), Error> {
+        let mut request = if self.posix_rename {
+            let mut request = self.request(FXP_EXTENDED);
+            put_bytes(&mut request, POSIX_RENAME.as_bytes());
+            request
+        } else {
+            self.remove(to)?;
+            self.request(FXP_RENAME)
+        };
+        put_bytes(&mut request, from.as_bytes());
+        put_bytes(&mut request, to.as_bytes());
+        self.expect_ok(to, request)
+    }
+
+    fn expect_ok(&mut self, path: &str, request: Vec<u8>) -> Result<(), Error> {
+        match self.call(request)? {
+            Response::Status(FX_OK, _) => Ok(()),
+            response => Err(failure(path, response)),
+        }
+    }
+
+    fn request(&mut self, kind: u8) -> Vec<u8> {
+        let mut request = vec![kind];
+        put_u32(&mut request, self.next_id);
+        request
+    }
+
+    fn call(&mut self, request: Vec<u8>) -> Result<Response, Error> {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.send(&request)?;
+        let packet = self.receive()?;
+        let mut reader = PacketReader::new(&packet);
+        let kind = reader.u8()?;
+        if reader.u32()? != id {
+            return Err(protocol_error("the response doesn't match the request"));
+        }
+        match kind {
+            FXP_STATUS => {
+                let code = reader.u32()?;
+                // Old servers may omit the message
+                let message = reader.string().unwrap_or_default();
+                Ok(Response::Status(
+                    code,
+                    String::from_utf8_lossy(message).into_owned(),
+                ))
+            }
+            FXP_HANDLE => Ok(Response::Handle(reader.string()?.to_vec())),
+            FXP_DATA => Ok(Response::Data(reader.string()?.to_vec())),
+            FXP_ATTRS => Ok(Response::Attrs),
+            _ => Err(protocol_error("unexpected response")),
+        }
+    }
+
+    fn send(&mut self, packet: &[u8]) -> Result<(), Error> {
+        let mut framed = Vec::with_capacity(packet.len() + 4);
+        put_u32(&mut framed, packet.len() as u32);
+        framed.extend_from_slice(packet);
+        self.writer
+            .write_all(&framed)
+            .and_then(|_| self.writer.flush())
+            .map_err(transport_error)
+    }
+
+    fn receive(&mut self) -> Result<Vec<u8>, Error> {
+        let mut length = [0; 4];
+        self.reader
+            .read_exact(&mut length)
+            .map_err(transport_error)?;
+        let length = u32::from_be_bytes(length) as usize;
+        if length == 0 || length > MAX_PACKET {
+            return Err(protocol_error("malformed packet"));
+        }
+        let mut packet = vec![0; length];
+        self.reader
+            .read_exact(&mut packet)
+            .map_err(transport_error)?;
+        Ok(packet)
+    }
+}
+
+struct PacketReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> PacketReader<'a> {
+    fn new(data: &'a [u8]) -> PacketReader<'a> {
+        PacketReader { data }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.data.len() < len {
+            return Err(protocol_error("truncated packet"));
+        }
+        let (head, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn string(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+}
+
+fn put_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_u64(buf: &mut Vec<u8>, value: u64) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_bytes(buf: &mut Vec<u8>, value: &[u8]) {
+    put_u32(buf, value.len() as u32);
+    buf.extend_from_slice(value);
+}
+
+fn put_attrs(buf: &mut Vec<u8>, mode: Option<u32>) {
+    match mode {
+        Some(mode) => {
+            put_u32(buf, ATTR_PERMISSIONS);
+            put_u32(buf, mode);
+        }
+        None => put_u32(buf, 0),
+    }
+}
+
+fn failure(path: &str, response: Response) -> Error {
+    match response {
+        Response::Status(FX_NO_SUCH_FILE, _) => Error::new(
+            ErrorCode::NotFound,
+            format!("{} doesn't exist on the server", path),
+        ),
+        Response::Status(_, message) if !message.is_empty() => Error::new(
+            ErrorCode::Io,
+            format!("SFTP error on {}: {}", path, message),
+        ),
+        _ => Error::new(ErrorCode::Io, format!("SFTP error on {}", path)),
+    }
+}
+
+fn transport_error(err: std::io::Error) -> Error {
+    Error::new(
+        ErrorCode::Network,
+        format!("SFTP connection failed: {}", err),
+    )
+}
+
+fn protocol_error(reason: &str) -> Error {
+    Error::new(
+        ErrorCode::Network,
+        format!("SFTP connection failed: {}", reason),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // Framed responses of a scripted server
+    fn script(packets: &[Vec<u8>]) -> Cursor<Vec<u8>> {
+        let mut data = Vec::new();
+        for packet in packets {
+            put_u32(&mut data, packet.len() as u32);
+            data.extend_from_slice(packet);
+        }
+        Cursor::new(data)
+    }
+
+    fn version(extensions: &[&str]) -> Vec<u8> {
+        let mut packet = vec![FXP_VERSION];
+        put_u32(&mut packet, VERSION);
+        for name in extensions {
+            put_bytes(&mut packet, name.as_bytes());
+            put_bytes(&mut packet, b"1");
+        }
+        packet
+    }
+
+    fn response(kind: u8, id: u32, body: &[u8]) -> Vec<u8> {
+        let mut packet = vec![kind];
+        put_u32(&mut packet, id);
+        packet.extend_from_slice(body);
+        packet
+    }
+
+    fn status(id: u32, code: u32) -> Vec<u8> {
+        let mut body = Vec::new();
+        put_u32(&mut body, code);
+        put_bytes(&mut body, b"");
+        put_bytes(&mut body, b"");
+        response(FXP_STATUS, id, &body)
+    }
+
+    fn string(value: &[u8]) -> Vec<u8> {
+        let mut body = Vec::new();
+        put_bytes(&mut body, value);
+        body
+    }
+
+    // Kinds and first string arguments of the requests sent by the client
+    fn requests(sent: &[u8]) -> Vec<(u8, String)> {
+        let mut reader = PacketReader::new(sent);
+        let mut requests = Vec::new();
+        while !reader.is_empty() {
+            let packet = reader.string().unwrap();
+            let mut packet = PacketReader::new(packet);
+            let kind = packet.u8().unwrap();
+            if kind == FXP_INIT {
+                continue;
+            }
+            packet.u32().unwrap();
+            let argument = packet.string().unwrap();
+            requests.push((kind, String::from_utf8_lossy(argument).into_owned()));
+        }
+        requests
+    }
+
+    #[test]
+    fn reads_file() {
+        let server = script(&[
+            version(&[]),
+            response(FXP_HANDLE, 1, &string(b"h")),
+            response(FXP_DATA, 2, &string(b"encrypted")),
+            status(3, FX_EOF),
+            status(4, FX_OK),
+        ]);
+        let mut sent = Vec::new();
+        let mut session = SftpSession::start(server, &mut sent).unwrap();
+        let content = session.read_file("db.json").unwrap();
+        assert_eq!(content.as_deref(), Some(&b"encrypted"[..]));
+        let kinds: Vec<u8> = requests(&sent).iter().map(|(kind, _)| *kind).collect();
+        assert_eq!(kinds, [FXP_OPEN, FXP_READ, FXP_READ, FXP_CLOSE]);
+    }
+
+    #[test]
+    fn missing_file_is_none() {
+        let server = script(&[version(&[]), status(1, FX_NO_SUCH_FILE)]);
+        let mut session = SftpSession::start(server, Vec::new()).unwrap();
+        assert!(session.read_file("db.json").unwrap().is_none());
+    }
+
+    #[test]
+    fn writes_through_temporary_file() {
+        let server = script(&[
+            version(&[POSIX_RENAME]),
+            status(1, FX_NO_SUCH_FILE),
+            status(2, FX_OK),
+            status(3, FX_NO_SUCH_FILE),
+            response(FXP_HANDLE, 4, &string(b"h")),
+            status(5, FX_OK),
+            status(6, FX_OK),
+            status(7, FX_OK),
+        ]);
+        let mut sent = Vec::new();
+        let mut session = SftpSession::start(server, &mut sent).unwrap();
+        session.write_file("vault/db.json", b"encrypted").unwrap();
+        assert_eq!(
+            requests(&sent),
+            [
+                (FXP_STAT, String::from("vault")),
+                (FXP_MKDIR, String::from("vault")),
+                (FXP_REMOVE, String::from("vault/db.json.tmp")),
+                (FXP_OPEN, String::from("vault/db.json.tmp")),
+                (FXP_WRITE, String::from("h")),
+                (FXP_CLOSE, String::from("h")),
+                (FXP_EXTENDED, String::from(POSIX_RENAME)),
+            ]
+        );
+    }
+
+    #[test]
+    fn mismatched_response_is_rejected() {
+        let server = script(&[version(&[]), status(7, FX_OK)]);
+        let mut session = SftpSession::start(server, Vec::new()).unwrap();
+        let err = session.read_file("db.json").err().unwrap();
+        assert_eq!(err.code(), ErrorCode::Network);
+    }
+}
@@ -0,0 +1,306 @@
+use crate::crypto::{self, Hash};
+use crate::errors::{Error, ErrorCode};
+use crate::header::Kdf;
+use crate::random;
+use crate::{JsonDatabase, KEY_SIZE};
+
+const PREFIX: &str = "RTPK1:";
+// Split ID, threshold and index of the share precede the key share, a part
+// of the checksum follows it
+const SPLIT_ID_SIZE: usize = 4;
+const CHECKSUM_SIZE: usize = 4;
+const SHARE_SIZE: usize = SPLIT_ID_SIZE + 2 + KEY_SIZE + CHECKSUM_SIZE;
+
+impl JsonDatabase {
+    /// Splits the key of the database into `shares` texts, any `threshold`
+    /// of which recover it with `recover_key`, while fewer tell nothing
+    /// about it (Shamir's secret sharing). Shares are meant to be given to
+    /// trusted people, so a forgotten password doesn't lose the database.
+    /// They stop working once the password is changed.
+    pub fn split_key(&self, shares: u8, threshold: u8) -> Result<Vec<String>, Error> {
+        if threshold < 2 || threshold > shares {
+            return Err(Error::new(
+                ErrorCode::InvalidArgument,
+                "Threshold must be at least 2 and not more than the number of shares",
+            ));
+        }
+        let data = self
+            .read_vaults()?
+            .ok_or_else(|| Error::new(ErrorCode::NotFound, "There is no database yet"))?;
+        // The password is checked, shares of a wrong key are useless
+        let key = self.database_key();
+        self.find_vault(&data, &key)?;
+        Ok(split(&key, shares, threshold))
+    }
+
+    /// Recovers the key of the database from shares made by `split_key` and
+    /// encrypts the database with a new password. Fails if there are fewer
+    /// shares than the threshold or they are of another database.
+    pub fn recover_key(&self, shares: &[String], new_password: &str) -> Result<(), Error> {
+        let key = combine(&decode_required(shares)?);
+        self.new_password_kdf(&key)
+            .and_then(|kdf| {
+                self.replace_key(&key, &Self::form_password_key(&kdf, new_password), kdf)
+            })
+            .map_err(not_recovered)
+    }
+
+    /// Key derivation `recover_key` uses for the new password, e.g. to tell
+    /// how fast it could be guessed.
+    pub fn recovery_kdf(&self, shares: &[String]) -> Result<Kdf, Error> {
+        let key = combine(&decode_required(shares)?);
+        self.new_password_kdf(&key).map_err(not_recovered)
+    }
+}
+
+fn not_recovered(err: Error) -> Error {
+    if err.code() == ErrorCode::WrongPassword {
+        Error::new(
+            ErrorCode::WrongPassword,
+            "Shares don't recover the key of this database",
+        )
+    } else {
+        err
+    }
+}
+
+struct Share {
+    split_id: [u8; SPLIT_ID_SIZE],
+    threshold: u8,
+    index: u8,
+    value: [u8; KEY_SIZE],
+}
+
+fn split(key: &[u8], shares: u8, threshold: u8) -> Vec<String> {
+    let mut split_id = [0u8; SPLIT_ID_SIZE];
+    random::fill(&mut split_id);
+    let mut values = vec![[0u8; KEY_SIZE]; shares as usize];
+    for (byte_idx, secret) in key.iter().enumerate() {
+        // Random polynomial of degree threshold - 1 with the key byte as
+        // its constant term
+        let mut coefficients = vec![0u8; threshold as usize];
+        random::fill(&mut coefficients);
+        coefficients[0] = *secret;
+        for (share_idx, value) in values.iter_mut().enumerate() {
+            value[byte_idx] = evaluate(&coefficients, share_idx as u8 + 1);
+        }
+    }
+    values
+        .iter()
+        .enumerate()
+        .map(|(idx, value)| encode(&split_id, threshold, idx as u8 + 1, value))
+        .collect()
+}
+
+// Decodes the shares and keeps as many distinct ones as the threshold needs
+fn decode_required(shares: &[String]) -> Result<Vec<Share>, Error> {
+    let mut decoded: Vec<Share> = Vec::new();
+    for share in shares {
+        let share = decode(share)?;
+        if decoded.iter().any(|other| other.index == share.index) {
+            continue;
+        }
+        if let Some(other) = decoded.first() {
+            if other.split_id != share.split_id {
+                return Err(Error::new(
+                    ErrorCode::InvalidArgument,
+                    "Shares come from different splits of the key",
+                ));
+            }
+            if other.threshold != share.threshold {
+                return Err(Error::new(
+                    ErrorCode::InvalidArgument,
+                    "Shares disagree on how many of them are required",
+                ));
+            }
+        }
+        decoded.push(share);
+    }
+    let threshold = match decoded.first() {
+        Some(share) => share.threshold as usize,
+        None => {
+            return Err(Error::new(
+                ErrorCode::InvalidArgument,
+                "No shares were given",
+            ))
+        }
+    };
+    if decoded.len() < threshold {
+        return Err(Error::new(
+            ErrorCode::InvalidArgument,
+            format!(
+                "{} of {} required shares were given",
+                decoded.len(),
+                threshold
+            ),
+        ));
+    }
+    decoded.truncate(threshold);
+    Ok(decoded)
+}
+
+fn combine(shares: &[Share]) -> [u8; KEY_SIZE] {
+    let mut key = [0u8; KEY_SIZE];
+    for (byte_idx, secret) in key.iter_mut().enumerate() {
+        *secret = interpolate(shares, byte_idx);
+    }
+    key
+}
+
+fn encode(split_id: &[u8], threshold: u8, index: u8, value: &[u8]) -> String {
+    let data = [split_id, &[threshold, index], value].concat();
+    let checksum = Hash::Sha256.digest(&data);
+    let data = [&data[..], &checksum[..CHECKSUM_SIZE]].concat();
+    format!(
+        "{}{}",
+        PREFIX,
+        base32::encode(base32::Alphabet::RFC4648 { padding: false }, &data)
+    )
+}
+
+fn decode(share: &str) -> Result<Share, Error> {
+    let invalid = || {
+        Error::new(
+            ErrorCode::InvalidArgument,
+            format!("'{}' isn't a share of a key", share.trim()),
+        )
+    };
+    let encoded = share.trim().strip_prefix(PREFIX).ok_or_else(invalid)?;
+    let data = base32::decode(base32::Alphabet::RFC4648 { padding: false }, encoded)
+        .filter(|data| data.len() == SHARE_SIZE)
+        .ok_or_else(invalid)?;
+    let (data, checksum) = data.split_at(SHARE_SIZE - CHECKSUM_SIZE);
+    if !crypto::constant_time_eq(&Hash::Sha256.digest(data)[..CHECKSUM_SIZE], checksum) {
+        return Err(Error::new(
+            ErrorCode::Damaged,
+            format!("Share '{}' is mistyped or damaged", share.trim()),
+        ));
+    }
+    let mut split_id = [0u8; SPLIT_ID_SIZE];
+    split_id.copy_from_slice(&data[..SPLIT_ID_SIZE]);
+    let mut value = [0u8; KEY_SIZE];
+    value.copy_from_slice(&data[SPLIT_ID_SIZE + 2..]);
+    Ok(Share {
+        split_id,
+        threshold: data[SPLIT_ID_SIZE],
+        index: data[SPLIT_ID_SIZE + 1],
+        value,
+    })
+}
+
+// Arithmetic of GF(256) with the polynomial of AES
+
+fn mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80 != 0;
+        a <<= 1;
+        if carry {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+// a^254 is the inverse of a, as a^255 = 1
+fn inverse(a: u8) -> u8 {
+    let mut result = 1;
+    for _ in 0..254 {
+        result = mul(result, a);
+    }
+    result
+}
+
+fn evaluate(coefficients: &[u8], x: u8) -> u8 {
+    coefficients
+        .iter()
+        .rev()
+        .fold(0, |value, coefficient| mul(value, x) ^ coefficient)
+}
+
+// Lagrange interpolation at x = 0
+fn interpolate(shares: &[Share], byte_idx: usize) -> u8 {
+    let mut secret = 0;
+    for share in shares {
+        let mut basis = 1;
+        for other in shares.iter().filter(|other| other.index != share.index) {
+            basis = mul(basis, mul(other.index, inverse(other.index ^ share.index)));
+        }
+        secret ^= mul(share.value[byte_idx], basis);
+    }
+    secret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // All subsets of `items` with `size` elements
+    fn subsets(items: &[String], size: usize) -> Vec<Vec<String>> {
+        if size == 0 {
+            return vec![Vec::new()];
+        }
+        let mut result = Vec::new();
+        for (idx, item) in items.iter().enumerate() {
+            for mut rest in subsets(&items[idx + 1..], size - 1) {
+                rest.insert(0, item.clone());
+                result.push(rest);
+            }
+        }
+        result
+    }
+
+    fn test_key() -> [u8; KEY_SIZE] {
+        let mut key = [0u8; KEY_SIZE];
+        random::fill(&mut key);
+        key
+    }
+
+    #[test]
+    fn any_threshold_of_shares_recovers_key() {
+        for shares in 2..=5u8 {
+            for threshold in 2..=shares {
+                let key = test_key();
+                let split = split(&key, shares, threshold);
+                for size in threshold as usize..=shares as usize {
+                    for subset in subsets(&split, size) {
+                        let decoded = decode_required(&subset).unwrap();
+                        assert_eq!(combine(&decoded), key, "{} of {}", threshold, shares);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fewer_shares_than_threshold_fail() {
+        for shares in 2..=5u8 {
+            for threshold in 2..=shares {
+                let key = test_key();
+                let split = split(&key, shares, threshold);
+                for subset in subsets(&split, threshold as usize - 1) {
+                    let err = decode_required(&subset).err().unwrap();
+                    assert_eq!(err.code(), ErrorCode::InvalidArgument);
+                    // Interpolating them anyway doesn't give the key either
+                    let decoded: Vec<Share> =
+                        subset.iter().map(|share| decode(share).unwrap()).collect();
+                    assert_ne!(combine(&decoded), key, "{} of {}", threshold, shares);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn shares_with_another_threshold_are_rejected() {
+        let key = test_key();
+        let mut shares = split(&key, 3, 2);
+        let share = decode(&shares[1]).unwrap();
+        shares[1] = encode(&share.split_id, 3, share.index, &share.value);
+        let err = decode_required(&shares).err().unwrap();
+        assert_eq!(err.code(), ErrorCode::InvalidArgument);
+    }
+}
@@ -0,0 +1,44 @@
+use std::sync::{Arc, RwLock};
+
+use crate::errors::Error;
+use crate::{Database, RusTOTPony};
+
+/// Handle to an opened database which can be cloned and shared between
+/// threads, e.g. by a UI thread and a thread refreshing codes.
+///
+/// Any number of readers can access the applications at once, changes
+/// lock out everybody else until they are done.
+pub struct SharedRusTOTPony<DB: Database> {
+    inner: Arc<RwLock<RusTOTPony<DB>>>,
+}
+
+impl<DB: Database> SharedRusTOTPony<DB> {
+    pub fn new(app: RusTOTPony<DB>) -> SharedRusTOTPony<DB> {
+        SharedRusTOTPony {
+            inner: Arc::new(RwLock::new(app)),
+        }
+    }
+
+    /// Runs the function with shared access to the applications.
+    pub fn read<R, F: FnOnce(&RusTOTPony<DB>) -> R>(&self, f: F) -> R {
+        f(&self.inner.read().expect("Database lock is poisoned"))
+    }
+
+    /// Runs the function with exclusive access, e.g. to change applications
+    /// and flush them.
+    pub fn write<R, F: FnOnce(&mut RusTOTPony<DB>) -> R>(&self, f: F) -> R {
+        f(&mut self.inner.write().expect("Database lock is poisoned"))
+    }
+
+    pub fn flush(&self) -> Result<(), Error> {
+        self.write(|app| app.flush())
+    }
+}
+
+impl<DB: Database> Clone for SharedRusTOTPony<DB> {
+    fn clone(&self) -> SharedRusTOTPony<DB> {
+        SharedRusTOTPony {
+            inner: self.inner.clone(),
+        }
+    }
+}
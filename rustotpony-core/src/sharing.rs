@@ -0,0 +1,184 @@
+use crate::cipher::AesCbc;
+use crate::errors::{Error, ErrorCode};
+use crate::kdf;
+use crate::{Database, GenApp, JsonDatabase, RusTOTPony};
+
+const PREFIX: &str = "RTPS2:";
+// Shares of older versions, encrypted with AES-CBC and a SHA-256 of the
+// passphrase
+const LEGACY_PREFIX: &str = "RTPS1:";
+
+impl GenApp {
+    /// Decrypts an application shared with `RusTOTPony::share_application`.
+    pub fn from_share(share: &str, passphrase: &str) -> Result<GenApp, Error> {
+        let share = share.trim();
+        let (encoded, legacy) = match (
+            share.strip_prefix(PREFIX),
+            share.strip_prefix(LEGACY_PREFIX),
+        ) {
+            (Some(encoded), _) => (encoded, false),
+            (None, Some(encoded)) => (encoded, true),
+            (None, None) => {
+                return Err(Error::new(
+                    ErrorCode::InvalidArgument,
+                    "It isn't a shared RusTOTPony application",
+                ))
+            }
+        };
+        let data = base32::decode(base32::Alphabet::RFC4648 { padding: false }, encoded)
+            .ok_or_else(|| Error::new(ErrorCode::Damaged, "Shared application is damaged"))?;
+        let json = if legacy {
+            JsonDatabase::decrypt_data(&AesCbc, &data, &JsonDatabase::form_secret_key(passphrase))
+                .ok()
+        } else {
+            kdf::open_with_passphrase(&data, passphrase)
+                .ok()
+                .and_then(|json| String::from_utf8(json).ok())
+        };
+        let json = json.ok_or_else(|| {
+            Error::new(
+                ErrorCode::WrongPassword,
+                "Wrong passphrase or damaged shared application",
+            )
+        })?;
+        // Formats are checked while parsing
+        let app: GenApp = serde_json::from_str(&json).map_err(|err| {
+            Error::new(
+                ErrorCode::Damaged,
+                format!("Shared application is damaged: {}", err),
+            )
+        })?;
+        app.check_kind().map_err(|err| {
+            Error::new(
+                ErrorCode::Damaged,
+                format!("Shared application is damaged: {}", err),
+            )
+        })?;
+        Ok(app)
+    }
+}
+
+impl<DB: Database> RusTOTPony<DB> {
+    /// Encrypts a single application with a passphrase agreed with the
+    /// recipient and returns it as a short text, which fits a QR code and
+    /// uses only characters of its alphanumeric mode. The key is derived
+    /// from the passphrase with scrypt and the share is authenticated, so
+    /// it can't be changed unnoticed. Nothing else from the database is
+    /// included. The disclosure is recorded in the history, `passphrase`
+    /// is required for protected applications.
+    pub fn share_application(
+        &mut self,
+        name: &str,
+        passphrase: Option<&str>,
+        share_passphrase: &str,
+    ) -> Result<String, Error> {
+        if share_passphrase.is_empty() {
+            return Err(Error::new(
+                ErrorCode::InvalidArgument,
+                "Passphrase of the share can't be empty",
+            ));
+        }
+        let mut app = self.disclose(name, passphrase, Some("share"))?;
+        // Aliases and tags are the sender's own, recovery codes aren't
+        // needed for codes, and the identity and past versions belong to
+        // the sender's database
+        app.aliases.clear();
+        app.tags.clear();
+        app.recovery_codes.clear();
+        app.previous.clear();
+        app.conflict_of = None;
+        app.id.clear();
+        let json = serde_json::to_string(&app).expect("Couldn't serialize application");
+        let data = kdf::seal_with_passphrase(json.as_bytes(), share_passphrase);
+        Ok(format!(
+            "{}{}",
+            PREFIX,
+            base32::encode(base32::Alphabet::RFC4648 { padding: false }, &data)
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DatabaseContent, STEAM_ALPHABET};
+
+    struct Memory;
+
+    impl Database for Memory {
+        fn get_content(&self) -> Result<DatabaseContent, Error> {
+            Ok(DatabaseContent::default())
+        }
+
+        fn save_content(&self, _content: &DatabaseContent) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    fn shared(format: Option<crate::CodeFormat>) -> String {
+        let mut totpony = RusTOTPony::with_content(Memory, DatabaseContent::default());
+        totpony
+            .create_application("GitHub", "octocat", "JBSWY3DPEHPK3PXP")
+            .unwrap();
+        if let Some(format) = format {
+            totpony.set_code_format("GitHub", format).unwrap();
+        }
+        totpony
+            .share_application("GitHub", None, "correct horse")
+            .unwrap()
+    }
+
+    // Seals JSON the way `share_application` does
+    fn forged(json: &serde_json::Value) -> String {
+        let data = kdf::seal_with_passphrase(json.to_string().as_bytes(), "correct horse");
+        format!(
+            "{}{}",
+            PREFIX,
+            base32::encode(base32::Alphabet::RFC4648 { padding: false }, &data)
+        )
+    }
+
+    fn app_json() -> serde_json::Value {
+        let app = GenApp::from_share(&shared(None), "correct horse").unwrap();
+        serde_json::to_value(&app).unwrap()
+    }
+
+    #[test]
+    fn share_survives_round_trip() {
+        let format = crate::CodeFormat::with_alphabet(STEAM_ALPHABET, 5).unwrap();
+        let app = GenApp::from_share(&shared(Some(format.clone())), "correct horse").unwrap();
+        assert_eq!(app.get_name(), "GitHub");
+        assert_eq!(app.get_username(), "octocat");
+        assert_eq!(app.get_secret(), "JBSWY3DPEHPK3PXP");
+        assert_eq!(app.get_format(), &format);
+    }
+
+    #[test]
+    fn wrong_passphrase_is_rejected() {
+        let err = GenApp::from_share(&shared(None), "wrong horse").unwrap_err();
+        assert_eq!(err.code(), ErrorCode::WrongPassword);
+    }
+
+    #[test]
+    fn malicious_formats_are_rejected() {
+        for format in [
+            serde_json::json!({ "length": 6, "alphabet": "" }),
+            serde_json::json!({ "length": 20 }),
+            serde_json::json!({ "length": 0 }),
+            serde_json::json!({ "length": usize::MAX, "alphabet": "AB" }),
+        ] {
+            let mut json = app_json();
+            json["format"] = format.clone();
+            let err = GenApp::from_share(&forged(&json), "correct horse").unwrap_err();
+            assert_eq!(err.code(), ErrorCode::Damaged, "{}", format);
+        }
+    }
+
+    #[test]
+    fn malicious_generators_are_rejected() {
+        let mut json = app_json();
+        json["kind"] = serde_json::json!({ "type": "ocra", "suite": "nonsense" });
+        let err = GenApp::from_share(&forged(&json), "correct horse").unwrap_err();
+        assert_eq!(err.code(), ErrorCode::Damaged);
+    }
+}
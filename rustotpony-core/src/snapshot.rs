@@ -0,0 +1,177 @@
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::{ErrorKind, Write};
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::PathBuf;
+use tracing::info;
+
+use crate::errors::{Error, ErrorCode};
+#[cfg(unix)]
+use crate::permissions;
+use crate::storage::Storage;
+use crate::{datetime, journal};
+
+const SNAPSHOT_EXTENSION: &str = "snapshot";
+const PRE_RESTORE_LABEL: &str = "before-restore";
+
+/// Labeled point-in-time copy of the encrypted database.
+pub struct Snapshot {
+    pub label: String,
+    pub created_at: u64,
+    path: PathBuf,
+}
+
+/// Directory with database snapshots.
+///
+/// Snapshots are plain copies of the encrypted database file named
+/// `<timestamp>-<label>.snapshot`, so they are as safe as the database
+/// itself and can be restored even if the password was changed since.
+/// Unlike automatic backups, snapshots are only created on request.
+pub struct SnapshotStore {
+    dir: PathBuf,
+}
+
+impl SnapshotStore {
+    pub fn new(dir: PathBuf) -> SnapshotStore {
+        SnapshotStore { dir }
+    }
+
+    pub fn create(&self, label: &str, storage: &dyn Storage) -> Result<Snapshot, Error> {
+        Self::validate_label(label)?;
+        if self.find(label)?.is_some() {
+            return Err(Error::new(
+                ErrorCode::AlreadyExists,
+                format!("Snapshot '{}' already exists", label),
+            ));
+        }
+        self.save(label, storage)
+    }
+
+    /// Returns all snapshots, the oldest first.
+    pub fn list(&self) -> Result<Vec<Snapshot>, Error> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(ref err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => {
+                return Err(Error::new(
+                    ErrorCode::Io,
+                    format!("Couldn't read snapshots directory: {}", err),
+                ))
+            }
+        };
+        let mut snapshots: Vec<Snapshot> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| Self::parse_path(entry.path()))
+            .collect();
+        snapshots.sort_by_key(|snapshot| snapshot.created_at);
+        Ok(snapshots)
+    }
+
+    /// Replaces the database with the given snapshot. The current state of
+    /// the database is saved as a snapshot first, so a restore can be undone.
+    /// The journal of the replaced file is removed.
+    pub fn restore(&self, label: &str, storage: &dyn Storage) -> Result<Snapshot, Error> {
+        let snapshot = self.find(label)?.ok_or_else(|| {
+            Error::new(
+                ErrorCode::NotFound,
+                format!("Snapshot '{}' wasn't found", label),
+            )
+        })?;
+        let data = fs::read(&snapshot.path).map_err(|err| {
+            Error::new(
+                ErrorCode::Io,
+                format!("Couldn't read snapshot '{}': {}", label, err),
+            )
+        })?;
+        if storage.read()?.is_some() {
+            let pre_restore_label = format!("{}-{}", PRE_RESTORE_LABEL, datetime::now());
+            self.save(&pre_restore_label, storage)?;
+        }
+        storage.write(&data)?;
+        if let Some(path) = storage.local_path() {
+            journal::discard_journal(path)?;
+        }
+        info!(label, "restored snapshot");
+        Ok(snapshot)
+    }
+
+    // Snapshots copy the file alone, the journal has to be compacted first
+    fn save(&self, label: &str, storage: &dyn Storage) -> Result<Snapshot, Error> {
+        if let Some(path) = storage.local_path() {
+            journal::check_no_journal(path)?;
+        }
+        let data = storage.read()?.ok_or_else(|| {
+            Error::new(
+                ErrorCode::NotFound,
+                "There is no database to take a snapshot of",
+            )
+        })?;
+        // Snapshots are as private as the database itself
+        let mut builder = DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        builder.mode(permissions::DIR_MODE);
+        builder.create(&self.dir).map_err(|err| {
+            Error::new(
+                ErrorCode::Io,
+                format!("Couldn't create snapshots directory: {}", err),
+            )
+        })?;
+        let created_at = datetime::now();
+        let path = self
+            .dir
+            .join(format!("{}-{}.{}", created_at, label, SNAPSHOT_EXTENSION));
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(permissions::FILE_MODE);
+        options
+            .open(&path)
+            .and_then(|mut file| file.write_all(&data))
+            .map_err(|err| Error::new(ErrorCode::Io, format!("Couldn't save snapshot: {}", err)))?;
+        info!(label, path = %path.display(), "saved snapshot");
+        Ok(Snapshot {
+            label: String::from(label),
+            created_at,
+            path,
+        })
+    }
+
+    fn find(&self, label: &str) -> Result<Option<Snapshot>, Error> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .find(|snapshot| snapshot.label == label))
+    }
+
+    fn parse_path(path: PathBuf) -> Option<Snapshot> {
+        if path.extension()? != SNAPSHOT_EXTENSION {
+            return None;
+        }
+        let (created_at, label) = {
+            let stem = path.file_stem()?.to_str()?;
+            let (timestamp, label) = stem.split_once('-')?;
+            (timestamp.parse().ok()?, String::from(label))
+        };
+        Some(Snapshot {
+            label,
+            created_at,
+            path,
+        })
+    }
+
+    fn validate_label(label: &str) -> Result<(), Error> {
+        let is_valid = !label.is_empty()
+            && label
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+        if is_valid {
+            Ok(())
+        } else {
+            Err(Error::new(
+                ErrorCode::InvalidArgument,
+                "Snapshot label may contain only letters, digits, '-', '_' and '.'",
+            ))
+        }
+    }
+}
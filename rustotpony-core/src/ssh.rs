@@ -0,0 +1,213 @@
+use std::process::{ChildStdin, ChildStdout, Command, Stdio};
+use tracing::debug;
+
+use crate::errors::{Error, ErrorCode};
+use crate::sftp::SftpSession;
+use crate::storage::Storage;
+
+/// Database stored on a remote host, accessed over SFTP with the system
+/// `ssh` client.
+///
+/// Locations look like `sftp://[user@]host[:port]/absolute/path` or
+/// `sftp://host/~/path/in/home`, IPv6 addresses are written in brackets.
+/// Authentication (keys, agent, known hosts) is entirely up to the ssh
+/// client configuration. The database is transferred encrypted and never
+/// touches the local disk. Servers allowing only SFTP work as well.
+pub struct SshStorage {
+    destination: String,
+    port: Option<u16>,
+    path: String,
+}
+
+impl SshStorage {
+    pub fn from_url(url: &str) -> Result<SshStorage, Error> {
+        let (_, rest) = url.split_once("://").ok_or_else(|| {
+            Error::new(
+                ErrorCode::InvalidArgument,
+                format!("Invalid remote location '{}'", url),
+            )
+        })?;
+        let (authority, path) = match rest.find('/') {
+            Some(idx) => rest.split_at(idx),
+            None => {
+                return Err(Error::new(
+                    ErrorCode::NotFound,
+                    format!("There is no file path in '{}'", url),
+                ))
+            }
+        };
+        let path = match path.strip_prefix("/~/") {
+            Some(relative) => relative,
+            None => path,
+        };
+        if authority.is_empty() || path.is_empty() || path.ends_with('/') {
+            return Err(Error::new(
+                ErrorCode::InvalidArgument,
+                format!(
+                    "Invalid remote location '{}', expected sftp://host/path/to/db.json",
+                    url
+                ),
+            ));
+        }
+        let (destination, port) = parse_authority(authority).ok_or_else(|| {
+            Error::new(
+                ErrorCode::InvalidArgument,
+                format!(
+                    "Invalid host in '{}', expected [user@]host[:port], IPv6 addresses in brackets",
+                    url
+                ),
+            )
+        })?;
+        Ok(SshStorage {
+            destination,
+            port,
+            path: String::from(path),
+        })
+    }
+
+    /// Runs `f` in an SFTP session with the host, the `sftp` subsystem of
+    /// its ssh server is used.
+    fn session<T>(
+        &self,
+        f: impl FnOnce(&mut SftpSession<ChildStdout, ChildStdin>) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let mut command = Command::new("ssh");
+        if let Some(port) = self.port {
+            command.arg("-p").arg(port.to_string());
+        }
+        let mut child = command
+            .arg("-s")
+            .arg("--")
+            .arg(&self.destination)
+            .arg("sftp")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|err| Error::new(ErrorCode::Network, format!("Couldn't run ssh: {}", err)))?;
+        let stdout = child.stdout.take().expect("Couldn't open ssh stdout");
+        let stdin = child.stdin.take().expect("Couldn't open ssh stdin");
+        // The session is dropped before waiting, closing stdin ends ssh
+        let result = SftpSession::start(stdout, stdin).and_then(|mut session| f(&mut session));
+        let status = child
+            .wait()
+            .map_err(|err| Error::new(ErrorCode::Network, format!("Couldn't run ssh: {}", err)))?;
+        match result {
+            // Connection problems are described by ssh itself on stderr
+            Err(err) if err.code() == ErrorCode::Network && !status.success() => Err(Error::new(
+                ErrorCode::Network,
+                format!(
+                    "Couldn't connect to {}: ssh exited with {}",
+                    self.destination, status
+                ),
+            )),
+            result => result,
+        }
+    }
+}
+
+impl Storage for SshStorage {
+    fn read(&self) -> Result<Option<Vec<u8>>, Error> {
+        debug!(destination = %self.destination, path = %self.path, "reading database over sftp");
+        self.session(|session| session.read_file(&self.path))
+    }
+
+    fn write(&self, data: &[u8]) -> Result<(), Error> {
+        debug!(destination = %self.destination, path = %self.path, bytes = data.len(), "writing database over sftp");
+        self.session(|session| session.write_file(&self.path, data))
+    }
+}
+
+/// Splits `[user@]host[:port]` into the ssh destination and the port. IPv6
+/// addresses are written in brackets, e.g. `[::1]:2222`.
+fn parse_authority(authority: &str) -> Option<(String, Option<u16>)> {
+    let (user, host_port) = match authority.rsplit_once('@') {
+        Some((user, rest)) if !user.is_empty() => (Some(user), rest),
+        Some(_) => return None,
+        None => (None, authority),
+    };
+    let (host, port) = match host_port.strip_prefix('[') {
+        Some(bracketed) => {
+            let (host, rest) = bracketed.split_once(']')?;
+            if !host.contains(':') {
+                return None;
+            }
+            match rest {
+                "" => (host, None),
+                _ => (host, Some(rest.strip_prefix(':')?)),
+            }
+        }
+        None => match host_port.split_once(':') {
+            // An IPv6 address without brackets can't be told from a port
+            Some((_, port)) if port.contains(':') => return None,
+            Some((host, port)) => (host, Some(port)),
+            None => (host_port, None),
+        },
+    };
+    if host.is_empty() {
+        return None;
+    }
+    let port = match port {
+        Some(port) => Some(port.parse::<u16>().ok().filter(|port| *port != 0)?),
+        None => None,
+    };
+    let destination = match user {
+        Some(user) => format!("{}@{}", user, host),
+        None => String::from(host),
+    };
+    Some((destination, port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn storage(url: &str) -> Result<(String, Option<u16>, String), ErrorCode> {
+        SshStorage::from_url(url)
+            .map(|storage| (storage.destination, storage.port, storage.path))
+            .map_err(|err| err.code())
+    }
+
+    #[test]
+    fn parses_hosts_and_ports() {
+        assert_eq!(
+            storage("sftp://me@vault.example.com:2222/~/db.json"),
+            Ok((
+                String::from("me@vault.example.com"),
+                Some(2222),
+                String::from("db.json")
+            ))
+        );
+        assert_eq!(
+            storage("sftp://vault/srv/db.json"),
+            Ok((String::from("vault"), None, String::from("/srv/db.json")))
+        );
+    }
+
+    #[test]
+    fn parses_bracketed_ipv6() {
+        assert_eq!(
+            storage("sftp://[::1]:22/db.json"),
+            Ok((String::from("::1"), Some(22), String::from("/db.json")))
+        );
+        assert_eq!(
+            storage("ssh://me@[fe80::1]/~/db.json"),
+            Ok((String::from("me@fe80::1"), None, String::from("db.json")))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_authorities() {
+        for url in [
+            "sftp://::1/db.json",
+            "sftp://[::1/db.json",
+            "sftp://[::1]x/db.json",
+            "sftp://host:port/db.json",
+            "sftp://host:0/db.json",
+            "sftp://@host/db.json",
+            "sftp://me@:22/db.json",
+        ] {
+            assert_eq!(storage(url), Err(ErrorCode::InvalidArgument), "{}", url);
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use serde_derive::Serialize;
+use std::collections::HashMap;
+
+use crate::summary::summarize;
+use crate::{Database, Error, HistoryRecord, Operation, RusTOTPony};
+
+/// Overview of the database, e.g. for deciding what to clean up.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Stats {
+    pub applications: usize,
+    pub hidden: usize,
+    pub archived: usize,
+    pub protected: usize,
+    /// Numbers of applications by algorithm, e.g. `TOTP-SHA1`, most used first
+    pub algorithms: Vec<(String, usize)>,
+    /// Numbers of applications by length of codes, most used first
+    pub digits: Vec<(usize, usize)>,
+    /// Applications with times their secrets were added, oldest first.
+    /// Applications added before the history was kept are left out.
+    pub oldest: Vec<(String, u64)>,
+    /// Names of applications without tags, sorted
+    pub untagged: Vec<String>,
+    /// Size of the stored database in bytes, if the storage knows it
+    pub size: Option<u64>,
+}
+
+impl<DB: Database> RusTOTPony<DB> {
+    /// Counts applications by their properties. `oldest` is limited to
+    /// `oldest` applications.
+    pub fn get_stats(&self, oldest: usize) -> Result<Stats, Error> {
+        let content = self.content()?;
+        let summaries = summarize(content);
+        let mut stats = Stats {
+            applications: summaries.len(),
+            hidden: summaries.iter().filter(|app| app.is_hidden()).count(),
+            archived: summaries.iter().filter(|app| app.is_archived()).count(),
+            protected: summaries.iter().filter(|app| app.is_protected()).count(),
+            algorithms: count(summaries.iter().map(|app| app.get_algorithm())),
+            digits: count(summaries.iter().map(|app| app.get_digits())),
+            size: self.database.get_size(),
+            ..Stats::default()
+        };
+        let created = creation_times(&content.history);
+        stats.oldest = content
+            .applications
+            .keys()
+            .filter_map(|name| created.get(name.as_str()).map(|time| (name.clone(), *time)))
+            .collect();
+        stats
+            .oldest
+            .sort_by(|(a_name, a), (b_name, b)| a.cmp(b).then(a_name.cmp(b_name)));
+        stats.oldest.truncate(oldest);
+        stats.untagged = content
+            .applications
+            .iter()
+            .filter(|(_, app)| app.get_tags().is_empty())
+            .map(|(name, _)| name.clone())
+            .collect();
+        stats.untagged.sort();
+        Ok(stats)
+    }
+}
+
+fn count<T: Ord>(values: impl Iterator<Item = T>) -> Vec<(T, usize)> {
+    let mut counts: Vec<(T, usize)> = Vec::new();
+    for value in values {
+        match counts.iter_mut().find(|(existing, _)| *existing == value) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((value, 1)),
+        }
+    }
+    counts.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+    counts
+}
+
+// Renamed applications keep the time of creation, deleted ones lose it
+fn creation_times(history: &[HistoryRecord]) -> HashMap<&str, u64> {
+    let mut created: HashMap<&str, u64> = HashMap::new();
+    for record in history {
+        match (record.get_operation(), record.get_details()) {
+            (Operation::Create, _) => {
+                created.insert(record.get_name(), record.get_timestamp());
+            }
+            (Operation::Rename, Some(newname)) => {
+                if let Some(time) = created.remove(record.get_name()) {
+                    created.insert(newname, time);
+                }
+            }
+            (Operation::Delete, _) => {
+                created.remove(record.get_name());
+            }
+            (Operation::DeleteAll, _) => created.clear(),
+            _ => {}
+        }
+    }
+    created
+}
@@ -0,0 +1,289 @@
+use std::fs::{DirBuilder, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::debug;
+
+use crate::errors::{Error, ErrorCode};
+use crate::git::GitHistory;
+use crate::permissions;
+#[cfg(feature = "s3")]
+use crate::s3::S3Storage;
+#[cfg(feature = "ssh")]
+use crate::ssh::SshStorage;
+
+/// Place where the encrypted database bytes are kept.
+///
+/// Storages know nothing about encryption or the database format, they only
+/// move opaque bytes around.
+pub trait Storage: Send + Sync {
+    /// Returns `None` if there is no database yet.
+    fn read(&self) -> Result<Option<Vec<u8>>, Error>;
+    fn write(&self, data: &[u8]) -> Result<(), Error>;
+
+    /// Path of the database file if it's stored on the local file system.
+    fn local_path(&self) -> Option<&Path> {
+        None
+    }
+}
+
+/// Creates a storage for the given location. Supported locations are local
+/// paths, `s3://bucket/key` and `sftp://host/path` URLs. Remote locations
+/// are available only if the corresponding feature is enabled, local paths
+/// starting with `~` are resolved with `expand_home`.
+pub fn storage_for(location: &str) -> Result<Box<dyn Storage>, Error> {
+    if location.starts_with("s3://") {
+        s3_storage(location)
+    } else if location.starts_with("sftp://") || location.starts_with("ssh://") {
+        ssh_storage(location)
+    } else {
+        Ok(Box::new(FileStorage::new(expand_home(location))))
+    }
+}
+
+/// Replaces a leading `~` with the home directory of the current user. The
+/// shell usually does it, but not for quoted arguments and not on Windows.
+pub fn expand_home(path: &str) -> PathBuf {
+    let rest = match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with(['/', '\\']) => rest,
+        _ => return PathBuf::from(path),
+    };
+    match dirs::home_dir() {
+        Some(home) => home.join(rest.trim_start_matches(['/', '\\'])),
+        None => PathBuf::from(path),
+    }
+}
+
+#[cfg(feature = "s3")]
+fn s3_storage(location: &str) -> Result<Box<dyn Storage>, Error> {
+    let mut parts = location["s3://".len()..].splitn(2, '/');
+    let bucket = parts.next().unwrap_or("");
+    let key = parts.next().unwrap_or("");
+    if bucket.is_empty() || key.is_empty() {
+        return Err(Error::new(
+            ErrorCode::InvalidArgument,
+            format!(
+                "Invalid S3 location '{}', expected s3://bucket/key",
+                location
+            ),
+        ));
+    }
+    Ok(Box::new(S3Storage::from_env(bucket, key)?))
+}
+
+#[cfg(not(feature = "s3"))]
+fn s3_storage(location: &str) -> Result<Box<dyn Storage>, Error> {
+    Err(disabled_backend(location, "s3"))
+}
+
+#[cfg(feature = "ssh")]
+fn ssh_storage(location: &str) -> Result<Box<dyn Storage>, Error> {
+    Ok(Box::new(SshStorage::from_url(location)?))
+}
+
+#[cfg(not(feature = "ssh"))]
+fn ssh_storage(location: &str) -> Result<Box<dyn Storage>, Error> {
+    Err(disabled_backend(location, "ssh"))
+}
+
+#[cfg(not(all(feature = "s3", feature = "ssh")))]
+fn disabled_backend(location: &str, feature: &str) -> Error {
+    Error::new(
+        ErrorCode::Unsupported,
+        format!(
+            "Can't open '{}', this build doesn't include the '{}' feature",
+            location, feature
+        ),
+    )
+}
+
+pub struct FileStorage {
+    file_path: PathBuf,
+}
+
+impl FileStorage {
+    pub fn new(path: PathBuf) -> FileStorage {
+        FileStorage { file_path: path }
+    }
+
+    fn create_database_file(&self, path: &Path) -> Result<File, std::io::Error> {
+        match self.file_path.parent() {
+            Some(dir) if dir != Path::new("") => {
+                let mut builder = DirBuilder::new();
+                builder.recursive(true);
+                #[cfg(unix)]
+                builder.mode(permissions::DIR_MODE);
+                builder.create(dir)?
+            }
+            _ => {}
+        }
+        Self::open_database_file_for_write(path)
+    }
+
+    // New files are created accessible only by the owner. The file must not
+    // exist, so a symbolic link planted in its place isn't followed
+    fn open_database_file_for_write(path: &Path) -> Result<File, std::io::Error> {
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(permissions::FILE_MODE);
+        options.open(path)
+    }
+
+    fn temporary_path(&self) -> PathBuf {
+        let mut name = self.file_path.as_os_str().to_owned();
+        name.push(".tmp");
+        PathBuf::from(name)
+    }
+}
+
+impl Storage for FileStorage {
+    fn read(&self) -> Result<Option<Vec<u8>>, Error> {
+        debug!(path = %self.file_path.display(), "reading database file");
+        match std::fs::read(&self.file_path) {
+            Ok(d) => Ok(Some(d)),
+            Err(ref err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(Error::new(
+                ErrorCode::Io,
+                format!("There was a problem opening file: {:?}", err),
+            )),
+        }
+    }
+
+    fn write(&self, data: &[u8]) -> Result<(), Error> {
+        debug!(path = %self.file_path.display(), bytes = data.len(), "writing database file");
+        permissions::check_write_target(&self.file_path)?;
+        let is_new = !self.file_path.exists();
+        // The data is written next to the file and moved over it, so a crash
+        // while writing leaves the previous version intact
+        let temporary_path = self.temporary_path();
+        // A file left by an interrupted write is removed, anything else in
+        // its place is refused
+        permissions::check_write_target(&temporary_path)?;
+        if temporary_path.exists() {
+            std::fs::remove_file(&temporary_path).map_err(|err| {
+                Error::new(
+                    ErrorCode::Io,
+                    format!("Couldn't remove {}: {}", temporary_path.display(), err),
+                )
+            })?;
+        }
+        let mut file = match Self::open_database_file_for_write(&temporary_path) {
+            Ok(f) => f,
+            Err(ref err) if err.kind() == ErrorKind::NotFound => {
+                self.create_database_file(&temporary_path).map_err(|err| {
+                    Error::new(
+                        ErrorCode::Io,
+                        format!("Couldn't create database file: {:?}", err),
+                    )
+                })?
+            }
+            Err(err) => {
+                return Err(Error::new(
+                    ErrorCode::Io,
+                    format!("Couldn't open database file: {:?}", err),
+                ))
+            }
+        };
+        file.write_all(data)
+            .and_then(|_| file.sync_all())
+            .map_err(|err| {
+                Error::new(
+                    ErrorCode::Io,
+                    format!("Couldn't write data to database file: {:?}", err),
+                )
+            })?;
+        drop(file);
+        // Permissions of existing files are left as they are
+        if !is_new {
+            let permissions = std::fs::metadata(&self.file_path)
+                .map_err(|err| {
+                    Error::new(
+                        ErrorCode::Io,
+                        format!("Couldn't inspect database file: {:?}", err),
+                    )
+                })?
+                .permissions();
+            std::fs::set_permissions(&temporary_path, permissions).map_err(|err| {
+                Error::new(
+                    ErrorCode::Io,
+                    format!("Couldn't write data to database file: {:?}", err),
+                )
+            })?;
+        }
+        std::fs::rename(&temporary_path, &self.file_path)
+            .and_then(|_| sync_dir(&self.file_path))
+            .map_err(|err| {
+                Error::new(
+                    ErrorCode::Io,
+                    format!("Couldn't replace database file: {:?}", err),
+                )
+            })?;
+        if is_new {
+            permissions::restrict_permissions(&self.file_path)?;
+        }
+        let history = GitHistory::for_database(&self.file_path);
+        if history.is_enabled() {
+            history.commit("Update database")?;
+        }
+        Ok(())
+    }
+
+    fn local_path(&self) -> Option<&Path> {
+        Some(&self.file_path)
+    }
+}
+
+/// Waits until a file created, renamed or removed at the path stays so
+/// after a power loss, which takes syncing the directory holding it.
+#[cfg(unix)]
+pub(crate) fn sync_dir(path: &Path) -> std::io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()
+}
+
+// Directories can't be opened for syncing elsewhere
+#[cfg(not(unix))]
+pub(crate) fn sync_dir(_: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Keeps the database in memory, for programs which read and write the
+/// bytes themselves, e.g. in a browser. Clones share the same bytes, so
+/// a clone kept outside the database sees what was saved.
+#[derive(Clone, Default)]
+pub struct MemoryStorage {
+    data: Arc<Mutex<Option<Vec<u8>>>>,
+}
+
+impl MemoryStorage {
+    pub fn new(data: Vec<u8>) -> MemoryStorage {
+        MemoryStorage {
+            data: Arc::new(Mutex::new(Some(data))),
+        }
+    }
+
+    /// Returns the last saved bytes, `None` if nothing was ever stored.
+    pub fn get_bytes(&self) -> Option<Vec<u8>> {
+        self.data
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn read(&self) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.get_bytes())
+    }
+
+    fn write(&self, data: &[u8]) -> Result<(), Error> {
+        *self.data.lock().unwrap_or_else(|err| err.into_inner()) = Some(data.to_vec());
+        Ok(())
+    }
+}
@@ -0,0 +1,385 @@
+use flate2::bufread::GzDecoder;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+use crate::cipher::Cipher;
+use crate::errors::{Error, ErrorCode};
+use crate::{compression, padding, random};
+
+/// Size of every sealed chunk of a vault but the last one. Chunks of all
+/// ciphers fill it exactly, so vaults still fill power-of-two buckets.
+pub const SEALED_CHUNK_SIZE: usize = 65536;
+
+/// Serializes the value into a vault, gzipped if the header of the file
+/// says so. The JSON is never held in memory as a whole. Bytes of the JSON
+/// written so far are reported with their total.
+pub fn write_vault<T: Serialize>(
+    cipher: &dyn Cipher,
+    value: &T,
+    key: &[u8],
+    compressed: bool,
+    report: &dyn Fn(u64, u64),
+) -> Vec<u8> {
+    let failed = |err| panic!("Couldn't serialize data to JSON: {}", err);
+    // The JSON is written twice, first only to count its length
+    let mut counter = Counter(0);
+    serde_json::to_writer(&mut counter, value).unwrap_or_else(failed);
+    let total = counter.0 as u64;
+    let mut writer = VaultWriter::new(cipher, key);
+    if compressed {
+        let mut encoder = compression::encoder(&mut writer);
+        serde_json::to_writer(Reporting::new(&mut encoder, total, report), value)
+            .unwrap_or_else(failed);
+        encoder.finish().expect("Couldn't compress data");
+    } else {
+        serde_json::to_writer(Reporting::new(&mut writer, total, report), value)
+            .unwrap_or_else(failed);
+    }
+    writer.finish(compressed)
+}
+
+// Reports the bytes written through it about once per chunk
+struct Reporting<'a, W: Write> {
+    inner: W,
+    done: u64,
+    total: u64,
+    report: &'a dyn Fn(u64, u64),
+}
+
+impl<'a, W: Write> Reporting<'a, W> {
+    fn new(inner: W, total: u64, report: &'a dyn Fn(u64, u64)) -> Reporting<'a, W> {
+        Reporting {
+            inner,
+            done: 0,
+            total,
+            report,
+        }
+    }
+}
+
+impl<W: Write> Write for Reporting<'_, W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(data)?;
+        let chunk = SEALED_CHUNK_SIZE as u64;
+        let before = self.done;
+        self.done += written as u64;
+        if self.done / chunk != before / chunk || self.done == self.total {
+            (self.report)(self.done, self.total);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+struct Counter(usize);
+
+impl Write for Counter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0 += data.len();
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Encrypts a vault as it's written, holding at most a chunk of plaintext.
+/// Ciphers sealing vaults whole get all of it at `finish` instead.
+pub struct VaultWriter<'a> {
+    cipher: &'a dyn Cipher,
+    key: &'a [u8],
+    nonce: Vec<u8>,
+    sealed: Vec<u8>,
+    buffer: Vec<u8>,
+    // Index of the next chunk and the range of the previous one in `sealed`
+    index: u32,
+    previous: (usize, usize),
+    length: usize,
+}
+
+impl<'a> VaultWriter<'a> {
+    pub fn new(cipher: &'a dyn Cipher, key: &'a [u8]) -> VaultWriter<'a> {
+        let nonce = random::bytes(cipher.nonce_size());
+        VaultWriter {
+            cipher,
+            key,
+            sealed: nonce.clone(),
+            nonce,
+            buffer: Vec::new(),
+            index: 0,
+            previous: (0, 0),
+            length: 0,
+        }
+    }
+
+    /// Pads the vault (with zero bytes after compressed data, spaces after
+    /// JSON) and seals the last chunk.
+    pub fn finish(mut self, compressed: bool) -> Vec<u8> {
+        let filler = if compressed { 0 } else { b' ' };
+        if !self.cipher.is_streamed() {
+            padding::pad(&mut self.buffer, self.cipher.overhead(), filler);
+            return self.cipher.seal(&self.buffer, self.key);
+        }
+        let padded = padding::padded_length(self.length, self.cipher);
+        let filling = vec![filler; padded - self.length];
+        self.write_all(&filling).expect("Couldn't pad vault");
+        self.seal_chunk(true);
+        self.sealed
+    }
+
+    fn chunk_size(&self) -> usize {
+        SEALED_CHUNK_SIZE - self.cipher.chunk_overhead()
+    }
+
+    fn seal_chunk(&mut self, last: bool) {
+        let size = if last {
+            self.buffer.len()
+        } else {
+            self.chunk_size()
+        };
+        let previous = &self.sealed[self.previous.0..self.previous.1];
+        let nonce = self
+            .cipher
+            .chunk_nonce(&self.nonce, previous, self.index, last);
+        let chunk = self
+            .cipher
+            .encrypt_chunk(&self.buffer[..size], self.key, &nonce, last)
+            .expect("Couldn't encrypt data");
+        self.buffer.drain(..size);
+        self.previous = (self.sealed.len(), self.sealed.len() + chunk.len());
+        self.sealed.extend(chunk);
+        self.index += 1;
+    }
+}
+
+impl Write for VaultWriter<'_> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(data);
+        self.length += data.len();
+        // A full chunk is kept until more data comes, it may be the last one
+        while self.cipher.is_streamed() && self.buffer.len() > self.chunk_size() {
+            self.seal_chunk(false);
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Decrypts a vault as it's read, chunk by chunk. Authenticated chunks are
+/// checked before any of their bytes are returned.
+struct VaultReader<'a> {
+    cipher: &'a dyn Cipher,
+    key: &'a [u8],
+    nonce: &'a [u8],
+    rest: &'a [u8],
+    previous: &'a [u8],
+    index: u32,
+    plain: Vec<u8>,
+    position: usize,
+    failed: bool,
+    total: usize,
+    report: &'a dyn Fn(u64, u64),
+}
+
+impl<'a> VaultReader<'a> {
+    fn new(
+        cipher: &'a dyn Cipher,
+        sealed: &'a [u8],
+        key: &'a [u8],
+        report: &'a dyn Fn(u64, u64),
+    ) -> Result<VaultReader<'a>, Error> {
+        if sealed.len() < cipher.nonce_size() {
+            return Err(Error::new(ErrorCode::Damaged, "Database file is too short"));
+        }
+        let (nonce, rest) = sealed.split_at(cipher.nonce_size());
+        Ok(VaultReader {
+            cipher,
+            key,
+            nonce,
+            rest,
+            previous: &[],
+            index: 0,
+            plain: Vec::new(),
+            position: 0,
+            failed: false,
+            total: sealed.len(),
+            report,
+        })
+    }
+
+    fn open_chunk(&mut self) -> Result<(), Error> {
+        if !self.cipher.is_streamed() {
+            self.plain = self.cipher.decrypt(self.rest, self.key, self.nonce)?;
+            self.rest = &[];
+            (self.report)(self.total as u64, self.total as u64);
+            return Ok(());
+        }
+        let last = self.rest.len() <= SEALED_CHUNK_SIZE;
+        let (chunk, rest) = self.rest.split_at(self.rest.len().min(SEALED_CHUNK_SIZE));
+        let nonce = self
+            .cipher
+            .chunk_nonce(self.nonce, self.previous, self.index, last);
+        self.plain = self.cipher.decrypt_chunk(chunk, self.key, &nonce, last)?;
+        self.previous = chunk;
+        self.rest = rest;
+        self.index += 1;
+        (self.report)((self.total - self.rest.len()) as u64, self.total as u64);
+        Ok(())
+    }
+}
+
+impl Read for VaultReader<'_> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.plain.len() {
+            // The first chunk is opened even if it's empty, the key is
+            // checked this way
+            if self.rest.is_empty() && self.index > 0 {
+                return Ok(0);
+            }
+            self.position = 0;
+            if let Err(err) = self.open_chunk() {
+                self.failed = true;
+                return Err(io::Error::new(io::ErrorKind::InvalidData, err));
+            }
+            if !self.cipher.is_streamed() {
+                self.index = 1;
+            }
+        }
+        let size = buffer.len().min(self.plain.len() - self.position);
+        buffer[..size].copy_from_slice(&self.plain[self.position..self.position + size]);
+        self.position += size;
+        Ok(size)
+    }
+}
+
+/// Decrypts the vault and parses the JSON inside it, gzipped if the header
+/// of the file says so, as it's decrypted. Decrypted bytes of the vault are
+/// reported with its length.
+pub fn read_vault<T: DeserializeOwned>(
+    cipher: &dyn Cipher,
+    sealed: &[u8],
+    key: &[u8],
+    compressed: bool,
+    report: &dyn Fn(u64, u64),
+) -> Result<T, Error> {
+    let wrong_key = || {
+        Error::new(
+            ErrorCode::WrongPassword,
+            "Couldn't decrypt data: wrong password or corrupted file",
+        )
+    };
+    let mut reader = VaultReader::new(cipher, sealed, key, report)?;
+    let parsed = {
+        let mut buffered = BufReader::new(&mut reader);
+        let start = buffered.fill_buf().map_err(|_| wrong_key())?;
+        // Without authentication a wrong key shows in the first bytes
+        if compressed && compression::has_gzip_magic(start) {
+            serde_json::from_reader(BufReader::new(GzDecoder::new(buffered)))
+        } else if !compressed && start.first() == Some(&b'{') {
+            serde_json::from_reader(buffered)
+        } else {
+            return Err(wrong_key());
+        }
+    };
+    // The rest is read to check it, compressed data is followed by padding
+    let rest = io::copy(&mut reader, &mut io::sink());
+    if reader.failed {
+        return Err(wrong_key());
+    }
+    let parsed: T = parsed.map_err(|err| {
+        Error::new(
+            ErrorCode::Damaged,
+            format!("Couldn't parse JSON from database file: {}", err),
+        )
+    })?;
+    rest.map_err(|err| {
+        Error::new(
+            ErrorCode::Io,
+            format!("Couldn't read database file: {}", err),
+        )
+    })?;
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cipher::{AesCbc, AesGcm, XChaCha20};
+    use crate::crypto;
+    use std::cell::Cell;
+
+    const KEY: [u8; 32] = [7; 32];
+
+    // Spans several chunks
+    fn payload() -> serde_json::Value {
+        let data = crypto::to_hex(&random::bytes(SEALED_CHUNK_SIZE));
+        serde_json::json!({ "data": data })
+    }
+
+    fn read(cipher: &dyn Cipher, vault: &[u8], key: &[u8]) -> Result<serde_json::Value, Error> {
+        read_vault(cipher, vault, key, false, &|_, _| {})
+    }
+
+    #[test]
+    fn vault_survives_round_trip() {
+        for cipher in [&AesCbc as &dyn Cipher, &AesGcm, &XChaCha20] {
+            let value = payload();
+            let vault = write_vault(cipher, &value, &KEY, false, &|_, _| {});
+            assert!(vault.len() > 2 * SEALED_CHUNK_SIZE);
+            let last = Cell::new((0, 0));
+            let opened: serde_json::Value =
+                read_vault(cipher, &vault, &KEY, false, &|done, total| {
+                    last.set((done, total))
+                })
+                .unwrap();
+            assert_eq!(opened, value);
+            assert_eq!(last.get(), (vault.len() as u64, vault.len() as u64));
+        }
+    }
+
+    #[test]
+    fn wrong_key_is_rejected() {
+        for cipher in [&AesCbc as &dyn Cipher, &AesGcm, &XChaCha20] {
+            let vault = write_vault(cipher, &payload(), &KEY, false, &|_, _| {});
+            let err = read(cipher, &vault, &[8; 32]).err().unwrap();
+            assert_eq!(err.code(), ErrorCode::WrongPassword);
+        }
+    }
+
+    #[test]
+    fn dropped_and_reordered_chunks_are_rejected() {
+        for cipher in [&AesGcm as &dyn Cipher, &XChaCha20] {
+            let vault = write_vault(cipher, &payload(), &KEY, false, &|_, _| {});
+            let nonce = cipher.nonce_size();
+            let truncated = &vault[..nonce + 2 * SEALED_CHUNK_SIZE];
+            assert!(read(cipher, truncated, &KEY).is_err());
+            let first = &vault[nonce..nonce + SEALED_CHUNK_SIZE];
+            let second = &vault[nonce + SEALED_CHUNK_SIZE..nonce + 2 * SEALED_CHUNK_SIZE];
+            let reordered = [
+                &vault[..nonce],
+                second,
+                first,
+                &vault[nonce + 2 * SEALED_CHUNK_SIZE..],
+            ]
+            .concat();
+            assert!(read(cipher, &reordered, &KEY).is_err());
+        }
+    }
+
+    #[test]
+    fn streamed_cbc_vault_is_a_single_chain() {
+        let value = payload();
+        let vault = write_vault(&AesCbc, &value, &KEY, false, &|_, _| {});
+        let opened = AesCbc.open(&vault, &KEY).unwrap();
+        let opened: serde_json::Value = serde_json::from_slice(&opened).unwrap();
+        assert_eq!(opened, value);
+    }
+}
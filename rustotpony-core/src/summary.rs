@@ -0,0 +1,289 @@
+use serde::de::IgnoredAny;
+use serde::Serializer;
+use serde_derive::{Deserialize, Serialize};
+use tracing::{info, info_span};
+
+use std::collections::HashMap;
+
+use crate::{
+    CodeFormat, Database, DatabaseContent, Error, GenApp, GeneratorKind, HistoryRecord,
+    JsonDatabase, Operation, RusTOTPony,
+};
+
+/// Metadata of an application, without its secret.
+///
+/// Summaries are parsed straight from the database with all secret fields
+/// skipped, so listing a large database doesn't build every generator.
+/// They are kept in the index of indexed databases too.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApplicationSummary {
+    name: String,
+    username: String,
+    #[serde(default)]
+    issuer: String,
+    #[serde(default)]
+    hidden: bool,
+    #[serde(default)]
+    archived: bool,
+    #[serde(
+        default,
+        rename = "protected_secret",
+        serialize_with = "serialize_present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    protected: Option<IgnoredAny>,
+    #[serde(default)]
+    format: CodeFormat,
+    #[serde(default)]
+    kind: KindSummary,
+    #[serde(skip)]
+    last_used: Option<u64>,
+}
+
+/// Type of the generator, other fields of the kind (e.g. the mOTP PIN) are
+/// skipped.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct KindSummary {
+    #[serde(default, rename = "type", skip_serializing_if = "String::is_empty")]
+    kind: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    suite: String,
+}
+
+impl ApplicationSummary {
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_username(&self) -> &str {
+        &self.username
+    }
+
+    pub fn get_issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.hidden
+    }
+
+    pub fn is_archived(&self) -> bool {
+        self.archived
+    }
+
+    pub fn is_protected(&self) -> bool {
+        self.protected.is_some()
+    }
+
+    /// Length of generated codes.
+    pub fn get_digits(&self) -> usize {
+        self.format.get_length()
+    }
+
+    /// Algorithm of generated codes, e.g. `TOTP-SHA1`, or the suite of an
+    /// OCRA generator.
+    pub fn get_algorithm(&self) -> String {
+        match self.kind.kind.as_str() {
+            "motp" => String::from("mOTP-MD5"),
+            "ocra" => self.kind.suite.clone(),
+            _ => String::from("TOTP-SHA1"),
+        }
+    }
+
+    /// Time of the latest history record of the application, e.g. of a
+    /// disclosed secret or a used recovery code. Generating codes isn't
+    /// recorded, it doesn't write to the database.
+    pub fn get_last_used(&self) -> Option<u64> {
+        self.last_used
+    }
+}
+
+impl From<&GenApp> for ApplicationSummary {
+    fn from(app: &GenApp) -> ApplicationSummary {
+        ApplicationSummary {
+            name: String::from(app.get_name()),
+            username: String::from(app.get_username()),
+            issuer: String::from(app.get_issuer()),
+            hidden: app.is_hidden(),
+            archived: app.is_archived(),
+            protected: if app.is_protected() {
+                Some(IgnoredAny)
+            } else {
+                None
+            },
+            format: app.get_format().clone(),
+            kind: match app.get_kind() {
+                GeneratorKind::Totp => KindSummary::default(),
+                GeneratorKind::Motp { .. } => KindSummary {
+                    kind: String::from("motp"),
+                    suite: String::new(),
+                },
+                GeneratorKind::Ocra { suite, .. } => KindSummary {
+                    kind: String::from("ocra"),
+                    suite: suite.clone(),
+                },
+            },
+            last_used: None,
+        }
+    }
+}
+
+// Only the presence of the protected secret is known
+fn serialize_present<S: Serializer>(
+    _: &Option<IgnoredAny>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_bool(true)
+}
+
+/// Summaries of all applications in the content, with their last use.
+pub(crate) fn summarize(content: &DatabaseContent) -> Vec<ApplicationSummary> {
+    let mut summaries: Vec<ApplicationSummary> = content
+        .applications
+        .values()
+        .map(ApplicationSummary::from)
+        .collect();
+    set_last_used(&mut summaries, &content.history);
+    summaries
+}
+
+// Renamed applications keep their records, deleted ones lose them
+pub(crate) fn set_last_used(summaries: &mut [ApplicationSummary], history: &[HistoryRecord]) {
+    let mut last_used: HashMap<&str, u64> = HashMap::new();
+    for record in history {
+        let name = match (record.get_operation(), record.get_details()) {
+            (Operation::Rename, Some(newname)) => {
+                last_used.remove(record.get_name());
+                newname
+            }
+            (Operation::Delete, _) => {
+                last_used.remove(record.get_name());
+                continue;
+            }
+            (Operation::DeleteAll, _) => {
+                last_used.clear();
+                continue;
+            }
+            _ => record.get_name(),
+        };
+        let timestamp = last_used.entry(name).or_default();
+        *timestamp = (*timestamp).max(record.get_timestamp());
+    }
+    for summary in summaries.iter_mut() {
+        summary.last_used = last_used.get(summary.name.as_str()).copied();
+    }
+}
+
+/// Part of a listing, pages are numbered from 1.
+#[derive(Debug, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub page: usize,
+    pub pages: usize,
+    pub total: usize,
+}
+
+#[derive(Deserialize)]
+struct SummarySchema {
+    content: SummaryContent,
+}
+
+#[derive(Deserialize)]
+struct SummaryContent {
+    applications: HashMap<String, ApplicationSummary>,
+    #[serde(default)]
+    history: Vec<HistoryRecord>,
+}
+
+impl JsonDatabase {
+    /// Decrypts the database and parses only metadata of the applications.
+    pub fn read_summaries(&self) -> Result<Vec<ApplicationSummary>, Error> {
+        let _span = info_span!("load_summaries").entered();
+        let data = match self.read_vaults()? {
+            Some(d) => d,
+            None => return Ok(Vec::new()),
+        };
+        if self.header().is_indexed() {
+            return self.read_indexed_summaries(&data);
+        }
+        let schema: SummarySchema = self.decrypt_with_secret(&data)?;
+        info!(
+            applications = schema.content.applications.len(),
+            "loaded application summaries"
+        );
+        let mut summaries: Vec<ApplicationSummary> =
+            schema.content.applications.into_values().collect();
+        set_last_used(&mut summaries, &schema.content.history);
+        Ok(summaries)
+    }
+}
+
+impl<DB: Database> RusTOTPony<DB> {
+    /// Returns a page of visible applications, neither hidden nor archived,
+    /// sorted by name. Zero `per_page` returns all of them at once. Secrets
+    /// aren't loaded unless the database content has already been loaded for
+    /// other operations.
+    pub fn list_applications(
+        &self,
+        page: usize,
+        per_page: usize,
+    ) -> Result<Page<ApplicationSummary>, Error> {
+        let mut summaries = self.summaries()?;
+        summaries.retain(|summary| !summary.is_hidden() && !summary.is_archived());
+        summaries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(paginate(summaries, page, per_page))
+    }
+
+    /// Returns archived applications sorted by name, hidden ones included.
+    pub fn list_archived_applications(&self) -> Result<Vec<ApplicationSummary>, Error> {
+        let mut summaries = self.summaries()?;
+        summaries.retain(ApplicationSummary::is_archived);
+        summaries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(summaries)
+    }
+
+    /// Returns visible applications whose name, username or issuer contains
+    /// the query, sorted by name. Case is ignored the same way as in lookups
+    /// by name, see `set_name_matching`.
+    pub fn search_applications(&self, query: &str) -> Result<Vec<ApplicationSummary>, Error> {
+        let query = self.name_key(query.trim());
+        let mut summaries = self.summaries()?;
+        summaries.retain(|summary| {
+            !summary.is_hidden()
+                && !summary.is_archived()
+                && [&summary.name, &summary.username, &summary.issuer]
+                    .iter()
+                    .any(|field| self.name_key(field).contains(&query))
+        });
+        summaries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(summaries)
+    }
+
+    fn summaries(&self) -> Result<Vec<ApplicationSummary>, Error> {
+        match self.content.get() {
+            Some(content) => Ok(summarize(content)),
+            None => self.database.get_summaries(),
+        }
+    }
+}
+
+fn paginate<T>(items: Vec<T>, page: usize, per_page: usize) -> Page<T> {
+    let total = items.len();
+    let per_page = if per_page == 0 {
+        total.max(1)
+    } else {
+        per_page
+    };
+    let pages = total.div_ceil(per_page).max(1);
+    let page = page.clamp(1, pages);
+    Page {
+        items: items
+            .into_iter()
+            .skip((page - 1) * per_page)
+            .take(per_page)
+            .collect(),
+        page,
+        pages,
+        total,
+    }
+}
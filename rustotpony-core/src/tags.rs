@@ -0,0 +1,137 @@
+use crate::errors::{Error, ErrorCode};
+use crate::{Change, Database, Operation, RusTOTPony};
+
+impl<DB: Database> RusTOTPony<DB> {
+    /// Adds tags to the application and returns the number of tags which
+    /// weren't there yet. Tags are trimmed, blank ones are ignored.
+    pub fn tag_application(&mut self, name: &str, tags: &[String]) -> Result<usize, Error> {
+        self.update_tags(name, |current| {
+            let mut added = 0;
+            for tag in tags.iter().map(|tag| tag.trim()) {
+                if !tag.is_empty() && !current.iter().any(|t| t == tag) {
+                    current.push(String::from(tag));
+                    added += 1;
+                }
+            }
+            added
+        })
+    }
+
+    /// Removes tags from the application and returns the number of tags
+    /// which were removed.
+    pub fn untag_application(&mut self, name: &str, tags: &[String]) -> Result<usize, Error> {
+        self.update_tags(name, |current| {
+            let before = current.len();
+            current.retain(|tag| !tags.iter().any(|t| t.trim() == tag));
+            before - current.len()
+        })
+    }
+
+    /// Returns all tags in use, sorted.
+    pub fn get_tags(&self) -> Result<Vec<String>, Error> {
+        let mut tags: Vec<String> = self
+            .content()?
+            .applications
+            .values()
+            .flat_map(|app| app.tags.iter().cloned())
+            .collect();
+        tags.sort();
+        tags.dedup();
+        Ok(tags)
+    }
+
+    /// Returns all tags in use with numbers of applications which have
+    /// them, sorted by tag.
+    pub fn get_tag_counts(&self) -> Result<Vec<(String, usize)>, Error> {
+        let mut counts: Vec<(String, usize)> = Vec::new();
+        for tag in self
+            .content()?
+            .applications
+            .values()
+            .flat_map(|app| &app.tags)
+        {
+            match counts.iter_mut().find(|(existing, _)| existing == tag) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((tag.clone(), 1)),
+            }
+        }
+        counts.sort();
+        Ok(counts)
+    }
+
+    /// Renames the tag on all applications and returns the number of
+    /// applications changed. Fails when the new name is already used as a
+    /// tag, see `merge_tags` for that.
+    pub fn rename_tag(&mut self, tag: &str, newtag: &str) -> Result<usize, Error> {
+        let newtag = newtag.trim();
+        if self.get_tags()?.iter().any(|existing| existing == newtag) {
+            return Err(Error::new(
+                ErrorCode::AlreadyExists,
+                format!("Tag '{}' is already in use, merge the tags instead", newtag),
+            ));
+        }
+        self.merge_tags(&[String::from(tag)], newtag)
+    }
+
+    /// Replaces the tags with one tag on all applications and returns the
+    /// number of applications changed. The tag may be one of the merged
+    /// ones or another tag in use.
+    pub fn merge_tags(&mut self, tags: &[String], into: &str) -> Result<usize, Error> {
+        let into = into.trim();
+        if into.is_empty() {
+            return Err(Error::new(ErrorCode::InvalidArgument, "Tag can't be empty"));
+        }
+        let in_use = self.get_tags()?;
+        let tags: Vec<&str> = tags.iter().map(|tag| tag.trim()).collect();
+        if let Some(unknown) = tags.iter().find(|tag| !in_use.iter().any(|t| t == *tag)) {
+            return Err(Error::new(
+                ErrorCode::NotFound,
+                format!("Tag '{}' isn't used by any application", unknown),
+            ));
+        }
+        let mut names: Vec<String> = self
+            .content()?
+            .applications
+            .iter()
+            .filter(|(_, app)| {
+                app.tags
+                    .iter()
+                    .any(|tag| tag != into && tags.contains(&tag.as_str()))
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+        names.sort();
+        for name in &names {
+            self.update_tags(name, |current| {
+                current.retain(|tag| !tags.contains(&tag.as_str()) && tag != into);
+                current.push(String::from(into));
+                1
+            })?;
+        }
+        Ok(names.len())
+    }
+
+    fn update_tags<F>(&mut self, name: &str, update: F) -> Result<usize, Error>
+    where
+        F: FnOnce(&mut Vec<String>) -> usize,
+    {
+        let name = &self.resolve_name(name)?;
+        let app = match self.content_mut()?.applications.get_mut(name) {
+            Some(app) => app,
+            None => {
+                return Err(Error::new(
+                    ErrorCode::NotFound,
+                    format!("Application '{}' wasn't found", name),
+                ))
+            }
+        };
+        let changed = update(&mut app.tags);
+        app.tags.sort();
+        if changed > 0 {
+            self.mark_dirty(name);
+            self.record(Operation::Update, name, Some("tags"));
+            self.push_change(Change::Updated(String::from(name), String::from("tags")));
+        }
+        Ok(changed)
+    }
+}
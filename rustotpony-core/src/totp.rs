@@ -0,0 +1,119 @@
+use rustotpony_otp::Algorithm;
+
+use crate::datetime;
+use crate::errors::{Error, ErrorCode};
+use crate::format::CodeFormat;
+use crate::validation;
+
+pub const TIME_STEP: u64 = 30;
+
+/// Time-based one-time password generator as described in RFC 6238.
+///
+/// Providers hand out secrets in different encodings, the constructors
+/// accept base32 (the usual one), hex or the raw string.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TOTP {
+    secret: Vec<u8>,
+    format: CodeFormat,
+    epoch: u64,
+}
+
+impl TOTP {
+    pub fn new(secret: &str) -> Result<TOTP, Error> {
+        validation::decode_secret(secret).map(|(bytes, _)| TOTP::from_bytes(bytes))
+    }
+
+    pub fn new_hex(secret: &str) -> Result<TOTP, Error> {
+        let digits: String = secret.chars().filter(|c| !c.is_whitespace()).collect();
+        let invalid: Vec<String> = digits
+            .chars()
+            .enumerate()
+            .filter(|(_, c)| !c.is_ascii_hexdigit())
+            .map(|(idx, c)| format!("{:?} at position {}", c, idx + 1))
+            .collect();
+        if !invalid.is_empty() {
+            return Err(Error::new(
+                ErrorCode::InvalidArgument,
+                format!("Secret isn't valid hex: {}", invalid.join(", ")),
+            ));
+        }
+        if digits.is_empty() || !digits.len().is_multiple_of(2) {
+            return Err(Error::new(
+                ErrorCode::InvalidArgument,
+                format!(
+                    "Hex secret must consist of pairs of digits, got {} digits",
+                    digits.len()
+                ),
+            ));
+        }
+        let secret = (0..digits.len())
+            .step_by(2)
+            .map(|idx| u8::from_str_radix(&digits[idx..idx + 2], 16).expect("Invalid hex digit"))
+            .collect();
+        Ok(TOTP::from_bytes(secret))
+    }
+
+    /// Uses bytes of the string itself as the secret.
+    pub fn new_raw(secret: &str) -> Result<TOTP, Error> {
+        if secret.is_empty() {
+            return Err(Error::new(ErrorCode::InvalidArgument, "Secret is empty"));
+        }
+        Ok(TOTP::from_bytes(secret.as_bytes().to_vec()))
+    }
+
+    pub fn from_bytes(secret: Vec<u8>) -> TOTP {
+        TOTP {
+            secret,
+            format: CodeFormat::default(),
+            epoch: 0,
+        }
+    }
+
+    pub fn with_format(mut self, format: CodeFormat) -> TOTP {
+        self.format = format;
+        self
+    }
+
+    pub fn get_format(&self) -> &CodeFormat {
+        &self.format
+    }
+
+    /// Sets the unix time from which time steps are counted (T0 in RFC 6238),
+    /// almost every service uses 0.
+    pub fn with_epoch(mut self, epoch: u64) -> TOTP {
+        self.epoch = epoch;
+        self
+    }
+
+    pub fn get_epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    pub fn get_secret_bytes(&self) -> &[u8] {
+        &self.secret
+    }
+
+    /// Returns the secret in base32, the form it's stored in the database.
+    pub fn get_secret_base32(&self) -> String {
+        base32::encode(base32::Alphabet::RFC4648 { padding: false }, &self.secret)
+    }
+
+    /// Returns the code valid at the given unix time. Times before the epoch
+    /// get the code of the first time step.
+    pub fn generate(&self, timestamp: u64) -> String {
+        let value = rustotpony_otp::totp(
+            &self.secret,
+            Algorithm::Sha1,
+            timestamp,
+            self.epoch,
+            TIME_STEP,
+        )
+        .expect("Time step is zero");
+        self.format.format(u64::from(value))
+    }
+
+    pub fn now(&self) -> String {
+        self.generate(datetime::now())
+    }
+}
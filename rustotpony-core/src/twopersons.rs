@@ -0,0 +1,150 @@
+use crate::crypto::{self, Hash};
+use crate::errors::{Error, ErrorCode};
+use crate::header::Kdf;
+use crate::kdf::scrypt_key;
+use crate::{JsonDatabase, KEY_SIZE};
+
+impl JsonDatabase {
+    /// Tells whether opening the database takes passphrases of two persons.
+    pub fn requires_two_persons(&self) -> Result<bool, Error> {
+        self.read_vaults()?;
+        Ok(self.kdf().is_two_persons())
+    }
+
+    /// Encrypts the database with a key derived from two passphrases, so it
+    /// can be opened only when two persons enter them together, e.g. for
+    /// break-glass accounts. The second one is asked by the function set
+    /// with `with_second_secret`. The passphrases must differ, a decoy vault
+    /// is dropped. `change_password` goes back to a single password.
+    ///
+    /// Each passphrase goes through scrypt with a salt of its own, tuned so
+    /// that both together take the unlock time of the builder.
+    pub fn require_two_persons(&self, first: &str, second: &str) -> Result<(), Error> {
+        if first.is_empty() || second.is_empty() {
+            return Err(Error::new(
+                ErrorCode::InvalidArgument,
+                "Passphrases can't be empty",
+            ));
+        }
+        if crypto::constant_time_eq(first.as_bytes(), second.as_bytes()) {
+            return Err(Error::new(
+                ErrorCode::InvalidArgument,
+                "Passphrases of the two persons must differ",
+            ));
+        }
+        let kdf = self.two_persons_kdf();
+        self.replace_key(
+            &self.database_key(),
+            &Self::form_two_persons_key(&kdf, first, second),
+            kdf,
+        )
+    }
+
+    /// Key derivation `require_two_persons` uses for the passphrases, e.g.
+    /// to tell how fast each of them could be guessed.
+    pub fn two_persons_kdf(&self) -> Kdf {
+        match Kdf::benchmark(self.unlock_time / 2) {
+            Kdf::Scrypt { log_n, r, p, salt } => Kdf::TwoPersonsScrypt { log_n, r, p, salt },
+            kdf => kdf,
+        }
+    }
+
+    // Each passphrase is derived on its own, so they can't be shifted into
+    // each other
+    pub(crate) fn form_two_persons_key(kdf: &Kdf, first: &str, second: &str) -> [u8; KEY_SIZE] {
+        let keys = match kdf {
+            Kdf::TwoPersonsScrypt { log_n, r, p, salt } => {
+                let derive = |person: u8, passphrase: &str| {
+                    let salt = [&salt[..], &[person]].concat();
+                    scrypt_key(*log_n, *r, *p, &salt, passphrase.as_bytes()).to_vec()
+                };
+                [derive(1, first), derive(2, second)].concat()
+            }
+            _ => [
+                Hash::Sha256.digest(first.as_bytes()),
+                Hash::Sha256.digest(second.as_bytes()),
+            ]
+            .concat(),
+        };
+        let mut key = [0; KEY_SIZE];
+        key.copy_from_slice(&Hash::Sha256.digest(&keys));
+        key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::errors::ErrorCode;
+    use crate::{JsonDatabase, MemoryStorage, RusTOTPony};
+
+    fn database(
+        storage: &MemoryStorage,
+        first: &'static str,
+        second: &'static str,
+    ) -> JsonDatabase {
+        JsonDatabase::builder()
+            .storage(Box::new(storage.clone()))
+            .secret(move || String::from(first))
+            .second_secret(move || String::from(second))
+            .unlock_time(Duration::ZERO)
+            .build()
+            .unwrap()
+    }
+
+    fn two_persons_storage() -> MemoryStorage {
+        let storage = MemoryStorage::default();
+        let mut totpony = RusTOTPony::new(database(&storage, "admin", ""));
+        totpony
+            .create_application("GitHub", "octocat", "JBSWY3DPEHPK3PXP")
+            .unwrap();
+        totpony.flush().unwrap();
+        let db = database(&storage, "admin", "");
+        assert!(!db.requires_two_persons().unwrap());
+        db.require_two_persons("alice", "bob").unwrap();
+        storage
+    }
+
+    #[test]
+    fn both_passphrases_open_database() {
+        let storage = two_persons_storage();
+        let db = database(&storage, "alice", "bob");
+        assert!(db.requires_two_persons().unwrap());
+        let content = db.load_content().unwrap();
+        assert!(content.applications.contains_key("GitHub"));
+    }
+
+    #[test]
+    fn wrong_or_swapped_passphrases_are_rejected() {
+        let storage = two_persons_storage();
+        for (first, second) in [("admin", ""), ("alice", "eve"), ("bob", "alice")] {
+            let err = database(&storage, first, second)
+                .load_content()
+                .err()
+                .unwrap();
+            assert_eq!(err.code(), ErrorCode::WrongPassword);
+        }
+    }
+
+    #[test]
+    fn change_password_returns_to_single_password() {
+        let storage = two_persons_storage();
+        database(&storage, "alice", "bob")
+            .change_password("admin")
+            .unwrap();
+        let db = database(&storage, "admin", "");
+        assert!(!db.requires_two_persons().unwrap());
+        assert!(db.load_content().is_ok());
+    }
+
+    #[test]
+    fn passphrases_must_differ() {
+        let storage = two_persons_storage();
+        let db = database(&storage, "alice", "bob");
+        for (first, second) in [("alice", "alice"), ("alice", "")] {
+            let err = db.require_two_persons(first, second).unwrap_err();
+            assert_eq!(err.code(), ErrorCode::InvalidArgument);
+        }
+    }
+}
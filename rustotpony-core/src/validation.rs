@@ -0,0 +1,123 @@
+use crate::errors::{Error, ErrorCode};
+use crate::{Database, GenApp, RusTOTPony, TOTP};
+
+/// RFC 4226 requires shared secrets to be at least 128 bits long
+const MIN_SECRET_BITS: usize = 128;
+
+impl<DB: Database> RusTOTPony<DB> {
+    /// Checks a secret before adding it. Returns warnings about secrets which
+    /// look weak or are already used by another application, or an error
+    /// pointing at characters which aren't valid base32.
+    pub fn check_secret(&self, secret: &str) -> Result<Vec<String>, Error> {
+        let (bytes, mut warnings) = decode_secret(secret)?;
+        warnings.extend(self.check_totp(&TOTP::from_bytes(bytes))?);
+        Ok(warnings)
+    }
+
+    /// Same as `check_secret` for a secret which is already decoded.
+    pub fn check_totp(&self, totp: &TOTP) -> Result<Vec<String>, Error> {
+        let bytes = totp.get_secret_bytes();
+        let mut warnings = Vec::new();
+        if bytes.len() * 8 < MIN_SECRET_BITS {
+            warnings.push(format!(
+                "Secret is only {} bits long, at least {} bits are recommended",
+                bytes.len() * 8,
+                MIN_SECRET_BITS
+            ));
+        }
+        if let Some(period) = repetition_period(bytes) {
+            warnings.push(match period {
+                1 => String::from("Secret is one byte repeated over and over"),
+                _ => format!(
+                    "Secret is a sequence of {} bytes repeated over and over",
+                    period
+                ),
+            });
+        } else if distinct_bytes(bytes) * 2 < bytes.len() {
+            warnings.push(String::from(
+                "Secret has too many repeated bytes, it's probably not random",
+            ));
+        }
+        let mut names: Vec<&String> = self
+            .content()?
+            .applications
+            .iter()
+            .filter(|(_, app)| !app.secret_bytes.is_empty() && app.secret_bytes == bytes)
+            .map(|(name, _)| name)
+            .collect();
+        names.sort();
+        for name in names {
+            warnings.push(format!("Application '{}' has the same secret", name));
+        }
+        Ok(warnings)
+    }
+}
+
+/// Decodes a base32 secret pasted in any common formatting: lowercase,
+/// grouped with spaces or dashes, with or without padding. Digits 0 and 1
+/// aren't part of the alphabet and are read as letters O and L, which is
+/// reported in the returned warnings. The error lists all invalid
+/// characters with their positions in the input.
+pub fn decode_secret(secret: &str) -> Result<(Vec<u8>, Vec<String>), Error> {
+    let mut normalized = String::new();
+    let mut warnings = Vec::new();
+    let mut invalid = Vec::new();
+    for (idx, c) in secret.trim().trim_end_matches('=').chars().enumerate() {
+        match c {
+            _ if c.is_whitespace() || c == '-' => {}
+            '0' | '1' => {
+                let letter = if c == '0' { 'O' } else { 'L' };
+                warnings.push(format!(
+                    "{:?} at position {} was read as {:?}",
+                    c,
+                    idx + 1,
+                    letter
+                ));
+                normalized.push(letter);
+            }
+            _ if is_base32(c) => normalized.push(c.to_ascii_uppercase()),
+            '8' => invalid.push(format!(
+                "{:?} at position {} (did you mean 'B'?)",
+                c,
+                idx + 1
+            )),
+            _ => invalid.push(format!("{:?} at position {}", c, idx + 1)),
+        }
+    }
+    if !invalid.is_empty() {
+        return Err(Error::new(
+            ErrorCode::InvalidArgument,
+            format!(
+                "Secret isn't valid base32, only letters A-Z and digits 2-7 are allowed: {}",
+                invalid.join(", ")
+            ),
+        ));
+    }
+    if normalized.is_empty() {
+        return Err(Error::new(ErrorCode::InvalidArgument, "Secret is empty"));
+    }
+    let bytes = GenApp::base32_to_bytes(&normalized)
+        .ok_or_else(|| Error::new(ErrorCode::InvalidArgument, "Couldn't decode secret key"))?;
+    Ok((bytes, warnings))
+}
+
+fn is_base32(c: char) -> bool {
+    c.is_ascii_alphabetic() || ('2'..='7').contains(&c)
+}
+
+/// Returns the length of a block which repeated makes up the whole secret.
+fn repetition_period(bytes: &[u8]) -> Option<usize> {
+    (1..=bytes.len() / 2).find(|&period| {
+        bytes
+            .iter()
+            .enumerate()
+            .all(|(idx, byte)| *byte == bytes[idx % period])
+    })
+}
+
+fn distinct_bytes(bytes: &[u8]) -> usize {
+    let mut sorted = bytes.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+    sorted.len()
+}
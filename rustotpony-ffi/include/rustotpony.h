@@ -0,0 +1,71 @@
+#ifndef RUSTOTPONY_H
+#define RUSTOTPONY_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Codes and their names never change */
+#define RUSTOTPONY_OK 0
+/* A null pointer or a string which isn't valid UTF-8 was passed */
+#define RUSTOTPONY_ERROR_ARGUMENT 1
+/* Database couldn't be read or decrypted */
+#define RUSTOTPONY_ERROR_DATABASE 2
+#define RUSTOTPONY_ERROR_NOT_FOUND 3
+/* Application is protected with an additional passphrase */
+#define RUSTOTPONY_ERROR_PROTECTED 4
+/* Application doesn't generate codes on its own, e.g. challenge-response */
+#define RUSTOTPONY_ERROR_UNSUPPORTED 5
+#define RUSTOTPONY_ERROR_INVALID_CODE 6
+/* Internal error of a debug build, release builds abort on it */
+#define RUSTOTPONY_ERROR_PANIC 7
+#define RUSTOTPONY_ERROR_WRONG_PASSWORD 8
+/* File is damaged or isn't a database */
+#define RUSTOTPONY_ERROR_DAMAGED 9
+/* File was written by a newer version */
+#define RUSTOTPONY_ERROR_NEWER_VERSION 10
+#define RUSTOTPONY_ERROR_READ_ONLY 11
+#define RUSTOTPONY_ERROR_ALREADY_EXISTS 12
+#define RUSTOTPONY_ERROR_CANCELLED 13
+#define RUSTOTPONY_ERROR_IO 14
+#define RUSTOTPONY_ERROR_NETWORK 15
+#define RUSTOTPONY_ERROR_SYNC_CONFLICT 16
+/* Name matches several applications, see rustotpony_last_candidates */
+#define RUSTOTPONY_ERROR_AMBIGUOUS 17
+#define RUSTOTPONY_ERROR_OTHER 99
+
+/* Opened database, it's read-only and never saved back */
+typedef struct Vault RusTOTPonyVault;
+
+/* Opens the database at the path (or URL) with the password */
+int rustotpony_open(const char *path, const char *password, RusTOTPonyVault **vault);
+
+/* Names of all applications except hidden and archived ones, sorted and separated by newlines */
+int rustotpony_list(const RusTOTPonyVault *vault, char **names);
+
+/* Current code of the application */
+int rustotpony_code(const RusTOTPonyVault *vault, const char *name, char **code);
+
+/* RUSTOTPONY_OK if the code is valid, RUSTOTPONY_ERROR_INVALID_CODE if it isn't */
+int rustotpony_verify(const RusTOTPonyVault *vault, const char *name, const char *code);
+
+/* Message of the last error on the calling thread or NULL */
+const char *rustotpony_last_error(void);
+
+/* Names the name given to the last call on the calling thread matches, separated by newlines, if it failed with
+   RUSTOTPONY_ERROR_AMBIGUOUS, otherwise NULL */
+const char *rustotpony_last_candidates(void);
+
+/* Name of an error code, e.g. "wrong-password", or NULL if it's unknown; the string is static */
+const char *rustotpony_error_id(int code);
+
+/* Releases a string returned by the library */
+void rustotpony_string_free(char *value);
+
+void rustotpony_close(RusTOTPonyVault *vault);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
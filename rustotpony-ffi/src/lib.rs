@@ -0,0 +1,405 @@
+#[cfg(feature = "python")]
+mod python;
+
+use rustotpony_core::{storage_for, Error, ErrorCode, GeneratorKind, JsonDatabase, RusTOTPony};
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Codes are the numbers of `ErrorCode`, they never change
+pub const RUSTOTPONY_OK: c_int = 0;
+/// A null pointer or a string which isn't valid UTF-8 was passed
+pub const RUSTOTPONY_ERROR_ARGUMENT: c_int = 1;
+/// Database couldn't be read or decrypted
+pub const RUSTOTPONY_ERROR_DATABASE: c_int = 2;
+pub const RUSTOTPONY_ERROR_NOT_FOUND: c_int = 3;
+/// Application is protected with an additional passphrase
+pub const RUSTOTPONY_ERROR_PROTECTED: c_int = 4;
+/// Application doesn't generate codes on its own, e.g. challenge-response
+pub const RUSTOTPONY_ERROR_UNSUPPORTED: c_int = 5;
+pub const RUSTOTPONY_ERROR_INVALID_CODE: c_int = 6;
+/// Internal error of a debug build, release builds abort on it
+pub const RUSTOTPONY_ERROR_PANIC: c_int = 7;
+pub const RUSTOTPONY_ERROR_WRONG_PASSWORD: c_int = 8;
+/// File is damaged or isn't a database
+pub const RUSTOTPONY_ERROR_DAMAGED: c_int = 9;
+/// File was written by a newer version
+pub const RUSTOTPONY_ERROR_NEWER_VERSION: c_int = 10;
+pub const RUSTOTPONY_ERROR_READ_ONLY: c_int = 11;
+pub const RUSTOTPONY_ERROR_ALREADY_EXISTS: c_int = 12;
+pub const RUSTOTPONY_ERROR_CANCELLED: c_int = 13;
+pub const RUSTOTPONY_ERROR_IO: c_int = 14;
+pub const RUSTOTPONY_ERROR_NETWORK: c_int = 15;
+pub const RUSTOTPONY_ERROR_SYNC_CONFLICT: c_int = 16;
+/// Name matches several applications, see `rustotpony_last_candidates`
+pub const RUSTOTPONY_ERROR_AMBIGUOUS: c_int = 17;
+pub const RUSTOTPONY_ERROR_OTHER: c_int = 99;
+
+/// Opened database, it's read-only and never saved back.
+pub struct Vault {
+    app: RusTOTPony<JsonDatabase>,
+}
+
+struct Failure(c_int, String);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+    static LAST_CANDIDATES: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+static ERROR_IDS: OnceLock<Vec<(c_int, CString)>> = OnceLock::new();
+
+/// Runs the body, stores the error message for `rustotpony_last_error` and
+/// converts the result to an error code.
+fn guard<F: FnOnce() -> Result<(), Failure>>(body: F) -> c_int {
+    LAST_CANDIDATES.with(|last| *last.borrow_mut() = None);
+    // Failures come back as errors. Release builds abort on a bug, which
+    // is reported as `RUSTOTPONY_ERROR_PANIC` only by builds that unwind.
+    let result = panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|_| {
+        Err(Failure(
+            RUSTOTPONY_ERROR_PANIC,
+            String::from("Unexpected internal error"),
+        ))
+    });
+    let (code, message) = match result {
+        Ok(()) => (RUSTOTPONY_OK, None),
+        Err(Failure(code, message)) => (code, CString::new(message).ok()),
+    };
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+    code
+}
+
+unsafe fn read_str<'a>(value: *const c_char, name: &str) -> Result<&'a str, Failure> {
+    if value.is_null() {
+        return Err(Failure(
+            RUSTOTPONY_ERROR_ARGUMENT,
+            format!("Argument '{}' is null", name),
+        ));
+    }
+    CStr::from_ptr(value).to_str().map_err(|_| {
+        Failure(
+            RUSTOTPONY_ERROR_ARGUMENT,
+            format!("Argument '{}' isn't valid UTF-8", name),
+        )
+    })
+}
+
+unsafe fn read_vault<'a>(vault: *const Vault) -> Result<&'a Vault, Failure> {
+    vault
+        .as_ref()
+        .ok_or_else(|| Failure(RUSTOTPONY_ERROR_ARGUMENT, String::from("Vault is null")))
+}
+
+unsafe fn write_string(output: *mut *mut c_char, value: String) -> Result<(), Failure> {
+    if output.is_null() {
+        return Err(Failure(
+            RUSTOTPONY_ERROR_ARGUMENT,
+            String::from("Output pointer is null"),
+        ));
+    }
+    let value = CString::new(value).map_err(|_| {
+        Failure(
+            RUSTOTPONY_ERROR_ARGUMENT,
+            String::from("Value contains a NUL character"),
+        )
+    })?;
+    *output = value.into_raw();
+    Ok(())
+}
+
+/// Error of the database with the code of its kind, `Other` is reported
+/// as `RUSTOTPONY_ERROR_DATABASE`. Names an ambiguous name matches are
+/// kept for `rustotpony_last_candidates`.
+fn database_error(err: Error) -> Failure {
+    if !err.get_candidates().is_empty() {
+        let candidates = CString::new(err.get_candidates().join("\n")).ok();
+        LAST_CANDIDATES.with(|last| *last.borrow_mut() = candidates);
+    }
+    match err.code() {
+        ErrorCode::Other => Failure(RUSTOTPONY_ERROR_DATABASE, err.to_string()),
+        code => Failure(code.code(), err.to_string()),
+    }
+}
+
+/// Opens the database at the path (or URL) with the password.
+///
+/// # Safety
+///
+/// `path` and `password` must be NUL-terminated strings, `vault` must point
+/// to writable memory. The handle has to be released with `rustotpony_close`.
+#[no_mangle]
+pub unsafe extern "C" fn rustotpony_open(
+    path: *const c_char,
+    password: *const c_char,
+    vault: *mut *mut Vault,
+) -> c_int {
+    guard(|| {
+        let path = read_str(path, "path")?;
+        let password = String::from(read_str(password, "password")?);
+        if vault.is_null() {
+            return Err(Failure(
+                RUSTOTPONY_ERROR_ARGUMENT,
+                String::from("Argument 'vault' is null"),
+            ));
+        }
+        let storage = storage_for(path).map_err(database_error)?;
+        let db = JsonDatabase::builder()
+            .storage(storage)
+            .secret(move || password.clone())
+            .read_only(true)
+            .build()
+            .map_err(database_error)?;
+        let content = db.load_content().map_err(database_error)?;
+        *vault = Box::into_raw(Box::new(Vault {
+            app: RusTOTPony::with_content(db, content),
+        }));
+        Ok(())
+    })
+}
+
+/// Returns names of all applications except hidden and archived ones,
+/// sorted and separated by newlines.
+///
+/// # Safety
+///
+/// `vault` must be a handle from `rustotpony_open`, `names` must point to
+/// writable memory. The result has to be released with
+/// `rustotpony_string_free`.
+#[no_mangle]
+pub unsafe extern "C" fn rustotpony_list(vault: *const Vault, names: *mut *mut c_char) -> c_int {
+    guard(|| {
+        let vault = read_vault(vault)?;
+        let apps = vault.app.get_applications().map_err(database_error)?;
+        let mut list: Vec<&str> = apps
+            .values()
+            .filter(|app| !app.is_hidden() && !app.is_archived())
+            .map(|app| app.get_name())
+            .collect();
+        list.sort_unstable();
+        write_string(names, list.join("\n"))
+    })
+}
+
+/// Returns the current code of the application.
+///
+/// # Safety
+///
+/// `vault` must be a handle from `rustotpony_open`, `name` must be
+/// a NUL-terminated string and `code` must point to writable memory. The
+/// result has to be released with `rustotpony_string_free`.
+#[no_mangle]
+pub unsafe extern "C" fn rustotpony_code(
+    vault: *const Vault,
+    name: *const c_char,
+    code: *mut *mut c_char,
+) -> c_int {
+    guard(|| {
+        let vault = read_vault(vault)?;
+        let name = read_str(name, "name")?;
+        let app = vault.app.get_application(name).map_err(database_error)?;
+        if app.is_protected() {
+            return Err(Failure(
+                RUSTOTPONY_ERROR_PROTECTED,
+                format!("Application '{}' is protected", name),
+            ));
+        }
+        if let GeneratorKind::Ocra { .. } = app.get_kind() {
+            return Err(Failure(
+                RUSTOTPONY_ERROR_UNSUPPORTED,
+                format!("Application '{}' requires a challenge", name),
+            ));
+        }
+        write_string(code, app.get_code())
+    })
+}
+
+/// Checks a code against the application, returns `RUSTOTPONY_OK` if it's
+/// valid and `RUSTOTPONY_ERROR_INVALID_CODE` if it isn't.
+///
+/// # Safety
+///
+/// `vault` must be a handle from `rustotpony_open`, `name` and `code` must
+/// be NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn rustotpony_verify(
+    vault: *const Vault,
+    name: *const c_char,
+    code: *const c_char,
+) -> c_int {
+    guard(|| {
+        let vault = read_vault(vault)?;
+        let name = read_str(name, "name")?;
+        let code = read_str(code, "code")?;
+        let app = vault.app.get_application(name).map_err(database_error)?;
+        if app.is_protected() {
+            return Err(Failure(
+                RUSTOTPONY_ERROR_PROTECTED,
+                format!("Application '{}' is protected", name),
+            ));
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or_default();
+        if app.verify(code, now) {
+            Ok(())
+        } else {
+            Err(Failure(
+                RUSTOTPONY_ERROR_INVALID_CODE,
+                String::from("Code is invalid"),
+            ))
+        }
+    })
+}
+
+/// Returns the message of the last error on the calling thread or null.
+/// The pointer stays valid until the next call on the same thread.
+#[no_mangle]
+pub extern "C" fn rustotpony_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |err| err.as_ptr())
+    })
+}
+
+/// Returns the names of applications the name given to the last call on
+/// the calling thread matches, separated by newlines, if it failed with
+/// `RUSTOTPONY_ERROR_AMBIGUOUS`, otherwise null. The pointer stays valid
+/// until the next call on the same thread.
+#[no_mangle]
+pub extern "C" fn rustotpony_last_candidates() -> *const c_char {
+    LAST_CANDIDATES.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |candidates| candidates.as_ptr())
+    })
+}
+
+/// Returns the name of an error code, e.g. "wrong-password", or null if
+/// the code is unknown. Names are stable like the codes; the string is
+/// static and isn't released.
+#[no_mangle]
+pub extern "C" fn rustotpony_error_id(code: c_int) -> *const c_char {
+    let ids = ERROR_IDS.get_or_init(|| {
+        ErrorCode::ALL
+            .iter()
+            .map(|kind| {
+                let id = CString::new(kind.id()).expect("Error ID contains a NUL character");
+                (kind.code(), id)
+            })
+            .collect()
+    });
+    ids.iter()
+        .find(|(known, _)| *known == code)
+        .map_or(ptr::null(), |(_, id)| id.as_ptr())
+}
+
+/// Releases a string returned by the library.
+///
+/// # Safety
+///
+/// `value` must be a string returned by the library or null, it can't be
+/// used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn rustotpony_string_free(value: *mut c_char) {
+    if !value.is_null() {
+        drop(CString::from_raw(value));
+    }
+}
+
+/// Closes the database.
+///
+/// # Safety
+///
+/// `vault` must be a handle from `rustotpony_open` or null, it can't be
+/// used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn rustotpony_close(vault: *mut Vault) {
+    if !vault.is_null() {
+        drop(Box::from_raw(vault));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn temp_file(name: &str, data: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("rustotpony-{}-{}", std::process::id(), name));
+        fs::write(&path, data).unwrap();
+        path
+    }
+
+    fn open(path: &Path, password: &str) -> (c_int, *mut Vault) {
+        let path = CString::new(path.to_str().unwrap()).unwrap();
+        let password = CString::new(password).unwrap();
+        let mut vault = ptr::null_mut();
+        let code = unsafe { rustotpony_open(path.as_ptr(), password.as_ptr(), &mut vault) };
+        (code, vault)
+    }
+
+    #[test]
+    fn damaged_file_is_reported_with_its_code() {
+        let path = temp_file("damaged", &[0x5a; 300]);
+        let (code, vault) = open(&path, "password");
+        fs::remove_file(&path).unwrap();
+        assert_eq!(code, RUSTOTPONY_ERROR_DAMAGED);
+        assert!(vault.is_null());
+        assert!(!rustotpony_last_error().is_null());
+        let id = unsafe { CStr::from_ptr(rustotpony_error_id(code)) };
+        assert_eq!(id.to_str(), Ok("damaged"));
+    }
+
+    #[test]
+    fn ambiguous_name_lists_candidates() {
+        let path =
+            std::env::temp_dir().join(format!("rustotpony-{}-ambiguous", std::process::id()));
+        let db = JsonDatabase::builder()
+            .path(path.clone())
+            .secret(|| String::from("password"))
+            .kdf(rustotpony_core::Kdf::scrypt(10).unwrap())
+            .backup_policy(rustotpony_core::BackupPolicy::None)
+            .build()
+            .unwrap();
+        let mut app = RusTOTPony::new(db);
+        for name in ["github", "gitlab"] {
+            app.create_application(name, "", "JBSWY3DPEHPK3PXP")
+                .unwrap();
+        }
+        app.flush().unwrap();
+        let (code, vault) = open(&path, "password");
+        fs::remove_file(&path).unwrap();
+        assert_eq!(code, RUSTOTPONY_OK);
+        let name = CString::new("git").unwrap();
+        let mut output = ptr::null_mut();
+        let code = unsafe { rustotpony_code(vault, name.as_ptr(), &mut output) };
+        assert_eq!(code, RUSTOTPONY_ERROR_AMBIGUOUS);
+        let candidates = unsafe { CStr::from_ptr(rustotpony_last_candidates()) };
+        assert_eq!(candidates.to_str(), Ok("github\ngitlab"));
+        let name = CString::new("gith").unwrap();
+        let code = unsafe { rustotpony_code(vault, name.as_ptr(), &mut output) };
+        assert_eq!(code, RUSTOTPONY_OK);
+        assert!(rustotpony_last_candidates().is_null());
+        unsafe {
+            rustotpony_string_free(output);
+            rustotpony_close(vault);
+        }
+    }
+
+    #[test]
+    fn null_arguments_are_rejected() {
+        let mut vault = ptr::null_mut();
+        let code = unsafe { rustotpony_open(ptr::null(), ptr::null(), &mut vault) };
+        assert_eq!(code, RUSTOTPONY_ERROR_ARGUMENT);
+        assert!(vault.is_null());
+    }
+}
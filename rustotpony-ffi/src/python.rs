@@ -0,0 +1,201 @@
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+
+use rustotpony_core::{storage_for, GenApp, GeneratorKind, JsonDatabase, RusTOTPony, TOTP};
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn value_error<E: ToString>(err: E) -> PyErr {
+    PyValueError::new_err(err.to_string())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+fn kind_name(kind: &GeneratorKind) -> &'static str {
+    match kind {
+        GeneratorKind::Totp => "totp",
+        GeneratorKind::Motp { .. } => "motp",
+        GeneratorKind::Ocra { .. } => "ocra",
+    }
+}
+
+/// TOTP generator which isn't stored anywhere.
+#[pyclass(name = "TOTP", module = "rustotpony", frozen)]
+struct PyTotp {
+    totp: TOTP,
+}
+
+#[pymethods]
+impl PyTotp {
+    /// Creates a generator from a base32 secret.
+    #[new]
+    fn new(secret: &str) -> PyResult<PyTotp> {
+        TOTP::new(secret)
+            .map(|totp| PyTotp { totp })
+            .map_err(value_error)
+    }
+
+    #[staticmethod]
+    fn from_hex(secret: &str) -> PyResult<PyTotp> {
+        TOTP::new_hex(secret)
+            .map(|totp| PyTotp { totp })
+            .map_err(value_error)
+    }
+
+    #[getter]
+    fn secret(&self) -> String {
+        self.totp.get_secret_base32()
+    }
+
+    fn now(&self) -> String {
+        self.totp.now()
+    }
+
+    /// Returns the code valid at the given unix time.
+    fn generate(&self, timestamp: u64) -> String {
+        self.totp.generate(timestamp)
+    }
+}
+
+/// Application stored in a database, a copy without the secret.
+#[pyclass(name = "Application", module = "rustotpony", frozen, get_all)]
+struct PyApplication {
+    name: String,
+    username: String,
+    kind: &'static str,
+    hidden: bool,
+    archived: bool,
+    protected: bool,
+}
+
+impl From<&GenApp> for PyApplication {
+    fn from(app: &GenApp) -> PyApplication {
+        PyApplication {
+            name: String::from(app.get_name()),
+            username: String::from(app.get_username()),
+            kind: kind_name(app.get_kind()),
+            hidden: app.is_hidden(),
+            archived: app.is_archived(),
+            protected: app.is_protected(),
+        }
+    }
+}
+
+#[pymethods]
+impl PyApplication {
+    fn __repr__(&self) -> String {
+        format!("Application(name={:?}, kind={:?})", self.name, self.kind)
+    }
+}
+
+/// Opened database. The location is a local path, `s3://bucket/key` or
+/// `sftp://host/path`, the same as for the `--database` option of `totp`.
+#[pyclass(name = "RusTOTPony", module = "rustotpony")]
+struct PyRusTOTPony {
+    app: RusTOTPony<JsonDatabase>,
+}
+
+#[pymethods]
+impl PyRusTOTPony {
+    #[new]
+    #[pyo3(signature = (location, password, read_only = false))]
+    fn new(location: &str, password: String, read_only: bool) -> PyResult<PyRusTOTPony> {
+        let storage = storage_for(location).map_err(value_error)?;
+        let db = JsonDatabase::builder()
+            .storage(storage)
+            .secret(move || password.clone())
+            .read_only(read_only)
+            .build()
+            .map_err(value_error)?;
+        let content = db.load_content().map_err(value_error)?;
+        Ok(PyRusTOTPony {
+            app: RusTOTPony::with_content(db, content),
+        })
+    }
+
+    /// Returns all applications sorted by name, hidden and archived ones
+    /// included.
+    fn applications(&self) -> PyResult<Vec<PyApplication>> {
+        let mut apps: Vec<PyApplication> = self
+            .app
+            .get_applications()
+            .map_err(value_error)?
+            .values()
+            .map(PyApplication::from)
+            .collect();
+        apps.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(apps)
+    }
+
+    /// Returns the current code, passphrase is required for protected
+    /// applications.
+    #[pyo3(signature = (name, passphrase = None))]
+    fn code(&self, name: &str, passphrase: Option<&str>) -> PyResult<String> {
+        let app = self.unlocked(name, passphrase)?;
+        if let GeneratorKind::Ocra { .. } = app.get_kind() {
+            return Err(value_error(format!(
+                "Application '{}' requires a challenge",
+                name
+            )));
+        }
+        Ok(app.get_code())
+    }
+
+    /// Checks a code, the previous and the next ones are accepted too.
+    #[pyo3(signature = (name, code, passphrase = None))]
+    fn verify(&self, name: &str, code: &str, passphrase: Option<&str>) -> PyResult<bool> {
+        Ok(self.unlocked(name, passphrase)?.verify(code, now()))
+    }
+
+    /// Adds a TOTP application with a base32 secret.
+    #[pyo3(signature = (name, secret, username = ""))]
+    fn add(&mut self, name: &str, secret: &str, username: &str) -> PyResult<()> {
+        self.app
+            .create_application(name, username, secret)
+            .map_err(value_error)
+    }
+
+    fn delete(&mut self, name: &str) -> PyResult<()> {
+        self.app.delete_application(name).map_err(value_error)
+    }
+
+    fn rename(&mut self, name: &str, new_name: &str) -> PyResult<()> {
+        self.app
+            .rename_application(name, new_name)
+            .map_err(value_error)
+    }
+
+    /// Saves changes, nothing is written before it's called.
+    fn flush(&mut self) -> PyResult<()> {
+        self.app
+            .flush()
+            .map_err(|err| PyRuntimeError::new_err(err.to_string()))
+    }
+}
+
+impl PyRusTOTPony {
+    fn unlocked(&self, name: &str, passphrase: Option<&str>) -> PyResult<GenApp> {
+        let app = self.app.get_application(name).map_err(value_error)?;
+        match passphrase {
+            Some(passphrase) => app.unlock(passphrase).map_err(value_error),
+            None if app.is_protected() => Err(value_error(format!(
+                "Application '{}' is protected, its passphrase is required",
+                name
+            ))),
+            None => Ok(app.clone()),
+        }
+    }
+}
+
+#[pymodule]
+fn rustotpony(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyTotp>()?;
+    module.add_class::<PyApplication>()?;
+    module.add_class::<PyRusTOTPony>()?;
+    Ok(())
+}